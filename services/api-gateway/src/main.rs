@@ -1,7 +1,10 @@
 use axum::{
     body::Body,
-    extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, Request, State,
+    },
+    http::{HeaderMap, HeaderValue, StatusCode},
     middleware::{self, Next},
     response::{Json, Response},
     routing::{any, get},
@@ -9,8 +12,9 @@ use axum::{
 };
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
@@ -21,9 +25,142 @@ struct AppState {
     asset_url: String,
     jwt_secret: String,
     rate_limiters: DashMap<String, TokenBucket>,
+    plans: Plans,
     start_time: Instant,
 }
 
+/// How long a user's plan lookup, or an API key's profile, is reused before
+/// asking Supabase again.
+const PLAN_TTL: Duration = Duration::from_secs(60);
+
+/// Plan lookups against Supabase's REST API (`profiles.plan`, `plan_configs`).
+/// Without `SUPABASE_URL` every caller falls back to their token's plan, or free.
+struct Plans {
+    supabase_url: String,
+    service_key: String,
+    client: reqwest::Client,
+    budgets: DashMap<String, (Instant, String)>,
+    /// Profiles by API key hash. Only keys that matched are kept, so unknown keys
+    /// cannot grow the map.
+    keys: DashMap<String, (Instant, ProfileRow)>,
+}
+
+#[derive(Deserialize, Clone)]
+struct ProfileRow {
+    id: String,
+    plan: String,
+}
+
+#[derive(Deserialize)]
+struct PlanConfigRow {
+    max_mesh_resolution: i64,
+}
+
+impl Plans {
+    async fn rows<T: serde::de::DeserializeOwned>(
+        &self,
+        table: &str,
+        query: &[(&str, String)],
+    ) -> Result<Vec<T>, String> {
+        let resp = self
+            .client
+            .get(format!("{}/rest/v1/{table}", self.supabase_url))
+            .header("apikey", &self.service_key)
+            .bearer_auth(&self.service_key)
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("{table}: HTTP {}", resp.status()));
+        }
+        resp.json().await.map_err(|e| e.to_string())
+    }
+
+    /// Profile owning an API key; keys are stored as hex SHA-256 in `api_key_hash`.
+    async fn profile_for_key(&self, key: &str) -> Option<ProfileRow> {
+        if self.supabase_url.is_empty() {
+            return None;
+        }
+        let hash: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        if let Some(p) = self.keys.get(&hash).filter(|p| p.0.elapsed() < PLAN_TTL) {
+            return Some(p.1.clone());
+        }
+        let q = [
+            ("api_key_hash", format!("eq.{hash}")),
+            ("select", "id,plan".into()),
+        ];
+        match self.rows::<ProfileRow>("profiles", &q).await {
+            Ok(rows) => {
+                let profile = rows.into_iter().next();
+                match &profile {
+                    Some(p) => {
+                        self.keys.insert(hash, (Instant::now(), p.clone()));
+                    }
+                    None => {
+                        self.keys.remove(&hash);
+                    }
+                }
+                profile
+            }
+            Err(e) => {
+                tracing::warn!("API key lookup failed: {e}");
+                None
+            }
+        }
+    }
+
+    /// `X-SDF-Budget` value for a user: their `profiles.plan`, else `fallback`,
+    /// with `plan_configs.max_mesh_resolution` as the resolution cap.
+    async fn budget(&self, sub: &str, fallback: Option<&str>) -> String {
+        if let Some(b) = self.budgets.get(sub).filter(|b| b.0.elapsed() < PLAN_TTL) {
+            return b.1.clone();
+        }
+        match self.lookup(sub).await {
+            Ok(Some((plan, res))) => {
+                let b = plan_budget(&plan, res);
+                self.budgets.insert(sub.into(), (Instant::now(), b.clone()));
+                b
+            }
+            Ok(None) => plan_budget(&normalize_plan(fallback.unwrap_or("free")), None),
+            Err(e) => {
+                tracing::warn!("plan lookup for {sub} failed: {e}");
+                plan_budget(&normalize_plan(fallback.unwrap_or("free")), None)
+            }
+        }
+    }
+
+    async fn lookup(&self, sub: &str) -> Result<Option<(String, Option<i64>)>, String> {
+        if self.supabase_url.is_empty() {
+            return Ok(None);
+        }
+        let q = [("id", format!("eq.{sub}")), ("select", "id,plan".into())];
+        let Some(profile) = self
+            .rows::<ProfileRow>("profiles", &q)
+            .await?
+            .into_iter()
+            .next()
+        else {
+            return Ok(None);
+        };
+        let plan = normalize_plan(&profile.plan);
+        let q = [
+            ("plan", format!("eq.{plan}")),
+            ("select", "max_mesh_resolution".into()),
+        ];
+        let res = self
+            .rows::<PlanConfigRow>("plan_configs", &q)
+            .await?
+            .into_iter()
+            .next()
+            .map(|c| c.max_mesh_resolution);
+        Ok(Some((plan, res)))
+    }
+}
+
 struct TokenBucket {
     tokens: f64,
     max_tokens: f64,
@@ -81,6 +218,8 @@ struct Claims {
     sub: String,
     email: Option<String>,
     role: Option<String>,
+    #[serde(default)]
+    plan: Option<String>,
     exp: usize,
 }
 
@@ -101,6 +240,13 @@ async fn main() {
         asset_url: env("ASSET_URL", "http://asset:8084"),
         jwt_secret: env("JWT_SECRET", "dev-secret-change-me"),
         rate_limiters: DashMap::new(),
+        plans: Plans {
+            supabase_url: env("SUPABASE_URL", "").trim_end_matches('/').into(),
+            service_key: env("SUPABASE_SERVICE_KEY", ""),
+            client: reqwest::Client::new(),
+            budgets: DashMap::new(),
+            keys: DashMap::new(),
+        },
         start_time: Instant::now(),
     });
 
@@ -114,6 +260,7 @@ async fn main() {
         .route("/license", get(license_handler));
 
     let api = Router::new()
+        .route("/ws/sdf/preview", get(proxy_preview))
         .route("/api/v1/sdf/*p", any(proxy_sdf))
         .route("/api/v1/mesh/*p", any(proxy_sdf))
         .route("/api/v1/shader/*p", any(proxy_sdf))
@@ -202,11 +349,15 @@ async fn auth_mw(
         }
     }
 
-    if api_key.is_some() {
+    if let Some(key) = &api_key {
+        let profile = s.plans.profile_for_key(key).await;
         req.extensions_mut().insert(Claims {
-            sub: "api-key-user".into(),
+            sub: profile
+                .as_ref()
+                .map_or_else(|| "api-key-user".into(), |p| p.id.clone()),
             email: None,
             role: Some("api".into()),
+            plan: profile.map(|p| p.plan),
             exp: usize::MAX,
         });
         return Ok(next.run(req).await);
@@ -321,12 +472,133 @@ async fn forward(
     })
 }
 
+/// Plan tier as stored in `plan_configs`; billing writes `'Pro'`/`'Free'`, and
+/// anything unrecognised is the free tier.
+fn normalize_plan(plan: &str) -> String {
+    match plan.trim().to_ascii_lowercase().as_str() {
+        p @ ("pro" | "enterprise") => p.into(),
+        _ => "free".into(),
+    }
+}
+
+/// SDF Engine resource limits for a plan, sent as the trusted `X-SDF-Budget`
+/// header. `max_resolution` comes from `plan_configs.max_mesh_resolution` when
+/// known; `PLAN_BUDGET_<PLAN>` (e.g. `PLAN_BUDGET_PRO`) overrides the whole value.
+fn plan_budget(plan: &str, max_resolution: Option<i64>) -> String {
+    let (res, rest) = match plan {
+        "enterprise" => (
+            1024,
+            "max_nodes=1000000, max_points=10000000, timeout_ms=300000",
+        ),
+        "pro" => (512, "max_nodes=500, max_points=1000000, timeout_ms=60000"),
+        _ => (128, "max_nodes=50, max_points=100000, timeout_ms=10000"),
+    };
+    let res = max_resolution.filter(|&r| r > 0).unwrap_or(res);
+    std::env::var(format!("PLAN_BUDGET_{}", plan.to_uppercase()))
+        .unwrap_or_else(|_| format!("max_resolution={res}, {rest}"))
+}
+
+/// `X-SDF-Budget` for the authenticated caller, or the free tier.
+async fn budget_header(s: &AppState, claims: Option<&Claims>) -> HeaderValue {
+    let budget = match claims {
+        Some(c) => s.plans.budget(&c.sub, c.plan.as_deref()).await,
+        None => plan_budget("free", None),
+    };
+    HeaderValue::from_str(&budget).unwrap_or_else(|_| HeaderValue::from_static("max_nodes=0"))
+}
+
+/// Replace any client-sent budget with the caller's plan limits.
+async fn set_budget(s: &AppState, req: &mut Request) {
+    let value = budget_header(s, req.extensions().get::<Claims>()).await;
+    req.headers_mut().insert("x-sdf-budget", value);
+}
+
 async fn proxy_sdf(
     State(s): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
 ) -> Result<Response, (StatusCode, Json<Err>)> {
+    set_budget(&s, &mut req).await;
     forward(&s.sdf_url, req).await
 }
+/// `/ws/sdf/preview`: connect to the engine with the caller's budget first, so a
+/// refused upstream is a 502 rather than a socket that closes at once, then
+/// relay messages both ways until either side closes.
+async fn proxy_preview(
+    State(s): State<Arc<AppState>>,
+    claims: Option<Extension<Claims>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, Json<Err>)> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    let unavailable = |e: String| {
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(Err {
+                error: "Upstream unavailable".into(),
+                details: Some(e),
+            }),
+        )
+    };
+    let url = format!("{}/ws/sdf/preview", ws_url(&s.sdf_url));
+    let mut up = url
+        .into_client_request()
+        .map_err(|e| unavailable(e.to_string()))?;
+    let budget = budget_header(&s, claims.as_ref().map(|c| &c.0)).await;
+    up.headers_mut().insert("x-sdf-budget", budget);
+    let (upstream, _) = tokio_tungstenite::connect_async(up)
+        .await
+        .map_err(|e| unavailable(e.to_string()))?;
+    Ok(ws.on_upgrade(move |client| relay(client, upstream)))
+}
+
+/// `ws://` form of a service's base URL; services talk over the internal network.
+fn ws_url(http: &str) -> String {
+    format!(
+        "ws://{}",
+        http.split_once("://").map_or(http, |(_, rest)| rest)
+    )
+}
+
+type Upstream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Copy text and binary messages between the client and the engine. Each
+/// direction closes its sink when its source ends, which ends the other.
+async fn relay(client: WebSocket, upstream: Upstream) {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message as Up;
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut up_tx, mut up_rx) = upstream.split();
+    let to_engine = async {
+        while let Some(Ok(m)) = client_rx.next().await {
+            let m = match m {
+                Message::Text(t) => Up::Text(t),
+                Message::Binary(b) => Up::Binary(b),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if up_tx.send(m).await.is_err() {
+                break;
+            }
+        }
+        let _ = up_tx.close().await;
+    };
+    let to_client = async {
+        while let Some(Ok(m)) = up_rx.next().await {
+            let m = match m {
+                Up::Text(t) => Message::Text(t),
+                Up::Binary(b) => Message::Binary(b),
+                Up::Close(_) => break,
+                _ => continue,
+            };
+            if client_tx.send(m).await.is_err() {
+                break;
+            }
+        }
+        let _ = client_tx.close().await;
+    };
+    tokio::join!(to_engine, to_client);
+}
+
 async fn proxy_ai(
     State(s): State<Arc<AppState>>,
     req: Request,
@@ -341,7 +613,167 @@ async fn proxy_collab(
 }
 async fn proxy_asset(
     State(s): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
 ) -> Result<Response, (StatusCode, Json<Err>)> {
+    set_budget(&s, &mut req).await;
     forward(&s.asset_url, req).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    #[test]
+    fn plans_are_case_insensitive() {
+        assert_eq!(normalize_plan("Pro"), "pro");
+        assert_eq!(normalize_plan(" ENTERPRISE "), "enterprise");
+        assert_eq!(normalize_plan("Free"), "free");
+        assert_eq!(normalize_plan("platinum"), "free");
+    }
+
+    #[test]
+    fn plan_configs_set_the_resolution() {
+        assert_eq!(
+            plan_budget("pro", None),
+            "max_resolution=512, max_nodes=500, max_points=1000000, timeout_ms=60000"
+        );
+        assert_eq!(
+            plan_budget("free", Some(96)),
+            "max_resolution=96, max_nodes=50, max_points=100000, timeout_ms=10000"
+        );
+        // Unlimited (-1) in plan_configs keeps the tier's default cap.
+        assert!(plan_budget("enterprise", Some(-1)).starts_with("max_resolution=1024,"));
+    }
+
+    #[tokio::test]
+    async fn without_supabase_the_token_plan_applies() {
+        let plans = Plans {
+            supabase_url: String::new(),
+            service_key: String::new(),
+            client: reqwest::Client::new(),
+            budgets: DashMap::new(),
+            keys: DashMap::new(),
+        };
+        assert!(plans
+            .budget("u1", Some("Pro"))
+            .await
+            .starts_with("max_resolution=512,"));
+        assert!(plans
+            .budget("u1", None)
+            .await
+            .starts_with("max_resolution=128,"));
+        assert!(plans.profile_for_key("amsk_x").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn api_key_profiles_are_cached_for_the_ttl() {
+        // Nothing listens here, so only cached keys resolve.
+        let plans = Plans {
+            supabase_url: "http://127.0.0.1:9".into(),
+            service_key: String::new(),
+            client: reqwest::Client::new(),
+            budgets: DashMap::new(),
+            keys: DashMap::new(),
+        };
+        let hash = |k: &str| -> String {
+            Sha256::digest(k.as_bytes())
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect()
+        };
+        let profile = |id: &str| ProfileRow {
+            id: id.into(),
+            plan: "pro".into(),
+        };
+        plans
+            .keys
+            .insert(hash("amsk_fresh"), (Instant::now(), profile("u1")));
+        let stale = Instant::now().checked_sub(PLAN_TTL * 2).unwrap();
+        plans
+            .keys
+            .insert(hash("amsk_stale"), (stale, profile("u2")));
+        assert_eq!(
+            plans.profile_for_key("amsk_fresh").await.map(|p| p.id),
+            Some("u1".into())
+        );
+        assert!(plans.profile_for_key("amsk_stale").await.is_none());
+        assert!(plans.profile_for_key("amsk_unknown").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn preview_sockets_carry_the_plan_budget() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message as Up;
+        // Stand-in engine: greets with the budget it was given, then echoes.
+        async fn engine(headers: HeaderMap, ws: WebSocketUpgrade) -> Response {
+            let budget = headers["x-sdf-budget"].to_str().unwrap().to_string();
+            ws.on_upgrade(|mut socket| async move {
+                socket.send(Message::Text(budget)).await.unwrap();
+                while let Some(Ok(Message::Text(t))) = socket.recv().await {
+                    socket
+                        .send(Message::Text(format!("echo {t}")))
+                        .await
+                        .unwrap();
+                }
+            })
+        }
+        async fn serve(app: Router) -> String {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            format!("{addr}")
+        }
+        let engine = serve(Router::new().route("/ws/sdf/preview", get(engine))).await;
+        let state = Arc::new(AppState {
+            sdf_url: format!("http://{engine}"),
+            ai_url: String::new(),
+            collab_url: String::new(),
+            asset_url: String::new(),
+            jwt_secret: String::new(),
+            rate_limiters: DashMap::new(),
+            plans: Plans {
+                supabase_url: String::new(),
+                service_key: String::new(),
+                client: reqwest::Client::new(),
+                budgets: DashMap::new(),
+                keys: DashMap::new(),
+            },
+            start_time: Instant::now(),
+        });
+        let claims = Claims {
+            sub: "u1".into(),
+            email: None,
+            role: None,
+            plan: Some("Pro".into()),
+            exp: usize::MAX,
+        };
+        let gateway = serve(
+            Router::new()
+                .route("/ws/sdf/preview", get(proxy_preview))
+                .layer(Extension(claims))
+                .with_state(state),
+        )
+        .await;
+
+        let mut req = format!("ws://{gateway}/ws/sdf/preview")
+            .into_client_request()
+            .unwrap();
+        req.headers_mut().insert(
+            "x-sdf-budget",
+            HeaderValue::from_static("max_nodes=1000000"),
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(req).await.unwrap();
+        let next = |m: Option<Result<Up, _>>| m.unwrap().unwrap().into_text().unwrap();
+        assert_eq!(next(socket.next().await), plan_budget("pro", None));
+        socket.send(Up::Text("hello".into())).await.unwrap();
+        assert_eq!(next(socket.next().await), "echo hello");
+        socket.close(None).await.unwrap();
+    }
+
+    #[test]
+    fn websocket_urls_keep_host_and_port() {
+        assert_eq!(ws_url("http://sdf-engine:8081"), "ws://sdf-engine:8081");
+        assert_eq!(ws_url("localhost:8081"), "ws://localhost:8081");
+    }
+}
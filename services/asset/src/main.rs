@@ -1,6 +1,6 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{Json, IntoResponse},
     routing::{get, post},
     Router,
@@ -108,14 +108,19 @@ fn default_resolution() -> usize {
 
 async fn export_asset(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<ExportRequest>,
 ) -> Result<Json<ExportResponse>, (StatusCode, Json<serde_json::Value>)> {
     let export_id = Uuid::new_v4().to_string();
 
     // Forward to SDF engine for mesh generation
     let client = reqwest::Client::new();
-    let engine_resp = client
-        .post(format!("{}/api/v1/export", state.sdf_engine_url))
+    let mut engine_req = client.post(format!("{}/api/v1/export", state.sdf_engine_url));
+    // Plan limits set by the gateway apply to the engine work done on the caller's behalf.
+    if let Some(budget) = headers.get("x-sdf-budget").and_then(|h| h.to_str().ok()) {
+        engine_req = engine_req.header("x-sdf-budget", budget);
+    }
+    let engine_resp = engine_req
         .json(&serde_json::json!({
             "tree": req.tree,
             "format": req.format,
@@ -134,7 +139,15 @@ async fn export_asset(
         })?;
 
     if !engine_resp.status().is_success() {
+        let status = StatusCode::from_u16(engine_resp.status().as_u16())
+            .unwrap_or(StatusCode::BAD_GATEWAY);
         let err = engine_resp.text().await.unwrap_or_default();
+        // Budget errors keep the engine's status and machine-readable body.
+        if let Ok(body) = serde_json::from_str::<serde_json::Value>(&err) {
+            if body.get("limit").is_some() {
+                return Err((status, Json(body)));
+            }
+        }
         return Err((
             StatusCode::BAD_REQUEST,
             Json(serde_json::json!({
@@ -0,0 +1,84 @@
+//! Keyframed parameters. Any number (or vec3/list) in `params` or `material`, and
+//! any root variable, may be a curve over time:
+//! `{"keys": [{"t": 0, "value": 0}, {"t": 2, "value": 6.28, "ease": "ease_in_out"}]}`.
+//!
+//! `ease` on a key shapes the segment that ends at it: `linear` (default), `step`
+//! (hold until the key), `ease_in`, `ease_out` or `ease_in_out`. Before the first
+//! key and after the last one the curve holds its end value.
+
+use serde_json::{json, Value};
+
+pub fn is_curve(v: &Value) -> bool { v.as_object().is_some_and(|o| o.contains_key("keys")) }
+
+struct Key { t: f64, value: Vec<f64>, ease: fn(f64) -> f64 }
+
+fn ease(name: &str) -> Option<fn(f64) -> f64> {
+    Some(match name {
+        "linear" => |u| u,
+        "step" => |u| if u < 1.0 { 0.0 } else { 1.0 },
+        "ease_in" => |u| u * u,
+        "ease_out" => |u| 1.0 - (1.0 - u) * (1.0 - u),
+        "ease_in_out" => |u| u * u * (3.0 - 2.0 * u),
+        _ => return None,
+    })
+}
+
+/// Keys of a curve plus whether its values are arrays.
+fn keys(v: &Value) -> Result<(Vec<Key>, bool), String> {
+    let o = v.as_object().ok_or("curve must be an object")?;
+    if let Some(k) = o.keys().find(|k| *k != "keys") { return Err(format!("curve has no field '{k}'")); }
+    let arr = o.get("keys").and_then(|k| k.as_array()).filter(|a| !a.is_empty()).ok_or("curve 'keys' must be a non-empty array")?;
+    let mut out: Vec<Key> = Vec::with_capacity(arr.len());
+    let mut vector = false;
+    for (i, k) in arr.iter().enumerate() {
+        let t = k.get("t").and_then(|t| t.as_f64()).ok_or_else(|| format!("keys[{i}] needs a numeric 't'"))?;
+        let value = match k.get("value") {
+            Some(Value::Number(x)) => vec![x.as_f64().unwrap_or(0.0)],
+            Some(Value::Array(a)) => { vector = true; a.iter().map(|x| x.as_f64()).collect::<Option<_>>().ok_or_else(|| format!("keys[{i}].value must hold numbers"))? }
+            _ => return Err(format!("keys[{i}] needs a 'value' number or array of numbers")),
+        };
+        let ease = match k.get("ease") {
+            None => ease("linear").unwrap_or(|u| u),
+            Some(e) => e.as_str().and_then(ease).ok_or_else(|| format!("keys[{i}].ease must be one of linear, step, ease_in, ease_out, ease_in_out"))?,
+        };
+        if let Some(p) = out.last() {
+            if t <= p.t { return Err(format!("keys[{i}].t must be greater than the previous key's")); }
+            if value.len() != p.value.len() { return Err(format!("keys[{i}].value must have the same shape as the other keys")); }
+        }
+        if let Some(k) = k.as_object().and_then(|o| o.keys().find(|k| !matches!(k.as_str(), "t" | "value" | "ease"))) { return Err(format!("keys[{i}] has no field '{k}'")); }
+        out.push(Key { t, value, ease });
+    }
+    Ok((out, vector))
+}
+
+/// Value of curve `v` at time `t`: a number, or an array when the keys hold arrays.
+pub fn sample(v: &Value, t: f64) -> Result<Value, String> {
+    let (keys, vector) = keys(v)?;
+    let i = keys.partition_point(|k| k.t <= t);
+    let value = if i == 0 { keys[0].value.clone() } else if i == keys.len() { keys[i - 1].value.clone() } else {
+        let (a, b) = (&keys[i - 1], &keys[i]);
+        let w = (b.ease)((t - a.t) / (b.t - a.t));
+        a.value.iter().zip(&b.value).map(|(x, y)| x + (y - x) * w).collect()
+    };
+    Ok(if vector { json!(value) } else { json!(value[0]) })
+}
+
+/// Span of key times over every curve in `tree`; `None` for a static tree.
+pub fn key_range(tree: &Value) -> Option<(f64, f64)> {
+    let mut span: Option<(f64, f64)> = None;
+    visit(tree, &mut |v| {
+        let Ok((keys, _)) = keys(v) else { return };
+        let (a, b) = (keys[0].t, keys[keys.len() - 1].t);
+        span = Some(span.map_or((a, b), |(lo, hi)| (lo.min(a), hi.max(b))));
+    });
+    span
+}
+
+fn visit(v: &Value, f: &mut dyn FnMut(&Value)) {
+    match v {
+        Value::Object(_) if is_curve(v) => f(v),
+        Value::Object(o) => o.values().for_each(|x| visit(x, f)),
+        Value::Array(a) => a.iter().for_each(|x| visit(x, f)),
+        _ => {}
+    }
+}
@@ -0,0 +1,271 @@
+//! `sdf`: the engine's tree tools without the HTTP stack, for batch jobs and CI.
+//!
+//! Every command reads one tree JSON file (`-` for stdin) and writes its result
+//! to stdout or `-o FILE`. Output holds no timings or other run-dependent data,
+//! so the same tree and options give the same bytes. Exit codes: 0 success,
+//! 1 the tree is invalid or has no surface, 2 bad usage, 3 an I/O error.
+
+mod render;
+
+use sdf_core::{bounds, compiler, eval::Field, eval_bvh, export, lipschitz, mesh, schema, shader, tree};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: sdf <command> <tree.json|-> [options]
+
+commands:
+  validate    check the tree against the schema and compile it
+  eval        distances at points
+  mesh        marching-tetrahedra mesh
+  transpile   WGSL / GLSL / HLSL source
+  render      sphere-traced PNG image
+  export      mesh file with its material library, or an animation sequence
+
+options:
+  -o, --output FILE    write to FILE instead of stdout (export: required)
+  --time T             sample keyframe curves at T (default 0)
+  --json               validate, eval: JSON output
+  --point X,Y,Z        eval: a point; repeatable
+  --points FILE        eval: JSON [[x, y, z], ...] or one `x y z` per line
+  --resolution N       mesh, export: cells along the longest side (default 128)
+  --format F           mesh: obj, stl, ply or glb (default from -o, else obj)
+  --target T           transpile: wgsl, glsl or hlsl (default wgsl)
+  --size WxH           render: image size (default 512x512)
+  --view AZ,EL         render: camera azimuth and elevation in degrees (default 30,20)
+  --frames S:E:N       export: N evenly spaced frames from time S to E";
+
+/// A failure and the exit code it maps to.
+enum Fail { Tree(String), Usage(String), Io(String) }
+
+impl Fail {
+    fn code(&self) -> u8 { match self { Fail::Tree(_) => 1, Fail::Usage(_) => 2, Fail::Io(_) => 3 } }
+}
+
+#[derive(Default)]
+struct Opts {
+    output: Option<String>, time: f64, json: bool, points: Vec<[f32; 3]>, points_file: Option<String>,
+    resolution: Option<usize>, format: Option<String>, target: Option<String>, size: Option<(usize, usize)>,
+    view: Option<(f32, f32)>, frames: Option<(f64, f64, usize)>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(f) => {
+            match &f {
+                Fail::Usage(m) => eprintln!("sdf: {m}\nrun `sdf --help` for usage"),
+                Fail::Tree(m) | Fail::Io(m) => eprintln!("sdf: {m}"),
+            }
+            ExitCode::from(f.code())
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Fail> {
+    let (cmd, rest) = args.split_first().ok_or_else(|| Fail::Usage("missing command".into()))?;
+    if matches!(cmd.as_str(), "-h" | "--help" | "help") { println!("{USAGE}"); return Ok(()); }
+    let (path, o) = parse_args(rest)?;
+    let path = path.ok_or_else(|| Fail::Usage("missing tree file".into()))?;
+    let tree: Value = serde_json::from_str(&read_input(&path)?).map_err(|e| Fail::Tree(format!("{path}: invalid JSON: {e}")))?;
+    match cmd.as_str() {
+        "validate" => validate(&tree, &o),
+        "eval" => eval(&tree, &o),
+        "mesh" => mesh_cmd(&tree, &o),
+        "transpile" => transpile(&tree, &o),
+        "render" => render_cmd(&tree, &o),
+        "export" => export_cmd(&tree, &o),
+        _ => Err(Fail::Usage(format!("unknown command '{cmd}'"))),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(Option<String>, Opts), Fail> {
+    let mut o = Opts::default();
+    let mut path = None;
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        let mut val = |name: &str| it.next().cloned().ok_or_else(|| Fail::Usage(format!("{name} needs a value")));
+        let bad = |name: &str, v: &str| Fail::Usage(format!("invalid {name} '{v}'"));
+        match a.as_str() {
+            "-o" | "--output" => o.output = Some(val(a)?),
+            "--json" => o.json = true,
+            "--time" => { let v = val(a)?; o.time = v.parse().ok().filter(|t: &f64| t.is_finite()).ok_or_else(|| bad(a, &v))?; }
+            "--point" => {
+                let v = val(a)?;
+                let c: Vec<f32> = v.split(',').map(|x| x.trim().parse()).collect::<Result<_, _>>().map_err(|_| bad(a, &v))?;
+                o.points.push(c.try_into().map_err(|_| bad(a, &v))?);
+            }
+            "--points" => o.points_file = Some(val(a)?),
+            "--resolution" => { let v = val(a)?; o.resolution = Some(v.parse().ok().filter(|&n| n > 0).ok_or_else(|| bad(a, &v))?); }
+            "--format" => o.format = Some(val(a)?),
+            "--target" => o.target = Some(val(a)?),
+            "--size" => {
+                let v = val(a)?;
+                let wh = v.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+                o.size = Some(wh.filter(|&(w, h)| (1..=8192).contains(&w) && (1..=8192).contains(&h)).ok_or_else(|| bad(a, &v))?);
+            }
+            "--view" => {
+                let v = val(a)?;
+                let ae = v.split_once(',').and_then(|(az, el)| Some((az.trim().parse().ok()?, el.trim().parse().ok()?)));
+                o.view = Some(ae.filter(|&(az, el): &(f32, f32)| az.is_finite() && el.abs() < 90.0).ok_or_else(|| bad(a, &v))?);
+            }
+            "--frames" => {
+                let v = val(a)?;
+                let p: Vec<&str> = v.split(':').collect();
+                let f = match p[..] { [s, e, n] => (|| Some((s.parse().ok()?, e.parse().ok()?, n.parse().ok()?)))(), _ => None };
+                o.frames = Some(f.filter(|&(s, e, n): &(f64, f64, usize)| s.is_finite() && e.is_finite() && s <= e && (1..=240).contains(&n)).ok_or_else(|| bad(a, &v))?);
+            }
+            _ if a.starts_with('-') && a != "-" => return Err(Fail::Usage(format!("unknown option '{a}'"))),
+            _ if path.is_none() => path = Some(a.clone()),
+            _ => return Err(Fail::Usage(format!("unexpected argument '{a}'"))),
+        }
+    }
+    Ok((path, o))
+}
+
+fn read_input(path: &str) -> Result<String, Fail> {
+    let mut s = String::new();
+    let r = if path == "-" { std::io::stdin().read_to_string(&mut s).map(|_| ()) } else { std::fs::File::open(path).and_then(|mut f| f.read_to_string(&mut s).map(|_| ())) };
+    r.map_err(|e| Fail::Io(format!("{path}: {e}")))?;
+    Ok(s)
+}
+
+fn write_file(path: &str, bytes: &[u8]) -> Result<(), Fail> { std::fs::write(path, bytes).map_err(|e| Fail::Io(format!("{path}: {e}"))) }
+
+/// Result to `-o FILE`, or stdout.
+fn write_output(o: &Opts, bytes: &[u8]) -> Result<(), Fail> {
+    match &o.output {
+        Some(p) => write_file(p, bytes),
+        None => { let mut out = std::io::stdout().lock(); out.write_all(bytes).and_then(|_| out.flush()).map_err(|e| Fail::Io(format!("stdout: {e}"))) }
+    }
+}
+
+fn compile_at(tree: &Value, time: f64) -> Result<(tree::SdfNode, compiler::CompiledSdf), Fail> {
+    let node = tree::SdfNode::from_json_at(tree, time).map_err(Fail::Tree)?;
+    let sdf = compiler::compile(&node).map_err(Fail::Tree)?;
+    Ok((node, sdf))
+}
+
+/// Same domain the engine meshes over: the tree's bounds, unbounded sides clamped to ±5.
+fn domain_of(node: &tree::SdfNode) -> Result<bounds::Aabb, Fail> {
+    let b = bounds::bounds(node).clamp_to(5.0);
+    if b.is_empty() { return Err(Fail::Tree("tree bounds are empty".into())); }
+    Ok(b)
+}
+
+fn validate(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let errors = schema::validate(tree);
+    let (nodes, depth) = (tree::count_nodes(tree), tree::tree_depth(tree));
+    let analysis = if errors.is_empty() { tree::SdfNode::from_json(tree).ok().map(|n| lipschitz::analyze(&n)) } else { None };
+    let out = if o.json {
+        let mut v = json!({ "valid": errors.is_empty(), "node_count": nodes, "depth": depth, "node_types": tree::collect_types(tree), "errors": errors });
+        if let (Some(m), Ok(Value::Object(a))) = (v.as_object_mut(), serde_json::to_value(&analysis)) { m.extend(a); }
+        v.to_string() + "\n"
+    } else if let Some(a) = analysis {
+        format!("valid: {nodes} nodes, depth {depth}, Lipschitz bound {:.2}\n", a.lipschitz) + &a.warnings.iter().map(|w| format!("warning: {}: {}\n", w.path, w.message)).collect::<String>()
+    } else if errors.is_empty() {
+        format!("valid: {nodes} nodes, depth {depth}\n")
+    } else {
+        errors.iter().map(|e| format!("error: {e}\n")).collect()
+    };
+    write_output(o, out.as_bytes())?;
+    if errors.is_empty() { Ok(()) } else { Err(Fail::Tree(format!("{} problem(s) found", errors.len()))) }
+}
+
+fn eval(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let mut points = o.points.clone();
+    if let Some(f) = &o.points_file { points.extend(read_points(f)?); }
+    if points.is_empty() { return Err(Fail::Usage("eval needs --point or --points".into())); }
+    let (_, sdf) = compile_at(tree, o.time)?;
+    let d = sdf.eval_batch(&points);
+    let out = if o.json { json!({ "distances": d }).to_string() + "\n" } else { d.iter().map(|x| format!("{x}\n")).collect() };
+    write_output(o, out.as_bytes())
+}
+
+fn read_points(path: &str) -> Result<Vec<[f32; 3]>, Fail> {
+    let text = read_input(path)?;
+    let bad = |e: String| Fail::Usage(format!("{path}: {e}"));
+    if text.trim_start().starts_with('[') { return serde_json::from_str(&text).map_err(|e| bad(e.to_string())); }
+    text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()).map(|(i, l)| {
+        let c: Vec<f32> = l.split_whitespace().map(str::parse).collect::<Result<_, _>>().map_err(|_| bad(format!("line {}: expected 'x y z'", i + 1)))?;
+        c.try_into().map_err(|_| bad(format!("line {}: expected 'x y z'", i + 1)))
+    }).collect()
+}
+
+/// Mesh format from `--format`, else the output file's extension, else OBJ.
+fn mesh_format(o: &Opts) -> Result<String, Fail> {
+    let ext = o.output.as_deref().and_then(|p| std::path::Path::new(p).extension()).and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+    let f = o.format.clone().or(ext.filter(|e| matches!(e.as_str(), "obj" | "stl" | "ply" | "glb"))).unwrap_or_else(|| "obj".into());
+    if !matches!(f.as_str(), "obj" | "stl" | "ply" | "glb") { return Err(Fail::Usage(format!("'{f}' is not one of obj, stl, ply, glb"))); }
+    Ok(f)
+}
+
+/// Painted mesh of `tree` at `time`; unions of many parts are sampled through a BVH.
+fn mesh_at(tree: &Value, time: f64, o: &Opts) -> Result<mesh::Mesh, Fail> {
+    let (node, sdf) = compile_at(tree, time)?;
+    let bvh = eval_bvh::BvhSdf::for_large(&node);
+    let field: &dyn Field = match &bvh { Some(b) => b, None => &sdf };
+    let mut m = mesh::generate(field, &domain_of(&node)?, o.resolution.unwrap_or(128), &mut |_| true).ok_or_else(|| Fail::Tree("mesh generation was cancelled".into()))?;
+    mesh::paint(&mut m, &sdf);
+    Ok(m)
+}
+
+fn encode(format: &str, name: &str, m: &mesh::Mesh) -> Vec<u8> {
+    match format {
+        "obj" => export::to_obj(name, m).into_bytes(),
+        "stl" => export::to_stl(name, m).into_bytes(),
+        "ply" => export::to_ply(m).into_bytes(),
+        _ => export::to_glb(&[(name.to_string(), m)]),
+    }
+}
+
+fn mesh_cmd(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let format = mesh_format(o)?;
+    let m = mesh_at(tree, o.time, o)?;
+    let name = tree.get("type").and_then(Value::as_str).unwrap_or("sdf");
+    write_output(o, &encode(&format, name, &m))
+}
+
+fn transpile(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let t = o.target.as_deref().unwrap_or("wgsl");
+    let target = shader::Target::parse(t).ok_or_else(|| Fail::Usage(format!("'{t}' is not one of wgsl, glsl, hlsl")))?;
+    let (_, sdf) = compile_at(tree, o.time)?;
+    write_output(o, (shader::transpile(&sdf, target) + "\n").as_bytes())
+}
+
+fn render_cmd(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let (node, sdf) = compile_at(tree, o.time)?;
+    let (width, height) = o.size.unwrap_or((512, 512));
+    let (azimuth, elevation) = o.view.unwrap_or((30.0, 20.0));
+    let v = render::View { width, height, azimuth, elevation };
+    write_output(o, &render::png(width, height, &render::render(&sdf, &domain_of(&node)?, &v)))
+}
+
+/// Asset files named after `-o`: a painted OBJ gets its `.mtl` beside it; with
+/// `--frames`, GLB holds the whole sequence and text formats get one numbered
+/// file per frame (`name_0000.obj`, ...).
+fn export_cmd(tree: &Value, o: &Opts) -> Result<(), Fail> {
+    let out = o.output.as_deref().ok_or_else(|| Fail::Usage("export needs -o FILE".into()))?;
+    let format = mesh_format(o)?;
+    let path = std::path::Path::new(out);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("sdf").to_string();
+    let sibling = |name: &str| path.with_file_name(name).to_string_lossy().into_owned();
+    let Some((start, end, n)) = o.frames else {
+        let m = mesh_at(tree, o.time, o)?;
+        if format == "obj" && !m.face_materials.is_empty() { write_file(&sibling(&format!("{stem}.mtl")), export::to_mtl(&m).as_bytes())?; }
+        return write_file(out, &encode(&format, &stem, &m));
+    };
+    let times: Vec<f64> = (0..n).map(|i| if n == 1 { start } else { start + (end - start) * i as f64 / (n - 1) as f64 }).collect();
+    let meshes = times.iter().map(|&t| mesh_at(tree, t, o)).collect::<Result<Vec<_>, _>>()?;
+    if format == "glb" {
+        let objects: Vec<(String, &mesh::Mesh)> = meshes.iter().enumerate().map(|(i, m)| (format!("{stem}_frame_{i:04}"), m)).collect();
+        return write_file(out, &export::to_glb_sequence(&objects, &times.iter().map(|&t| t as f32).collect::<Vec<_>>()));
+    }
+    for (i, m) in meshes.iter().enumerate() {
+        let name = format!("{stem}_{i:04}");
+        if format == "obj" && !m.face_materials.is_empty() { write_file(&sibling(&format!("{name}.mtl")), export::to_mtl(m).as_bytes())?; }
+        write_file(&sibling(&format!("{name}.{format}")), &encode(&format, &name, m))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Sphere-traced preview images and a dependency-free PNG encoder.
+//!
+//! The camera orbits the centre of the meshing domain at a distance that fits
+//! its bounding sphere in a 40° field of view. Surfaces are lit by one
+//! directional light over an ambient term, in their blended base colour plus
+//! emission. Rows trace in parallel, but every pixel is independent, so the
+//! image does not depend on scheduling.
+
+use rayon::prelude::*;
+use sdf_core::bounds::Aabb;
+use sdf_core::compiler::CompiledSdf;
+use sdf_core::math::{self, V3};
+
+const FOV: f32 = 40.0;
+const MAX_STEPS: usize = 256;
+const BACKGROUND: V3 = [0.12, 0.12, 0.14];
+
+pub struct View { pub width: usize, pub height: usize, pub azimuth: f32, pub elevation: f32 }
+
+/// RGB8 pixels, rows top to bottom.
+pub fn render(sdf: &CompiledSdf, domain: &Aabb, v: &View) -> Vec<u8> {
+    let (az, el) = (v.azimuth.to_radians(), v.elevation.to_radians());
+    let back = [el.cos() * az.sin(), el.sin(), el.cos() * az.cos()];
+    let centre = domain.center();
+    let radius = (0.5 * math::len(domain.size())).max(1e-3);
+    let half = (0.5 * FOV).to_radians().tan();
+    let eye = math::add(centre, math::mul(back, 1.1 * radius / (0.5 * FOV).to_radians().sin()));
+    let forward = math::mul(back, -1.0);
+    let right = math::normalize(math::cross(forward, [0.0, 1.0, 0.0]));
+    let up = math::cross(right, forward);
+    let light = math::normalize(math::add(math::mul(back, 0.6), math::add(math::mul(right, -0.4), [0.0, 0.8, 0.0])));
+    let (eps, far) = (radius * 1e-4, math::len(math::sub(eye, centre)) + 2.0 * radius);
+    let aspect = v.width as f32 / v.height as f32;
+    (0..v.height).into_par_iter().flat_map_iter(|j| {
+        let (mut vs, mut ps, mut ms) = (Vec::new(), Vec::new(), Vec::new());
+        let mut row = Vec::with_capacity(v.width * 3);
+        for i in 0..v.width {
+            let x = (2.0 * (i as f32 + 0.5) / v.width as f32 - 1.0) * half * aspect;
+            let y = (1.0 - 2.0 * (j as f32 + 0.5) / v.height as f32) * half;
+            let dir = math::normalize(math::add(forward, math::add(math::mul(right, x), math::mul(up, y))));
+            let colour = trace(sdf, eye, dir, eps, far).map_or(BACKGROUND, |t| {
+                let p = math::add(eye, math::mul(dir, t));
+                let (_, s) = sdf.eval_material_with(p, &mut vs, &mut ps, &mut ms);
+                let n = normal(sdf, p, eps * 10.0);
+                let lit = 0.2 + 0.8 * math::dot(n, light).max(0.0);
+                [0, 1, 2].map(|c| s.value[c] * lit + s.value[5 + c])
+            });
+            row.extend(colour.map(|c| (c.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0 + 0.5) as u8));
+        }
+        row
+    }).collect()
+}
+
+/// Ray parameter of the first hit, if any before `far`. Steps are scaled by the
+/// tree's `step_scale` so fields that overstate distances don't overshoot.
+fn trace(sdf: &CompiledSdf, eye: V3, dir: V3, eps: f32, far: f32) -> Option<f32> {
+    let mut t = 0.0;
+    for _ in 0..MAX_STEPS {
+        let d = sdf.eval(math::add(eye, math::mul(dir, t)));
+        if d.abs() < eps * (1.0 + t) { return Some(t); }
+        t += (d * sdf.step_scale).max(eps);
+        if t > far { return None; }
+    }
+    None
+}
+
+fn normal(sdf: &CompiledSdf, p: V3, e: f32) -> V3 {
+    let g = |i: usize| { let mut a = p; let mut b = p; a[i] += e; b[i] -= e; sdf.eval(a) - sdf.eval(b) };
+    math::normalize([g(0), g(1), g(2)])
+}
+
+/// 8-bit RGB PNG with stored (uncompressed) deflate blocks.
+pub fn png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3) { raw.push(0); raw.extend_from_slice(row); }
+    let mut z = vec![0x78, 0x01];
+    let blocks = raw.chunks(0xffff).count();
+    for (i, b) in raw.chunks(0xffff).enumerate() {
+        let n = b.len() as u16;
+        z.push(u8::from(i + 1 == blocks));
+        z.extend_from_slice(&n.to_le_bytes());
+        z.extend_from_slice(&(!n).to_le_bytes());
+        z.extend_from_slice(b);
+    }
+    if raw.is_empty() { z.extend_from_slice(&[1, 0, 0, 0xff, 0xff]); }
+    z.extend_from_slice(&adler32(&raw).to_be_bytes());
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (ty, data) in [(b"IHDR", &ihdr), (b"IDAT", &z), (b"IEND", &Vec::new())] {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(ty);
+        out.extend_from_slice(data);
+        let crc = crc32(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |c, &b| (0..8).fold(c ^ b as u32, |c, _| if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 }))
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &x| { let a = (a + x as u32) % 65521; (a, (b + a) % 65521) });
+    (b << 16) | a
+}
@@ -0,0 +1,290 @@
+//! Conservative axis-aligned bounds of the region where a tree's distance is ≤ 0.
+
+use crate::math::{self, V3};
+use crate::tree::SdfNode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Aabb { pub min: V3, pub max: V3 }
+
+const INF: f32 = f32::INFINITY;
+
+impl Aabb {
+    pub const EVERYTHING: Aabb = Aabb { min: [-INF; 3], max: [INF; 3] };
+    pub const EMPTY: Aabb = Aabb { min: [INF; 3], max: [-INF; 3] };
+
+    pub fn sym(h: V3) -> Aabb { Aabb { min: math::mul(h, -1.0), max: h } }
+    pub fn is_empty(&self) -> bool { (0..3).any(|i| self.min[i] > self.max[i]) }
+    pub fn is_finite(&self) -> bool { self.min.iter().chain(&self.max).all(|v| v.is_finite()) }
+    pub fn union(&self, o: &Aabb) -> Aabb {
+        Aabb { min: std::array::from_fn(|i| self.min[i].min(o.min[i])), max: std::array::from_fn(|i| self.max[i].max(o.max[i])) }
+    }
+    pub fn intersect(&self, o: &Aabb) -> Aabb {
+        Aabb { min: std::array::from_fn(|i| self.min[i].max(o.min[i])), max: std::array::from_fn(|i| self.max[i].min(o.max[i])) }
+    }
+    pub fn expand(&self, e: f32) -> Aabb { Aabb { min: math::sub(self.min, [e; 3]), max: math::add(self.max, [e; 3]) } }
+    pub fn center(&self) -> V3 { math::mul(math::add(self.min, self.max), 0.5) }
+    pub fn size(&self) -> V3 { math::sub(self.max, self.min) }
+    /// Replace unbounded axes by `[-limit, limit]`.
+    pub fn clamp_to(&self, limit: f32) -> Aabb {
+        Aabb { min: self.min.map(|v| v.max(-limit)), max: self.max.map(|v| v.min(limit)) }
+    }
+    fn map_corners(&self, f: impl Fn(V3) -> V3) -> Aabb {
+        if !self.is_finite() { return if self.is_empty() { *self } else { Aabb::EVERYTHING }; }
+        let mut r = Aabb::EMPTY;
+        for c in 0..8 {
+            let p = [if c & 1 == 0 { self.min[0] } else { self.max[0] }, if c & 2 == 0 { self.min[1] } else { self.max[1] }, if c & 4 == 0 { self.min[2] } else { self.max[2] }];
+            let q = f(p);
+            r = r.union(&Aabb { min: q, max: q });
+        }
+        r
+    }
+}
+
+fn floats(n: &SdfNode, k: &str) -> Vec<f32> {
+    n.params.get(k).and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect()).unwrap_or_default()
+}
+
+pub fn bounds(n: &SdfNode) -> Aabb {
+    let c = |i: usize| n.children.get(i).map_or(Aabb::EMPTY, bounds);
+    match n.ty.as_str() {
+        "Sphere" => Aabb::sym([n.f("radius", 1.0); 3]),
+        "Box3d" => Aabb::sym(n.v3("half_size", [0.5; 3])),
+        "RoundedBox" => Aabb::sym(n.v3("half_size", [0.5; 3])),
+        "Cylinder" => { let (r, h) = (n.f("radius", 0.5), n.f("half_height", 1.0)); Aabb::sym([r, h, r]) }
+        "Torus" => { let (a, b) = (n.f("major_radius", 1.0), n.f("minor_radius", 0.25)); Aabb::sym([a + b, b, a + b]) }
+        "Capsule" => { let (r, h) = (n.f("radius", 0.3), n.f("half_height", 0.5)); Aabb::sym([r, h + r, r]) }
+        "Cone" => { let (r, h) = (n.f("radius", 0.5), n.f("height", 1.0)); Aabb::sym([r, h * 0.5, r]) }
+        "Ellipsoid" => Aabb::sym(n.v3("radii", [1.0, 0.5, 0.5])),
+        "Pyramid" => { let (h, b) = (n.f("height", 1.0), n.f("base", 1.0) * 0.5); Aabb { min: [-b, 0.0, -b], max: [b, h, b] } }
+        "Octahedron" => Aabb::sym([n.f("size", 1.0); 3]),
+        "Tetrahedron" => Aabb::sym([n.f("size", 1.0); 3]),
+        "RoundedCone" => {
+            let (r1, r2, h) = (n.f("radius_bottom", 0.5), n.f("radius_top", 0.25), n.f("height", 1.0) * 0.5);
+            let r = r1.max(r2);
+            Aabb { min: [-r, -h - r1, -r], max: [r, h + r2, r] }
+        }
+        "HexPrism" => { let r = n.f("radius", 0.5); Aabb::sym([r * 1.154_7, r, n.f("half_depth", 0.5)]) }
+        "Link" => { let (r1, r2) = (n.f("radius", 0.5), n.f("thickness", 0.1)); Aabb::sym([r1 + r2, n.f("half_length", 0.5) + r1 + r2, r2]) }
+        "Triangle" | "Bezier" => {
+            // Both stay inside the hull of their control points.
+            let (a, b, c) = if n.ty == "Triangle" { (n.v3("a", [-0.5, 0.0, 0.0]), n.v3("b", [0.5, 0.0, 0.0]), n.v3("c", [0.0, 0.8, 0.0])) } else { (n.v3("a", [-1.0, 0.0, 0.0]), n.v3("b", [0.0, 1.0, 0.0]), n.v3("c", [1.0, 0.0, 0.0])) };
+            let e = if n.ty == "Triangle" { n.f("thickness", 0.02) } else { n.f("radius", 0.1) };
+            Aabb { min: a, max: a }.union(&Aabb { min: b, max: b }).union(&Aabb { min: c, max: c }).expand(e)
+        }
+        "CappedCone" => { let r = n.f("radius_bottom", 0.5).max(n.f("radius_top", 0.25)); Aabb::sym([r, n.f("half_height", 0.5), r]) }
+        "CappedTorus" => { let (a, b) = (n.f("major_radius", 1.0), n.f("minor_radius", 0.25)); Aabb::sym([a + b, a + b, b]) }
+        "RoundedCylinder" | "Tube" => { let r = n.f("radius", 0.5); Aabb::sym([r, n.f("half_height", 1.0), r]) }
+        "TriangularPrism" => { let s = n.f("size", 1.0); Aabb { min: [-0.866_1 * s, -0.5 * s, -n.f("half_depth", 0.5)], max: [0.866_1 * s, s, n.f("half_depth", 0.5)] } }
+        "CutSphere" => {
+            let (r, h) = (n.f("radius", 1.0), n.f("cut_height", 0.5));
+            let w = if h > 0.0 { (r * r - h * h).max(0.0).sqrt() } else { r };
+            Aabb { min: [-w, h.max(-r), -w], max: [w, r, w] }
+        }
+        "CutHollowSphere" => { let r = n.f("radius", 1.0) + n.f("thickness", 0.05); Aabb { min: [-r; 3], max: [r, (n.f("cut_height", 0.5) + n.f("thickness", 0.05)).min(r), r] } }
+        "DeathStar" | "SolidAngle" => Aabb::sym([n.f("radius", 1.0); 3]),
+        "Rhombus" => { let ra = n.f("radius", 0.05); Aabb::sym([n.f("half_width", 0.6) + ra, n.f("half_height", 0.1), n.f("half_depth", 0.3) + ra]) }
+        "Vesica" => {
+            let (r, d) = (n.f("radius", 1.0), n.f("distance", 0.5));
+            Aabb::sym([r - d, (r * r - d * d).max(0.0).sqrt(), r - d])
+        }
+        "Egg" => { let (ra, rb) = (n.f("radius", 0.5), n.f("tip_radius", 0.1)); Aabb { min: [-ra; 3], max: [ra, 3f32.sqrt() * (ra - rb) + rb, ra] } }
+        "Barrel" => { let r = n.f("radius", 0.5) + n.f("bulge", 0.1).max(0.0); Aabb::sym([r, n.f("half_height", 1.0), r]) }
+        "ChamferedCube" | "BoxFrame" => Aabb::sym(n.v3("half_size", [0.5; 3])),
+        "Superellipsoid" => Aabb::sym(n.v3("radii", [1.0; 3])),
+        "Helix" => { let r = n.f("radius", 1.0) + n.f("thickness", 0.1); Aabb::sym([r, n.f("half_height", 1.0), r]) }
+        "InfiniteCylinder" => { let r = n.f("radius", 0.5); Aabb { min: [-r, -INF, -r], max: [r, INF, r] } }
+        "InfiniteCone" => Aabb { min: [-INF; 3], max: [INF, 0.0, INF] },
+        // Face-plane polyhedra: circumradius / inradius is at most ~1.258 for the icosahedral family.
+        "Dodecahedron" | "Icosahedron" | "TruncatedIcosahedron" => Aabb::sym([n.f("radius", 1.0) * 1.26; 3]),
+        "TruncatedOctahedron" => Aabb::sym([n.f("radius", 1.0); 3]),
+        "Horseshoe" | "Heart" | "RoundedX" | "Pie" | "Trapezoid" | "Parallelogram" | "Tunnel" | "UnevenCapsule" | "ArcShape"
+        | "Moon" | "CrossShape" | "BlobbyCross" | "ParabolaSegment" | "StairsPrim" => {
+            let f = |k: &str, d: f32| n.f(k, d);
+            let (lo, hi) = match n.ty.as_str() {
+                "Horseshoe" => { let r = f("radius", 0.5) + f("thickness", 0.05) + f("length", 0.25).max(0.0); ([-r, -r], [r, r]) }
+                "Heart" => { let s = f("size", 1.0); ([-0.61 * s, -0.5 * s], [0.61 * s, 0.61 * s]) }
+                "RoundedX" => { let r = f("width", 1.0) * 0.5 + f("radius", 0.1); ([-r, -r], [r, r]) }
+                "Pie" => { let r = f("radius", 1.0); ([-r, -r], [r, r]) }
+                "Trapezoid" => { let (r, h) = (f("bottom_width", 0.5).max(f("top_width", 0.25)), f("half_height", 0.5)); ([-r, -h], [r, h]) }
+                "Parallelogram" => { let (w, h) = (f("half_width", 0.5) + f("skew", 0.2).abs(), f("half_height", 0.3)); ([-w, -h], [w, h]) }
+                "Tunnel" => { let w = f("half_width", 0.5); ([-w, -f("height", 1.0)], [w, w]) }
+                "UnevenCapsule" => {
+                    let (r1, r2, h) = (f("radius_bottom", 0.3), f("radius_top", 0.15), f("height", 1.0) * 0.5);
+                    ([-r1.max(r2), -h - r1], [r1.max(r2), h + r2])
+                }
+                "ArcShape" => { let r = f("radius", 1.0) + f("thickness", 0.1); ([-r, -r], [r, r]) }
+                "Moon" => { let r = f("radius", 1.0); ([-r, -r], [r, r]) }
+                "CrossShape" => { let r = f("half_length", 0.5).max(f("half_width", 0.15)); ([-r, -r], [r, r]) }
+                "BlobbyCross" => { let r = f("size", 1.0) * (1.0 + f("height", 0.5)); ([-r, -r], [r, r]) }
+                "ParabolaSegment" => { let w = f("half_width", 0.5); ([-w, 0.0], [w, f("height", 1.0)]) }
+                _ => { let c = n.u("steps", 5).max(1) as f32; ([0.0, 0.0], [f("step_width", 0.2) * c, f("step_height", 0.2) * c]) }
+            };
+            let hd = if n.ty == "StairsPrim" { f("half_depth", 0.5) } else { f("half_depth", 0.1) };
+            Aabb { min: [lo[0], lo[1], -hd], max: [hi[0], hi[1], hd] }
+        }
+        "Circle2d" | "Rect2d" | "RoundedRect2d" | "Segment2d" | "Polygon2d" | "Annular2d" | "RegularPolygon" | "StarPolygon" => {
+            let f = |k: &str, d: f32| n.f(k, d);
+            let (lo, hi) = match n.ty.as_str() {
+                "Rect2d" | "RoundedRect2d" => { let (w, h) = (f("half_width", 0.5), f("half_height", 0.5)); ([-w, -h], [w, h]) }
+                "Segment2d" => {
+                    let (a, b, t) = (floats(n, "a"), floats(n, "b"), f("thickness", 0.05));
+                    let (a, b) = (if a.len() == 2 { [a[0], a[1]] } else { [-0.5, 0.0] }, if b.len() == 2 { [b[0], b[1]] } else { [0.5, 0.0] });
+                    ([a[0].min(b[0]) - t, a[1].min(b[1]) - t], [a[0].max(b[0]) + t, a[1].max(b[1]) + t])
+                }
+                "Polygon2d" => {
+                    let v = floats(n, "points");
+                    let v = if v.len() >= 6 { v } else { vec![-0.5, -0.5, 0.5, -0.5, 0.0, 0.5] };
+                    v.chunks(2).fold(([INF; 2], [-INF; 2]), |(lo, hi), c| ([lo[0].min(c[0]), lo[1].min(c[c.len() - 1])], [hi[0].max(c[0]), hi[1].max(c[c.len() - 1])]))
+                }
+                "Annular2d" => { let r = f("radius", 0.5) + f("thickness", 0.05); ([-r, -r], [r, r]) }
+                _ => { let r = f("radius", 0.5); ([-r, -r], [r, r]) }
+            };
+            Aabb { min: [lo[0], lo[1], -INF], max: [hi[0], hi[1], INF] }
+        }
+        "MeshSdf" if n.f("grid_cell", 0.0) > 0.0 => {
+            let (o, d) = (n.v3("grid_origin", [0.0; 3]), floats(n, "grid_dims"));
+            let size = if d.len() == 3 { math::mul([d[0] - 1.0, d[1] - 1.0, d[2] - 1.0], n.f("grid_cell", 0.0)) } else { [0.0; 3] };
+            Aabb { min: o, max: math::add(o, size) }
+        }
+        "MeshSdf" => floats(n, "vertices").chunks_exact(3).fold(Aabb::EMPTY, |b, v| b.union(&Aabb { min: [v[0], v[1], v[2]], max: [v[0], v[1], v[2]] })),
+        "Plane" => {
+            let nv = math::normalize(n.v3("normal", [0.0, 1.0, 0.0]));
+            let h = n.f("distance", 0.0);
+            // Axis-aligned half-spaces are bounded on one side.
+            let mut b = Aabb::EVERYTHING;
+            for i in 0..3 {
+                if (nv[i].abs() - 1.0).abs() < 1e-6 && nv[(i + 1) % 3] == 0.0 && nv[(i + 2) % 3] == 0.0 {
+                    if nv[i] > 0.0 { b.max[i] = -h; } else { b.min[i] = h; }
+                }
+            }
+            b
+        }
+
+        "Union" | "SmoothUnion" | "ChamferUnion" | "Xor" | "Morph" => {
+            let u = n.children.iter().map(bounds).fold(Aabb::EMPTY, |a, b| a.union(&b));
+            let pad = match n.ty.as_str() { "SmoothUnion" => n.f("k", 0.1) * 0.25, "ChamferUnion" => n.f("r", 0.1), _ => 0.0 };
+            if pad > 0.0 { u.expand(pad) } else { u }
+        }
+        "StairsUnion" | "ColumnsUnion" | "ExpSmoothUnion" => {
+            let pad = if n.ty == "ExpSmoothUnion" { n.f("k", 0.1) * std::f32::consts::LN_2 } else { n.f("r", 0.1) };
+            n.children.iter().map(bounds).fold(Aabb::EMPTY, |a, b| a.union(&b)).expand(pad.max(0.0))
+        }
+        "Intersection" | "SmoothIntersection" | "ChamferIntersection" | "StairsIntersection" | "ExpSmoothIntersection" | "ColumnsIntersection" => {
+            n.children.iter().map(bounds).fold(Aabb::EVERYTHING, |a, b| a.intersect(&b))
+        }
+        "Subtraction" | "SmoothSubtraction" | "ChamferSubtraction" | "StairsSubtraction" | "ExpSmoothSubtraction" | "ColumnsSubtraction"
+        | "Engrave" | "Groove" => c(0),
+        "Tongue" => c(0).expand(n.f("height", 0.1).max(0.0)),
+        "Pipe" => {
+            let r = n.f("r", 0.1).max(0.0);
+            n.children.iter().skip(1).fold(c(0), |a, b| a.expand(r).intersect(&bounds(b).expand(r)))
+        }
+
+        "Translate" => { let o = n.v3("offset", [0.0; 3]); c(0).map_corners(|p| math::add(p, o)) }
+        "RotateQuat" => {
+            let q = n.params.get("quaternion").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect::<Vec<_>>()).unwrap_or_default();
+            if q.len() != 4 { return c(0); }
+            let m = crate::compiler::quat_matrix([q[0], q[1], q[2], q[3]]);
+            c(0).map_corners(|p| math::mat_vec(&m, p))
+        }
+        "ProjectiveTransform" => {
+            let m: Vec<f32> = n.params.get("matrix").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect()).unwrap_or_default();
+            if m.len() != 16 { return c(0); }
+            let b = c(0);
+            let w = |p: V3| m[12] * p[0] + m[13] * p[1] + m[14] * p[2] + m[15];
+            // The image of a box is the hull of its corners only while w keeps one sign.
+            let corners_w: Vec<f32> = (0..8).map(|k| w([if k & 1 == 0 { b.min[0] } else { b.max[0] }, if k & 2 == 0 { b.min[1] } else { b.max[1] }, if k & 4 == 0 { b.min[2] } else { b.max[2] }])).collect();
+            if !b.is_finite() || !(corners_w.iter().all(|x| *x > 1e-6) || corners_w.iter().all(|x| *x < -1e-6)) { return if b.is_empty() { b } else { Aabb::EVERYTHING }; }
+            b.map_corners(|p| { let r = |i: usize| m[i] * p[0] + m[i + 1] * p[1] + m[i + 2] * p[2] + m[i + 3]; let w = w(p); [r(0) / w, r(4) / w, r(8) / w] })
+        }
+        "LatticeDeform" => {
+            let o = n.params.get("offsets").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_f64()).fold(0.0f32, |m, x| m.max(x.abs() as f32))).unwrap_or(0.0);
+            c(0).expand(o)
+        }
+        "SdfSkinning" => {
+            // Skinned points are blends of the per-bone poses; bound them by the rest pose and every posed box.
+            let b = c(0);
+            let bones = n.params.get("bones").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+            bones.iter().fold(b, |acc, bone| {
+                let g = |k: &str| SdfNode { params: bone.as_object().cloned().unwrap_or_default(), ..SdfNode::leaf("Bone", serde_json::Value::Null) }.v3(k, [0.0; 3]);
+                let (pivot, offset, m) = (g("pivot"), g("offset"), math::euler_xyz(g("angles")));
+                acc.union(&b.map_corners(|p| math::add(math::add(math::mat_vec(&m, math::sub(p, pivot)), pivot), offset)))
+            })
+        }
+        "Taper" => {
+            let (b, k) = (c(0), n.f("strength", 0.5));
+            if !b.is_finite() { return if b.is_empty() { b } else { Aabb::EVERYTHING }; }
+            let s = (1.0 + k * b.min[1]).max(1.0 + k * b.max[1]).max(0.05);
+            let r = [b.min[0].abs().max(b.max[0].abs()) * s, b.min[2].abs().max(b.max[2].abs()) * s];
+            Aabb { min: [-r[0], b.min[1], -r[1]], max: [r[0], b.max[1], r[1]] }
+        }
+        "MirrorOctant" => { let b = c(0); Aabb::sym(std::array::from_fn(|i| b.min[i].abs().max(b.max[i].abs()))) }
+        "IcosahedralSymmetry" | "IFS" => {
+            let b = c(0);
+            if !b.is_finite() { return if b.is_empty() { b } else { Aabb::EVERYTHING }; }
+            let mut r = math::len(std::array::from_fn(|i| b.min[i].abs().max(b.max[i].abs())));
+            if n.ty == "IFS" {
+                // Folds keep |p|; each scale-and-shift step can move a point by at most 2|offset|(s - 1).
+                let (it, s, o) = (n.u("iterations", 4).min(16), n.f("scale", 3.0), math::len(n.v3("offset", [1.0; 3])));
+                for _ in 0..it { r = (r + 2.0 * o * (s - 1.0)) / s; }
+            }
+            Aabb::sym([r; 3])
+        }
+        "RotateEuler" => { let m = math::euler_xyz(n.v3("angles", [0.0; 3])); c(0).map_corners(|p| math::mat_vec(&m, p)) }
+        "Scale" => { let s = n.f("factor", 1.0); c(0).map_corners(|p| math::mul(p, s)) }
+        "ScaleNonUniform" => { let f = n.v3("factors", [1.0; 3]); c(0).map_corners(|p| math::mul3(p, f)) }
+        "Twist" => {
+            let b = c(0);
+            let r = (0..4).map(|i| math::len2(if i & 1 == 0 { b.min[0] } else { b.max[0] }, if i & 2 == 0 { b.min[2] } else { b.max[2] })).fold(0.0, f32::max);
+            Aabb { min: [-r, b.min[1], -r], max: [r, b.max[1], r] }
+        }
+        "Bend" => {
+            let b = c(0);
+            let r = (0..4).map(|i| math::len2(if i & 1 == 0 { b.min[0] } else { b.max[0] }, if i & 2 == 0 { b.min[1] } else { b.max[1] })).fold(0.0, f32::max);
+            Aabb { min: [-r, -r, b.min[2]], max: [r, r, b.max[2]] }
+        }
+        "Mirror" => {
+            let (b, a) = (c(0), n.v3("axis", [1.0, 0.0, 0.0]));
+            let mut r = b;
+            for (i, _) in a.iter().enumerate().filter(|(_, v)| **v != 0.0) { let m = b.min[i].abs().max(b.max[i].abs()); r.min[i] = -m; r.max[i] = m; }
+            r
+        }
+        "RepeatFinite" => {
+            let (b, s, cnt) = (c(0), n.v3("spacing", [2.0; 3]), n.v3("count", [3.0; 3]));
+            let e: V3 = std::array::from_fn(|i| if s[i] > 0.0 && cnt[i] >= 1.0 { (cnt[i].round() - 1.0) * 0.5 * s[i] } else { 0.0 });
+            Aabb { min: math::sub(b.min, e), max: math::add(b.max, e) }
+        }
+        "PolarRepeat" => {
+            let b = c(0);
+            let rad = n.f("radius", 0.0);
+            let r = (0..4).map(|i| math::len2((if i & 1 == 0 { b.min[0] } else { b.max[0] }) + rad, if i & 2 == 0 { b.min[2] } else { b.max[2] })).fold(0.0, f32::max);
+            Aabb { min: [-r, b.min[1], -r], max: [r, b.max[1], r] }
+        }
+        "Extrude" => { let (b, h) = (c(0), n.f("half_depth", 0.5)); Aabb { min: [b.min[0], b.min[1], -h], max: [b.max[0], b.max[1], h] } }
+        "Revolution" => {
+            let b = c(0);
+            if b.is_empty() { return b; }
+            let r = (n.f("offset", 0.0) + b.max[0]).max(0.0);
+            Aabb { min: [-r, b.min[1], -r], max: [r, b.max[1], r] }
+        }
+        "Sweep" => {
+            // The profile sweeps within its XY radius of the path, which stays inside the hull of a, b, c.
+            let b = c(0);
+            if b.is_empty() { return b; }
+            let r = math::len2(b.min[0].abs().max(b.max[0].abs()), b.min[1].abs().max(b.max[1].abs()));
+            let (a, bb, cc) = (n.v3("a", [-1.0, 0.0, 0.0]), n.v3("b", [0.0, 0.0, 1.0]), n.v3("c", [1.0, 0.0, 0.0]));
+            Aabb { min: a, max: a }.union(&Aabb { min: bb, max: bb }).union(&Aabb { min: cc, max: cc }).expand(r)
+        }
+        "Noise" => { let a = n.f("amplitude", 0.1).abs(); c(0).expand(a * 2.0) }
+        "Shell" => c(0),
+        "Displacement" => c(0).expand(n.f("amplitude", 0.05).abs()),
+        "SurfaceRoughness" => c(0).expand(n.f("amplitude", 0.02).abs() * 2.0),
+        "HeightmapDisplacement" => {
+            let h = n.params.get("heights").and_then(|v| v.as_array()).map(|a| a.iter().filter_map(|x| x.as_f64()).fold(0.0f32, |m, x| m.max(x.abs() as f32))).unwrap_or(0.0);
+            c(0).expand(h * n.f("amplitude", 0.1).abs())
+        }
+        "Onion" => c(0).expand(n.f("thickness", 0.1)),
+        _ => Aabb::EVERYTHING,
+    }
+}
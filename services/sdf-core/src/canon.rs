@@ -0,0 +1,153 @@
+//! Canonical tree form and content hash (`/api/v1/sdf/canonical`).
+//!
+//! The canonical form of a schema-valid tree (variables resolved, curves sampled)
+//! fills every param the schema knows with its default, writes vec3 params as
+//! three numbers, rounds every number to the f32 the engine evaluates, puts n-ary
+//! operands under `children` and spells out materials in full. Its text is
+//! compact JSON with keys in byte order and numbers in their shortest round-trip
+//! decimal form without exponent; the hash is taken over that text. Any change
+//! to these rules must bump [`VERSION`].
+
+use crate::schema::{self, Arity, ParamDefault, ParamSchema, ParamType};
+use crate::tree::SdfNode;
+use serde_json::{json, Map, Value};
+use sha2::Digest;
+
+pub const VERSION: u32 = 1;
+
+/// Canonical JSON of `n`, which must pass `schema::check`.
+pub fn canonical(n: &SdfNode) -> Value {
+    let s = schema::lookup(&n.ty).expect("canonical form of a checked tree");
+    let mut o = Map::new();
+    o.insert("type".into(), json!(n.ty));
+    o.insert("params".into(), Value::Object(s.params.iter().map(|p| (p.name.to_string(), param(p, n.params.get(p.name)))).collect()));
+    if let Some(m) = &n.material {
+        o.insert("material".into(), json!({ "base_color": m.base_color.map(num), "emissive": m.emissive.map(num), "metallic": num(m.metallic), "roughness": num(m.roughness) }));
+    }
+    let mut kids = n.children.iter().map(canonical);
+    match s.arity {
+        Arity::Leaf => {}
+        Arity::Unary => { o.insert("child".into(), kids.next().unwrap_or_default()); }
+        Arity::Binary => { o.insert("a".into(), kids.next().unwrap_or_default()); o.insert("b".into(), kids.next().unwrap_or_default()); }
+        Arity::Nary => { o.insert("children".into(), Value::Array(kids.collect())); }
+    }
+    Value::Object(o)
+}
+
+/// `x` as the f32 the engine uses, without negative zero.
+fn num(x: f32) -> Value { if x == 0.0 { json!(0.0) } else { json!(crate::math::short(x)) } }
+
+fn param(p: &ParamSchema, v: Option<&Value>) -> Value {
+    let f = |x: &Value| x.as_f64().unwrap_or(0.0) as f32;
+    match (p.ty, v) {
+        (ParamType::U32, Some(x)) => json!(x.as_f64().unwrap_or(0.0) as u32),
+        (ParamType::F32, Some(x)) => num(f(x)),
+        (ParamType::Vec3, Some(Value::Array(a))) => Value::Array(a.iter().map(|x| num(f(x))).collect()),
+        (ParamType::Vec3, Some(x)) => Value::Array(vec![num(f(x)); 3]),
+        (ParamType::F32Array, Some(Value::Array(a))) => Value::Array(a.iter().map(|x| num(f(x))).collect()),
+        (ParamType::Bones, Some(Value::Array(a))) => Value::Array(a.iter().map(|b| {
+            Value::Object(schema::BONE.iter().map(|bp| (bp.name.to_string(), param(bp, b.get(bp.name)))).collect())
+        }).collect()),
+        (_, _) => match p.default {
+            ParamDefault::Scalar(x) => num(x),
+            ParamDefault::Int(x) => json!(x),
+            ParamDefault::Vec3(v) => Value::Array(v.map(num).to_vec()),
+            ParamDefault::List(l) => Value::Array(l.iter().map(|&x| num(x)).collect()),
+        },
+    }
+}
+
+/// Canonical text of a canonical value.
+pub fn text(v: &Value) -> String {
+    let mut s = String::new();
+    write(v, &mut s);
+    s
+}
+
+fn write(v: &Value, s: &mut String) {
+    match v {
+        Value::Object(o) => {
+            let mut keys: Vec<&String> = o.keys().collect();
+            keys.sort();
+            s.push('{');
+            for (i, k) in keys.into_iter().enumerate() {
+                if i > 0 { s.push(','); }
+                s.push_str(&Value::String(k.clone()).to_string());
+                s.push(':');
+                write(&o[k], s);
+            }
+            s.push('}');
+        }
+        Value::Array(a) => {
+            s.push('[');
+            for (i, x) in a.iter().enumerate() {
+                if i > 0 { s.push(','); }
+                write(x, s);
+            }
+            s.push(']');
+        }
+        // Integers print as such; every other number is an f32 whose `Display` is the shortest round-trip decimal.
+        Value::Number(n) => match n.as_u64() { Some(u) => s.push_str(&u.to_string()), None => s.push_str(&(n.as_f64().unwrap_or(0.0) as f32).to_string()) },
+        other => s.push_str(&other.to_string()),
+    }
+}
+
+/// Hex digest of `text` with `algorithm` (`sha256` or `blake3`).
+pub fn hash(text: &str, algorithm: &str) -> Option<String> {
+    let bytes: Vec<u8> = match algorithm {
+        "sha256" => sha2::Sha256::digest(text.as_bytes()).to_vec(),
+        "blake3" => blake3::hash(text.as_bytes()).as_bytes().to_vec(),
+        _ => return None,
+    };
+    Some(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canon(v: Value) -> String { text(&canonical(&SdfNode::from_json(&v).unwrap())) }
+
+    /// Text, sha256 and blake3 of fixed trees. Changing any of these means the
+    /// canonical rules changed, and [`VERSION`] must be bumped.
+    #[test]
+    fn pinned_texts_and_digests() {
+        let cases = [
+            (json!({"type": "Sphere", "params": {}}), r#"{"params":{"radius":1},"type":"Sphere"}"#,
+                "6322cadda9058bf82e459e08c8935035f31221844eb01bc18dd33ab70589e513", "cfdf9aa51699fd467bd3ff9873bde305e94dea0141c79afdfd5a061446f74594"),
+            (json!({"type": "Box3d", "params": {"half_size": 0.25}}), r#"{"params":{"half_size":[0.25,0.25,0.25]},"type":"Box3d"}"#,
+                "23af5b583edee01de1968883b170f7ead75802eeb4ee8f891e6545e0177fe93f", "bc5328b84093592337a0a65a9df1fc9a56c9f8f7d32ac74491036537f91da75e"),
+            (json!({"type": "SmoothUnion", "params": {"k": 0.1}, "a": {"type": "Sphere", "params": {"radius": 1.1}},
+                    "b": {"type": "Translate", "params": {"offset": [1, -0.0, 0.3]}, "child": {"type": "Torus", "params": {"major_radius": 2}}}}),
+                r#"{"children":[{"params":{"radius":1.1},"type":"Sphere"},{"child":{"params":{"major_radius":2,"minor_radius":0.25},"type":"Torus"},"params":{"offset":[1,0,0.3]},"type":"Translate"}],"params":{"k":0.1},"type":"SmoothUnion"}"#,
+                "b1115e1129ccc1f716b727d74d88abe50ff8237dfa8deb0892783c2c7d8be275", "3854d7ea11da2b99c9415dd08df02c0ff65995cc86de91dfeb16a08b3a9b7498"),
+            (json!({"type": "Union", "children": [{"type": "Sphere", "params": {"radius": 0.5}, "material": {"base_color": [1, 0, 0]}}, {"type": "Cylinder", "params": {}}]}),
+                r#"{"children":[{"material":{"base_color":[1,0,0],"emissive":[0,0,0],"metallic":0,"roughness":0.5},"params":{"radius":0.5},"type":"Sphere"},{"params":{"half_height":1,"radius":0.5},"type":"Cylinder"}],"params":{},"type":"Union"}"#,
+                "bfa376ab0c6cd975c23b95ec8d2de2141babe866b55b06621ef5502f5e038822", "74e45d7941027be0ac94944ab77fdebfaa637ec204af114ab1fc5427bf758a20"),
+        ];
+        for (tree, want, sha, b3) in cases {
+            let t = canon(tree);
+            assert_eq!(t, want);
+            assert_eq!(hash(&t, "sha256").as_deref(), Some(sha), "{t}");
+            assert_eq!(hash(&t, "blake3").as_deref(), Some(b3), "{t}");
+        }
+        // Reference vectors of the empty input.
+        assert_eq!(hash("", "sha256").as_deref(), Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"));
+        assert_eq!(hash("", "blake3").as_deref(), Some("af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"));
+        assert_eq!(hash("", "md5"), None);
+    }
+
+    #[test]
+    fn equivalent_spellings_share_a_text() {
+        let same = |a: Value, b: Value| assert_eq!(canon(a), canon(b));
+        // Defaults filled in, vec3 splats and negative zero.
+        same(json!({"type": "Sphere"}), json!({"type": "Sphere", "params": {"radius": 1.0}}));
+        same(json!({"type": "Box3d", "params": {"half_size": 0.5}}), json!({"type": "Box3d", "params": {"half_size": [0.5, 0.5, 0.5]}}));
+        same(json!({"type": "Translate", "params": {"offset": [0, -0.0, 0]}, "child": {"type": "Sphere"}}), json!({"type": "Translate", "params": {"offset": 0}, "child": {"type": "Sphere"}}));
+        // Numbers round to the f32 the engine evaluates.
+        same(json!({"type": "Sphere", "params": {"radius": 0.1}}), json!({"type": "Sphere", "params": {"radius": 0.1f32 as f64}}));
+        // Binary operands written as a/b or as children.
+        same(json!({"type": "SmoothUnion", "a": {"type": "Sphere"}, "b": {"type": "Torus"}}), json!({"type": "SmoothUnion", "children": [{"type": "Sphere"}, {"type": "Torus"}]}));
+        assert_ne!(canon(json!({"type": "Sphere", "params": {"radius": 1.0}})), canon(json!({"type": "Sphere", "params": {"radius": 1.0001}})));
+    }
+}
@@ -0,0 +1,165 @@
+//! Procedural cave networks built from plain SDF nodes.
+//!
+//! Chambers are noise-sized ellipsoids scattered through the region; tunnels are
+//! worm paths between them, a chain of capsule segments whose midpoints wander
+//! along a seeded noise field. Chambers are always linked by a minimum spanning
+//! tree so the network is connected; `connectivity` adds extra links between
+//! near neighbours, i.e. loops. The result is the cave *volume* (negative inside
+//! the voids), meant to be carved out of a solid with Subtraction or
+//! SmoothSubtraction.
+
+use crate::bounds::Aabb;
+use crate::compiler::CompiledSdf;
+use crate::math::{self, V3};
+use crate::noise::{noise3, Rng};
+use crate::tree::SdfNode;
+use serde_json::json;
+
+pub struct CaveParams {
+    pub seed: u64,
+    /// Chambers per unit volume.
+    pub density: f32,
+    pub tunnel_radius: f32,
+    pub chamber_radius: f32,
+    /// 0 = tree-shaped network, 1 = every near-neighbour pair linked.
+    pub connectivity: f32,
+    /// Amplitude of the wall noise; 0 disables it.
+    pub roughness: f32,
+}
+
+pub struct Cave { pub tree: SdfNode, pub chambers: usize, pub tunnels: usize, pub segments: usize }
+
+pub const MAX_CHAMBERS: usize = 256;
+const SEGMENT_LEN_FACTOR: f32 = 3.0;
+
+/// Build a cave inside `region`. With a `host` solid, chambers are only placed where the host is solid.
+pub fn generate(region: &Aabb, p: &CaveParams, host: Option<&CompiledSdf>) -> Cave {
+    let mut rng = Rng::new(p.seed);
+    let nseed = (p.seed ^ (p.seed >> 32)) as u32;
+    let size = region.size();
+    let count = ((p.density * size[0] * size[1] * size[2]).round() as usize).clamp(1, MAX_CHAMBERS);
+    let inner = Aabb { min: math::add(region.min, [p.chamber_radius; 3]), max: math::sub(region.max, [p.chamber_radius; 3]) };
+    let inner = if inner.is_empty() { *region } else { inner };
+
+    let mut centers: Vec<V3> = Vec::with_capacity(count);
+    for _ in 0..count * 200 {
+        if centers.len() == count { break; }
+        let c = rng.point_in(inner.min, inner.max);
+        if host.is_some_and(|h| h.eval(c) > -p.chamber_radius * 0.5) { continue; }
+        centers.push(c);
+    }
+    if centers.is_empty() { centers.push(region.center()); }
+
+    let mut parts = Vec::new();
+    for &c in &centers {
+        let radii = std::array::from_fn::<f32, 3, _>(|_| p.chamber_radius * rng.range(0.6, 1.2));
+        parts.push(SdfNode::unary("Translate", json!({ "offset": c }), SdfNode::leaf("Ellipsoid", json!({ "radii": radii }))));
+    }
+
+    // Links: minimum spanning tree (Prim), plus extra near-neighbour links by connectivity.
+    let n = centers.len();
+    let d = |a: usize, b: usize| math::len(math::sub(centers[a], centers[b]));
+    let mut links: Vec<(usize, usize)> = Vec::new();
+    let mut in_tree = vec![false; n];
+    let mut best: Vec<(f32, usize)> = vec![(f32::INFINITY, 0); n];
+    in_tree[0] = true;
+    for (j, b) in best.iter_mut().enumerate().skip(1) { *b = (d(0, j), 0); }
+    for _ in 1..n {
+        let Some(j) = (0..n).filter(|&j| !in_tree[j]).min_by(|&a, &b| best[a].0.total_cmp(&best[b].0)) else { break };
+        in_tree[j] = true;
+        links.push((best[j].1, j));
+        for k in 0..n { if !in_tree[k] && d(j, k) < best[k].0 { best[k] = (d(j, k), j); } }
+    }
+    for a in 0..n {
+        let mut near: Vec<usize> = (0..n).filter(|&b| b != a).collect();
+        near.sort_by(|&x, &y| d(a, x).total_cmp(&d(a, y)));
+        for &b in near.iter().take(3) {
+            let (lo, hi) = (a.min(b), a.max(b));
+            if links.iter().any(|&(x, y)| (x.min(y), x.max(y)) == (lo, hi)) { continue; }
+            if rng.f32() < p.connectivity { links.push((lo, hi)); }
+        }
+    }
+
+    let mut segments = 0;
+    for (li, &(a, b)) in links.iter().enumerate() {
+        let (pa, pb) = (centers[a], centers[b]);
+        let len = math::len(math::sub(pb, pa));
+        let steps = ((len / (p.tunnel_radius * SEGMENT_LEN_FACTOR)).ceil() as usize).clamp(1, 64);
+        let wander = len * 0.15;
+        let path: Vec<V3> = (0..=steps).map(|s| {
+            let t = s as f32 / steps as f32;
+            let base = math::add(pa, math::mul(math::sub(pb, pa), t));
+            // Endpoints stay on the chambers; the middle drifts along the noise field.
+            let w = wander * (std::f32::consts::PI * t).sin();
+            let q = [t * 3.0, li as f32 * 7.31, 0.0];
+            let off = [noise3(q, nseed), noise3(q, nseed.wrapping_add(1)), noise3(q, nseed.wrapping_add(2))];
+            math::add(base, math::mul(off, w))
+        }).collect();
+        for (s, seg) in path.windows(2).enumerate() {
+            let r = p.tunnel_radius * (1.0 + 0.25 * noise3([s as f32 * 0.7, li as f32 * 3.1, 5.0], nseed));
+            parts.push(capsule_between(seg[0], seg[1], r));
+            segments += 1;
+        }
+    }
+
+    let body = if parts.len() == 1 { parts.pop().unwrap() } else { SdfNode::list("SmoothUnion", json!({ "k": p.tunnel_radius * 0.5 }), parts) };
+    let tree = if p.roughness > 0.0 {
+        SdfNode::unary("Noise", json!({ "amplitude": p.roughness, "frequency": 1.0 / p.tunnel_radius.max(1e-3), "seed": nseed }), body)
+    } else { body };
+    Cave { tree, chambers: n, tunnels: links.len(), segments }
+}
+
+/// A Y-axis Capsule rotated and moved so its core runs from `a` to `b`.
+fn capsule_between(a: V3, b: V3, r: f32) -> SdfNode {
+    let dir = math::sub(b, a);
+    let half = math::len(dir) * 0.5;
+    let u = math::normalize(dir);
+    // RotateEuler applies X then Z: X tilts +Y to (0, cos ax, sin ax), Z then swings it to `u`.
+    let ax = u[2].clamp(-1.0, 1.0).asin();
+    let az = (-u[0]).atan2(u[1]);
+    let cap = SdfNode::leaf("Capsule", json!({ "radius": r, "half_height": half }));
+    let rot = SdfNode::unary("RotateEuler", json!({ "angles": [ax, 0.0, az] }), cap);
+    SdfNode::unary("Translate", json!({ "offset": math::mul(math::add(a, b), 0.5) }), rot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{canon, compiler};
+
+    fn params(seed: u64, connectivity: f32) -> CaveParams {
+        CaveParams { seed, density: 0.05, tunnel_radius: 0.3, chamber_radius: 0.8, connectivity, roughness: 0.05 }
+    }
+
+    fn text(c: &Cave) -> String { canon::text(&c.tree.to_json()) }
+
+    #[test]
+    fn same_seed_same_cave() {
+        let region = Aabb::sym([5.0; 3]);
+        let (a, b) = (generate(&region, &params(42, 0.5), None), generate(&region, &params(42, 0.5), None));
+        assert_eq!(text(&a), text(&b));
+        assert_eq!((a.chambers, a.tunnels, a.segments), (b.chambers, b.tunnels, b.segments));
+        assert_ne!(text(&a), text(&generate(&region, &params(43, 0.5), None)));
+    }
+
+    #[test]
+    fn chambers_are_spanned_by_a_tree() {
+        // 0.05 chambers per unit over a 10³ box.
+        let cave = generate(&Aabb::sym([5.0; 3]), &params(7, 0.0), None);
+        assert_eq!((cave.chambers, cave.tunnels), (50, 49));
+        let looped = generate(&Aabb::sym([5.0; 3]), &params(7, 1.0), None);
+        assert!(looped.tunnels > 49);
+        assert!(compiler::compile(&cave.tree).is_ok());
+    }
+
+    #[test]
+    fn chambers_stay_inside_the_host() {
+        let host = compiler::compile(&SdfNode::leaf("Sphere", json!({ "radius": 4.0 }))).unwrap();
+        let cave = generate(&Aabb::sym([5.0; 3]), &params(3, 0.0), Some(&host));
+        // Noise over a SmoothUnion of translated chambers and tunnel segments.
+        let body = &cave.tree.children[0];
+        let centres: Vec<V3> = body.children.iter().filter(|k| k.children[0].ty == "Ellipsoid").map(|k| k.v3("offset", [0.0; 3])).collect();
+        assert_eq!(centres.len(), cave.chambers);
+        assert!(centres.iter().all(|&c| host.eval(c) <= -0.4), "{centres:?}");
+    }
+}
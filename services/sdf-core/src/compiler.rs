@@ -0,0 +1,503 @@
+//! SdfNode → CompiledSdf: a flat postfix program for the stack evaluator.
+//!
+//! Leaves push a distance measured at the current point. Point ops push a new
+//! current point that stays active until the matching `PopPoint`; distance ops
+//! rewrite the top of the value stack.
+
+use crate::eval::bezier_pos;
+use crate::lipschitz;
+use crate::material::Material;
+use crate::math::{self, V3, M3};
+use crate::meshsdf::{Grid, MeshSdf, TriMesh};
+use crate::tree::SdfNode;
+use serde_json::Value;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tpms { Gyroid, SchwarzP, Diamond, Neovius, Lidinoid, Iwp, Frd, FischerKochS, Pmy }
+
+/// Leaf distance functions. Angles are stored as they come from the tree and turned
+/// into sin/cos by the evaluator, so the shader emitter sees the same numbers.
+#[derive(Clone, Debug)]
+pub enum Prim {
+    Sphere(f32), Box3d(V3), Cylinder(f32, f32), Torus(f32, f32), Plane(V3, f32), Capsule(f32, f32),
+    Cone(f32, f32), RoundedBox(V3, f32), Ellipsoid(V3), Pyramid(f32, f32), Octahedron(f32), Tetrahedron(f32),
+    RoundedCone(f32, f32, f32), HexPrism(f32, f32), Link(f32, f32, f32), Triangle(V3, V3, V3, f32), Bezier(V3, V3, V3, f32),
+    CappedCone(f32, f32, f32), CappedTorus(f32, f32, f32), RoundedCylinder(f32, f32, f32), TriangularPrism(f32, f32),
+    CutSphere(f32, f32), CutHollowSphere(f32, f32, f32), DeathStar(f32, f32, f32), SolidAngle(f32, f32),
+    Rhombus(f32, f32, f32, f32), Vesica(f32, f32), Egg(f32, f32), Tube(f32, f32, f32), Barrel(f32, f32, f32),
+    ChamferedCube(V3, f32), Superellipsoid(V3, f32, f32), Helix(f32, f32, f32, f32), BoxFrame(V3, f32),
+    InfiniteCylinder(f32), InfiniteCone(f32),
+    /// Polyhedra as the max over face-normal planes; the list is a slice of `eval::GDF`.
+    Gdf(usize, usize, f32),
+    Tpms(Tpms, f32, f32),
+    // 2D profiles in XY extruded along Z; the last field is the half depth.
+    Horseshoe(f32, f32, f32, f32, f32), Heart(f32, f32), RoundedX(f32, f32, f32), Pie(f32, f32, f32),
+    Trapezoid(f32, f32, f32, f32), Parallelogram(f32, f32, f32, f32), Tunnel(f32, f32, f32),
+    UnevenCapsule(f32, f32, f32, f32), ArcShape(f32, f32, f32, f32), Moon(f32, f32, f32, f32), CrossShape(f32, f32, f32),
+    BlobbyCross(f32, f32, f32), ParabolaSegment(f32, f32, f32), Stairs(f32, f32, f32, f32),
+    // Plain 2D shapes in XY, unbounded along Z until a sketch modifier lifts them.
+    Circle2d(f32), Rect2d(f32, f32, f32), Segment2d([f32; 2], [f32; 2], f32), Polygon2d(Vec<[f32; 2]>), Annular2d(f32, f32),
+    /// Star with outer radius, inner radius and tip count; regular polygons put the inner vertices on the edges.
+    Star(f32, f32, f32),
+    /// Imported triangle mesh, exact or baked.
+    Mesh(Arc<MeshSdf>),
+}
+
+/// Binary distance combinators; n-ary nodes fold left.
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Union, Intersection, Subtraction, SmoothUnion(f32), SmoothIntersection(f32), SmoothSubtraction(f32),
+    ChamferUnion(f32), ChamferIntersection(f32), ChamferSubtraction(f32),
+    StairsUnion(f32, f32), StairsIntersection(f32, f32), StairsSubtraction(f32, f32),
+    ExpSmoothUnion(f32), ExpSmoothIntersection(f32), ExpSmoothSubtraction(f32),
+    ColumnsUnion(f32, f32), ColumnsIntersection(f32, f32), ColumnsSubtraction(f32, f32),
+    Xor, Morph(f32), Pipe(f32), Engrave(f32), Groove(f32, f32), Tongue(f32, f32),
+}
+
+/// Control points per lattice axis; the schema publishes the same cap.
+pub const MAX_LATTICE_DIVISIONS: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct Lattice { pub divisions: [usize; 3], pub min: V3, pub max: V3, pub offsets: Vec<V3> }
+
+/// One skinning bone: `rot` is the inverse rotation about `pivot`, then `offset` undone.
+#[derive(Clone, Debug)]
+pub struct Bone { pub pivot: V3, pub offset: V3, pub rot: M3, pub radius: f32 }
+
+/// Sweep path: quadratic Bezier `a b c`, the fixed profile up vector and the path length.
+#[derive(Clone, Debug)]
+pub struct Sweep { pub a: V3, pub b: V3, pub c: V3, pub up: V3, pub len: f32 }
+
+#[derive(Clone, Debug)]
+pub struct Heightmap { pub cols: usize, pub rows: usize, pub size: [f32; 2], pub amplitude: f32, pub data: Vec<f32> }
+
+/// Domain (point) transforms, each undone by the matching `PopPoint`.
+#[derive(Clone, Debug)]
+pub enum PointOp {
+    Translate(V3), Rotate(M3), Scale(f32), ScaleNonUniform(V3), Twist(f32), Bend(f32), Repeat(V3),
+    RepeatFinite(V3, V3), Mirror([bool; 3]), PolarRepeat(f32, f32),
+    /// Inverse of the node's 4x4 matrix, row-major.
+    Projective([f32; 16]),
+    Lattice(Box<Lattice>), Skin(Vec<Bone>),
+    Ifs(u32, f32, V3), Icosahedral, Taper(f32),
+    /// Profile plane coordinates: `(radius - offset, y, 0)`.
+    Revolve(f32),
+    /// Profile plane coordinates along the path, z measured from the path midpoint.
+    Sweep(Box<Sweep>),
+}
+
+/// Distance post-processing; runs with the parent's point current.
+#[derive(Clone, Debug)]
+pub enum Post {
+    MulDist(f32), Noise(f32, f32, u32), Shell(f32), Onion(f32), Displacement(f32, f32),
+    Heightmap(Box<Heightmap>), Roughness(f32, f32, u32, u32), Taper(f32),
+    /// Cap a 2D distance at `|z| = h`.
+    Extrude(f32),
+}
+
+#[derive(Clone, Debug)]
+pub enum Inst { Prim(Prim), Op(Op), Push(PointOp), PopPoint, Post(Post) }
+
+#[derive(Clone, Debug)]
+pub struct CompiledSdf {
+    pub code: Vec<Inst>,
+    /// Distinct materials in first-use order; id 0 is the default.
+    pub materials: Vec<Material>,
+    /// Material id of each `Prim` instruction, in program order.
+    pub(crate) leaf_materials: Vec<u32>,
+    pub(crate) max_values: usize,
+    pub(crate) max_points: usize,
+    /// Factor on sphere-tracing steps that keeps them inside the surface; see `lipschitz`.
+    pub step_scale: f32,
+}
+
+impl CompiledSdf {
+    pub fn has_materials(&self) -> bool { self.materials.len() > 1 }
+}
+
+struct Materials { table: Vec<Material>, leaves: Vec<u32> }
+
+impl Materials {
+    fn id(&mut self, m: Option<Material>) -> u32 {
+        let Some(m) = m else { return 0 };
+        match self.table.iter().position(|t| *t == m) {
+            Some(i) => i as u32,
+            None => { self.table.push(m); (self.table.len() - 1) as u32 }
+        }
+    }
+}
+
+pub fn compile(tree: &SdfNode) -> Result<CompiledSdf, String> {
+    let mut code = Vec::new();
+    let mut mats = Materials { table: vec![Material::default()], leaves: vec![] };
+    emit(tree, "root", &mut code, &mut mats, None)?;
+    let (mut v, mut p, mut mv, mut mp) = (0usize, 1usize, 0usize, 1usize);
+    for i in &code {
+        match i {
+            Inst::Prim(_) => v += 1,
+            Inst::Op(_) => v -= 1,
+            Inst::Push(_) => p += 1,
+            Inst::PopPoint => p -= 1,
+            Inst::Post(_) => {}
+        }
+        mv = mv.max(v); mp = mp.max(p);
+    }
+    Ok(CompiledSdf { code, materials: mats.table, leaf_materials: mats.leaves, max_values: mv, max_points: mp, step_scale: lipschitz::analyze(tree).step_scale })
+}
+
+fn arity(n: &SdfNode, path: &str, want: usize) -> Result<(), String> {
+    if n.children.len() != want {
+        return Err(format!("{path}: {} expects {want} child node(s), got {}", n.ty, n.children.len()));
+    }
+    Ok(())
+}
+
+fn positive(n: &SdfNode, path: &str, k: &str, v: f32) -> Result<f32, String> {
+    if v.is_nan() || v <= 0.0 { return Err(format!("{path}: {} {k} must be > 0", n.ty)); }
+    Ok(v)
+}
+
+fn f32_list(n: &SdfNode, path: &str, k: &str) -> Result<Vec<f32>, String> {
+    match n.params.get(k) {
+        None => Ok(vec![]),
+        Some(Value::Array(a)) => a.iter().map(|x| x.as_f64().map(|x| x as f32)).collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("{path}: {} {k} must be an array of numbers", n.ty)),
+        Some(_) => Err(format!("{path}: {} {k} must be an array of numbers", n.ty)),
+    }
+}
+
+fn mesh_sdf(n: &SdfNode, path: &str) -> Result<MeshSdf, String> {
+    if n.f("grid_cell", 0.0) > 0.0 {
+        let dims = f32_list(n, path, "grid_dims")?;
+        let grid = Grid {
+            origin: n.v3("grid_origin", [0.0; 3]), cell: n.f("grid_cell", 0.0), band: n.f("grid_band", 0.0),
+            dims: if dims.len() == 3 { [dims[0] as usize, dims[1] as usize, dims[2] as usize] } else { [0; 3] },
+            bricks: f32_list(n, path, "grid_bricks")?.into_iter().map(|b| b as i32).collect(), values: f32_list(n, path, "grid_values")?,
+        };
+        grid.check().map_err(|e| format!("{path}: MeshSdf {e}"))?;
+        return Ok(MeshSdf::Baked(grid));
+    }
+    let (v, ix) = (f32_list(n, path, "vertices")?, f32_list(n, path, "indices")?);
+    let count = v.len() / 3;
+    if v.len() % 3 != 0 || ix.is_empty() || ix.len() % 3 != 0 || ix.iter().any(|&i| i < 0.0 || i.fract() != 0.0 || i as usize >= count) {
+        return Err(format!("{path}: MeshSdf needs [x, y, z] vertices and triangle indices into them, or a baked grid"));
+    }
+    let at = |i: f32| { let i = i as usize * 3; [v[i], v[i + 1], v[i + 2]] };
+    Ok(MeshSdf::Exact(TriMesh::new(ix.chunks(3).map(|t| [at(t[0]), at(t[1]), at(t[2])]).collect())))
+}
+
+fn pair(n: &SdfNode, path: &str, k: &str, d: [f32; 2]) -> Result<[f32; 2], String> {
+    match f32_list(n, path, k)?[..] {
+        [] => Ok(d),
+        [x, y] => Ok([x, y]),
+        _ => Err(format!("{path}: {} {k} must have 2 components", n.ty)),
+    }
+}
+
+fn emit_prim(n: &SdfNode, path: &str) -> Result<Option<Prim>, String> {
+    use Prim::*;
+    let f = |k: &str, d: f32| n.f(k, d);
+    let hd = || f("half_depth", 0.1);
+    Ok(Some(match n.ty.as_str() {
+        "Sphere" => Sphere(f("radius", 1.0)),
+        "Box3d" => Box3d(n.v3("half_size", [0.5; 3])),
+        "Cylinder" => Cylinder(f("radius", 0.5), f("half_height", 1.0)),
+        "Torus" => Torus(f("major_radius", 1.0), f("minor_radius", 0.25)),
+        "Plane" => Plane(math::normalize(n.v3("normal", [0.0, 1.0, 0.0])), f("distance", 0.0)),
+        "Capsule" => Capsule(f("radius", 0.3), f("half_height", 0.5)),
+        "Cone" => Cone(f("radius", 0.5), f("height", 1.0)),
+        "RoundedBox" => RoundedBox(n.v3("half_size", [0.5; 3]), f("radius", 0.1)),
+        "Ellipsoid" => Ellipsoid(n.v3("radii", [1.0, 0.5, 0.5])),
+        "Pyramid" => Pyramid(f("height", 1.0), positive(n, path, "base", f("base", 1.0))?),
+        "Octahedron" => Octahedron(f("size", 1.0)),
+        "Tetrahedron" => Tetrahedron(f("size", 1.0)),
+        "RoundedCone" => RoundedCone(f("radius_bottom", 0.5), f("radius_top", 0.25), positive(n, path, "height", f("height", 1.0))?),
+        "HexPrism" => HexPrism(f("radius", 0.5), f("half_depth", 0.5)),
+        "Link" => Link(f("half_length", 0.5), f("radius", 0.5), f("thickness", 0.1)),
+        "Triangle" => Triangle(n.v3("a", [-0.5, 0.0, 0.0]), n.v3("b", [0.5, 0.0, 0.0]), n.v3("c", [0.0, 0.8, 0.0]), f("thickness", 0.02)),
+        "Bezier" => Bezier(n.v3("a", [-1.0, 0.0, 0.0]), n.v3("b", [0.0, 1.0, 0.0]), n.v3("c", [1.0, 0.0, 0.0]), f("radius", 0.1)),
+        "CappedCone" => CappedCone(f("half_height", 0.5), f("radius_bottom", 0.5), f("radius_top", 0.25)),
+        "CappedTorus" => CappedTorus(f("angle", 1.0), f("major_radius", 1.0), f("minor_radius", 0.25)),
+        "RoundedCylinder" => RoundedCylinder(f("radius", 0.5), f("round_radius", 0.1), f("half_height", 1.0)),
+        "TriangularPrism" => TriangularPrism(f("size", 1.0), f("half_depth", 0.5)),
+        "CutSphere" => { let r = f("radius", 1.0); CutSphere(r, f("cut_height", 0.5).clamp(-r, r)) }
+        "CutHollowSphere" => { let r = f("radius", 1.0); CutHollowSphere(r, f("cut_height", 0.5).clamp(-r, r), f("thickness", 0.05)) }
+        "DeathStar" => DeathStar(f("radius", 1.0), f("cut_radius", 0.7), positive(n, path, "cut_distance", f("cut_distance", 1.2))?),
+        "SolidAngle" => SolidAngle(f("angle", 0.5), f("radius", 1.0)),
+        "Rhombus" => Rhombus(f("half_width", 0.6), f("half_depth", 0.3), f("half_height", 0.1), f("radius", 0.05)),
+        "Vesica" => {
+            let (r, d) = (f("radius", 1.0), f("distance", 0.5));
+            if d.abs() >= r { return Err(format!("{path}: Vesica distance must be smaller than radius")); }
+            Vesica(r, d)
+        }
+        "Egg" => Egg(f("radius", 0.5), f("tip_radius", 0.1)),
+        "Tube" => Tube(f("radius", 0.5), f("thickness", 0.1), f("half_height", 1.0)),
+        "Barrel" => Barrel(f("radius", 0.5), positive(n, path, "half_height", f("half_height", 1.0))?, f("bulge", 0.1)),
+        "ChamferedCube" => ChamferedCube(n.v3("half_size", [0.5; 3]), f("chamfer", 0.1)),
+        "Superellipsoid" => Superellipsoid(n.v3("radii", [1.0; 3]), positive(n, path, "exponent_1", f("exponent_1", 1.0))?, positive(n, path, "exponent_2", f("exponent_2", 1.0))?),
+        "Helix" => Helix(f("radius", 1.0), f("thickness", 0.1), positive(n, path, "pitch", f("pitch", 0.5))?, f("half_height", 1.0)),
+        "BoxFrame" => BoxFrame(n.v3("half_size", [0.5; 3]), f("thickness", 0.05)),
+        "InfiniteCylinder" => InfiniteCylinder(f("radius", 0.5)),
+        "InfiniteCone" => InfiniteCone(f("angle", 0.5)),
+        "Dodecahedron" => Gdf(13, 19, f("radius", 1.0)),
+        "Icosahedron" => Gdf(3, 13, f("radius", 1.0)),
+        "TruncatedOctahedron" => Gdf(0, 7, f("radius", 1.0)),
+        "TruncatedIcosahedron" => Gdf(3, 19, f("radius", 1.0)),
+        "Gyroid" | "SchwarzP" | "Diamond" | "DiamondSurface" | "Neovius" | "Lidinoid" | "IWP" | "FRD" | "FischerKochS" | "PMY" => {
+            let kind = match n.ty.as_str() {
+                "Gyroid" => self::Tpms::Gyroid, "SchwarzP" => self::Tpms::SchwarzP, "Neovius" => self::Tpms::Neovius, "Lidinoid" => self::Tpms::Lidinoid,
+                "IWP" => self::Tpms::Iwp, "FRD" => self::Tpms::Frd, "FischerKochS" => self::Tpms::FischerKochS, "PMY" => self::Tpms::Pmy, _ => self::Tpms::Diamond,
+            };
+            Tpms(kind, positive(n, path, "scale", f("scale", 1.0))?, f("thickness", 0.1))
+        }
+        "Horseshoe" => Horseshoe(f("angle", 1.2), f("radius", 0.5), f("length", 0.25), f("thickness", 0.05), hd()),
+        "Heart" => Heart(positive(n, path, "size", f("size", 1.0))?, hd()),
+        "RoundedX" => RoundedX(f("width", 1.0), f("radius", 0.1), hd()),
+        "Pie" => Pie(f("angle", 0.8), f("radius", 1.0), hd()),
+        "Trapezoid" => Trapezoid(f("bottom_width", 0.5), f("top_width", 0.25), f("half_height", 0.5), hd()),
+        "Parallelogram" => Parallelogram(f("half_width", 0.5), f("half_height", 0.3), f("skew", 0.2), hd()),
+        "Tunnel" => Tunnel(f("half_width", 0.5), f("height", 1.0), hd()),
+        "UnevenCapsule" => UnevenCapsule(f("radius_bottom", 0.3), f("radius_top", 0.15), positive(n, path, "height", f("height", 1.0))?, hd()),
+        "ArcShape" => ArcShape(f("angle", 1.0), f("radius", 1.0), f("thickness", 0.1), hd()),
+        "Moon" => Moon(f("radius", 1.0), f("cut_radius", 0.8), positive(n, path, "cut_distance", f("cut_distance", 0.5))?, hd()),
+        "CrossShape" => CrossShape(f("half_length", 0.5), f("half_width", 0.15), hd()),
+        "BlobbyCross" => BlobbyCross(positive(n, path, "size", f("size", 1.0))?, positive(n, path, "height", f("height", 0.5))?, hd()),
+        "ParabolaSegment" => ParabolaSegment(positive(n, path, "half_width", f("half_width", 0.5))?, positive(n, path, "height", f("height", 1.0))?, hd()),
+        "Circle2d" => Circle2d(f("radius", 0.5)),
+        "Rect2d" => Rect2d(f("half_width", 0.5), f("half_height", 0.5), 0.0),
+        "RoundedRect2d" => { let (w, h) = (f("half_width", 0.5), f("half_height", 0.5)); Rect2d(w, h, f("radius", 0.1).clamp(0.0, w.min(h))) }
+        "Segment2d" => Segment2d(pair(n, path, "a", [-0.5, 0.0])?, pair(n, path, "b", [0.5, 0.0])?, f("thickness", 0.05)),
+        "Polygon2d" => {
+            let pts = f32_list(n, path, "points")?;
+            if pts.is_empty() { Polygon2d(vec![[-0.5, -0.5], [0.5, -0.5], [0.0, 0.5]]) }
+            else if pts.len() % 2 != 0 || pts.len() < 6 { return Err(format!("{path}: Polygon2d points must hold at least 3 [x, y] pairs")); }
+            else { Polygon2d(pts.chunks(2).map(|c| [c[0], c[1]]).collect()) }
+        }
+        "Annular2d" => Annular2d(f("radius", 0.5), f("thickness", 0.05)),
+        "RegularPolygon" => { let (r, k) = (f("radius", 0.5), n.u("sides", 6).max(3) as f32); Star(r, r * (PI / k).cos(), k) }
+        "StarPolygon" => { let r = f("radius", 0.5); Star(r, f("inner_radius", 0.25).clamp(0.0, r), n.u("points", 5).max(3) as f32) }
+        "MeshSdf" => Mesh(Arc::new(mesh_sdf(n, path)?)),
+        "StairsPrim" => Stairs(positive(n, path, "step_width", f("step_width", 0.2))?, positive(n, path, "step_height", f("step_height", 0.2))?, n.u("steps", 5).max(1) as f32, f("half_depth", 0.5)),
+        _ => return Ok(None),
+    }))
+}
+
+fn emit_op(n: &SdfNode) -> Option<Op> {
+    use Op::*;
+    let (k, r, steps) = (n.f("k", 0.1), n.f("r", 0.1), n.u("steps", 4).max(1) as f32);
+    Some(match n.ty.as_str() {
+        "Union" => Union, "Intersection" => Intersection, "Subtraction" => Subtraction,
+        "SmoothUnion" => SmoothUnion(k), "SmoothIntersection" => SmoothIntersection(k), "SmoothSubtraction" => SmoothSubtraction(k),
+        "ChamferUnion" => ChamferUnion(r), "ChamferIntersection" => ChamferIntersection(r), "ChamferSubtraction" => ChamferSubtraction(r),
+        "StairsUnion" => StairsUnion(r, steps), "StairsIntersection" => StairsIntersection(r, steps), "StairsSubtraction" => StairsSubtraction(r, steps),
+        "ExpSmoothUnion" => ExpSmoothUnion(k), "ExpSmoothIntersection" => ExpSmoothIntersection(k), "ExpSmoothSubtraction" => ExpSmoothSubtraction(k),
+        "ColumnsUnion" => ColumnsUnion(r, steps), "ColumnsIntersection" => ColumnsIntersection(r, steps), "ColumnsSubtraction" => ColumnsSubtraction(r, steps),
+        "Xor" => Xor, "Morph" => Morph(n.f("t", 0.5)), "Pipe" => Pipe(r), "Engrave" => Engrave(r),
+        "Groove" => Groove(n.f("depth", 0.1), n.f("width", 0.05)), "Tongue" => Tongue(n.f("height", 0.1), n.f("width", 0.05)),
+        _ => return None,
+    })
+}
+
+/// Quaternion `[x, y, z, w]` to a rotation matrix.
+pub(crate) fn quat_matrix(q: [f32; 4]) -> M3 {
+    let l = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let [x, y, z, w] = if l > 1e-12 { q.map(|c| c / l) } else { [0.0, 0.0, 0.0, 1.0] };
+    [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w),
+     2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w),
+     2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)]
+}
+
+/// Row-major 4x4 inverse by cofactors; `None` when singular.
+pub fn invert4(m: &[f32; 16]) -> Option<[f32; 16]> {
+    let m: [f64; 16] = m.map(|x| x as f64);
+    let mut inv = [0.0f64; 16];
+    inv[0] = m[5]*m[10]*m[15] - m[5]*m[11]*m[14] - m[9]*m[6]*m[15] + m[9]*m[7]*m[14] + m[13]*m[6]*m[11] - m[13]*m[7]*m[10];
+    inv[4] = -m[4]*m[10]*m[15] + m[4]*m[11]*m[14] + m[8]*m[6]*m[15] - m[8]*m[7]*m[14] - m[12]*m[6]*m[11] + m[12]*m[7]*m[10];
+    inv[8] = m[4]*m[9]*m[15] - m[4]*m[11]*m[13] - m[8]*m[5]*m[15] + m[8]*m[7]*m[13] + m[12]*m[5]*m[11] - m[12]*m[7]*m[9];
+    inv[12] = -m[4]*m[9]*m[14] + m[4]*m[10]*m[13] + m[8]*m[5]*m[14] - m[8]*m[6]*m[13] - m[12]*m[5]*m[10] + m[12]*m[6]*m[9];
+    inv[1] = -m[1]*m[10]*m[15] + m[1]*m[11]*m[14] + m[9]*m[2]*m[15] - m[9]*m[3]*m[14] - m[13]*m[2]*m[11] + m[13]*m[3]*m[10];
+    inv[5] = m[0]*m[10]*m[15] - m[0]*m[11]*m[14] - m[8]*m[2]*m[15] + m[8]*m[3]*m[14] + m[12]*m[2]*m[11] - m[12]*m[3]*m[10];
+    inv[9] = -m[0]*m[9]*m[15] + m[0]*m[11]*m[13] + m[8]*m[1]*m[15] - m[8]*m[3]*m[13] - m[12]*m[1]*m[11] + m[12]*m[3]*m[9];
+    inv[13] = m[0]*m[9]*m[14] - m[0]*m[10]*m[13] - m[8]*m[1]*m[14] + m[8]*m[2]*m[13] + m[12]*m[1]*m[10] - m[12]*m[2]*m[9];
+    inv[2] = m[1]*m[6]*m[15] - m[1]*m[7]*m[14] - m[5]*m[2]*m[15] + m[5]*m[3]*m[14] + m[13]*m[2]*m[7] - m[13]*m[3]*m[6];
+    inv[6] = -m[0]*m[6]*m[15] + m[0]*m[7]*m[14] + m[4]*m[2]*m[15] - m[4]*m[3]*m[14] - m[12]*m[2]*m[7] + m[12]*m[3]*m[6];
+    inv[10] = m[0]*m[5]*m[15] - m[0]*m[7]*m[13] - m[4]*m[1]*m[15] + m[4]*m[3]*m[13] + m[12]*m[1]*m[7] - m[12]*m[3]*m[5];
+    inv[14] = -m[0]*m[5]*m[14] + m[0]*m[6]*m[13] + m[4]*m[1]*m[14] - m[4]*m[2]*m[13] - m[12]*m[1]*m[6] + m[12]*m[2]*m[5];
+    inv[3] = -m[1]*m[6]*m[11] + m[1]*m[7]*m[10] + m[5]*m[2]*m[11] - m[5]*m[3]*m[10] - m[9]*m[2]*m[7] + m[9]*m[3]*m[6];
+    inv[7] = m[0]*m[6]*m[11] - m[0]*m[7]*m[10] - m[4]*m[2]*m[11] + m[4]*m[3]*m[10] + m[8]*m[2]*m[7] - m[8]*m[3]*m[6];
+    inv[11] = -m[0]*m[5]*m[11] + m[0]*m[7]*m[9] + m[4]*m[1]*m[11] - m[4]*m[3]*m[9] - m[8]*m[1]*m[7] + m[8]*m[3]*m[5];
+    inv[15] = m[0]*m[5]*m[10] - m[0]*m[6]*m[9] - m[4]*m[1]*m[10] + m[4]*m[2]*m[9] + m[8]*m[1]*m[6] - m[8]*m[2]*m[5];
+    let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+    if det.abs() < 1e-12 { return None; }
+    Some(inv.map(|x| (x / det) as f32))
+}
+
+fn bones(n: &SdfNode, path: &str) -> Result<Vec<Bone>, String> {
+    let Some(list) = n.params.get("bones") else { return Ok(vec![]) };
+    let list = list.as_array().ok_or_else(|| format!("{path}: SdfSkinning bones must be an array"))?;
+    list.iter().enumerate().map(|(i, b)| {
+        let b = SdfNode { params: b.as_object().cloned().ok_or_else(|| format!("{path}: bones[{i}] must be an object"))?, ..SdfNode::leaf("Bone", Value::Null) };
+        Ok(Bone {
+            pivot: b.v3("pivot", [0.0; 3]), offset: b.v3("offset", [0.0; 3]),
+            rot: math::transpose(&math::euler_xyz(b.v3("angles", [0.0; 3]))),
+            radius: positive(n, path, &format!("bones[{i}].radius"), b.f("radius", 1.0))?,
+        })
+    }).collect()
+}
+
+/// Up vector: the normal of the path's plane (pointing +Y where possible), or any
+/// perpendicular of a straight path. Length by summing 64 chords.
+fn sweep(a: V3, b: V3, c: V3) -> Sweep {
+    let len = (0..64).map(|i| math::len(math::sub(bezier_pos(a, b, c, (i + 1) as f32 / 64.0), bezier_pos(a, b, c, i as f32 / 64.0)))).sum();
+    let nrm = math::cross(math::sub(b, a), math::sub(c, b));
+    let up = if math::len(nrm) > 1e-6 * math::dot(math::sub(c, a), math::sub(c, a)).max(1e-12) {
+        let u = math::normalize(nrm);
+        if u[1] < 0.0 { math::mul(u, -1.0) } else { u }
+    } else {
+        let dir = if math::len(math::sub(c, a)) > 1e-9 { math::normalize(math::sub(c, a)) } else if math::len(math::sub(b, a)) > 1e-9 { math::normalize(math::sub(b, a)) } else { [1.0, 0.0, 0.0] };
+        let r = if dir[1].abs() < 0.9 { [0.0, 1.0, 0.0] } else { [0.0, 0.0, 1.0] };
+        math::normalize(math::sub(r, math::mul(dir, math::dot(r, dir))))
+    };
+    Sweep { a, b, c, up, len }
+}
+
+fn emit_point_op(n: &SdfNode, path: &str) -> Result<Option<(PointOp, Option<Post>)>, String> {
+    Ok(Some(match n.ty.as_str() {
+        "Translate" => (PointOp::Translate(n.v3("offset", [0.0; 3])), None),
+        "RotateEuler" => (PointOp::Rotate(math::transpose(&math::euler_xyz(n.v3("angles", [0.0; 3])))), None),
+        "RotateQuat" => {
+            let q = f32_list(n, path, "quaternion")?;
+            let q = if q.is_empty() { [0.0, 0.0, 0.0, 1.0] } else if q.len() == 4 { [q[0], q[1], q[2], q[3]] } else { return Err(format!("{path}: RotateQuat quaternion must have 4 components")) };
+            (PointOp::Rotate(math::transpose(&quat_matrix(q))), None)
+        }
+        "Scale" => { let s = n.f("factor", 1.0); if s <= 0.0 { return Err(format!("{path}: Scale factor must be > 0")); } (PointOp::Scale(s), Some(Post::MulDist(s))) }
+        "ScaleNonUniform" => {
+            let f = n.v3("factors", [1.0; 3]);
+            if math::min_c(f) <= 0.0 { return Err(format!("{path}: ScaleNonUniform factors must be > 0")); }
+            (PointOp::ScaleNonUniform(f), Some(Post::MulDist(math::min_c(f))))
+        }
+        "ProjectiveTransform" => {
+            let m = f32_list(n, path, "matrix")?;
+            let m: [f32; 16] = if m.is_empty() { std::array::from_fn(|i| if i % 5 == 0 { 1.0 } else { 0.0 }) } else { m.try_into().map_err(|_| format!("{path}: ProjectiveTransform matrix must have 16 components"))? };
+            let inv = invert4(&m).ok_or_else(|| format!("{path}: ProjectiveTransform matrix is singular"))?;
+            (PointOp::Projective(inv), Some(Post::MulDist(1.0 / positive(n, path, "lipschitz", n.f("lipschitz", 1.0))?)))
+        }
+        "LatticeDeform" => {
+            let d = n.v3("divisions", [2.0; 3]);
+            if !d.iter().all(|&x| x <= MAX_LATTICE_DIVISIONS as f32) { return Err(format!("{path}: LatticeDeform divisions must be <= {MAX_LATTICE_DIVISIONS} per axis")); }
+            let d = d.map(|x| x.round().max(2.0) as usize);
+            let flat = f32_list(n, path, "offsets")?;
+            let count = d[0] * d[1] * d[2];
+            if !flat.is_empty() && flat.len() != count * 3 { return Err(format!("{path}: LatticeDeform needs {} offset components, got {}", count * 3, flat.len())); }
+            let offsets = if flat.is_empty() { vec![[0.0; 3]; count] } else { flat.chunks(3).map(|c| [c[0], c[1], c[2]]).collect() };
+            let (min, max) = (n.v3("min", [-1.0; 3]), n.v3("max", [1.0; 3]));
+            if (0..3).any(|i| max[i] <= min[i]) { return Err(format!("{path}: LatticeDeform max must exceed min on every axis")); }
+            (PointOp::Lattice(Box::new(Lattice { divisions: d, min, max, offsets })), None)
+        }
+        "SdfSkinning" => (PointOp::Skin(bones(n, path)?), None),
+        "Revolution" => (PointOp::Revolve(n.f("offset", 0.0)), None),
+        "Sweep" => (PointOp::Sweep(Box::new(sweep(n.v3("a", [-1.0, 0.0, 0.0]), n.v3("b", [0.0, 0.0, 1.0]), n.v3("c", [1.0, 0.0, 0.0])))), None),
+        "Twist" => (PointOp::Twist(n.f("strength", 1.0)), None),
+        "Bend" => (PointOp::Bend(n.f("strength", 1.0)), None),
+        "Taper" => { let k = n.f("strength", 0.5); (PointOp::Taper(k), Some(Post::Taper(k))) }
+        "Repeat" => (PointOp::Repeat(n.v3("spacing", [2.0; 3])), None),
+        "RepeatFinite" => (PointOp::RepeatFinite(n.v3("spacing", [2.0; 3]), n.v3("count", [3.0; 3])), None),
+        "Mirror" => { let a = n.v3("axis", [1.0, 0.0, 0.0]); (PointOp::Mirror([a[0] != 0.0, a[1] != 0.0, a[2] != 0.0]), None) }
+        "MirrorOctant" => (PointOp::Mirror([true; 3]), None),
+        "PolarRepeat" => (PointOp::PolarRepeat(n.f("count", 6.0).max(1.0).round(), n.f("radius", 0.0)), None),
+        "IcosahedralSymmetry" => (PointOp::Icosahedral, None),
+        "IFS" => {
+            let (it, s) = (n.u("iterations", 4).min(16), n.f("scale", 3.0));
+            if s <= 1.0 { return Err(format!("{path}: IFS scale must be > 1")); }
+            (PointOp::Ifs(it, s, n.v3("offset", [1.0; 3])), Some(Post::MulDist(s.powi(-(it as i32)))))
+        }
+        _ => return Ok(None),
+    }))
+}
+
+fn emit_post(n: &SdfNode, path: &str) -> Result<Option<Post>, String> {
+    Ok(Some(match n.ty.as_str() {
+        "Noise" => Post::Noise(n.f("amplitude", 0.1), n.f("frequency", 1.0), n.u("seed", 0)),
+        "Shell" => Post::Shell(n.f("thickness", 0.1)),
+        "Onion" => Post::Onion(n.f("thickness", 0.1)),
+        "Extrude" => Post::Extrude(n.f("half_depth", 0.5)),
+        "Displacement" => Post::Displacement(n.f("amplitude", 0.05), n.f("frequency", 5.0)),
+        "SurfaceRoughness" => Post::Roughness(n.f("amplitude", 0.02), n.f("frequency", 4.0), n.u("octaves", 4).clamp(1, 8), n.u("seed", 0)),
+        "HeightmapDisplacement" => {
+            let data = f32_list(n, path, "heights")?;
+            let (cols, rows) = (n.u("columns", 2) as usize, n.u("rows", 2) as usize);
+            if cols < 2 || rows < 2 || data.len() != cols * rows {
+                return Err(format!("{path}: HeightmapDisplacement needs columns, rows >= 2 and columns*rows heights"));
+            }
+            let size = n.v3("size", [2.0, 0.0, 2.0]);
+            Post::Heightmap(Box::new(Heightmap { cols, rows, size: [positive(n, path, "size.x", size[0])?, positive(n, path, "size.z", size[2])?], amplitude: n.f("amplitude", 0.1), data }))
+        }
+        _ => return Ok(None),
+    }))
+}
+
+fn emit(n: &SdfNode, path: &str, out: &mut Vec<Inst>, mats: &mut Materials, inherited: Option<Material>) -> Result<(), String> {
+    let m = n.material.or(inherited);
+    if let Some(p) = emit_prim(n, path)? {
+        arity(n, path, 0)?;
+        out.push(Inst::Prim(p));
+        let id = mats.id(m);
+        mats.leaves.push(id);
+        return Ok(());
+    }
+    if let Some(op) = emit_op(n) {
+        if n.children.len() < 2 { return Err(format!("{path}: {} expects at least 2 child nodes, got {}", n.ty, n.children.len())); }
+        if n.ty == "Morph" { arity(n, path, 2)?; }
+        // n-ary lists fold left: ((c0 op c1) op c2) ...
+        for (i, c) in n.children.iter().enumerate() {
+            emit(c, &n.child_path(path, i), out, mats, m)?;
+            if i > 0 { out.push(Inst::Op(op)); }
+        }
+        return Ok(());
+    }
+    if let Some((push, post)) = emit_point_op(n, path)? {
+        arity(n, path, 1)?;
+        // Sweep caps its ends in the profile frame, before the point is popped.
+        let cap = if let PointOp::Sweep(s) = &push { Some(Post::Extrude(s.len * 0.5)) } else { None };
+        out.push(Inst::Push(push));
+        emit(&n.children[0], &n.child_path(path, 0), out, mats, m)?;
+        out.extend(cap.map(Inst::Post));
+        out.push(Inst::PopPoint);
+        out.extend(post.map(Inst::Post));
+        return Ok(());
+    }
+    if let Some(post) = emit_post(n, path)? {
+        arity(n, path, 1)?;
+        emit(&n.children[0], &n.child_path(path, 0), out, mats, m)?;
+        out.push(Inst::Post(post));
+        return Ok(());
+    }
+    Err(format!("{path}: unknown node type '{}'", n.ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema;
+    use serde_json::json;
+
+    fn lattice(params: Value) -> Value { json!({ "type": "LatticeDeform", "params": params, "child": { "type": "Sphere", "params": {} } }) }
+
+    #[test]
+    fn oversized_lattices_are_rejected() {
+        let huge = lattice(json!({ "divisions": [1e5, 1e5, 1e5] }));
+        assert!(schema::validate(&huge).iter().any(|e| e.contains("must be <= 64")), "{:?}", schema::validate(&huge));
+        let node = SdfNode::from_json(&huge).unwrap();
+        assert_eq!(compile(&node).err().as_deref(), Some("root: LatticeDeform divisions must be <= 64 per axis"));
+        // Analysis runs on unchecked trees too: a lattice too big to index has no slope.
+        let overflow = SdfNode::from_json(&lattice(json!({ "divisions": [1e10, 1e10, 1e10] }))).unwrap();
+        assert_eq!(lipschitz::analyze(&overflow).lipschitz, 1.0);
+
+        let short = lattice(json!({ "divisions": 3, "offsets": [0, 0, 0] }));
+        assert_eq!(schema::validate(&short), ["root: LatticeDeform needs 81 offset components, got 3"]);
+        let full = SdfNode::from_json(&lattice(json!({ "divisions": [64, 64, 2] }))).unwrap();
+        assert!(compile(&full).is_ok());
+    }
+}
@@ -0,0 +1,364 @@
+//! Structural tree diff and patch (`tree_diff` / `apply_patch`).
+//!
+//! Works on the JSON form so fields the engine does not interpret survive a round
+//! trip. Node paths use the same syntax as engine errors: `root`, `root.a`,
+//! `root.children[2].child`. Ops apply in order and every path refers to the tree
+//! as left by the previous op. Ops produced by `diff` carry the value they expect
+//! to find (`old`), which is what lets `apply` report conflicts against a tree that
+//! has moved on since the patch was made.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PatchOp {
+    /// Set (or with `value: null`, delete) one param of the node at `path`.
+    SetParam { path: String, key: String, value: Value, #[serde(default, skip_serializing_if = "Option::is_none")] old: Option<Value> },
+    /// Swap the subtree at `path` for `node`.
+    Replace { path: String, node: Value, #[serde(default, skip_serializing_if = "Option::is_none")] old: Option<Value> },
+    /// Insert `node` at `path`: an index into a `children` list, or an empty `a`/`b`/`child` slot.
+    Add { path: String, node: Value },
+    Remove { path: String, #[serde(default, skip_serializing_if = "Option::is_none")] old: Option<Value> },
+    /// Detach the subtree at `from`, then insert it at `to` (interpreted after the detach).
+    Move { from: String, to: String },
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TreePatch { pub ops: Vec<PatchOp> }
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Conflict { pub op_index: usize, pub path: String, pub reason: String }
+
+const SLOTS: [&str; 4] = ["a", "b", "child", "children"];
+
+/// Largest LCS table `diff_list` builds (old × new children left after trimming
+/// the shared prefix and suffix); wider reorders replace the whole node instead.
+pub const MAX_ALIGN_CELLS: usize = 1 << 22;
+
+// ── diff ────────────────────────────────────────────────────────────────────
+
+pub fn diff(old: &Value, new: &Value) -> TreePatch {
+    let mut ops = Vec::new();
+    diff_node(old, new, "root", &mut ops);
+    TreePatch { ops }
+}
+
+fn same_shape(a: &Map<String, Value>, b: &Map<String, Value>) -> bool {
+    a.get("type") == b.get("type")
+        && SLOTS.iter().all(|s| a.contains_key(*s) == b.contains_key(*s))
+        && a.keys().chain(b.keys()).filter(|k| *k != "params" && !SLOTS.contains(&k.as_str())).all(|k| a.get(k) == b.get(k))
+}
+
+fn diff_node(old: &Value, new: &Value, path: &str, ops: &mut Vec<PatchOp>) {
+    if old == new { return; }
+    let (Some(o), Some(n)) = (old.as_object(), new.as_object()) else {
+        ops.push(PatchOp::Replace { path: path.into(), node: new.clone(), old: Some(old.clone()) });
+        return;
+    };
+    let lists = match (o.get("children"), n.get("children")) {
+        (Some(Value::Array(a)), Some(Value::Array(b))) => Some((a, b, trim(a, b))),
+        _ => None,
+    };
+    let too_wide = lists.is_some_and(|(a, b, (pre, suf))| (a.len() - pre - suf).saturating_mul(b.len() - pre - suf) > MAX_ALIGN_CELLS);
+    if !same_shape(o, n) || too_wide {
+        ops.push(PatchOp::Replace { path: path.into(), node: new.clone(), old: Some(old.clone()) });
+        return;
+    }
+    let empty = Map::new();
+    let po = o.get("params").and_then(|p| p.as_object()).unwrap_or(&empty);
+    let pn = n.get("params").and_then(|p| p.as_object()).unwrap_or(&empty);
+    let mut keys: Vec<&String> = po.keys().chain(pn.keys()).collect();
+    keys.sort(); keys.dedup();
+    for k in keys {
+        if po.get(k) != pn.get(k) {
+            ops.push(PatchOp::SetParam { path: path.into(), key: k.clone(), value: pn.get(k).cloned().unwrap_or(Value::Null), old: po.get(k).cloned() });
+        }
+    }
+    for s in ["a", "b", "child"] {
+        if let (Some(a), Some(b)) = (o.get(s), n.get(s)) { diff_node(a, b, &format!("{path}.{s}"), ops); }
+    }
+    if let Some((a, b, (pre, suf))) = lists { diff_list(&a[pre..a.len() - suf], &b[pre..b.len() - suf], pre, path, ops); }
+}
+
+/// Lengths of the shared prefix and suffix of two lists, not overlapping.
+fn trim(old: &[Value], new: &[Value]) -> (usize, usize) {
+    let pre = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suf = old[pre..].iter().rev().zip(new[pre..].iter().rev()).take_while(|(a, b)| a == b).count();
+    (pre, suf)
+}
+
+/// Align two child lists: identical children are matched by LCS (kept in order),
+/// remaining identical pairs become moves, and leftover same-type pairs are diffed
+/// in place. Everything else is a remove or an add. The lists are the middles left
+/// by `trim`, starting at child `base`.
+fn diff_list(old: &[Value], new: &[Value], base: usize, path: &str, ops: &mut Vec<PatchOp>) {
+    let (m, n) = (old.len(), new.len());
+    let mut lcs = vec![vec![0u32; n + 1]; m + 1];
+    for i in (0..m).rev() { for j in (0..n).rev() {
+        lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+    }}
+    let mut src: Vec<Option<usize>> = vec![None; n];
+    let mut used = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < m && j < n {
+        if old[i] == new[j] { src[j] = Some(i); used[i] = true; i += 1; j += 1; }
+        else if lcs[i + 1][j] >= lcs[i][j + 1] { i += 1 } else { j += 1 }
+    }
+    let ty = |v: &Value| v.get("type").cloned();
+    for pass in 0..2 {
+        for j in 0..n {
+            if src[j].is_some() { continue; }
+            let hit = (0..m).find(|&i| !used[i] && if pass == 0 { old[i] == new[j] } else { ty(&old[i]) == ty(&new[j]) });
+            if let Some(i) = hit { src[j] = Some(i); used[i] = true; }
+        }
+    }
+    let child = |k: usize| format!("{path}.children[{}]", base + k);
+    for i in (0..m).rev().filter(|&i| !used[i]) {
+        ops.push(PatchOp::Remove { path: child(i), old: Some(old[i].clone()) });
+    }
+    // Simulate the list so every emitted index is valid at the moment it applies.
+    let mut sim: Vec<Option<usize>> = (0..m).filter(|&i| used[i]).map(Some).collect();
+    for (j, s) in src.iter().enumerate() {
+        match s {
+            Some(i) => {
+                let k = sim.iter().position(|x| *x == Some(*i)).unwrap_or(j);
+                if k != j {
+                    ops.push(PatchOp::Move { from: child(k), to: child(j) });
+                    let v = sim.remove(k);
+                    sim.insert(j, v);
+                }
+            }
+            None => {
+                ops.push(PatchOp::Add { path: child(j), node: new[j].clone() });
+                sim.insert(j, None);
+            }
+        }
+    }
+    for (j, s) in src.iter().enumerate() {
+        if let Some(i) = s { diff_node(&old[*i], &new[j], &child(j), ops); }
+    }
+}
+
+// ── apply ───────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Step { Slot(String), Index(usize) }
+
+pub(crate) fn parse_path(p: &str) -> Result<Vec<Step>, String> {
+    let rest = p.strip_prefix("root").filter(|r| r.is_empty() || r.starts_with('.')).ok_or_else(|| format!("path '{p}' must start with 'root'"))?;
+    let mut steps = Vec::new();
+    for part in rest.split('.').skip(1) {
+        let (name, idx) = match part.split_once('[') {
+            Some((name, i)) => (name, Some(i.strip_suffix(']').and_then(|i| i.parse::<usize>().ok()).ok_or_else(|| format!("bad index in '{p}'"))?)),
+            None => (part, None),
+        };
+        if !SLOTS.contains(&name) { return Err(format!("unknown slot '{name}' in '{p}'")); }
+        if (name == "children") != idx.is_some() { return Err(format!("'{name}' {} an index in '{p}'", if idx.is_some() { "does not take" } else { "needs" })); }
+        steps.push(Step::Slot(name.into()));
+        if let Some(i) = idx { steps.push(Step::Index(i)); }
+    }
+    Ok(steps)
+}
+
+fn get_mut<'a>(v: &'a mut Value, steps: &[Step]) -> Option<&'a mut Value> {
+    steps.iter().try_fold(v, |cur, s| match s {
+        Step::Slot(k) => cur.as_object_mut()?.get_mut(k),
+        Step::Index(i) => cur.as_array_mut()?.get_mut(*i),
+    })
+}
+
+fn detach(tree: &mut Value, steps: &[Step]) -> Result<Value, String> {
+    let (last, parent) = steps.split_last().ok_or("cannot detach the root")?;
+    let container = get_mut(tree, parent).ok_or("parent not found")?;
+    match last {
+        Step::Index(i) => {
+            let arr = container.as_array_mut().ok_or("parent is not a list")?;
+            if *i >= arr.len() { return Err(format!("index {i} out of range (len {})", arr.len())); }
+            Ok(arr.remove(*i))
+        }
+        Step::Slot(k) => container.as_object_mut().and_then(|o| o.remove(k)).ok_or_else(|| format!("slot '{k}' is empty")),
+    }
+}
+
+/// Insert `node` at `steps`, or hand it back with the reason it does not fit.
+fn attach(tree: &mut Value, steps: &[Step], node: Value) -> Result<(), (String, Value)> {
+    let Some((last, parent)) = steps.split_last() else { return Err(("cannot add at the root".into(), node)) };
+    let Some(container) = get_mut(tree, parent) else { return Err(("parent not found".into(), node)) };
+    match (last, container) {
+        (Step::Index(i), Value::Array(arr)) if *i <= arr.len() => arr.insert(*i, node),
+        (Step::Index(i), Value::Array(arr)) => return Err((format!("index {i} out of range (len {})", arr.len()), node)),
+        (Step::Index(_), _) => return Err(("parent is not a list".into(), node)),
+        (Step::Slot(k), Value::Object(o)) if !o.contains_key(k) => { o.insert(k.clone(), node); }
+        (Step::Slot(k), Value::Object(_)) => return Err((format!("slot '{k}' is occupied"), node)),
+        (Step::Slot(_), _) => return Err(("parent is not a node".into(), node)),
+    }
+    Ok(())
+}
+
+fn apply_op(tree: &mut Value, op: &PatchOp) -> Result<(), String> {
+    match op {
+        PatchOp::SetParam { path, key, value, old } => {
+            let node = get_mut(tree, &parse_path(path)?).ok_or("node not found")?;
+            let o = node.as_object_mut().ok_or("target is not a node")?;
+            let cur = match o.get("params") { None => None, Some(Value::Object(p)) => p.get(key), Some(_) => return Err("'params' is not an object".into()) };
+            if let Some(expected) = old {
+                if cur != Some(expected) { return Err(format!("param '{key}' is {}, expected {expected}", cur.map_or("unset".into(), |v| v.to_string()))); }
+            }
+            if let Value::Object(params) = o.entry("params").or_insert_with(|| Value::Object(Map::new())) {
+                if value.is_null() { params.remove(key); } else { params.insert(key.clone(), value.clone()); }
+            }
+            Ok(())
+        }
+        PatchOp::Replace { path, node, old } => {
+            let cur = get_mut(tree, &parse_path(path)?).ok_or("node not found")?;
+            if old.as_ref().is_some_and(|o| o != cur) { return Err("subtree was modified concurrently".into()); }
+            *cur = node.clone();
+            Ok(())
+        }
+        PatchOp::Add { path, node } => attach(tree, &parse_path(path)?, node.clone()).map_err(|(e, _)| e),
+        PatchOp::Remove { path, old } => {
+            let steps = parse_path(path)?;
+            let cur = get_mut(tree, &steps).ok_or("node not found")?;
+            if old.as_ref().is_some_and(|o| o != cur) { return Err("subtree was modified concurrently".into()); }
+            detach(tree, &steps).map(|_| ())
+        }
+        PatchOp::Move { from, to } => {
+            let (f, t) = (parse_path(from)?, parse_path(to)?);
+            if t.len() > f.len() && t[..f.len()] == f[..] { return Err("cannot move a node into itself".into()); }
+            let node = detach(tree, &f)?;
+            // Put it back where it was when the target is invalid; that slot or index was just vacated.
+            attach(tree, &t, node).map_err(|(e, node)| { let _ = attach(tree, &f, node); e })
+        }
+    }
+}
+
+/// Apply ops in order to one copy of `tree`. Ops that conflict are skipped and
+/// reported; the rest still apply. Every check in `apply_op` runs before it
+/// changes anything, so a conflicting op leaves the copy as it was.
+pub fn apply(tree: &Value, patch: &TreePatch) -> (Value, Vec<Conflict>) {
+    let mut out = tree.clone();
+    let mut conflicts = Vec::new();
+    for (i, op) in patch.ops.iter().enumerate() {
+        match apply_op(&mut out, op) {
+            Ok(()) => {}
+            Err(reason) => {
+                let path = match op {
+                    PatchOp::SetParam { path, .. } | PatchOp::Replace { path, .. } | PatchOp::Add { path, .. } | PatchOp::Remove { path, .. } => path.clone(),
+                    PatchOp::Move { from, .. } => from.clone(),
+                };
+                conflicts.push(Conflict { op_index: i, path, reason });
+            }
+        }
+    }
+    (out, conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::Rng;
+    use serde_json::json;
+
+    fn sphere(r: f64) -> Value { json!({ "type": "Sphere", "params": { "radius": r } }) }
+
+    /// Small random tree over a few types, so random pairs share some structure.
+    fn tree(rng: &mut Rng, depth: u32) -> Value {
+        let pick = (rng.f32() * 6.0) as u32;
+        match if depth == 0 { pick % 2 } else { pick } {
+            0 => sphere((rng.f32() * 4.0).round() as f64 / 2.0),
+            1 => json!({ "type": "Box3d", "params": { "half_size": [0.5, (rng.f32() * 2.0).round(), 0.5] } }),
+            2 => json!({ "type": "Translate", "params": { "offset": [(rng.f32() * 3.0).round(), 0, 0] }, "child": tree(rng, depth - 1) }),
+            3 => json!({ "type": "SmoothUnion", "params": { "k": 0.1 }, "a": tree(rng, depth - 1), "b": tree(rng, depth - 1) }),
+            _ => json!({ "type": "Union", "children": (0..2 + (rng.f32() * 4.0) as u32).map(|_| tree(rng, depth - 1)).collect::<Vec<_>>() }),
+        }
+    }
+
+    #[test]
+    fn apply_of_diff_gives_the_new_tree() {
+        let a = json!({ "type": "Union", "children": [sphere(1.0), sphere(2.0), sphere(3.0), json!({ "type": "Box3d", "params": {} })] });
+        let b = json!({ "type": "Union", "children": [sphere(3.0), sphere(1.0), json!({ "type": "Box3d", "params": { "half_size": 2 } }), sphere(4.0)] });
+        let p = diff(&a, &b);
+        assert!(p.ops.iter().any(|o| matches!(o, PatchOp::Move { .. })) && p.ops.iter().any(|o| matches!(o, PatchOp::SetParam { .. })), "{p:?}");
+        let (out, conflicts) = apply(&a, &p);
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+        assert_eq!(out, b);
+        let mut rng = Rng::new(29);
+        for _ in 0..500 {
+            let (a, b) = (tree(&mut rng, 3), tree(&mut rng, 3));
+            let (out, conflicts) = apply(&a, &diff(&a, &b));
+            assert!(conflicts.is_empty(), "{a}\n{b}\n{conflicts:?}");
+            assert_eq!(out, b, "from {a}");
+            // Through JSON, as clients send it.
+            let p: TreePatch = serde_json::from_value(serde_json::to_value(diff(&a, &b)).unwrap()).unwrap();
+            assert_eq!(apply(&a, &p).0, b);
+        }
+    }
+
+    #[test]
+    fn stale_ops_are_reported_and_skipped() {
+        let a = json!({ "type": "Union", "children": [sphere(1.0), sphere(2.0)] });
+        let b = json!({ "type": "Union", "children": [sphere(1.5), sphere(2.0), sphere(3.0)] });
+        // Someone else moved the first radius on in the meantime.
+        let theirs = json!({ "type": "Union", "children": [sphere(1.2), sphere(2.0)] });
+        let (out, conflicts) = apply(&theirs, &diff(&a, &b));
+        assert_eq!(conflicts.len(), 1, "{conflicts:?}");
+        assert_eq!((conflicts[0].path.as_str(), conflicts[0].reason.as_str()), ("root.children[0]", "param 'radius' is 1.2, expected 1.0"));
+        assert_eq!(out["children"][0], sphere(1.2));
+        assert_eq!(out["children"][2], sphere(3.0));
+
+        let bad = TreePatch { ops: vec![
+            PatchOp::Remove { path: "root.children[5]".into(), old: None },
+            PatchOp::Add { path: "root.a".into(), node: sphere(1.0) },
+            PatchOp::Move { from: "root.children[0]".into(), to: "root.children[0].child".into() },
+            PatchOp::SetParam { path: "top".into(), key: "k".into(), value: json!(1), old: None },
+        ] };
+        let (out, conflicts) = apply(&a, &bad);
+        assert_eq!(conflicts.iter().map(|c| c.op_index).collect::<Vec<_>>(), [0, 2, 3]);
+        assert_eq!(conflicts[2].reason, "path 'top' must start with 'root'");
+        assert_eq!(out["a"], sphere(1.0));
+    }
+
+    #[test]
+    fn paths_must_name_root_exactly() {
+        assert_eq!(parse_path("root"), Ok(vec![]));
+        assert_eq!(parse_path("root.children[1].a"), Ok(vec![Step::Slot("children".into()), Step::Index(1), Step::Slot("a".into())]));
+        for p in ["rootfoo", "root_x", "rootchild", "rootchildren[0]"] {
+            assert_eq!(parse_path(p), Err(format!("path '{p}' must start with 'root'")));
+        }
+        assert_eq!(parse_path("root."), Err("unknown slot '' in 'root.'".into()));
+        // A typo must not replace the whole tree.
+        let a = json!({ "type": "Translate", "params": {}, "child": sphere(1.0) });
+        let (out, conflicts) = apply(&a, &TreePatch { ops: vec![PatchOp::Replace { path: "rootchild".into(), node: sphere(2.0), old: None }] });
+        assert_eq!((out, conflicts.len()), (a, 1));
+    }
+
+    #[test]
+    fn conflicting_ops_leave_the_tree_untouched() {
+        let a = json!({ "type": "SmoothUnion", "a": { "type": "Sphere" }, "b": sphere(2.0) });
+        let (out, conflicts) = apply(&a, &TreePatch { ops: vec![
+            PatchOp::SetParam { path: "root.a".into(), key: "radius".into(), value: json!(3), old: Some(json!(1)) },
+            PatchOp::Move { from: "root.a".into(), to: "root.b".into() },
+            PatchOp::Move { from: "root.b".into(), to: "root.children[0]".into() },
+        ] });
+        assert_eq!(conflicts.iter().map(|c| c.reason.as_str()).collect::<Vec<_>>(), ["param 'radius' is unset, expected 1", "slot 'b' is occupied", "parent not found"]);
+        assert_eq!(out, a);
+    }
+
+    #[test]
+    fn wide_lists_align_only_what_changed() {
+        let union = |c: Vec<Value>| json!({ "type": "Union", "children": c });
+        let a: Vec<Value> = (0..5000).map(|i| sphere(i as f64)).collect();
+        let mut b = a.clone();
+        b[2500] = sphere(-1.0);
+        b.insert(4000, json!({ "type": "Box3d", "params": {} }));
+        let p = diff(&union(a.clone()), &union(b.clone()));
+        assert_eq!(p.ops.len(), 2, "{p:?}");
+        assert_eq!(apply(&union(a.clone()), &p).0, union(b));
+        // Reversing leaves nothing to trim; past MAX_ALIGN_CELLS the node is replaced outright.
+        let reversed: Vec<Value> = a.iter().rev().cloned().collect();
+        let p = diff(&union(a.clone()), &union(reversed.clone()));
+        assert!(matches!(&p.ops[..], [PatchOp::Replace { path, .. }] if path == "root"), "{} ops", p.ops.len());
+        assert_eq!(apply(&union(a), &p).0, union(reversed));
+    }
+}
@@ -0,0 +1,861 @@
+//! Stack evaluator for CompiledSdf, plus the distance functions it is built from.
+
+use crate::compiler::{CompiledSdf, Heightmap, Inst, Lattice, Op, PointOp, Post, Prim, Tpms};
+use crate::material::{self, Sample};
+use crate::math::{self, len, len2, max0, max_c, V3};
+use crate::noise;
+use std::f32::consts::{FRAC_1_SQRT_2, PI, SQRT_2, TAU};
+use rayon::prelude::*;
+
+#[inline] pub(crate) fn rnd(x: f32) -> f32 { (x + 0.5).floor() }
+
+pub fn sd_sphere(p: V3, r: f32) -> f32 { len(p) - r }
+
+pub fn sd_box(p: V3, h: V3) -> f32 {
+    let q = math::sub(math::abs(p), h);
+    len(max0(q)) + max_c(q).min(0.0)
+}
+
+pub fn sd_cylinder(p: V3, r: f32, h: f32) -> f32 {
+    let (dx, dy) = (len2(p[0], p[2]) - r, p[1].abs() - h);
+    dx.max(dy).min(0.0) + len2(dx.max(0.0), dy.max(0.0))
+}
+
+pub fn sd_torus(p: V3, big: f32, small: f32) -> f32 { len2(len2(p[0], p[2]) - big, p[1]) - small }
+
+pub fn sd_capsule(p: V3, r: f32, h: f32) -> f32 { len([p[0], p[1] - p[1].clamp(-h, h), p[2]]) - r }
+
+/// Cone standing on the XZ plane region y ∈ [-height/2, height/2], base radius `r`, apex up.
+pub fn sd_cone(p: V3, r: f32, height: f32) -> f32 {
+    let h = height * 0.5;
+    let q = [len2(p[0], p[2]), p[1]];
+    let k1 = [0.0, h];
+    let k2 = [-r, 2.0 * h];
+    let ca = [q[0] - q[0].min(if q[1] < 0.0 { r } else { 0.0 }), q[1].abs() - h];
+    let t = (((k1[0] - q[0]) * k2[0] + (k1[1] - q[1]) * k2[1]) / (k2[0] * k2[0] + k2[1] * k2[1])).clamp(0.0, 1.0);
+    let cb = [q[0] - k1[0] + k2[0] * t, q[1] - k1[1] + k2[1] * t];
+    let s = if cb[0] < 0.0 && ca[1] < 0.0 { -1.0 } else { 1.0 };
+    s * (ca[0] * ca[0] + ca[1] * ca[1]).min(cb[0] * cb[0] + cb[1] * cb[1]).sqrt()
+}
+
+pub fn sd_rounded_box(p: V3, h: V3, r: f32) -> f32 { sd_box(p, math::sub(h, [r; 3])) - r }
+
+pub fn sd_ellipsoid(p: V3, r: V3) -> f32 {
+    let k0 = len(math::div3(p, r));
+    let k1 = len(math::div3(p, math::mul3(r, r)));
+    if k1 == 0.0 { return -math::min_c(r); }
+    k0 * (k0 - 1.0) / k1
+}
+
+/// Square pyramid with base side `base` on y = 0 and apex at y = `height`.
+pub fn sd_pyramid(p: V3, height: f32, base: f32) -> f32 {
+    let h = height / base;
+    let mut p = math::mul(p, 1.0 / base);
+    // The lateral-face formula ignores the base: below it the base square is nearest.
+    if p[1] < 0.0 { return len([(p[0].abs() - 0.5).max(0.0), p[1], (p[2].abs() - 0.5).max(0.0)]) * base; }
+    let m2 = h * h + 0.25;
+    p[0] = p[0].abs(); p[2] = p[2].abs();
+    if p[2] > p[0] { p.swap(0, 2); }
+    p[0] -= 0.5; p[2] -= 0.5;
+    let q = [p[2], h * p[1] - 0.5 * p[0], h * p[0] + 0.5 * p[1]];
+    let s = (-q[0]).max(0.0);
+    let t = ((q[1] - 0.5 * p[2]) / (m2 + 0.25)).clamp(0.0, 1.0);
+    let a = m2 * (q[0] + s) * (q[0] + s) + q[1] * q[1];
+    let b = m2 * (q[0] + 0.5 * t) * (q[0] + 0.5 * t) + (q[1] - m2 * t) * (q[1] - m2 * t);
+    let d2 = if q[1].min(-q[0] * m2 - q[1] * 0.5) > 0.0 { 0.0 } else { a.min(b) };
+    // Inside, the base is the nearest face whenever it is nearer than every lateral one.
+    (((d2 + q[2] * q[2]) / m2).sqrt() * q[2].max(-p[1]).signum()).max(-p[1]) * base
+}
+
+pub fn sd_octahedron(p: V3, s: f32) -> f32 {
+    let p = math::abs(p);
+    let m = p[0] + p[1] + p[2] - s;
+    let q = if 3.0 * p[0] < m { p } else if 3.0 * p[1] < m { [p[1], p[2], p[0]] } else if 3.0 * p[2] < m { [p[2], p[0], p[1]] } else { return m * 0.577_350_26 };
+    let k = (0.5 * (q[2] - q[1] + s)).clamp(0.0, s);
+    len([q[0], q[1] - s + k, q[2] - k])
+}
+
+pub fn sd_tetrahedron(p: V3, s: f32) -> f32 {
+    ((p[0] + p[1]).abs() - p[2]).max((p[0] - p[1]).abs() + p[2]) / 3f32.sqrt() - s / 3f32.sqrt()
+}
+
+/// Triply periodic minimal surfaces as thickened level sets. Each implicit is
+/// divided by its mean gradient magnitude on the surface so `thickness` means
+/// roughly the same wall for every kind.
+pub fn sd_tpms(kind: Tpms, p: V3, scale: f32, t: f32) -> f32 {
+    let q = math::mul(p, scale);
+    let (s, c) = (q.map(f32::sin), q.map(f32::cos));
+    let (s2, c2) = (q.map(|x| (2.0 * x).sin()), q.map(|x| (2.0 * x).cos()));
+    let (f, norm) = match kind {
+        Tpms::Gyroid => (s[0] * c[1] + s[1] * c[2] + s[2] * c[0], 1.0),
+        Tpms::SchwarzP => (c[0] + c[1] + c[2], 1.0),
+        Tpms::Diamond => (s[0] * s[1] * s[2] + s[0] * c[1] * c[2] + c[0] * s[1] * c[2] + c[0] * c[1] * s[2], 1.0),
+        Tpms::Neovius => (3.0 * (c[0] + c[1] + c[2]) + 4.0 * c[0] * c[1] * c[2], 2.6),
+        Tpms::Lidinoid => (0.5 * (s2[0] * c[1] * s[2] + s2[1] * c[2] * s[0] + s2[2] * c[0] * s[1])
+            - 0.5 * (c2[0] * c2[1] + c2[1] * c2[2] + c2[2] * c2[0]) + 0.15, 1.3),
+        Tpms::Iwp => (2.0 * (c[0] * c[1] + c[1] * c[2] + c[2] * c[0]) - (c2[0] + c2[1] + c2[2]), 4.3),
+        Tpms::Frd => (4.0 * c[0] * c[1] * c[2] - (c2[0] * c2[1] + c2[1] * c2[2] + c2[2] * c2[0]), 3.6),
+        Tpms::FischerKochS => (c2[0] * s[1] * c[2] + c[0] * c2[1] * s[2] + s[0] * c[1] * c2[2], 1.75),
+        Tpms::Pmy => (2.0 * c[0] * c[1] * c[2] + s2[0] * s[1] + s[0] * s2[2] + s2[1] * s[2], 1.95),
+    };
+    f.abs() / (scale * norm) - t
+}
+
+/// GLSL `sign`: zero maps to zero, unlike `f32::signum`.
+#[inline] fn sgn(x: f32) -> f32 { if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 } }
+#[inline] fn dot2(a: [f32; 2], b: [f32; 2]) -> f32 { a[0] * b[0] + a[1] * b[1] }
+#[inline] fn clamp01(x: f32) -> f32 { x.clamp(0.0, 1.0) }
+
+/// Extrude a 2D distance along Z to `±h`.
+pub fn extrude(d: f32, z: f32, h: f32) -> f32 {
+    let w = [d, z.abs() - h];
+    w[0].max(w[1]).min(0.0) + len2(w[0].max(0.0), w[1].max(0.0))
+}
+
+/// Face normals for the generalized distance function polyhedra (hg_sdf order):
+/// 0..3 cube, 3..7 octahedron, 7..13 icosahedron extras, 13..19 dodecahedron.
+pub const GDF: [V3; 19] = {
+    const A: f32 = 0.577_350_26;
+    const B: f32 = 0.356_822_1;
+    const C: f32 = 0.934_172_4;
+    const D: f32 = 0.850_650_8;
+    const E: f32 = 0.525_731_1;
+    [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0],
+     [A, A, A], [-A, A, A], [A, -A, A], [A, A, -A],
+     [0.0, B, C], [0.0, -B, C], [C, 0.0, B], [-C, 0.0, B], [B, C, 0.0], [-B, C, 0.0],
+     [0.0, D, E], [0.0, -D, E], [E, 0.0, D], [-E, 0.0, D], [D, E, 0.0], [-D, E, 0.0]]
+};
+
+pub fn sd_gdf(p: V3, begin: usize, end: usize, r: f32) -> f32 {
+    GDF[begin..end].iter().map(|v| math::dot(p, *v).abs()).fold(f32::MIN, f32::max) - r
+}
+
+/// Round cone centred on the origin: radius `r1` at y = -h/2, `r2` at y = h/2.
+pub fn sd_round_cone(p: V3, r1: f32, r2: f32, h: f32) -> f32 { sd2_uneven_capsule([len2(p[0], p[2]), p[1]], r1, r2, h) }
+
+/// Hexagonal prism along Z with inradius `r`.
+pub fn sd_hex_prism(p: V3, r: f32, hd: f32) -> f32 {
+    let k = [-0.866_025_4, 0.5, 0.577_350_26];
+    let mut q = math::abs(p);
+    let t = 2.0 * (k[0] * q[0] + k[1] * q[1]).min(0.0);
+    q[0] -= t * k[0]; q[1] -= t * k[1];
+    let dx = len2(q[0] - q[0].clamp(-k[2] * r, k[2] * r), q[1] - r) * sgn(q[1] - r);
+    let dy = q[2] - hd;
+    dx.max(dy).min(0.0) + len2(dx.max(0.0), dy.max(0.0))
+}
+
+/// Chain link: a torus of radius `r1` in XY stretched by `le` along Y, tube radius `r2`.
+pub fn sd_link(p: V3, le: f32, r1: f32, r2: f32) -> f32 {
+    let q = [p[0], (p[1].abs() - le).max(0.0), p[2]];
+    len2(len2(q[0], q[1]) - r1, q[2]) - r2
+}
+
+fn seg_dist2(v: V3, pv: V3) -> f32 {
+    let t = clamp01(math::dot(v, pv) / math::dot(v, v).max(1e-12));
+    let d = math::sub(math::mul(v, t), pv);
+    math::dot(d, d)
+}
+
+/// Unsigned distance to triangle `abc`, thickened by `t`.
+pub fn sd_triangle(p: V3, a: V3, b: V3, c: V3, t: f32) -> f32 {
+    let (ba, pa, cb, pb, ac, pc) = (math::sub(b, a), math::sub(p, a), math::sub(c, b), math::sub(p, b), math::sub(a, c), math::sub(p, c));
+    let nor = math::cross(ba, ac);
+    let nn = math::dot(nor, nor);
+    let inside = sgn(math::dot(math::cross(ba, nor), pa)) + sgn(math::dot(math::cross(cb, nor), pb)) + sgn(math::dot(math::cross(ac, nor), pc));
+    let d2 = if inside < 2.0 || nn < 1e-12 { seg_dist2(ba, pa).min(seg_dist2(cb, pb)).min(seg_dist2(ac, pc)) } else { math::dot(nor, pa) * math::dot(nor, pa) / nn };
+    d2.sqrt() - t
+}
+
+/// Parameter of the point on the quadratic Bezier `a b c` closest to `p`.
+pub fn bezier_t(p: V3, a: V3, b: V3, c: V3) -> f32 {
+    let aa = math::sub(b, a);
+    let bb = math::add(math::sub(a, math::mul(b, 2.0)), c);
+    let cc = math::mul(aa, 2.0);
+    let d = math::sub(a, p);
+    let bb2 = math::dot(bb, bb);
+    if bb2 < 1e-10 { let ca = math::sub(c, a); return clamp01(math::dot(ca, math::sub(p, a)) / math::dot(ca, ca).max(1e-12)); }
+    let kk = 1.0 / bb2;
+    let kx = kk * math::dot(aa, bb);
+    let ky = kk * (2.0 * math::dot(aa, aa) + math::dot(d, bb)) / 3.0;
+    let kz = kk * math::dot(d, aa);
+    let pp = ky - kx * kx;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * pp * pp * pp;
+    if h >= 0.0 {
+        let h = h.sqrt();
+        return clamp01(((h - q) * 0.5).cbrt() + ((-h - q) * 0.5).cbrt() - kx);
+    }
+    let at = |t: f32| { let v = math::add(d, math::mul(math::add(cc, math::mul(bb, t)), t)); math::dot(v, v) };
+    let z = (-pp).sqrt();
+    let v = (q / (pp * z * 2.0)).clamp(-1.0, 1.0).acos() / 3.0;
+    let (m, n) = (v.cos(), v.sin() * 1.732_050_8);
+    let (t1, t2) = (clamp01((m + m) * z - kx), clamp01((-n - m) * z - kx));
+    if at(t1) <= at(t2) { t1 } else { t2 }
+}
+
+pub fn bezier_pos(a: V3, b: V3, c: V3, t: f32) -> V3 {
+    math::add(a, math::mul(math::add(math::mul(math::sub(b, a), 2.0), math::mul(math::add(math::sub(a, math::mul(b, 2.0)), c), t)), t))
+}
+
+/// Quadratic Bezier tube through control points `a`, `b`, `c`.
+pub fn sd_bezier(p: V3, a: V3, b: V3, c: V3, r: f32) -> f32 { len(math::sub(bezier_pos(a, b, c, bezier_t(p, a, b, c)), p)) - r }
+
+/// Cone frustum along Y: radius `r1` at y = -h, `r2` at y = h.
+pub fn sd_capped_cone(p: V3, h: f32, r1: f32, r2: f32) -> f32 { sd2_trapezoid([len2(p[0], p[2]), p[1]], r1, r2, h) }
+
+/// Torus arc in the XY plane covering `±angle` around +Y.
+pub fn sd_capped_torus(p: V3, angle: f32, ra: f32, rb: f32) -> f32 {
+    let (s, c) = angle.sin_cos();
+    let px = p[0].abs();
+    let k = if c * px > s * p[1] { px * s + p[1] * c } else { len2(px, p[1]) };
+    (math::dot(p, p) + ra * ra - 2.0 * ra * k).max(0.0).sqrt() - rb
+}
+
+pub fn sd_rounded_cylinder(p: V3, r: f32, rb: f32, h: f32) -> f32 {
+    let d = [len2(p[0], p[2]) - r + rb, p[1].abs() - h + rb];
+    d[0].max(d[1]).min(0.0) + len2(d[0].max(0.0), d[1].max(0.0)) - rb
+}
+
+/// Equilateral triangle of side `size` in XY, extruded along Z.
+pub fn sd_tri_prism(p: V3, size: f32, hd: f32) -> f32 {
+    let q = math::abs(p);
+    (q[2] - hd).max((q[0] * 0.866_025_4 + p[1] * 0.5).max(-p[1]) - size * 0.5)
+}
+
+/// The cap of a sphere of radius `r` above the plane y = `h`.
+pub fn sd_cut_sphere(p: V3, r: f32, h: f32) -> f32 {
+    let w = (r * r - h * h).max(0.0).sqrt();
+    let q = [len2(p[0], p[2]), p[1]];
+    let s = ((h - r) * q[0] * q[0] + w * w * (h + r - 2.0 * q[1])).max(h * q[0] - w * q[1]);
+    if s < 0.0 { len2(q[0], q[1]) - r } else if q[0] < w { h - q[1] } else { len2(q[0] - w, q[1] - h) }
+}
+
+pub fn sd_cut_hollow_sphere(p: V3, r: f32, h: f32, t: f32) -> f32 {
+    let w = (r * r - h * h).max(0.0).sqrt();
+    let q = [len2(p[0], p[2]), p[1]];
+    (if h * q[0] < w * q[1] { len2(q[0] - w, q[1] - h) } else { (len2(q[0], q[1]) - r).abs() }) - t
+}
+
+/// Sphere `ra` with a sphere `rb` at distance `d` along +X carved out.
+pub fn sd_death_star(p: V3, ra: f32, rb: f32, d: f32) -> f32 { sd2_moon([p[0], len2(p[1], p[2])], ra, rb, d) }
+
+/// Spherical sector of radius `r` and half-angle `angle` around +Y.
+pub fn sd_solid_angle(p: V3, angle: f32, r: f32) -> f32 { sd2_pie([len2(p[0], p[2]), p[1]], angle, r) }
+
+/// Rhombus with diagonals `la`, `lb` in XZ, thickness `h` along Y, rounded by `ra`.
+pub fn sd_rhombus(p: V3, la: f32, lb: f32, h: f32, ra: f32) -> f32 {
+    let p = math::abs(p);
+    let b = [la, lb];
+    let f = ((b[0] * (b[0] - 2.0 * p[0]) - b[1] * (b[1] - 2.0 * p[2])) / dot2(b, b)).clamp(-1.0, 1.0);
+    let q = [len2(p[0] - 0.5 * la * (1.0 - f), p[2] - 0.5 * lb * (1.0 + f)) * sgn(p[0] * lb + p[2] * la - la * lb) - ra, p[1] - h];
+    q[0].max(q[1]).min(0.0) + len2(q[0].max(0.0), q[1].max(0.0))
+}
+
+/// Lens from intersecting two spheres of radius `r` whose centres sit `±d` along X, revolved around Y.
+pub fn sd_vesica(p: V3, r: f32, d: f32) -> f32 {
+    let q = [len2(p[0], p[2]), p[1].abs()];
+    let b = (r * r - d * d).max(0.0).sqrt();
+    if (q[1] - b) * d > q[0] * b { len2(q[0], q[1] - b) } else { len2(q[0] + d, q[1]) - r }
+}
+
+/// Egg with bottom radius `ra` at the origin and a tip rounded by `rb`, pointing +Y.
+pub fn sd_egg(p: V3, ra: f32, rb: f32) -> f32 {
+    let k = 3f32.sqrt();
+    let q = [len2(p[0], p[2]), p[1]];
+    let r = ra - rb;
+    (if q[1] < 0.0 { len2(q[0], q[1]) - r } else if k * (q[0] + r) < q[1] { len2(q[0], q[1] - k * r) } else { len2(q[0] + r, q[1]) - 2.0 * r }) - rb
+}
+
+/// Open tube along Y: outer radius `r`, wall `t`.
+pub fn sd_tube(p: V3, r: f32, t: f32, h: f32) -> f32 {
+    let d = [(len2(p[0], p[2]) - (r - t * 0.5)).abs() - t * 0.5, p[1].abs() - h];
+    d[0].max(d[1]).min(0.0) + len2(d[0].max(0.0), d[1].max(0.0))
+}
+
+/// Cylinder whose radius bulges by `bulge` at mid height.
+pub fn sd_barrel(p: V3, r: f32, h: f32, bulge: f32) -> f32 {
+    let y = p[1] / h;
+    let d = [len2(p[0], p[2]) - (r + bulge * (1.0 - (y * y).min(1.0))), p[1].abs() - h];
+    let k = 1.0 / (1.0 + (2.0 * bulge / h).powi(2)).sqrt();
+    (d[0].max(d[1]).min(0.0) + len2(d[0].max(0.0), d[1].max(0.0))) * k
+}
+
+pub fn sd_chamfered_cube(p: V3, h: V3, c: f32) -> f32 {
+    let q = math::abs(p);
+    let e = |a: f32, b: f32, ha: f32, hb: f32| (a + b - (ha + hb - c)) * FRAC_1_SQRT_2;
+    sd_box(p, h).max(e(q[0], q[1], h[0], h[1])).max(e(q[1], q[2], h[1], h[2])).max(e(q[2], q[0], h[2], h[0]))
+}
+
+/// Superquadric `(|x|^(2/e2) + |z|^(2/e2))^(e2/e1) + |y|^(2/e1) = 1`; a bound, not exact.
+pub fn sd_superellipsoid(p: V3, r: V3, e1: f32, e2: f32) -> f32 {
+    let q = math::abs(math::div3(p, r));
+    let f = (q[0].powf(2.0 / e2) + q[2].powf(2.0 / e2)).powf(e2 / e1) + q[1].powf(2.0 / e1);
+    (f.powf(e1 * 0.5) - 1.0) * math::min_c(r)
+}
+
+/// Helical coil around Y: coil radius `big`, wire radius `t`, rise `pitch` per turn, cut at `±h`.
+pub fn sd_helix(p: V3, big: f32, t: f32, pitch: f32, h: f32) -> f32 {
+    let a = p[2].atan2(p[0]);
+    let y = p[1] - pitch * a / TAU;
+    let y = y - pitch * rnd(y / pitch);
+    let cosa = 1.0 / (1.0 + (pitch / (TAU * big.max(1e-6))).powi(2)).sqrt();
+    (len2(len2(p[0], p[2]) - big, y * cosa) - t).max(p[1].abs() - h)
+}
+
+pub fn sd_box_frame(p: V3, b: V3, e: f32) -> f32 {
+    let p = math::sub(math::abs(p), b);
+    let q = math::sub(math::abs(math::add(p, [e; 3])), [e; 3]);
+    let f = |v: V3| len(max0(v)) + max_c(v).min(0.0);
+    f([p[0], q[1], q[2]]).min(f([q[0], p[1], q[2]])).min(f([q[0], q[1], p[2]]))
+}
+
+/// Infinite cone with its apex at the origin, opening downward with half-angle `angle`.
+pub fn sd_infinite_cone(p: V3, angle: f32) -> f32 {
+    let c = [angle.sin(), angle.cos()];
+    let q = [len2(p[0], p[2]), -p[1]];
+    let t = dot2(q, c).max(0.0);
+    let d = len2(q[0] - c[0] * t, q[1] - c[1] * t);
+    if q[0] * c[1] - q[1] * c[0] < 0.0 { -d } else { d }
+}
+
+// ── 2D profiles (XY) ────────────────────────────────────────────────────────
+
+pub fn sd2_horseshoe(p: [f32; 2], angle: f32, r: f32, le: f32, th: f32) -> f32 {
+    let (s, c) = angle.sin_cos();
+    let px = p[0].abs();
+    let l = len2(px, p[1]);
+    let q = [-c * px + s * p[1], s * px + c * p[1]];
+    let q = [if q[1] > 0.0 || q[0] > 0.0 { q[0] } else { l * sgn(-c) }, if q[0] > 0.0 { q[1] } else { l }];
+    let q = [q[0] - le, (q[1] - r).abs() - th];
+    len2(q[0].max(0.0), q[1].max(0.0)) + q[0].max(q[1]).min(0.0)
+}
+
+pub fn sd2_heart(p: [f32; 2], size: f32) -> f32 {
+    let (x, y) = ((p[0] / size).abs(), p[1] / size + 0.5);
+    let d = if y + x > 1.0 { len2(x - 0.25, y - 0.75) - SQRT_2 * 0.25 } else {
+        let m = 0.5 * (x + y).max(0.0);
+        (x * x + (y - 1.0) * (y - 1.0)).min((x - m) * (x - m) + (y - m) * (y - m)).sqrt() * sgn(x - y)
+    };
+    d * size
+}
+
+pub fn sd2_rounded_x(p: [f32; 2], w: f32, r: f32) -> f32 {
+    let q = [p[0].abs(), p[1].abs()];
+    let m = (q[0] + q[1]).min(w) * 0.5;
+    len2(q[0] - m, q[1] - m) - r
+}
+
+pub fn sd2_pie(p: [f32; 2], angle: f32, r: f32) -> f32 {
+    let c = [angle.sin(), angle.cos()];
+    let q = [p[0].abs(), p[1]];
+    let t = dot2(q, c).clamp(0.0, r);
+    (len2(q[0], q[1]) - r).max(len2(q[0] - c[0] * t, q[1] - c[1] * t) * sgn(c[1] * q[0] - c[0] * q[1]))
+}
+
+pub fn sd2_trapezoid(p: [f32; 2], r1: f32, r2: f32, he: f32) -> f32 {
+    let k2 = [r2 - r1, 2.0 * he];
+    let px = p[0].abs();
+    let ca = [px - px.min(if p[1] < 0.0 { r1 } else { r2 }), p[1].abs() - he];
+    let t = clamp01(dot2([r2 - px, he - p[1]], k2) / dot2(k2, k2));
+    let cb = [px - r2 + k2[0] * t, p[1] - he + k2[1] * t];
+    let s = if cb[0] < 0.0 && ca[1] < 0.0 { -1.0 } else { 1.0 };
+    s * dot2(ca, ca).min(dot2(cb, cb)).sqrt()
+}
+
+pub fn sd2_parallelogram(p: [f32; 2], wi: f32, he: f32, sk: f32) -> f32 {
+    let e = [sk, he];
+    let q = if p[1] < 0.0 { [-p[0], -p[1]] } else { p };
+    let w = [q[0] - e[0], q[1] - e[1]];
+    let w = [w[0] - w[0].clamp(-wi, wi), w[1]];
+    let d = [dot2(w, w), -w[1]];
+    let s = q[0] * e[1] - q[1] * e[0];
+    let q = if s < 0.0 { [-q[0], -q[1]] } else { q };
+    let v = [q[0] - wi, q[1]];
+    let t = (dot2(v, e) / dot2(e, e)).clamp(-1.0, 1.0);
+    let v = [v[0] - e[0] * t, v[1] - e[1] * t];
+    let d = [d[0].min(dot2(v, v)), d[1].min(wi * he - s.abs())];
+    d[0].sqrt() * sgn(-d[1])
+}
+
+/// Arch: a `2w`-wide slot from y = -h up to y = 0, capped by a half disc.
+pub fn sd2_tunnel(p: [f32; 2], w: f32, h: f32) -> f32 {
+    let (px, py) = (p[0].abs(), -p[1]);
+    let qy = py - h;
+    let d1 = dot2([(px - w).max(0.0), qy], [(px - w).max(0.0), qy]);
+    let qx = if py > 0.0 { px - w } else { len2(px, py) - w };
+    let d2 = qx * qx + qy.max(0.0) * qy.max(0.0);
+    let d = d1.min(d2).sqrt();
+    if qx.max(qy) < 0.0 { -d } else { d }
+}
+
+/// Capsule from radius `r1` at y = -h/2 to `r2` at y = h/2.
+pub fn sd2_uneven_capsule(p: [f32; 2], r1: f32, r2: f32, h: f32) -> f32 {
+    let q = [p[0].abs(), p[1] + h * 0.5];
+    let b = ((r1 - r2) / h).clamp(-1.0, 1.0);
+    let a = (1.0 - b * b).sqrt();
+    let k = -b * q[0] + a * q[1];
+    if k < 0.0 { len2(q[0], q[1]) - r1 } else if k > a * h { len2(q[0], q[1] - h) - r2 } else { a * q[0] + b * q[1] - r1 }
+}
+
+pub fn sd2_arc(p: [f32; 2], angle: f32, ra: f32, rb: f32) -> f32 {
+    let (s, c) = angle.sin_cos();
+    let q = [p[0].abs(), p[1]];
+    (if c * q[0] > s * q[1] { len2(q[0] - s * ra, q[1] - c * ra) } else { (len2(q[0], q[1]) - ra).abs() }) - rb
+}
+
+pub fn sd2_moon(p: [f32; 2], ra: f32, rb: f32, d: f32) -> f32 {
+    let q = [p[0], p[1].abs()];
+    let a = (ra * ra - rb * rb + d * d) / (2.0 * d);
+    let b = (ra * ra - a * a).max(0.0).sqrt();
+    if d * (q[0] * b - q[1] * a) > d * d * (b - q[1]).max(0.0) { len2(q[0] - a, q[1] - b) }
+    else { (len2(q[0], q[1]) - ra).max(-(len2(q[0] - d, q[1]) - rb)) }
+}
+
+pub fn sd2_cross(p: [f32; 2], bx: f32, by: f32) -> f32 {
+    let (a, b) = (p[0].abs(), p[1].abs());
+    let q = if b > a { [b - bx, a - by] } else { [a - bx, b - by] };
+    let k = q[0].max(q[1]);
+    let w = if k > 0.0 { q } else { [by - if b > a { b } else { a }, -k] };
+    sgn(k) * len2(w[0].max(0.0), w[1].max(0.0))
+}
+
+pub fn sd2_blobby_cross(p: [f32; 2], size: f32, he: f32) -> f32 {
+    let (a, b) = ((p[0] / size).abs(), (p[1] / size).abs());
+    let q = [(a - b).abs() * FRAC_1_SQRT_2, (1.0 - a - b) * FRAC_1_SQRT_2];
+    let pp = (he - q[1] - 0.25 / he) / (6.0 * he);
+    let qq = q[0] / (he * he * 16.0);
+    let h = qq * qq - pp * pp * pp;
+    let x = if h > 0.0 {
+        let r = h.sqrt();
+        (qq + r).cbrt() - (qq - r).abs().cbrt() * sgn(r - qq)
+    } else {
+        let r = pp.sqrt();
+        2.0 * r * ((qq / (pp * r)).clamp(-1.0, 1.0).acos() / 3.0).cos()
+    };
+    let x = x.min(FRAC_1_SQRT_2);
+    let z = [x - q[0], he * (1.0 - 2.0 * x * x) - q[1]];
+    len2(z[0], z[1]) * sgn(z[1]) * size
+}
+
+/// Region under the parabola y = he·(1 - (x/wi)²), above y = 0.
+pub fn sd2_parabola_segment(p: [f32; 2], wi: f32, he: f32) -> f32 {
+    let (px, py) = (p[0].abs(), p[1]);
+    if py < 0.0 { return len2((px - wi).max(0.0), py); }
+    let ik = wi * wi / he;
+    let pp = ik * (he - py - 0.5 * ik) / 3.0;
+    let q = px * ik * ik * 0.25;
+    let h = q * q - pp * pp * pp;
+    let r = h.abs().sqrt();
+    let x = if h > 0.0 { (q + r).cbrt() - (q - r).abs().cbrt() * sgn(r - q) } else { 2.0 * (r.atan2(q) / 3.0).cos() * pp.sqrt() };
+    let x = x.min(wi);
+    (len2(px - x, py - (he - x * x / ik)) * sgn(ik * (py - he) + px * px)).max(-py)
+}
+
+/// `n` steps of `w` × `h` climbing from the origin towards +X+Y.
+pub fn sd2_stairs(p: [f32; 2], w: f32, h: f32, n: f32) -> f32 {
+    let ba = [w * n, h * n];
+    let d2 = |v: [f32; 2]| dot2(v, v);
+    let mut d = d2([p[0] - p[0].clamp(0.0, ba[0]), p[1]]).min(d2([p[0] - ba[0], p[1] - p[1].clamp(0.0, ba[1])]));
+    let mut s = sgn((-p[1]).max(p[0] - ba[0]));
+    let dia = len2(w, h);
+    let q = [(w * p[0] + h * p[1]) / dia, (-h * p[0] + w * p[1]) / dia];
+    let id = rnd(q[0] / dia).clamp(0.0, n - 1.0);
+    let q0 = q[0] - id * dia;
+    let q = [(w * q0 - h * q[1]) / dia, (h * q0 + w * q[1]) / dia];
+    let hh = h * 0.5;
+    let q = [q[0], q[1] - hh];
+    if q[1] > hh * sgn(q[0]) { s = 1.0; }
+    let q = if id < 0.5 || q[0] > 0.0 { q } else { [-q[0], -q[1]] };
+    d = d.min(d2([q[0], q[1] - q[1].clamp(-hh, hh)]));
+    d = d.min(d2([q[0] - q[0].clamp(0.0, w), q[1] - hh]));
+    d.sqrt() * s
+}
+
+pub fn sd2_segment(p: [f32; 2], a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (pa, ba) = ([p[0] - a[0], p[1] - a[1]], [b[0] - a[0], b[1] - a[1]]);
+    let h = clamp01(dot2(pa, ba) / dot2(ba, ba).max(1e-12));
+    len2(pa[0] - ba[0] * h, pa[1] - ba[1] * h)
+}
+
+/// Closed polygon, either winding; self-intersections use the even-odd rule.
+pub fn sd2_polygon(p: [f32; 2], v: &[[f32; 2]]) -> f32 {
+    let mut d = dot2([p[0] - v[0][0], p[1] - v[0][1]], [p[0] - v[0][0], p[1] - v[0][1]]);
+    let mut s = 1.0;
+    for i in 0..v.len() {
+        let (vi, vj) = (v[i], v[(i + v.len() - 1) % v.len()]);
+        let (e, w) = ([vj[0] - vi[0], vj[1] - vi[1]], [p[0] - vi[0], p[1] - vi[1]]);
+        let h = clamp01(dot2(w, e) / dot2(e, e).max(1e-12));
+        d = d.min(dot2([w[0] - e[0] * h, w[1] - e[1] * h], [w[0] - e[0] * h, w[1] - e[1] * h]));
+        let c = [p[1] >= vi[1], p[1] < vj[1], e[0] * w[1] > e[1] * w[0]];
+        if c.iter().all(|x| *x) || c.iter().all(|x| !*x) { s = -s; }
+    }
+    s * d.sqrt()
+}
+
+/// `n`-pointed star with a tip on +Y: fold into one half-sector, then one edge.
+pub fn sd2_star(p: [f32; 2], r: f32, ri: f32, n: f32) -> f32 {
+    let an = PI / n;
+    let a = p[0].atan2(p[1]);
+    let a = (a - 2.0 * an * rnd(a / (2.0 * an))).abs();
+    let l = len2(p[0], p[1]);
+    let w = [l * a.cos() - r, l * a.sin()];
+    let e = [ri * an.cos() - r, ri * an.sin()];
+    let h = clamp01(dot2(w, e) / dot2(e, e).max(1e-12));
+    let d = len2(w[0] - e[0] * h, w[1] - e[1] * h);
+    if e[0] * w[1] - e[1] * w[0] > 0.0 { -d } else { d }
+}
+
+pub fn prim(pr: &Prim, p: V3) -> f32 {
+    use Prim::*;
+    let xy = [p[0], p[1]];
+    match *pr {
+        Sphere(r) => sd_sphere(p, r),
+        Box3d(h) => sd_box(p, h),
+        Cylinder(r, h) => sd_cylinder(p, r, h),
+        Torus(a, b) => sd_torus(p, a, b),
+        Plane(n, h) => math::dot(p, n) + h,
+        Capsule(r, h) => sd_capsule(p, r, h),
+        Cone(r, h) => sd_cone(p, r, h),
+        RoundedBox(h, r) => sd_rounded_box(p, h, r),
+        Ellipsoid(r) => sd_ellipsoid(p, r),
+        Pyramid(h, b) => sd_pyramid(p, h, b),
+        Octahedron(s) => sd_octahedron(p, s),
+        Tetrahedron(s) => sd_tetrahedron(p, s),
+        RoundedCone(r1, r2, h) => sd_round_cone(p, r1, r2, h),
+        HexPrism(r, hd) => sd_hex_prism(p, r, hd),
+        Link(le, r1, r2) => sd_link(p, le, r1, r2),
+        Triangle(a, b, c, t) => sd_triangle(p, a, b, c, t),
+        Bezier(a, b, c, r) => sd_bezier(p, a, b, c, r),
+        CappedCone(h, r1, r2) => sd_capped_cone(p, h, r1, r2),
+        CappedTorus(an, ra, rb) => sd_capped_torus(p, an, ra, rb),
+        RoundedCylinder(r, rb, h) => sd_rounded_cylinder(p, r, rb, h),
+        TriangularPrism(s, hd) => sd_tri_prism(p, s, hd),
+        CutSphere(r, h) => sd_cut_sphere(p, r, h),
+        CutHollowSphere(r, h, t) => sd_cut_hollow_sphere(p, r, h, t),
+        DeathStar(ra, rb, d) => sd_death_star(p, ra, rb, d),
+        SolidAngle(an, r) => sd_solid_angle(p, an, r),
+        Rhombus(la, lb, h, ra) => sd_rhombus(p, la, lb, h, ra),
+        Vesica(r, d) => sd_vesica(p, r, d),
+        Egg(ra, rb) => sd_egg(p, ra, rb),
+        Tube(r, t, h) => sd_tube(p, r, t, h),
+        Barrel(r, h, b) => sd_barrel(p, r, h, b),
+        ChamferedCube(h, c) => sd_chamfered_cube(p, h, c),
+        Superellipsoid(r, e1, e2) => sd_superellipsoid(p, r, e1, e2),
+        Helix(big, t, pitch, h) => sd_helix(p, big, t, pitch, h),
+        BoxFrame(h, e) => sd_box_frame(p, h, e),
+        InfiniteCylinder(r) => len2(p[0], p[2]) - r,
+        InfiniteCone(an) => sd_infinite_cone(p, an),
+        Gdf(b, e, r) => sd_gdf(p, b, e, r),
+        Tpms(k, s, t) => sd_tpms(k, p, s, t),
+        Horseshoe(an, r, le, th, hd) => extrude(sd2_horseshoe(xy, an, r, le, th), p[2], hd),
+        Heart(s, hd) => extrude(sd2_heart(xy, s), p[2], hd),
+        RoundedX(w, r, hd) => extrude(sd2_rounded_x(xy, w, r), p[2], hd),
+        Pie(an, r, hd) => extrude(sd2_pie(xy, an, r), p[2], hd),
+        Trapezoid(r1, r2, he, hd) => extrude(sd2_trapezoid(xy, r1, r2, he), p[2], hd),
+        Parallelogram(wi, he, sk, hd) => extrude(sd2_parallelogram(xy, wi, he, sk), p[2], hd),
+        Tunnel(w, h, hd) => extrude(sd2_tunnel(xy, w, h), p[2], hd),
+        UnevenCapsule(r1, r2, h, hd) => extrude(sd2_uneven_capsule(xy, r1, r2, h), p[2], hd),
+        ArcShape(an, ra, rb, hd) => extrude(sd2_arc(xy, an, ra, rb), p[2], hd),
+        Moon(ra, rb, d, hd) => extrude(sd2_moon(xy, ra, rb, d), p[2], hd),
+        CrossShape(bx, by, hd) => extrude(sd2_cross(xy, bx, by), p[2], hd),
+        BlobbyCross(s, he, hd) => extrude(sd2_blobby_cross(xy, s, he), p[2], hd),
+        ParabolaSegment(wi, he, hd) => extrude(sd2_parabola_segment(xy, wi, he), p[2], hd),
+        Stairs(w, h, n, hd) => extrude(sd2_stairs(xy, w, h, n), p[2], hd),
+        Circle2d(r) => len2(p[0], p[1]) - r,
+        Rect2d(w, h, r) => { let q = [p[0].abs() - w + r, p[1].abs() - h + r]; q[0].max(q[1]).min(0.0) + len2(q[0].max(0.0), q[1].max(0.0)) - r }
+        Segment2d(a, b, t) => sd2_segment(xy, a, b) - t,
+        Polygon2d(ref v) => sd2_polygon(xy, v),
+        Annular2d(r, t) => (len2(p[0], p[1]) - r).abs() - t,
+        Star(r, ri, n) => sd2_star(xy, r, ri, n),
+        Mesh(ref m) => m.distance(p),
+    }
+}
+
+// ── operations ──────────────────────────────────────────────────────────────
+
+pub fn smin(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 { return a.min(b); }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+pub fn smax(a: f32, b: f32, k: f32) -> f32 { -smin(-a, -b, k) }
+
+/// Exponential smooth minimum, shifted by the hard minimum so it cannot overflow.
+pub fn exp_smin(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 { return a.min(b); }
+    let m = a.min(b);
+    m - k * ((-(a - m) / k).exp() + (-(b - m) / k).exp()).ln()
+}
+
+/// hg_sdf `fOpUnionStairs`: `n` steps across a blend of radius `r`.
+pub fn stairs_union(a: f32, b: f32, r: f32, n: f32) -> f32 {
+    if r <= 0.0 { return a.min(b); }
+    let s = r / n;
+    let u = b - r;
+    let m = u - a + s;
+    let md = m - 2.0 * s * (m / (2.0 * s)).floor();
+    a.min(b).min(0.5 * (u + a + (md - s).abs()))
+}
+
+fn mod_centered(x: f32, size: f32) -> f32 { let h = size * 0.5; (x + h) - size * ((x + h) / size).floor() - h }
+
+/// hg_sdf `fOpUnionColumns`.
+pub fn columns_union(a: f32, b: f32, r: f32, n: f32) -> f32 {
+    if r <= 0.0 || !(a < r && b < r) { return a.min(b); }
+    let cr = r * SQRT_2 / ((n - 1.0) * 2.0 + SQRT_2);
+    let mut p = [(a + b) * FRAC_1_SQRT_2, (b - a) * FRAC_1_SQRT_2];
+    p[0] += cr * SQRT_2 - SQRT_2 * 0.5 * r;
+    if n as i32 % 2 == 1 { p[1] += cr; }
+    p[1] = mod_centered(p[1], cr * 2.0);
+    (len2(p[0], p[1]) - cr).min(p[0]).min(a).min(b)
+}
+
+/// hg_sdf `fOpDifferenceColumns`: `a` minus `b`.
+pub fn columns_subtraction(a: f32, b: f32, r: f32, n: f32) -> f32 {
+    let a = -a;
+    if r <= 0.0 || !(a < r && b < r) { return -a.min(b); }
+    let cr = r * SQRT_2 / ((n - 1.0) * 2.0 + SQRT_2);
+    let mut p = [(a + b) * FRAC_1_SQRT_2, (b - a) * FRAC_1_SQRT_2];
+    p[1] += cr;
+    p[0] -= SQRT_2 * 0.5 * r + cr * SQRT_2 * 0.5;
+    if n as i32 % 2 == 1 { p[1] += cr; }
+    p[1] = mod_centered(p[1], cr * 2.0);
+    -(cr - len2(p[0], p[1])).max(p[0]).min(a).min(b)
+}
+
+pub fn op(o: Op, a: f32, b: f32) -> f32 {
+    use Op::*;
+    match o {
+        Union => a.min(b),
+        Intersection => a.max(b),
+        Subtraction => a.max(-b),
+        SmoothUnion(k) => smin(a, b, k),
+        SmoothIntersection(k) => smax(a, b, k),
+        SmoothSubtraction(k) => smax(a, -b, k),
+        ChamferUnion(r) => a.min(b).min((a + b - r) * FRAC_1_SQRT_2),
+        ChamferIntersection(r) => a.max(b).max((a + b + r) * FRAC_1_SQRT_2),
+        ChamferSubtraction(r) => a.max(-b).max((a - b + r) * FRAC_1_SQRT_2),
+        StairsUnion(r, n) => stairs_union(a, b, r, n),
+        StairsIntersection(r, n) => -stairs_union(-a, -b, r, n),
+        StairsSubtraction(r, n) => -stairs_union(-a, b, r, n),
+        ExpSmoothUnion(k) => exp_smin(a, b, k),
+        ExpSmoothIntersection(k) => -exp_smin(-a, -b, k),
+        ExpSmoothSubtraction(k) => -exp_smin(-a, b, k),
+        ColumnsUnion(r, n) => columns_union(a, b, r, n),
+        ColumnsIntersection(r, n) => columns_subtraction(a, -b, r, n),
+        ColumnsSubtraction(r, n) => columns_subtraction(a, b, r, n),
+        Xor => a.min(b).max(-a.max(b)),
+        Morph(t) => math::lerp(a, b, t),
+        Pipe(r) => len2(a, b) - r,
+        Engrave(r) => a.max((a + r - b.abs()) * FRAC_1_SQRT_2),
+        Groove(ra, rb) => a.max((a + ra).min(rb - b.abs())),
+        Tongue(ra, rb) => a.min((a - ra).max(b.abs() - rb)),
+    }
+}
+
+// ── point and distance ops ──────────────────────────────────────────────────
+
+/// Icosahedral fold plane normal; five reflections map any point into one fundamental domain.
+pub const ICOSA_FOLD: V3 = [-0.5, -0.809_017, 0.309_017];
+
+fn lattice_offset(l: &Lattice, p: V3) -> V3 {
+    let [nx, ny, _] = l.divisions;
+    let cell = |i: usize| {
+        let t = ((p[i] - l.min[i]) / (l.max[i] - l.min[i])).clamp(0.0, 1.0) * (l.divisions[i] - 1) as f32;
+        let k = (t.floor() as usize).min(l.divisions[i] - 2);
+        (k, t - k as f32)
+    };
+    let ((ix, fx), (iy, fy), (iz, fz)) = (cell(0), cell(1), cell(2));
+    let at = |x: usize, y: usize, z: usize| l.offsets[(z * ny + y) * nx + x];
+    let mut o = [0.0; 3];
+    for (dz, wz) in [(0, 1.0 - fz), (1, fz)] { for (dy, wy) in [(0, 1.0 - fy), (1, fy)] { for (dx, wx) in [(0, 1.0 - fx), (1, fx)] {
+        o = math::add(o, math::mul(at(ix + dx, iy + dy, iz + dz), wx * wy * wz));
+    }}}
+    o
+}
+
+fn point_op(o: &PointOp, p: V3) -> V3 {
+    match *o {
+        PointOp::Translate(o) => math::sub(p, o),
+        PointOp::Rotate(ref m) => math::mat_vec(m, p),
+        PointOp::Scale(s) => math::mul(p, 1.0 / s),
+        PointOp::ScaleNonUniform(f) => math::div3(p, f),
+        PointOp::Twist(k) => { let (s, c) = (k * p[1]).sin_cos(); [c * p[0] - s * p[2], p[1], s * p[0] + c * p[2]] }
+        PointOp::Bend(k) => { let (s, c) = (k * p[0]).sin_cos(); [c * p[0] - s * p[1], s * p[0] + c * p[1], p[2]] }
+        PointOp::Repeat(s) => {
+            let r = |x: f32, s: f32| if s > 0.0 { x - s * rnd(x / s) } else { x };
+            [r(p[0], s[0]), r(p[1], s[1]), r(p[2], s[2])]
+        }
+        PointOp::RepeatFinite(s, n) => {
+            let r = |x: f32, s: f32, n: f32| {
+                if s <= 0.0 || n < 1.0 { return x; }
+                let c = (n.round() - 1.0) * 0.5;
+                x - s * (rnd(x / s + c).clamp(0.0, n.round() - 1.0) - c)
+            };
+            [r(p[0], s[0], n[0]), r(p[1], s[1], n[1]), r(p[2], s[2], n[2])]
+        }
+        PointOp::Mirror(m) => [if m[0] { p[0].abs() } else { p[0] }, if m[1] { p[1].abs() } else { p[1] }, if m[2] { p[2].abs() } else { p[2] }],
+        PointOp::PolarRepeat(n, r) => {
+            let sector = TAU / n;
+            let a = p[2].atan2(p[0]);
+            let a = a - sector * rnd(a / sector);
+            let l = len2(p[0], p[2]);
+            [a.cos() * l - r, p[1], a.sin() * l]
+        }
+        PointOp::Projective(ref m) => {
+            let r = |i: usize| m[i] * p[0] + m[i + 1] * p[1] + m[i + 2] * p[2] + m[i + 3];
+            let w = r(12);
+            let w = if w.abs() < 1e-6 { 1e-6f32.copysign(w) } else { w };
+            [r(0) / w, r(4) / w, r(8) / w]
+        }
+        PointOp::Lattice(ref l) => math::sub(p, lattice_offset(l, p)),
+        PointOp::Skin(ref bones) => {
+            // A small identity weight keeps points far from every bone where they are.
+            let (mut acc, mut wsum) = (math::mul(p, 1e-4), 1e-4);
+            for b in bones.iter() {
+                let c = math::add(b.pivot, b.offset);
+                let d = math::sub(p, c);
+                let w = (-math::dot(d, d) / (b.radius * b.radius)).exp();
+                acc = math::add(acc, math::mul(math::add(math::mat_vec(&b.rot, d), b.pivot), w));
+                wsum += w;
+            }
+            math::mul(acc, 1.0 / wsum)
+        }
+        PointOp::Ifs(n, s, o) => {
+            let mut p = p;
+            for _ in 0..n {
+                p = math::abs(p);
+                if p[0] < p[1] { p.swap(0, 1); }
+                if p[0] < p[2] { p.swap(0, 2); }
+                if p[1] < p[2] { p.swap(1, 2); }
+                p = math::sub(math::mul(p, s), math::mul(o, s - 1.0));
+                if p[2] < -0.5 * o[2] * (s - 1.0) { p[2] += o[2] * (s - 1.0); }
+            }
+            p
+        }
+        PointOp::Icosahedral => {
+            let mut p = p;
+            for _ in 0..5 {
+                p[0] = p[0].abs(); p[1] = p[1].abs();
+                let t = math::dot(p, ICOSA_FOLD);
+                if t < 0.0 { p = math::sub(p, math::mul(ICOSA_FOLD, 2.0 * t)); }
+            }
+            p
+        }
+        PointOp::Taper(k) => { let s = (1.0 + k * p[1]).max(0.05); [p[0] / s, p[1], p[2] / s] }
+        PointOp::Revolve(o) => [len2(p[0], p[2]) - o, p[1], 0.0],
+        PointOp::Sweep(ref s) => {
+            let t = bezier_t(p, s.a, s.b, s.c);
+            let tan = math::normalize(math::add(math::sub(s.b, s.a), math::mul(math::add(math::sub(s.a, math::mul(s.b, 2.0)), s.c), t)));
+            let d = math::sub(p, bezier_pos(s.a, s.b, s.c, t));
+            [math::dot(d, math::cross(tan, s.up)), math::dot(d, s.up), (t - 0.5) * s.len + math::dot(d, tan)]
+        }
+    }
+}
+
+fn heightmap(h: &Heightmap, x: f32, z: f32) -> f32 {
+    let g = |v: f32, size: f32, n: usize| ((v / size + 0.5).clamp(0.0, 1.0) * (n - 1) as f32).min((n - 1) as f32 - 1e-4);
+    let (u, v) = (g(x, h.size[0], h.cols), g(z, h.size[1], h.rows));
+    let (i, j) = (u.floor() as usize, v.floor() as usize);
+    let (fu, fv) = (u - i as f32, v - j as f32);
+    let at = |i: usize, j: usize| h.data[j * h.cols + i];
+    math::lerp(math::lerp(at(i, j), at(i + 1, j), fu), math::lerp(at(i, j + 1), at(i + 1, j + 1), fu), fv)
+}
+
+fn post(o: &Post, d: f32, p: V3) -> f32 {
+    match *o {
+        Post::MulDist(s) => d * s,
+        Post::Noise(a, f, seed) => d + a * noise::noise3(math::mul(p, f), seed),
+        Post::Shell(t) => d.max(-d - t),
+        Post::Onion(t) => d.abs() - t,
+        Post::Displacement(a, f) => d + a * (f * p[0]).sin() * (f * p[1]).sin() * (f * p[2]).sin(),
+        Post::Heightmap(ref h) => d - h.amplitude * heightmap(h, p[0], p[2]),
+        Post::Roughness(a, f, oct, seed) => d + a * noise::fbm(math::mul(p, f), oct, seed),
+        Post::Taper(k) => {
+            let s = (1.0 + k * p[1]).max(0.05);
+            // Bound the spectral norm of the warp's Jacobian by its row norms.
+            let r = len2(p[0], p[2]) / s;
+            d / ((1.0 / (s * s)).max(1.0) + k * k * r * r / (s * s)).sqrt()
+        }
+        Post::Extrude(h) => extrude(d, p[2], h),
+    }
+}
+
+impl CompiledSdf {
+    pub fn eval(&self, p: V3) -> f32 {
+        let mut vs = Vec::with_capacity(self.max_values);
+        let mut ps = Vec::with_capacity(self.max_points);
+        self.eval_with(p, &mut vs, &mut ps)
+    }
+
+    pub fn eval_with(&self, p: V3, vs: &mut Vec<f32>, ps: &mut Vec<V3>) -> f32 {
+        vs.clear(); ps.clear(); ps.push(p);
+        for i in &self.code {
+            let p = *ps.last().unwrap();
+            match i {
+                Inst::Prim(pr) => vs.push(prim(pr, p)),
+                Inst::Op(o) => { let b = vs.pop().unwrap(); let a = vs.pop().unwrap(); vs.push(op(*o, a, b)); }
+                Inst::Push(o) => ps.push(point_op(o, p)),
+                Inst::PopPoint => { ps.pop(); }
+                Inst::Post(o) => { let d = vs.last_mut().unwrap(); *d = post(o, *d, p); }
+            }
+        }
+        vs.pop().unwrap_or(f32::INFINITY)
+    }
+
+    /// Distance plus the material of the surface that decides it.
+    pub fn eval_material_with(&self, p: V3, vs: &mut Vec<f32>, ps: &mut Vec<V3>, ms: &mut Vec<Sample>) -> (f32, Sample) {
+        vs.clear(); ps.clear(); ms.clear(); ps.push(p);
+        let mut leaf = 0;
+        for i in &self.code {
+            let p = *ps.last().unwrap();
+            match i {
+                Inst::Prim(pr) => {
+                    let id = self.leaf_materials[leaf];
+                    leaf += 1;
+                    vs.push(prim(pr, p));
+                    ms.push(Sample { id, value: self.materials[id as usize].pack() });
+                }
+                Inst::Op(o) => {
+                    let (b, a) = (vs.pop().unwrap(), vs.pop().unwrap());
+                    let (mb, ma) = (ms.pop().unwrap(), ms.pop().unwrap());
+                    vs.push(op(*o, a, b));
+                    ms.push(Sample::mix(&ma, &mb, material::weight(*o, a, b)));
+                }
+                Inst::Push(o) => ps.push(point_op(o, p)),
+                Inst::PopPoint => { ps.pop(); }
+                Inst::Post(o) => { let d = vs.last_mut().unwrap(); *d = post(o, *d, p); }
+            }
+        }
+        let d = vs.pop().unwrap_or(f32::INFINITY);
+        (d, ms.pop().unwrap_or(Sample { id: 0, value: self.materials[0].pack() }))
+    }
+
+    pub fn eval_material_batch(&self, points: &[V3]) -> Vec<(f32, Sample)> {
+        points.par_chunks(1024).flat_map_iter(|chunk| {
+            let (mut vs, mut ps, mut ms) = (Vec::with_capacity(self.max_values), Vec::with_capacity(self.max_points), Vec::with_capacity(self.max_values));
+            chunk.iter().map(|&p| self.eval_material_with(p, &mut vs, &mut ps, &mut ms)).collect::<Vec<_>>()
+        }).collect()
+    }
+
+    pub fn eval_batch(&self, points: &[V3]) -> Vec<f32> {
+        points.par_chunks(1024).flat_map_iter(|chunk| {
+            let mut vs = Vec::with_capacity(self.max_values);
+            let mut ps = Vec::with_capacity(self.max_points);
+            chunk.iter().map(|&p| self.eval_with(p, &mut vs, &mut ps)).collect::<Vec<_>>()
+        }).collect()
+    }
+}
+
+/// A distance field the mesher and batch callers can sample: this interpreter,
+/// or native code from the `jit` module.
+pub trait Field: Sync {
+    fn eval(&self, p: V3) -> f32;
+    fn eval_batch(&self, points: &[V3]) -> Vec<f32>;
+}
+
+impl Field for CompiledSdf {
+    fn eval(&self, p: V3) -> f32 { CompiledSdf::eval(self, p) }
+    fn eval_batch(&self, points: &[V3]) -> Vec<f32> { CompiledSdf::eval_batch(self, points) }
+}
@@ -0,0 +1,125 @@
+//! BVH-pruned evaluation of large unions.
+//!
+//! A tree whose root is a hard union, possibly under translations, rotations and
+//! scales, is split into its parts, each compiled on its own with its bounds.
+//! Points walk a BVH over those bounds nearest first and skip every part whose
+//! box lies beyond the best distance so far. A box's signed distance is a lower
+//! bound on an exact field inside it, so for exact fields the result is the
+//! interpreter's; bound fields may read larger where a part was skipped, but
+//! never with the other sign. Unbounded parts are always evaluated.
+
+use crate::bounds::{self, Aabb};
+use crate::compiler::{self, CompiledSdf};
+use crate::eval::{sd_box, Field};
+use crate::math::{self, V3};
+use crate::tree::SdfNode;
+use rayon::prelude::*;
+
+/// Parts per BVH leaf.
+const LEAF: usize = 4;
+
+/// Below this many parts, a BVH does not pay for its walk.
+const MIN_PARTS: usize = 8;
+
+struct Part { sdf: CompiledSdf, bounds: Aabb }
+
+/// Inner nodes point at two nodes, leaves at a run of `order`.
+enum Kind { Inner(usize, usize), Leaf(usize, usize) }
+
+struct Node { bounds: Aabb, kind: Kind }
+
+pub struct BvhSdf { parts: Vec<Part>, order: Vec<usize>, nodes: Vec<Node>, always: Vec<usize>, max_values: usize, max_points: usize }
+
+/// Union operands of `n`, with transforms over a union pushed down onto its operands.
+fn union_parts(n: &SdfNode, out: &mut Vec<SdfNode>) {
+    match n.ty.as_str() {
+        "Union" => for c in &n.children { union_parts(c, out) },
+        "Translate" | "RotateEuler" | "RotateQuat" | "Scale" | "ScaleNonUniform" if n.children.len() == 1 => {
+            let mut inner = Vec::new();
+            union_parts(&n.children[0], &mut inner);
+            out.extend(inner.into_iter().map(|c| SdfNode { children: vec![c], ..n.clone() }));
+        }
+        _ => out.push(n.clone()),
+    }
+}
+
+/// Signed distance to `b`: a lower bound on any exact field whose inside `b` holds.
+fn box_sd(p: V3, b: &Aabb) -> f32 { sd_box(math::sub(p, b.center()), math::mul(b.size(), 0.5)) }
+
+impl BvhSdf {
+    pub fn new(tree: &SdfNode) -> Result<BvhSdf, String> {
+        let mut nodes = Vec::new();
+        union_parts(tree, &mut nodes);
+        let parts = nodes.iter().map(|n| Ok(Part { sdf: compiler::compile(n)?, bounds: bounds::bounds(n) })).collect::<Result<Vec<_>, String>>()?;
+        let (always, mut order): (Vec<usize>, Vec<usize>) = (0..parts.len()).partition(|&i| !parts[i].bounds.is_finite() || parts[i].bounds.is_empty());
+        let mut bvh = BvhSdf {
+            max_values: parts.iter().map(|p| p.sdf.max_values).max().unwrap_or(0), max_points: parts.iter().map(|p| p.sdf.max_points).max().unwrap_or(1),
+            parts, order: Vec::new(), nodes: Vec::new(), always,
+        };
+        if !order.is_empty() { bvh.build(&mut order, 0); }
+        bvh.order = order;
+        Ok(bvh)
+    }
+
+    /// Number of separately compiled parts.
+    pub fn parts(&self) -> usize { self.parts.len() }
+
+    /// A BVH for `tree` when it has enough parts to be worth walking, as when meshing large scenes.
+    pub fn for_large(tree: &SdfNode) -> Option<BvhSdf> { BvhSdf::new(tree).ok().filter(|b| b.parts() >= MIN_PARTS) }
+
+    /// Node over `order[..]`, which starts at `at` in the final order; split at
+    /// the median along the widest axis of the part centres.
+    fn build(&mut self, order: &mut [usize], at: usize) -> usize {
+        let bounds = order.iter().fold(Aabb::EMPTY, |b, &i| b.union(&self.parts[i].bounds));
+        let id = self.nodes.len();
+        self.nodes.push(Node { bounds, kind: Kind::Leaf(at, at + order.len()) });
+        if order.len() <= LEAF { return id; }
+        let centres = order.iter().fold(Aabb::EMPTY, |b, &i| { let c = self.parts[i].bounds.center(); b.union(&Aabb { min: c, max: c }) });
+        let s = centres.size();
+        let axis = if s[0] >= s[1] && s[0] >= s[2] { 0 } else if s[1] >= s[2] { 1 } else { 2 };
+        let mid = order.len() / 2;
+        order.select_nth_unstable_by(mid, |&a, &b| self.parts[a].bounds.center()[axis].total_cmp(&self.parts[b].bounds.center()[axis]));
+        let (lo, hi) = order.split_at_mut(mid);
+        let l = self.build(lo, at);
+        let r = self.build(hi, at + mid);
+        self.nodes[id].kind = Kind::Inner(l, r);
+        id
+    }
+
+    pub fn eval(&self, p: V3) -> f32 {
+        let (mut vs, mut ps, mut stack) = (Vec::with_capacity(self.max_values), Vec::with_capacity(self.max_points), Vec::new());
+        self.eval_with(p, &mut vs, &mut ps, &mut stack)
+    }
+
+    pub fn eval_with(&self, p: V3, vs: &mut Vec<f32>, ps: &mut Vec<V3>, stack: &mut Vec<usize>) -> f32 {
+        let mut best = self.always.iter().fold(f32::INFINITY, |d, &i| d.min(self.parts[i].sdf.eval_with(p, vs, ps)));
+        if self.nodes.is_empty() { return best; }
+        stack.clear();
+        stack.push(0);
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if box_sd(p, &node.bounds) >= best { continue; }
+            match node.kind {
+                Kind::Leaf(a, b) => for &i in &self.order[a..b] {
+                    let part = &self.parts[i];
+                    if box_sd(p, &part.bounds) < best { best = best.min(part.sdf.eval_with(p, vs, ps)); }
+                },
+                // Nearer child on top, so it can tighten `best` before the other is tested.
+                Kind::Inner(l, r) => if box_sd(p, &self.nodes[l].bounds) <= box_sd(p, &self.nodes[r].bounds) { stack.extend([r, l]) } else { stack.extend([l, r]) },
+            }
+        }
+        best
+    }
+
+    pub fn eval_batch(&self, points: &[V3]) -> Vec<f32> {
+        points.par_chunks(1024).flat_map_iter(|chunk| {
+            let (mut vs, mut ps, mut stack) = (Vec::with_capacity(self.max_values), Vec::with_capacity(self.max_points), Vec::new());
+            chunk.iter().map(|&p| self.eval_with(p, &mut vs, &mut ps, &mut stack)).collect::<Vec<_>>()
+        }).collect()
+    }
+}
+
+impl Field for BvhSdf {
+    fn eval(&self, p: V3) -> f32 { BvhSdf::eval(self, p) }
+    fn eval_batch(&self, points: &[V3]) -> Vec<f32> { BvhSdf::eval_batch(self, points) }
+}
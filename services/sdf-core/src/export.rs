@@ -0,0 +1,254 @@
+//! Mesh encoders. Text formats return `String`, binary formats `Vec<u8>`.
+
+use crate::mesh::Mesh;
+use serde_json::json;
+
+/// Wavefront OBJ. Painted meshes reference `{name}.mtl` (see [`to_mtl`]) and
+/// group their faces by material.
+pub fn to_obj(name: &str, m: &Mesh) -> String {
+    let mut s = format!("# AI Modeler - {name}\n# V:{} F:{}\n", m.vertex_count(), m.face_count());
+    if !m.face_materials.is_empty() { s += &format!("mtllib {name}.mtl\n"); }
+    s += &format!("o {name}\n");
+    for v in &m.positions { s += &format!("v {:.6} {:.6} {:.6}\n", v[0], v[1], v[2]); }
+    for n in &m.normals { s += &format!("vn {:.6} {:.6} {:.6}\n", n[0], n[1], n[2]); }
+    let face = |f: &[u32; 3]| format!("f {0}//{0} {1}//{1} {2}//{2}\n", f[0] + 1, f[1] + 1, f[2] + 1);
+    if m.face_materials.is_empty() {
+        for f in &m.indices { s += &face(f); }
+        return s;
+    }
+    for id in m.used_materials() {
+        s += &format!("usemtl material_{id}\n");
+        for (f, _) in m.indices.iter().zip(&m.face_materials).filter(|(_, &fm)| fm == id) { s += &face(f); }
+    }
+    s
+}
+
+/// Material library for a painted OBJ, with the PBR extension keys (Pm, Pr, Ke).
+pub fn to_mtl(m: &Mesh) -> String {
+    let mut s = String::from("# AI Modeler materials\n");
+    for id in m.used_materials() {
+        let t = &m.materials[id as usize];
+        let (c, e) = (t.base_color, t.emissive);
+        s += &format!("newmtl material_{id}\nKd {:.6} {:.6} {:.6}\nKe {:.6} {:.6} {:.6}\nPm {:.6}\nPr {:.6}\nillum 2\n", c[0], c[1], c[2], e[0], e[1], e[2], t.metallic, t.roughness);
+    }
+    s
+}
+
+pub fn to_stl(name: &str, m: &Mesh) -> String {
+    let mut s = format!("solid {name}\n");
+    for f in &m.indices {
+        let [a, b, c] = f.map(|i| m.positions[i as usize]);
+        let n = crate::math::normalize(crate::math::cross(crate::math::sub(b, a), crate::math::sub(c, a)));
+        s += &format!("facet normal {:.6} {:.6} {:.6}\n outer loop\n", n[0], n[1], n[2]);
+        for v in [a, b, c] { s += &format!("  vertex {:.6} {:.6} {:.6}\n", v[0], v[1], v[2]); }
+        s += " endloop\nendfacet\n";
+    }
+    s + &format!("endsolid {name}\n")
+}
+
+/// ASCII PLY; painted meshes add `red`/`green`/`blue` vertex properties.
+pub fn to_ply(m: &Mesh) -> String {
+    let rgb = if m.colors.is_empty() { "" } else { "property uchar red\nproperty uchar green\nproperty uchar blue\n" };
+    let mut s = format!("ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\n{rgb}element face {}\nproperty list uchar int vertex_indices\nend_header\n", m.vertex_count(), m.face_count());
+    let byte = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+    for (i, (v, n)) in m.positions.iter().zip(&m.normals).enumerate() {
+        s += &format!("{:.6} {:.6} {:.6} {:.6} {:.6} {:.6}", v[0], v[1], v[2], n[0], n[1], n[2]);
+        if let Some(c) = m.colors.get(i) { s += &format!(" {} {} {}", byte(c[0]), byte(c[1]), byte(c[2])); }
+        s += "\n";
+    }
+    for f in &m.indices { s += &format!("3 {} {} {}\n", f[0], f[1], f[2]); }
+    s
+}
+
+/// glTF 2.0 binary with one scene node per object. Painted meshes get a
+/// `COLOR_0` attribute and one primitive per material; the material's
+/// `baseColorFactor` stays white because glTF multiplies it into `COLOR_0`,
+/// which already carries the blended color.
+pub fn to_glb(objects: &[(String, &Mesh)]) -> Vec<u8> { glb(objects, &[]) }
+
+/// Mesh sequence: one node per frame plus a step animation that shows frame `i`
+/// from `times[i]` until the next frame's time.
+pub fn to_glb_sequence(frames: &[(String, &Mesh)], times: &[f32]) -> Vec<u8> { glb(frames, times) }
+
+fn glb(objects: &[(String, &Mesh)], times: &[f32]) -> Vec<u8> {
+    let mut bin: Vec<u8> = Vec::new();
+    let (mut views, mut accessors, mut meshes, mut nodes, mut materials) = (vec![], vec![], vec![], vec![], vec![]);
+    for (name, m) in objects {
+        let mut view = |bytes: &[u8], target: u32| {
+            while bin.len() % 4 != 0 { bin.push(0); }
+            views.push(json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": bytes.len(), "target": target }));
+            bin.extend_from_slice(bytes);
+            views.len() - 1
+        };
+        let f32s = |vs: &[[f32; 3]]| vs.iter().flatten().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>();
+        let pv = view(&f32s(&m.positions), 34962);
+        let nv = view(&f32s(&m.normals), 34962);
+        let cv = (!m.colors.is_empty()).then(|| view(&f32s(&m.colors), 34962));
+        let u32s = |fs: &mut dyn Iterator<Item = &[u32; 3]>| fs.flatten().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>();
+        let groups: Vec<(Option<u32>, usize, usize)> = if m.face_materials.is_empty() {
+            vec![(None, view(&u32s(&mut m.indices.iter()), 34963), m.face_count())]
+        } else {
+            m.used_materials().into_iter().map(|id| {
+                let faces: Vec<&[u32; 3]> = m.indices.iter().zip(&m.face_materials).filter(|(_, &fm)| fm == id).map(|(f, _)| f).collect();
+                (Some(id), view(&u32s(&mut faces.iter().copied()), 34963), faces.len())
+            }).collect()
+        };
+        let (mut lo, mut hi) = ([f32::MAX; 3], [f32::MIN; 3]);
+        for p in &m.positions { for i in 0..3 { lo[i] = lo[i].min(p[i]); hi[i] = hi[i].max(p[i]); } }
+        if m.positions.is_empty() { lo = [0.0; 3]; hi = [0.0; 3]; }
+        let a = accessors.len();
+        accessors.push(json!({ "bufferView": pv, "componentType": 5126, "count": m.vertex_count(), "type": "VEC3", "min": lo, "max": hi }));
+        accessors.push(json!({ "bufferView": nv, "componentType": 5126, "count": m.normals.len(), "type": "VEC3" }));
+        let mut attributes = json!({ "POSITION": a, "NORMAL": a + 1 });
+        if let Some(cv) = cv {
+            attributes["COLOR_0"] = json!(accessors.len());
+            accessors.push(json!({ "bufferView": cv, "componentType": 5126, "count": m.colors.len(), "type": "VEC3" }));
+        }
+        let mut primitives = vec![];
+        for (id, iv, faces) in groups {
+            let mut prim = json!({ "attributes": attributes, "indices": accessors.len(), "mode": 4 });
+            accessors.push(json!({ "bufferView": iv, "componentType": 5125, "count": faces * 3, "type": "SCALAR" }));
+            if let Some(id) = id {
+                let t = &m.materials[id as usize];
+                prim["material"] = json!(materials.len());
+                materials.push(json!({
+                    "name": format!("material_{id}"),
+                    "pbrMetallicRoughness": { "baseColorFactor": [1.0, 1.0, 1.0, 1.0], "metallicFactor": t.metallic, "roughnessFactor": t.roughness },
+                    "emissiveFactor": t.emissive.map(|e| e.min(1.0)),
+                }));
+            }
+            primitives.push(prim);
+        }
+        meshes.push(json!({ "name": name, "primitives": primitives }));
+        nodes.push(json!({ "name": name, "mesh": meshes.len() - 1 }));
+    }
+    let mut animations = vec![];
+    if !times.is_empty() {
+        let mut raw = |bytes: Vec<u8>| {
+            while bin.len() % 4 != 0 { bin.push(0); }
+            views.push(json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": bytes.len() }));
+            bin.extend_from_slice(&bytes);
+            views.len() - 1
+        };
+        let input = accessors.len();
+        let tv = raw(times.iter().flat_map(|t| t.to_le_bytes()).collect());
+        accessors.push(json!({ "bufferView": tv, "componentType": 5126, "count": times.len(), "type": "SCALAR", "min": [times[0]], "max": [times[times.len() - 1]] }));
+        let (mut samplers, mut channels) = (vec![], vec![]);
+        for (i, node) in nodes.iter_mut().enumerate() {
+            if i > 0 { node["scale"] = json!([0.0, 0.0, 0.0]); }
+            let sv = raw((0..times.len()).flat_map(|j| [if i == j { 1.0f32 } else { 0.0 }; 3]).flat_map(|x| x.to_le_bytes()).collect());
+            accessors.push(json!({ "bufferView": sv, "componentType": 5126, "count": times.len(), "type": "VEC3" }));
+            channels.push(json!({ "sampler": samplers.len(), "target": { "node": i, "path": "scale" } }));
+            samplers.push(json!({ "input": input, "output": accessors.len() - 1, "interpolation": "STEP" }));
+        }
+        animations.push(json!({ "name": "frames", "samplers": samplers, "channels": channels }));
+    }
+    while bin.len() % 4 != 0 { bin.push(0); }
+    let mut doc = json!({
+        "asset": { "version": "2.0", "generator": "AI Modeler SDF Engine" },
+        "scene": 0, "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes, "meshes": meshes, "accessors": accessors, "bufferViews": views,
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+    if !materials.is_empty() { doc["materials"] = json!(materials); }
+    if !animations.is_empty() { doc["animations"] = json!(animations); }
+    let mut js = serde_json::to_vec(&doc).unwrap_or_default();
+    while js.len() % 4 != 0 { js.push(b' '); }
+    let total = 12 + 8 + js.len() + 8 + bin.len();
+    let mut out = Vec::with_capacity(total);
+    out.extend_from_slice(b"glTF");
+    out.extend_from_slice(&2u32.to_le_bytes());
+    out.extend_from_slice(&(total as u32).to_le_bytes());
+    out.extend_from_slice(&(js.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"JSON");
+    out.extend_from_slice(&js);
+    out.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"BIN\0");
+    out.extend_from_slice(&bin);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bounds, compiler, mesh, tree::SdfNode};
+    use serde_json::Value;
+
+    fn painted() -> Mesh {
+        let tree = SdfNode::from_json(&json!({ "type": "Union", "children": [
+            { "type": "Translate", "params": { "offset": [-1, 0, 0] }, "child": { "type": "Sphere", "params": { "radius": 0.6 } },
+              "material": { "base_color": [1, 0, 0], "metallic": 1, "roughness": 0.25 } },
+            { "type": "Translate", "params": { "offset": [1, 0, 0] }, "child": { "type": "Box3d", "params": { "half_size": 0.5 } },
+              "material": { "base_color": [0, 0, 1], "emissive": [2, 0.5, 0] } },
+        ] })).unwrap();
+        let sdf = compiler::compile(&tree).unwrap();
+        let mut m = mesh::generate(&sdf, &bounds::bounds(&tree), 24, &mut |_| true).unwrap();
+        mesh::paint(&mut m, &sdf);
+        m
+    }
+
+    #[test]
+    fn obj_and_mtl_keep_face_materials() {
+        let m = painted();
+        assert_eq!(m.used_materials(), [1, 2]);
+        let (obj, mtl) = (to_obj("scene", &m), to_mtl(&m));
+        assert!(obj.contains("mtllib scene.mtl\n"));
+        // Faces listed under each `usemtl`, as OBJ readers group them.
+        let (mut current, mut faces) = (None, Vec::new());
+        for l in obj.lines() {
+            if let Some(name) = l.strip_prefix("usemtl material_") { current = name.parse::<u32>().ok(); }
+            if let Some(f) = l.strip_prefix("f ") {
+                let ids: Vec<u32> = f.split(' ').map(|c| c.split("//").next().unwrap().parse::<u32>().unwrap() - 1).collect();
+                faces.push(([ids[0], ids[1], ids[2]], current.unwrap()));
+            }
+        }
+        let mut want: Vec<([u32; 3], u32)> = m.indices.iter().copied().zip(m.face_materials.iter().copied()).collect();
+        want.sort_by_key(|w| w.1);
+        assert_eq!(faces, want);
+        // MTL entries by name, with the PBR keys.
+        let block = |id: u32| mtl.split("newmtl ").find(|b| b.starts_with(&format!("material_{id}\n"))).unwrap().to_string();
+        for id in m.used_materials() {
+            let t = &m.materials[id as usize];
+            let b = block(id);
+            let key = |k: &str| b.lines().find_map(|l| l.strip_prefix(&format!("{k} "))).unwrap().split(' ').map(|x| x.parse::<f32>().unwrap()).collect::<Vec<_>>();
+            assert_eq!(key("Kd"), t.base_color);
+            assert_eq!(key("Ke"), t.emissive);
+            assert_eq!((key("Pm")[0], key("Pr")[0]), (t.metallic, t.roughness));
+        }
+    }
+
+    #[test]
+    fn glb_keeps_colors_and_materials() {
+        let m = painted();
+        let glb = to_glb(&[("scene".into(), &m)]);
+        assert_eq!(&glb[..4], b"glTF");
+        let u32_at = |i: usize| u32::from_le_bytes(glb[i..i + 4].try_into().unwrap()) as usize;
+        assert_eq!(u32_at(8), glb.len());
+        let js = u32_at(12);
+        let doc: Value = serde_json::from_slice(&glb[20..20 + js]).unwrap();
+        let bin = &glb[20 + js + 8..];
+        assert_eq!(bin.len(), u32_at(20 + js));
+        let floats = |acc: usize| {
+            let a = &doc["accessors"][acc];
+            let v = &doc["bufferViews"][a["bufferView"].as_u64().unwrap() as usize];
+            let off = v["byteOffset"].as_u64().unwrap() as usize;
+            bin[off..off + v["byteLength"].as_u64().unwrap() as usize].chunks(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).collect::<Vec<f32>>()
+        };
+        let prims = doc["meshes"][0]["primitives"].as_array().unwrap();
+        assert_eq!(prims.len(), 2);
+        let colors = floats(prims[0]["attributes"]["COLOR_0"].as_u64().unwrap() as usize);
+        assert_eq!(colors, m.colors.iter().flatten().copied().collect::<Vec<_>>());
+        let mut faces = 0;
+        for (p, id) in prims.iter().zip(m.used_materials()) {
+            let (t, g) = (&m.materials[id as usize], &doc["materials"][p["material"].as_u64().unwrap() as usize]);
+            assert_eq!(g["name"], format!("material_{id}"));
+            assert_eq!(g["pbrMetallicRoughness"]["metallicFactor"].as_f64().unwrap() as f32, t.metallic);
+            assert_eq!(g["pbrMetallicRoughness"]["roughnessFactor"].as_f64().unwrap() as f32, t.roughness);
+            assert_eq!(g["emissiveFactor"], json!(t.emissive.map(|e| e.min(1.0))));
+            let count = doc["accessors"][p["indices"].as_u64().unwrap() as usize]["count"].as_u64().unwrap() as usize;
+            assert_eq!(count, 3 * m.face_materials.iter().filter(|&&f| f == id).count());
+            faces += count / 3;
+        }
+        assert_eq!(faces, m.face_count());
+    }
+}
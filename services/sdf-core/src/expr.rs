@@ -0,0 +1,339 @@
+//! Parameter expressions over a root `variables` block.
+//!
+//! `{"variables": {"wall": 2, "hole": "wall * 3"}, "type": "Cylinder", "params": {"radius": "hole / 2"}}`
+//! Every param is numeric, so any string inside `params` or `material` is an
+//! expression: numbers, variables, + - * / % ^, parentheses, the constants `pi`
+//! and `tau`, and the functions listed in `FUNCS`. Variables may refer to each
+//! other in any order; cycles are errors. Keyframe curves (see `anim`) are
+//! sampled at the time the variables are declared for.
+
+use crate::anim;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::f64::consts::{PI, TAU};
+
+/// Longest expression source, and deepest nesting of operations; together they
+/// keep parsing and evaluation well inside a worker thread's stack.
+const MAX_LEN: usize = 1024;
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug)]
+pub enum Expr { Num(f64), Var(String), Neg(Box<Expr>), Bin(u8, Box<Expr>, Box<Expr>), Call(&'static str, Vec<Expr>) }
+
+/// Function name and argument count.
+const FUNCS: &[(&str, usize)] = &[
+    ("min", 2), ("max", 2), ("clamp", 3), ("abs", 1), ("floor", 1), ("ceil", 1), ("round", 1), ("sqrt", 1), ("pow", 2),
+    ("exp", 1), ("ln", 1), ("sin", 1), ("cos", 1), ("tan", 1), ("asin", 1), ("acos", 1), ("atan", 1), ("atan2", 2),
+];
+
+impl Expr {
+    /// Evaluate, asking `var` for every variable other than the constants.
+    pub fn eval(&self, var: &mut dyn FnMut(&str) -> Result<f64, String>) -> Result<f64, String> {
+        Ok(match self {
+            Expr::Num(x) => *x,
+            Expr::Var(n) => match n.as_str() { "pi" => PI, "tau" => TAU, _ => var(n)? },
+            Expr::Neg(e) => -e.eval(var)?,
+            Expr::Bin(o, a, b) => {
+                let (a, b) = (a.eval(var)?, b.eval(var)?);
+                match o { b'+' => a + b, b'-' => a - b, b'*' => a * b, b'/' => a / b, b'%' => a % b, _ => a.powf(b) }
+            }
+            Expr::Call(f, args) => {
+                let a = args.iter().map(|e| e.eval(var)).collect::<Result<Vec<_>, _>>()?;
+                match *f {
+                    "min" => a[0].min(a[1]), "max" => a[0].max(a[1]), "clamp" => a[0].max(a[1]).min(a[2]),
+                    "abs" => a[0].abs(), "floor" => a[0].floor(), "ceil" => a[0].ceil(), "round" => a[0].round(),
+                    "sqrt" => a[0].sqrt(), "pow" => a[0].powf(a[1]), "exp" => a[0].exp(), "ln" => a[0].ln(),
+                    "sin" => a[0].sin(), "cos" => a[0].cos(), "tan" => a[0].tan(),
+                    "asin" => a[0].asin(), "acos" => a[0].acos(), "atan" => a[0].atan(), _ => a[0].atan2(a[1]),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok { Num(f64), Ident(String), Sym(u8) }
+
+fn lex(src: &str) -> Result<Vec<Tok>, String> {
+    let b = src.as_bytes();
+    let (mut out, mut i) = (Vec::new(), 0);
+    while i < b.len() {
+        let c = b[i];
+        if c.is_ascii_whitespace() { i += 1; continue; }
+        let start = i;
+        if c.is_ascii_digit() || c == b'.' {
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'.' || (matches!(b[i], b'+' | b'-') && matches!(b[i - 1], b'e' | b'E'))) { i += 1; }
+            out.push(Tok::Num(src[start..i].parse().map_err(|_| format!("bad number '{}'", &src[start..i]))?));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'_') { i += 1; }
+            out.push(Tok::Ident(src[start..i].into()));
+        } else if b"+-*/%^(),".contains(&c) {
+            out.push(Tok::Sym(c));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character '{}'", src[i..].chars().next().unwrap_or('?')));
+        }
+    }
+    Ok(out)
+}
+
+struct Parser { toks: Vec<Tok>, at: usize, depth: usize }
+
+impl Parser {
+    fn deeper(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH { return Err(format!("expression nests deeper than {MAX_DEPTH} levels")); }
+        Ok(())
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let hit = self.toks.get(self.at) == Some(&Tok::Sym(c));
+        if hit { self.at += 1; }
+        hit
+    }
+
+    fn binary(&mut self, ops: &[u8], next: fn(&mut Parser) -> Result<Expr, String>) -> Result<Expr, String> {
+        let depth = self.depth;
+        let mut l = next(self)?;
+        // Chains nest to the left, one level per operator.
+        while let Some(&o) = ops.iter().find(|&&o| self.toks.get(self.at) == Some(&Tok::Sym(o))) {
+            self.at += 1;
+            self.deeper()?;
+            l = Expr::Bin(o, Box::new(l), Box::new(next(self)?));
+        }
+        self.depth = depth;
+        Ok(l)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> { self.binary(b"+-", Parser::product) }
+    fn product(&mut self) -> Result<Expr, String> { self.binary(b"*/%", Parser::unary) }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let depth = self.depth;
+        self.deeper()?;
+        let e = if self.eat(b'-') {
+            Expr::Neg(Box::new(self.unary()?))
+        } else if self.eat(b'+') {
+            self.unary()?
+        } else {
+            let base = self.atom()?;
+            // Right-associative and binding tighter than unary minus: -2^2 = -4.
+            if self.eat(b'^') { Expr::Bin(b'^', Box::new(base), Box::new(self.unary()?)) } else { base }
+        };
+        self.depth = depth;
+        Ok(e)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let t = self.toks.get(self.at).cloned().ok_or("unexpected end of expression")?;
+        self.at += 1;
+        match t {
+            Tok::Num(x) => Ok(Expr::Num(x)),
+            Tok::Ident(n) if self.eat(b'(') => {
+                let &(f, want) = FUNCS.iter().find(|f| f.0 == n).ok_or_else(|| format!("unknown function '{n}'"))?;
+                let mut args = Vec::new();
+                if !self.eat(b')') {
+                    loop {
+                        args.push(self.sum()?);
+                        if self.eat(b')') { break; }
+                        if !self.eat(b',') { return Err(format!("expected ',' or ')' in {f}(...)")); }
+                    }
+                }
+                if args.len() != want { return Err(format!("{f} takes {want} argument(s), got {}", args.len())); }
+                Ok(Expr::Call(f, args))
+            }
+            Tok::Ident(n) => Ok(Expr::Var(n)),
+            Tok::Sym(b'(') => {
+                let e = self.sum()?;
+                if !self.eat(b')') { return Err("missing ')'".into()); }
+                Ok(e)
+            }
+            Tok::Sym(c) => Err(format!("unexpected '{}'", c as char)),
+        }
+    }
+}
+
+pub fn parse(src: &str) -> Result<Expr, String> {
+    if src.len() > MAX_LEN { return Err(format!("expression is longer than {MAX_LEN} characters")); }
+    let mut p = Parser { toks: lex(src)?, at: 0, depth: 0 };
+    let e = p.sum()?;
+    if p.at < p.toks.len() { return Err("unexpected trailing input".into()); }
+    Ok(e)
+}
+
+/// Resolved values of the root `variables` block at one point in time.
+#[derive(Clone, Debug, Default)]
+pub struct Vars { pub values: BTreeMap<String, f64>, pub time: f64 }
+
+impl Vars {
+    /// Resolve a `variables` block at `time`; `overrides` replace declarations of the same name.
+    pub fn declare(block: Option<&Value>, overrides: &Map<String, Value>, time: f64) -> Result<Vars, String> {
+        let mut decls = BTreeMap::new();
+        match block {
+            None | Some(Value::Null) => {}
+            Some(Value::Object(m)) => for (k, v) in m { decls.insert(k.as_str(), declaration("variables", k, v, time)?); },
+            Some(_) => return Err("variables: must be an object".into()),
+        }
+        for (k, v) in overrides {
+            if !decls.contains_key(k.as_str()) { return Err(format!("overrides.{k}: the tree declares no variable '{k}'")); }
+            decls.insert(k.as_str(), declaration("overrides", k, v, time)?);
+        }
+        let mut vars = Vars { values: BTreeMap::new(), time };
+        for k in decls.keys() { resolve(k, &decls, &mut vars.values, &mut vec![])?; }
+        Ok(vars)
+    }
+
+    /// Replace every expression string and keyframe curve inside `v` by its value.
+    pub fn substitute(&self, v: &Value) -> Result<Value, String> {
+        Ok(match v {
+            Value::String(s) => {
+                let x = parse(s).and_then(|e| e.eval(&mut |n| self.values.get(n).copied().ok_or_else(|| format!("unknown variable '{n}'"))))
+                    .map_err(|e| format!("expression '{s}': {e}"))?;
+                if !x.is_finite() { return Err(format!("expression '{s}' evaluates to {x}")); }
+                json!(x)
+            }
+            Value::Array(a) => Value::Array(a.iter().map(|x| self.substitute(x)).collect::<Result<_, _>>()?),
+            Value::Object(_) if anim::is_curve(v) => self.substitute(&anim::sample(v, self.time).map_err(|e| format!("curve: {e}"))?)?,
+            Value::Object(o) => Value::Object(o.iter().map(|(k, x)| Ok((k.clone(), self.substitute(x)?))).collect::<Result<_, String>>()?),
+            _ => v.clone(),
+        })
+    }
+}
+
+fn declaration(block: &str, k: &str, v: &Value, time: f64) -> Result<Expr, String> {
+    let ident = k.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !ident { return Err(format!("{block}.{k}: variable names must be identifiers")); }
+    if matches!(k, "pi" | "tau") || FUNCS.iter().any(|f| f.0 == k) { return Err(format!("{block}.{k}: '{k}' is reserved")); }
+    match v {
+        Value::Number(x) => Ok(Expr::Num(x.as_f64().unwrap_or(0.0))),
+        Value::String(s) => parse(s).map_err(|e| format!("{block}.{k}: {e}")),
+        Value::Object(_) if anim::is_curve(v) => match anim::sample(v, time).map_err(|e| format!("{block}.{k}: curve {e}"))? {
+            Value::Number(x) => Ok(Expr::Num(x.as_f64().unwrap_or(0.0))),
+            _ => Err(format!("{block}.{k}: curve values must be numbers")),
+        },
+        _ => Err(format!("{block}.{k}: must be a number, an expression string or a keyframe curve")),
+    }
+}
+
+fn resolve<'a>(k: &'a str, decls: &BTreeMap<&'a str, Expr>, done: &mut BTreeMap<String, f64>, stack: &mut Vec<&'a str>) -> Result<f64, String> {
+    if let Some(&x) = done.get(k) { return Ok(x); }
+    if stack.contains(&k) { return Err(format!("variables: cycle {} -> {k}", stack.join(" -> "))); }
+    if stack.len() >= MAX_DEPTH { return Err(format!("variables.{k}: references nest deeper than {MAX_DEPTH} levels")); }
+    stack.push(k);
+    let x = decls[k].eval(&mut |n| match decls.get_key_value(n) {
+        Some((&n, _)) => resolve(n, decls, done, stack),
+        None => Err(format!("variables.{k}: unknown variable '{n}'")),
+    })?;
+    stack.pop();
+    if !x.is_finite() { return Err(format!("variables.{k}: evaluates to {x}")); }
+    done.insert(k.into(), x);
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> Result<f64, String> {
+        parse(src)?.eval(&mut |n| Err(format!("unknown variable '{n}'")))
+    }
+
+    fn vars(block: Value, overrides: Value) -> Result<Vars, String> {
+        Vars::declare(Some(&block), overrides.as_object().unwrap(), 0.0)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        for (src, want) in [
+            ("2 + 3 * 4", 14.0), ("(2 + 3) * 4", 20.0), ("10 - 4 - 3", 3.0), ("12 / 3 / 2", 2.0), ("7 % 4 * 2", 6.0),
+            ("-2^2", -4.0), ("2^3^2", 512.0), ("2^-1", 0.5), ("--3", 3.0), ("+4", 4.0), ("1.5e1 + .5", 15.5),
+        ] {
+            assert_eq!(eval(src), Ok(want), "{src}");
+        }
+    }
+
+    #[test]
+    fn functions_and_constants() {
+        for (src, want) in [
+            ("min(3, -1)", -1.0), ("max(3, -1)", 3.0), ("clamp(5, 0, 2)", 2.0), ("clamp(-5, 0, 2)", 0.0), ("abs(-2) + floor(1.7) + ceil(1.2)", 5.0),
+            ("sin(pi / 2)", 1.0), ("cos(0)", 1.0), ("atan2(1, 1) * 4", PI), ("tau / 2", PI), ("pow(2, 10)", 1024.0), ("sqrt(min(16, 25))", 4.0),
+        ] {
+            let x = eval(src).unwrap();
+            assert!((x - want).abs() < 1e-12, "{src} = {x}, want {want}");
+        }
+        assert_eq!(eval("foo(1)").unwrap_err(), "unknown function 'foo'");
+        assert_eq!(eval("min(1)").unwrap_err(), "min takes 2 argument(s), got 1");
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert_eq!(eval("1 2").unwrap_err(), "unexpected trailing input");
+    }
+
+    #[test]
+    fn nesting_and_length_are_capped() {
+        let deep = format!("{}1{}", "(".repeat(200_000), ")".repeat(200_000));
+        assert_eq!(eval(&deep).unwrap_err(), format!("expression is longer than {MAX_LEN} characters"));
+        let parens = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(eval(&parens).unwrap_err(), format!("expression nests deeper than {MAX_DEPTH} levels"));
+        assert_eq!(eval(&"-".repeat(100)).unwrap_err(), format!("expression nests deeper than {MAX_DEPTH} levels"));
+        assert_eq!(eval(&vec!["1"; 100].join("+")).unwrap_err(), format!("expression nests deeper than {MAX_DEPTH} levels"));
+        assert_eq!(eval(&format!("{}1{}", "(".repeat(20), ")".repeat(20))), Ok(1.0));
+        assert_eq!(eval(&vec!["1"; 40].join("+")), Ok(40.0));
+        // A chain of variables each one deeper than the last.
+        let chain: Map<String, Value> = (0..200).map(|i| (format!("v{i}"), if i == 0 { json!(1) } else { json!(format!("v{} + 1", i - 1)) })).collect();
+        let e = vars(Value::Object(chain), json!({})).unwrap_err();
+        assert!(e.contains(&format!("references nest deeper than {MAX_DEPTH} levels")), "{e}");
+    }
+
+    #[test]
+    fn variables_resolve_in_any_order() {
+        let v = vars(json!({"hole": "wall * 3", "wall": 2, "r": "hole / 2"}), json!({})).unwrap();
+        assert_eq!(v.values["r"], 3.0);
+        let p = v.substitute(&json!({"radius": "r + 1", "size": ["wall", 1, "-wall"], "name": null})).unwrap();
+        assert_eq!(p, json!({"radius": 4.0, "size": [2.0, 1, -2.0], "name": null}));
+        assert_eq!(v.substitute(&json!("x * 2")).unwrap_err(), "expression 'x * 2': unknown variable 'x'");
+        assert_eq!(v.substitute(&json!("wall / 0")).unwrap_err(), "expression 'wall / 0' evaluates to inf");
+    }
+
+    #[test]
+    fn unknown_and_cyclic_variables_are_errors() {
+        assert_eq!(vars(json!({"a": "b + 1"}), json!({})).unwrap_err(), "variables.a: unknown variable 'b'");
+        assert_eq!(vars(json!({"a": "b + 1", "b": "c", "c": "a * 2"}), json!({})).unwrap_err(), "variables: cycle a -> b -> c -> a");
+        assert_eq!(vars(json!({"a": "a"}), json!({})).unwrap_err(), "variables: cycle a -> a");
+        assert_eq!(vars(json!({"sin": 1}), json!({})).unwrap_err(), "variables.sin: 'sin' is reserved");
+        assert_eq!(vars(json!({"2x": 1}), json!({})).unwrap_err(), "variables.2x: variable names must be identifiers");
+        assert_eq!(vars(json!({"a": true}), json!({})).unwrap_err(), "variables.a: must be a number, an expression string or a keyframe curve");
+    }
+
+    #[test]
+    fn overrides_replace_declarations() {
+        let block = json!({"wall": 2, "hole": "wall * 3"});
+        let v = vars(block.clone(), json!({"wall": "0.5 * 3"})).unwrap();
+        assert_eq!((v.values["wall"], v.values["hole"]), (1.5, 4.5));
+        // Overrides may refer to other variables, and can close a cycle.
+        assert_eq!(vars(block.clone(), json!({"hole": "wall + 1"})).unwrap().values["hole"], 3.0);
+        assert_eq!(vars(block.clone(), json!({"wall": "hole"})).unwrap_err(), "variables: cycle hole -> wall -> hole");
+        assert_eq!(vars(block.clone(), json!({"depth": 1})).unwrap_err(), "overrides.depth: the tree declares no variable 'depth'");
+        assert_eq!(vars(block, json!({"wall": "1 +"})).unwrap_err().split(':').next(), Some("overrides.wall"));
+    }
+
+    #[test]
+    fn overridden_trees_resolve_like_the_engine() {
+        // The path `/resolve` takes: declare with overrides, then substitute while parsing.
+        let tree = json!({"variables": {"wall": 2, "hole": "wall * 3"}, "type": "Cylinder", "params": {"radius": "hole / 2", "height": "wall"}});
+        let overrides = json!({"wall": 1});
+        let v = Vars::declare(tree.get("variables"), overrides.as_object().unwrap(), 0.0).unwrap();
+        let node = crate::tree::SdfNode::from_json_with(&tree, &v).unwrap();
+        assert_eq!((node.f("radius", 0.0), node.f("height", 0.0)), (1.5, 1.0));
+        let bad = json!({"type": "Sphere", "params": {"radius": "wall"}});
+        assert!(crate::tree::SdfNode::from_json_with(&bad, &Vars::default()).unwrap_err().contains("unknown variable 'wall'"));
+    }
+
+    #[test]
+    fn curves_are_sampled_at_the_declared_time() {
+        let block = json!({"angle": {"keys": [{"t": 0, "value": 0}, {"t": 2, "value": 4}]}, "half": "angle / 2"});
+        let at = |t| Vars::declare(Some(&block), &Map::new(), t).unwrap();
+        assert_eq!((at(1.0).values["angle"], at(1.0).values["half"]), (2.0, 1.0));
+        assert_eq!(at(5.0).values["angle"], 4.0);
+        assert_eq!(at(1.0).substitute(&json!({"radius": {"keys": [{"t": 0, "value": 1}, {"t": 2, "value": 3}]}})).unwrap(), json!({"radius": 2.0}));
+    }
+}
@@ -0,0 +1,443 @@
+//! Fitting primitives to point clouds (`/api/v1/sdf/fit`).
+//!
+//! A fit model is one primitive under `Translate` and `RotateEuler`; its unknowns
+//! are the offset, the angles and every continuous (f32 / vec3) param of the
+//! primitive. Levenberg–Marquardt minimises the points' distances to the surface.
+//! Each fit starts from the principal axes of the points, once per cyclic order
+//! of the axes so elongated primitives can line up with either, and keeps the
+//! best. Greedy CSG grows a Union one primitive at a time: candidates are seeded
+//! on the points the union leaves unexplained (all of them and k-means clusters)
+//! and fitted against the union with the earlier primitives frozen, under a
+//! residual that saturates far outside so other parts do not drag the fit; a
+//! candidate is kept only if it clearly lowers the error.
+
+use crate::bounds;
+use crate::compiler;
+use crate::math::{self, V3};
+use crate::schema::{self, Group, ParamDefault, ParamType};
+use crate::solve::levenberg_marquardt;
+use crate::tree::SdfNode;
+use serde_json::{json, Map, Value};
+use std::cell::Cell;
+use std::f64::consts::PI;
+
+/// Points used by the solver; larger clouds are subsampled evenly.
+const SAMPLE: usize = 4000;
+const CSG_SAMPLE: usize = 1500;
+/// Seed clusters tried per CSG step, besides all unexplained points.
+const SEEDS: usize = 2;
+/// A CSG step must cut the score by this factor to be kept.
+const GAIN: f64 = 0.9;
+
+pub struct Fit { pub tree: Value, pub primitives: usize, pub iterations: usize, pub converged: bool }
+
+/// Centroid, principal axes (a right-handed frame) and the half extent and
+/// middle of the points along each axis.
+struct Frame { centre: V3, axes: [V3; 3], half: V3, mid: V3 }
+
+impl Frame {
+    fn of(pts: &[V3]) -> Frame {
+        let n = pts.len().max(1) as f32;
+        let centre = math::mul(pts.iter().fold([0.0; 3], |a, p| math::add(a, *p)), 1.0 / n);
+        let mut cov = [[0.0f64; 3]; 3];
+        for p in pts {
+            let d = math::sub(*p, centre);
+            for i in 0..3 { for j in 0..3 { cov[i][j] += (d[i] * d[j]) as f64; } }
+        }
+        let mut axes = eigenvectors(cov);
+        if math::dot(math::cross(axes[0], axes[1]), axes[2]) < 0.0 { axes[2] = math::mul(axes[2], -1.0); }
+        let (mut lo, mut hi) = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+        for p in pts {
+            let d = math::sub(*p, centre);
+            for i in 0..3 { let t = math::dot(d, axes[i]); lo[i] = lo[i].min(t); hi[i] = hi[i].max(t); }
+        }
+        Frame { centre, axes, half: std::array::from_fn(|i| 0.5 * (hi[i] - lo[i])), mid: std::array::from_fn(|i| 0.5 * (hi[i] + lo[i])) }
+    }
+
+    /// The same frame with its axes rotated `k` places.
+    fn cycled(&self, k: usize) -> Frame {
+        let at = |i: usize| (i + k) % 3;
+        Frame { centre: self.centre, axes: std::array::from_fn(|i| self.axes[at(i)]), half: std::array::from_fn(|i| self.half[at(i)]), mid: std::array::from_fn(|i| self.mid[at(i)]) }
+    }
+}
+
+/// Unit eigenvectors of a symmetric 3x3 matrix (cyclic Jacobi rotations).
+fn eigenvectors(mut a: [[f64; 3]; 3]) -> [V3; 3] {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0f64]];
+    for _ in 0..32 {
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-12 * (a[p][p].abs() + a[q][q].abs()).max(1e-30) { continue; }
+            let theta = 0.5 * (2.0 * a[p][q]).atan2(a[q][q] - a[p][p]);
+            let (s, c) = theta.sin_cos();
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (rp, rq) = (a[p], a[q]);
+            for k in 0..3 {
+                a[p][k] = c * rp[k] - s * rq[k];
+                a[q][k] = s * rp[k] + c * rq[k];
+            }
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+    std::array::from_fn(|j| math::normalize([v[0][j] as f32, v[1][j] as f32, v[2][j] as f32]))
+}
+
+/// XYZ Euler angles of the rotation taking the unit axes to `axes` (inverse of `math::euler_xyz`).
+fn euler_of(axes: &[V3; 3]) -> V3 {
+    let m = |r: usize, c: usize| axes[c][r];
+    [m(2, 1).atan2(m(2, 2)), (-m(2, 0)).clamp(-1.0, 1.0).asin(), m(1, 0).atan2(m(0, 0))]
+}
+
+/// One free scalar of the primitive: param name, vec3 component, range.
+struct Slot { param: &'static str, component: Option<usize>, lo: f64, hi: f64, length: bool, default: f32 }
+
+/// A primitive with its unknowns laid out as offset (3), angles (3), then slots.
+struct Model { ty: &'static str, slots: Vec<Slot>, bounds: bounds::Aabb }
+
+impl Model {
+    fn new(ty: &str) -> Result<Model, String> {
+        let s = schema::lookup(ty).filter(|s| s.group == Group::Primitive).ok_or_else(|| format!("'{ty}' is not a primitive"))?;
+        let b = bounds::bounds(&SdfNode::from_json(&json!({ "type": s.name })).map_err(|e| format!("{ty}: {e}"))?);
+        if s.name == "MeshSdf" || !b.is_finite() || b.is_empty() { return Err(format!("{ty} is not a bounded parametric primitive")); }
+        let mut slots = vec![];
+        for p in s.params {
+            let lo = p.min.map_or(f64::NEG_INFINITY, |m| if p.exclusive_min { m as f64 + 1e-6 * (m as f64).abs().max(1.0) } else { m as f64 });
+            let hi = p.max.map_or(f64::INFINITY, |m| m as f64);
+            let length = p.unit == "length";
+            match (p.ty, p.default) {
+                (ParamType::F32, ParamDefault::Scalar(d)) => slots.push(Slot { param: p.name, component: None, lo, hi, length, default: d }),
+                (ParamType::Vec3, ParamDefault::Vec3(d)) => (0..3).for_each(|c| slots.push(Slot { param: p.name, component: Some(c), lo, hi, length, default: d[c] })),
+                _ => {}
+            }
+        }
+        Ok(Model { ty: s.name, slots, bounds: b })
+    }
+
+    /// Ranges of the unknowns; lengths stay below `limit` so a primitive can't
+    /// grow without bound to reach points it doesn't explain.
+    fn range(&self, limit: f64) -> Vec<(f64, f64)> {
+        let free = (f64::NEG_INFINITY, f64::INFINITY);
+        [free; 6].into_iter().chain(self.slots.iter().map(|s| (s.lo, if s.length { s.hi.min(limit.max(s.lo)) } else { s.hi }))).collect()
+    }
+
+    /// Defaults scaled so the default bounds match the frame's extents, placed and oriented on the frame.
+    fn initial(&self, f: &Frame) -> Vec<f64> {
+        let (dh, dc) = (math::mul(self.bounds.size(), 0.5), self.bounds.center());
+        let scale: V3 = std::array::from_fn(|i| if dh[i] > 1e-6 { f.half[i].max(1e-4) / dh[i] } else { 1.0 });
+        let mean = (scale[0] + scale[1] + scale[2]) / 3.0;
+        let local = math::sub(f.mid, math::mul3(dc, scale));
+        let offset = (0..3).fold(f.centre, |o, i| math::add(o, math::mul(f.axes[i], local[i])));
+        let params = self.slots.iter().map(|s| {
+            let k = if !s.length { 1.0 } else { s.component.map_or(mean, |c| scale[c]) };
+            ((s.default * k) as f64).clamp(s.lo, s.hi)
+        });
+        offset.iter().chain(euler_of(&f.axes).iter()).map(|&v| v as f64).chain(params).collect()
+    }
+
+    fn tree(&self, x: &[f64]) -> Value {
+        let mut params = Map::new();
+        for (s, &v) in self.slots.iter().zip(&x[6..]) {
+            match s.component {
+                None => { params.insert(s.param.into(), json!(math::short(v as f32))); }
+                Some(c) => { params.entry(s.param).or_insert_with(|| json!([0.0, 0.0, 0.0]))[c] = json!(math::short(v as f32)); }
+            }
+        }
+        let v = |i: usize| math::short(x[i] as f32);
+        let angle = |i: usize| math::short((x[i] + PI).rem_euclid(2.0 * PI) as f32 - PI as f32);
+        json!({
+            "type": "Translate", "params": { "offset": [v(0), v(1), v(2)] },
+            "child": { "type": "RotateEuler", "params": { "angles": [angle(3), angle(4), angle(5)] }, "child": { "type": self.ty, "params": params } },
+        })
+    }
+
+    fn distances(&self, x: &[f64], pts: &[V3]) -> Result<Vec<f32>, String> {
+        let node = SdfNode::from_json(&self.tree(x))?;
+        Ok(compiler::compile(&node)?.eval_batch(pts))
+    }
+}
+
+struct Candidate { tree: Value, distances: Vec<f32>, score: f64, iterations: usize, converged: bool }
+
+/// Outside distances saturate at `tau`, so points far from a primitive hardly
+/// pull on it; inside ones don't, as a point inside a union can't be on its surface.
+fn robust(d: f64, tau: f64) -> f64 {
+    let q = d / tau;
+    if q <= 0.0 { d } else if q > 1e6 { tau } else { d / (1.0 + q * q).sqrt() }
+}
+
+/// Best fit of `ty` to `pts` seeded on `frame`, lengths up to `limit`, scored by the mean squared
+/// residual. With `under = (distances, tau)` the residual is the robust distance
+/// to the union with those fixed distances.
+fn fit_model(ty: &str, pts: &[V3], frame: &Frame, limit: f64, under: Option<(&[f32], f64)>, max_iterations: usize, expired: &dyn Fn() -> bool) -> Result<Candidate, String> {
+    let model = Model::new(ty)?;
+    let range = model.range(limit);
+    let residuals = |x: &[f64]| -> Result<Vec<f64>, String> {
+        let d = model.distances(x, pts)?;
+        Ok(match under {
+            Some((u, tau)) => d.iter().zip(u).map(|(a, b)| robust(a.min(*b) as f64, tau)).collect(),
+            None => d.iter().map(|&a| a as f64).collect(),
+        })
+    };
+    let mut best: Option<Candidate> = None;
+    for k in 0..3 {
+        let prev = Cell::new(f64::INFINITY);
+        // Converged once an iteration improves the cost by less than 1e-7 relative.
+        let done = |r: &[f64]| { let c: f64 = r.iter().map(|v| v * v).sum(); let stop = prev.get().is_finite() && prev.get() - c <= 1e-7 * prev.get(); prev.set(c); stop };
+        let lm = levenberg_marquardt(model.initial(&frame.cycled(k)), &range, &residuals, &done, max_iterations, expired)?;
+        let score = lm.r.iter().map(|v| v * v).sum::<f64>() / lm.r.len().max(1) as f64;
+        if best.as_ref().is_none_or(|b| score < b.score) {
+            let iterations = lm.iterations + best.as_ref().map_or(0, |b| b.iterations);
+            best = Some(Candidate { tree: model.tree(&lm.x), distances: model.distances(&lm.x, pts)?, score, iterations, converged: lm.converged });
+        } else if let Some(b) = best.as_mut() { b.iterations += lm.iterations; }
+        if expired() { break; }
+    }
+    best.ok_or_else(|| "no fit".into())
+}
+
+fn sample(pts: &[V3], n: usize) -> Vec<V3> { pts.iter().step_by(pts.len().div_ceil(n).max(1)).copied().collect() }
+
+/// `k` spatial clusters: farthest-point seeds, then Lloyd iterations.
+fn clusters(pts: &[V3], k: usize) -> Vec<Vec<V3>> {
+    let d2 = |a: V3, b: V3| { let d = math::sub(a, b); math::dot(d, d) };
+    let mut centres = vec![Frame::of(pts).centre];
+    for _ in 0..k {
+        let far = pts.iter().copied().max_by(|a, b| {
+            let da = centres.iter().map(|c| d2(*a, *c)).fold(f32::INFINITY, f32::min);
+            let db = centres.iter().map(|c| d2(*b, *c)).fold(f32::INFINITY, f32::min);
+            da.total_cmp(&db)
+        });
+        centres.extend(far);
+    }
+    // The first centre was only there to pick an extreme point.
+    centres.remove(0);
+    let mut groups = vec![];
+    for _ in 0..10 {
+        groups = vec![Vec::new(); centres.len()];
+        for p in pts {
+            let i = (0..centres.len()).min_by(|&a, &b| d2(*p, centres[a]).total_cmp(&d2(*p, centres[b]))).unwrap_or(0);
+            groups[i].push(*p);
+        }
+        for (c, g) in centres.iter_mut().zip(&groups) { if !g.is_empty() { *c = Frame::of(g).centre; } }
+    }
+    groups
+}
+
+/// Least-squares fit of one primitive `ty` with its transform.
+pub fn fit_primitive(ty: &str, pts: &[V3], max_iterations: usize, expired: &dyn Fn() -> bool) -> Result<Fit, String> {
+    let pts = sample(pts, SAMPLE);
+    let f = Frame::of(&pts);
+    let c = fit_model(ty, &pts, &f, 4.0 * math::len(f.half) as f64, None, max_iterations, expired)?;
+    Ok(Fit { tree: c.tree, primitives: 1, iterations: c.iterations, converged: c.converged })
+}
+
+/// Greedy Union of up to `max_primitives` primitives drawn from `types`.
+pub fn fit_csg(types: &[String], pts: &[V3], max_primitives: usize, max_iterations: usize, expired: &dyn Fn() -> bool) -> Result<Fit, String> {
+    for t in types { Model::new(t)?; }
+    let pts = sample(pts, CSG_SAMPLE);
+    // Residuals saturate at 2% of the cloud's size; points within half of that count as explained.
+    let size = 2.0 * math::len(Frame::of(&pts).half) as f64;
+    let tau = 0.02 * size;
+    let (mut parts, mut under, mut score) = (Vec::<Value>::new(), vec![f32::INFINITY; pts.len()], tau * tau);
+    let (mut iterations, mut converged) = (0, true);
+    while parts.len() < max_primitives && !expired() {
+        let rest: Vec<V3> = pts.iter().zip(&under).filter(|(_, d)| d.abs() as f64 > 0.5 * tau).map(|(p, _)| *p).collect();
+        if rest.len() < 8 { break; }
+        let mut best: Option<Candidate> = None;
+        let k = (max_primitives - parts.len()).min(SEEDS);
+        let seeds = std::iter::once(rest.clone()).chain(if k > 1 { clusters(&rest, k) } else { vec![] });
+        for seed in seeds.filter(|g| g.len() >= 8) {
+            let frame = Frame::of(&seed);
+            for t in types {
+                let c = fit_model(t, &pts, &frame, 2.0 * size, Some((&under, tau)), max_iterations, expired)?;
+                iterations += c.iterations;
+                if best.as_ref().is_none_or(|b| c.score < b.score) { best = Some(c); }
+            }
+        }
+        let Some(best) = best else { break };
+        if best.score >= score * GAIN { break; }
+        converged &= best.converged;
+        under.iter_mut().zip(&best.distances).for_each(|(u, d)| *u = u.min(*d));
+        score = best.score;
+        parts.push(best.tree);
+    }
+    if parts.is_empty() { return Err("no primitive fits the points".into()); }
+    let primitives = parts.len();
+    let tree = if primitives == 1 { parts.remove(0) } else { json!({ "type": "Union", "children": parts }) };
+    Ok(Fit { tree, primitives, iterations, converged })
+}
+
+// ── PLY ─────────────────────────────────────────────────────────────────────
+
+/// One PLY element: its name, row count and `(type, name)` properties.
+type PlyElement = (String, usize, Vec<(String, String)>);
+
+/// A parsed PLY header and where the body starts.
+struct PlyHeader { format: String, elements: Vec<PlyElement>, body: usize }
+
+fn ply_header(data: &[u8]) -> Result<PlyHeader, String> {
+    // `end_header` on a line of its own, ended by LF or CRLF.
+    let tag = (0..data.len()).find(|&i| data[i..].starts_with(b"end_header") && (i == 0 || data[i - 1] == b'\n')).ok_or("PLY has no end_header")?;
+    let eol = match &data[tag + 10..] { [b'\n', ..] => 1, [b'\r', b'\n', ..] => 2, _ => return Err("PLY end_header must end its line".into()) };
+    let header = std::str::from_utf8(&data[..tag]).map_err(|_| "PLY header is not text")?;
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") { return Err("not a PLY file".into()); }
+    let mut h = PlyHeader { format: String::new(), elements: Vec::new(), body: tag + 10 + eol };
+    for l in lines {
+        let w: Vec<&str> = l.split_whitespace().collect();
+        match w.as_slice() {
+            ["format", f, ..] => h.format = f.to_string(),
+            ["element", name, n] => h.elements.push((name.to_string(), n.parse().map_err(|_| format!("bad element count '{n}'"))?, vec![])),
+            ["property", "list", ..] => h.elements.last_mut().ok_or("property before element")?.2.push(("list".into(), String::new())),
+            ["property", ty, name] => h.elements.last_mut().ok_or("property before element")?.2.push((ty.to_string(), name.to_string())),
+            _ => {}
+        }
+    }
+    Ok(h)
+}
+
+fn ply_size(ty: &str) -> Option<usize> {
+    match ty {
+        "char" | "int8" | "uchar" | "uint8" => Some(1), "short" | "int16" | "ushort" | "uint16" => Some(2),
+        "int" | "int32" | "uint" | "uint32" | "float" | "float32" => Some(4), "double" | "float64" => Some(8), _ => None,
+    }
+}
+
+/// Vertex count a PLY header declares, so callers can budget it before parsing.
+pub fn ply_vertex_count(data: &[u8]) -> Result<usize, String> {
+    ply_header(data)?.elements.iter().find(|e| e.0 == "vertex").map(|e| e.1).ok_or_else(|| "PLY has no vertex element".into())
+}
+
+/// Vertex positions of a PLY file (ASCII or binary, either byte order). Other
+/// elements are skipped when they come first and have no list properties.
+/// Declared counts are checked against the rows the body can hold before
+/// anything is allocated or skipped.
+pub fn parse_ply(data: &[u8]) -> Result<Vec<V3>, String> {
+    let h = ply_header(data)?;
+    let body = &data[h.body..];
+    let big = match h.format.as_str() { "ascii" | "binary_little_endian" => false, "binary_big_endian" => true, f => return Err(format!("unknown PLY format '{f}'")) };
+    let rows: Option<Vec<&str>> = if h.format == "ascii" {
+        Some(std::str::from_utf8(body).map_err(|_| "PLY body is not text")?.lines().filter(|l| !l.trim().is_empty()).collect())
+    } else { None };
+    let short = |name: &str, count: usize| format!("PLY ends early: element '{name}' declares {count} rows");
+    let mut at = 0usize;
+    for (name, count, props) in &h.elements {
+        // Rows (ASCII) or bytes (binary) this element spans, checked against what is left.
+        let span = match &rows {
+            Some(r) => Some(*count).filter(|&c| c <= r.len() - at),
+            None => {
+                if let Some((ty, _)) = props.iter().find(|(t, _)| ply_size(t).is_none()) {
+                    return Err(if name == "vertex" { format!("unsupported vertex property type '{ty}'") } else { format!("can't skip binary element '{name}' with list properties") });
+                }
+                let row: usize = props.iter().filter_map(|(t, _)| ply_size(t)).sum();
+                row.checked_mul(*count).filter(|&n| n <= body.len() - at)
+            }
+        }.ok_or_else(|| short(name, *count))?;
+        if name != "vertex" {
+            at += span;
+            continue;
+        }
+        let col = |k: &str| props.iter().position(|(_, n)| n == k).ok_or_else(|| format!("vertex element has no '{k}' property"));
+        let (xi, yi, zi) = (col("x")?, col("y")?, col("z")?);
+        let mut out = Vec::with_capacity(*count);
+        for v in 0..*count {
+            let row: Vec<f64> = match &rows {
+                Some(r) => r[at + v].split_whitespace().map(|x| x.parse::<f64>()).collect::<Result<_, _>>().map_err(|_| format!("vertex {v}: bad number"))?,
+                None => props.iter().map(|(ty, _)| {
+                    let n = ply_size(ty).unwrap_or(0);
+                    let mut a = [0u8; 8];
+                    a[..n].copy_from_slice(&body[at..at + n]);
+                    at += n;
+                    if big { a[..n].reverse(); }
+                    match ty.as_str() {
+                        "char" | "int8" => a[0] as i8 as f64, "uchar" | "uint8" => a[0] as f64,
+                        "short" | "int16" => i16::from_le_bytes([a[0], a[1]]) as f64, "ushort" | "uint16" => u16::from_le_bytes([a[0], a[1]]) as f64,
+                        "int" | "int32" => i32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64, "uint" | "uint32" => u32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64,
+                        "float" | "float32" => f32::from_le_bytes([a[0], a[1], a[2], a[3]]) as f64,
+                        _ => f64::from_le_bytes(a),
+                    }
+                }).collect(),
+            };
+            let get = |i: usize| row.get(i).copied().filter(|x| x.is_finite()).ok_or_else(|| format!("vertex {v}: missing or non-finite coordinate"));
+            out.push([get(xi)? as f32, get(yi)? as f32, get(zi)? as f32]);
+        }
+        return Ok(out);
+    }
+    Err("PLY has no vertex element".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(format: &str, pre: &[u8], rows: &[[f32; 3]], encode: impl Fn(f32) -> Vec<u8>) -> Vec<u8> {
+        let ty = if format.ends_with("big_endian") { "double" } else { "float" };
+        let mut d = format!("ply\nformat {format} 1.0\nelement tag 2\nproperty uchar id\nelement vertex {}\nproperty {ty} x\nproperty {ty} y\nproperty {ty} z\nproperty uchar red\nend_header\n", rows.len()).into_bytes();
+        d.extend(pre);
+        for r in rows {
+            for &x in r { d.extend(encode(x)); }
+            d.push(255);
+        }
+        d
+    }
+
+    #[test]
+    fn ascii_ply_with_lf_or_crlf() {
+        let text = "ply\nformat ascii 1.0\ncomment made by hand\nelement note 1\nproperty float w\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n9\n0 0 0\n1 2.5 -3\n\n-1e-2 4 5\n3 0 1 2\n";
+        let want = vec![[0.0, 0.0, 0.0], [1.0, 2.5, -3.0], [-0.01, 4.0, 5.0]];
+        assert_eq!(parse_ply(text.as_bytes()), Ok(want.clone()));
+        assert_eq!(parse_ply(text.replace('\n', "\r\n").as_bytes()), Ok(want));
+        assert_eq!(ply_vertex_count(text.as_bytes()), Ok(3));
+    }
+
+    #[test]
+    fn binary_ply_in_either_byte_order() {
+        let rows = [[1.0, -2.0, 0.5], [3.0, 4.0, -5.25]];
+        let le = binary("binary_little_endian", &[7, 8], &rows, |x| x.to_le_bytes().to_vec());
+        assert_eq!(parse_ply(&le), Ok(rows.to_vec()));
+        let be = binary("binary_big_endian", &[7, 8], &rows, |x| (x as f64).to_be_bytes().to_vec());
+        assert_eq!(parse_ply(&be), Ok(rows.to_vec()));
+        // A truncated body is an error, not a short read.
+        assert_eq!(parse_ply(&le[..le.len() - 1]), Err("PLY ends early: element 'vertex' declares 2 rows".into()));
+    }
+
+    #[test]
+    fn malformed_ply_is_rejected() {
+        let err = |d: &str| parse_ply(d.as_bytes()).unwrap_err();
+        let head = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n";
+        assert_eq!(err("ply\nformat ascii 1.0\n"), "PLY has no end_header");
+        assert_eq!(err(&format!("{head}comment end_header\n0 0 0\n")), "PLY has no end_header");
+        assert_eq!(err(&format!("{head}end_header 0 0 0\n")), "PLY end_header must end its line");
+        assert_eq!(err("obj\nend_header\n"), "not a PLY file");
+        assert_eq!(err("ply\nformat binary_middle_endian 1.0\nend_header\n"), "unknown PLY format 'binary_middle_endian'");
+        assert_eq!(err(&format!("{head}end_header\n0 x 0\n")), "vertex 0: bad number");
+        assert_eq!(err(&format!("{head}end_header\n0 0\n")), "vertex 0: missing or non-finite coordinate");
+        assert_eq!(err("ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n0 0\n"), "vertex element has no 'z' property");
+        assert_eq!(err("ply\nformat ascii 1.0\nelement face 0\nend_header\n"), "PLY has no vertex element");
+        assert_eq!(err("ply\nformat ascii 1.0\nelement vertex -1\nend_header\n"), "bad element count '-1'");
+        let list = "ply\nformat binary_little_endian 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\nproperty list uchar int n\nend_header\n";
+        assert_eq!(err(list), "unsupported vertex property type 'list'");
+        let skip = "ply\nformat binary_little_endian 1.0\nelement face 1\nproperty list uchar int n\nelement vertex 0\nend_header\n";
+        assert_eq!(err(skip), "can't skip binary element 'face' with list properties");
+    }
+
+    #[test]
+    fn huge_counts_fail_before_allocating() {
+        let max = usize::MAX;
+        let ascii = format!("ply\nformat ascii 1.0\nelement vertex {max}\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n");
+        assert_eq!(parse_ply(ascii.as_bytes()), Err(format!("PLY ends early: element 'vertex' declares {max} rows")));
+        assert_eq!(ply_vertex_count(ascii.as_bytes()), Ok(max));
+        let skipped = format!("ply\nformat ascii 1.0\nelement junk {max}\nelement vertex 0\nend_header\n");
+        assert_eq!(parse_ply(skipped.as_bytes()), Err(format!("PLY ends early: element 'junk' declares {max} rows")));
+        // Row size times count overflows usize.
+        let bin = format!("ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty double x\nproperty double y\nproperty double z\nend_header\n", max / 8);
+        assert_eq!(parse_ply(bin.as_bytes()), Err(format!("PLY ends early: element 'vertex' declares {} rows", max / 8)));
+    }
+}
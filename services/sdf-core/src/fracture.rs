@@ -0,0 +1,111 @@
+//! Voronoi fracture: split a solid into one `Intersection(original, cell)` subtree per seed.
+//!
+//! A cell is the intersection of the bisector half-spaces towards every other seed,
+//! expressed with ordinary `Plane` nodes so the pieces stay plain SDF trees. With
+//! inner noise each shared face gets the same noise field with opposite sign on
+//! its two sides, so neighbouring pieces still fit together without gaps.
+
+use crate::bounds::Aabb;
+use crate::compiler::CompiledSdf;
+use crate::math::{self, V3};
+use crate::noise::Rng;
+use crate::tree::SdfNode;
+use serde_json::json;
+
+pub struct InnerNoise { pub amplitude: f32, pub frequency: f32, pub seed: u32 }
+
+pub struct Cell { pub seed: V3, pub tree: SdfNode }
+
+/// Draw `count` seeds uniformly from the inside of the solid.
+pub fn sample_seeds(sdf: &CompiledSdf, domain: &Aabb, count: usize, seed: u64) -> Result<Vec<V3>, String> {
+    let mut rng = Rng::new(seed);
+    let mut out = Vec::with_capacity(count);
+    for _ in 0..count.saturating_mul(2000) {
+        if out.len() == count { break; }
+        let p = rng.point_in(domain.min, domain.max);
+        if sdf.eval(p) < 0.0 { out.push(p); }
+    }
+    if out.len() < count {
+        return Err(format!("could only place {} of {count} seeds inside the solid", out.len()));
+    }
+    Ok(out)
+}
+
+pub fn fracture(tree: &SdfNode, domain: &Aabb, seeds: &[V3], noise: Option<&InnerNoise>) -> Vec<Cell> {
+    let margin = noise.map_or(0.0, |n| n.amplitude.abs() * 2.0);
+    seeds.iter().enumerate().map(|(i, &si)| {
+        let mut planes = Vec::new();
+        for (j, &sj) in seeds.iter().enumerate() {
+            if i == j || sj == si { continue; }
+            let n = math::normalize(math::sub(sj, si));
+            let h = -math::dot(math::mul(math::add(si, sj), 0.5), n);
+            // Skip half-spaces that already contain the whole domain.
+            let worst = (0..8).map(|c| {
+                let p = [if c & 1 == 0 { domain.min[0] } else { domain.max[0] }, if c & 2 == 0 { domain.min[1] } else { domain.max[1] }, if c & 4 == 0 { domain.min[2] } else { domain.max[2] }];
+                math::dot(p, n) + h
+            }).fold(f32::MIN, f32::max);
+            if worst + margin <= 0.0 { continue; }
+            let plane = SdfNode::leaf("Plane", json!({ "normal": n, "distance": h }));
+            planes.push(match noise {
+                Some(nz) => {
+                    let amp = if i < j { nz.amplitude } else { -nz.amplitude };
+                    SdfNode::unary("Noise", json!({ "amplitude": amp, "frequency": nz.frequency, "seed": nz.seed }), plane)
+                }
+                None => plane,
+            });
+        }
+        let piece = match planes.len() {
+            0 => tree.clone(),
+            1 => SdfNode::binary("Intersection", json!({}), tree.clone(), planes.pop().unwrap()),
+            _ => SdfNode::binary("Intersection", json!({}), tree.clone(), SdfNode::list("Intersection", json!({}), planes)),
+        };
+        Cell { seed: si, tree: piece }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bounds, compiler};
+
+    fn ball() -> SdfNode { SdfNode::leaf("Sphere", json!({ "radius": 1.0 })) }
+
+    #[test]
+    fn pieces_reassemble_the_solid() {
+        let (tree, mut rng) = (ball(), Rng::new(11));
+        let (sdf, domain) = (compiler::compile(&tree).unwrap(), bounds::bounds(&tree));
+        let seeds = sample_seeds(&sdf, &domain, 6, 7).unwrap();
+        let pieces: Vec<CompiledSdf> = fracture(&tree, &domain, &seeds, None).iter().map(|c| compiler::compile(&c.tree).unwrap()).collect();
+        assert_eq!(pieces.len(), 6);
+        for _ in 0..4000 {
+            let p = rng.point_in([-1.5; 3], [1.5; 3]);
+            let (d, u) = (sdf.eval(p), pieces.iter().map(|s| s.eval(p)).fold(f32::INFINITY, f32::min));
+            if d.abs() < 1e-4 { continue; }
+            // Outside, the nearest piece is as near as the solid; inside, some piece holds the point.
+            assert_eq!(d < 0.0, u < 0.0, "{p:?}: solid {d}, pieces {u}");
+            if d > 0.0 { assert!((d - u).abs() < 1e-5, "{p:?}: solid {d}, pieces {u}"); }
+        }
+    }
+
+    #[cfg(feature = "mesh")]
+    #[test]
+    fn pieces_are_watertight() {
+        let tree = ball();
+        let (sdf, domain) = (compiler::compile(&tree).unwrap(), bounds::bounds(&tree));
+        let seeds = sample_seeds(&sdf, &domain, 5, 3).unwrap();
+        for noise in [None, Some(InnerNoise { amplitude: 0.05, frequency: 2.0, seed: 9 })] {
+            for (i, c) in fracture(&tree, &domain, &seeds, noise.as_ref()).iter().enumerate() {
+                let piece = compiler::compile(&c.tree).unwrap();
+                let m = crate::mesh::generate(&piece, &bounds::bounds(&c.tree).intersect(&domain.expand(0.2)), 32, &mut |_| true).unwrap();
+                assert!(m.face_count() > 0 && m.is_watertight(), "piece {i} ({} faces) is not closed", m.face_count());
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_need_room_inside() {
+        let tree = SdfNode::leaf("Sphere", json!({ "radius": 0.0 }));
+        let sdf = compiler::compile(&tree).unwrap();
+        assert!(sample_seeds(&sdf, &Aabb::sym([1.0; 3]), 3, 1).unwrap_err().contains("0 of 3"));
+    }
+}
@@ -0,0 +1,384 @@
+//! Golden-distance regression corpus for the evaluator.
+//!
+//! Every node type has a small tree with points whose distances were worked out
+//! by hand from the node's formula. Each point goes through the scalar
+//! interpreter, the batch evaluator and the generated GLSL, baked and parametric
+//! (run by [`interp`]), and all must match the reference. Seeded random points
+//! check that the three paths agree away from the hand-picked ones, and property
+//! tests check that exact-distance primitives are 1-Lipschitz. With `jit`, native
+//! code must give the interpreter's distances exactly wherever it compiles.
+
+mod interp;
+
+use crate::compiler::{self, CompiledSdf};
+use crate::lipschitz::{self, EXACT};
+use crate::math::{self, V3};
+use crate::noise::Rng;
+use crate::schema;
+use crate::shader::{self, Target};
+use crate::tree::SdfNode;
+use interp::{Program, Val};
+use serde_json::{json, Value};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, LN_2, PI, SQRT_2};
+
+struct Case { tree: Value, points: Vec<(V3, f32)> }
+
+fn leaf(ty: &str, params: Value) -> Value { json!({ "type": ty, "params": params }) }
+fn unary(ty: &str, params: Value, child: Value) -> Value { json!({ "type": ty, "params": params, "child": child }) }
+fn binary(ty: &str, params: Value, a: Value, b: Value) -> Value { json!({ "type": ty, "params": params, "a": a, "b": b }) }
+fn nary(ty: &str, params: Value, children: Vec<Value>) -> Value { json!({ "type": ty, "params": params, "children": children }) }
+fn sphere(r: f32) -> Value { leaf("Sphere", json!({ "radius": r })) }
+fn at(offset: V3, child: Value) -> Value { unary("Translate", json!({ "offset": offset }), child) }
+/// `ty` over the half-spaces `x <= 0` and `y <= 0`, so `(a, b, 0)` evaluates `op(a, b)`.
+fn planes(ty: &str, params: Value) -> Value {
+    binary(ty, params, leaf("Plane", json!({ "normal": [1, 0, 0] })), leaf("Plane", json!({ "normal": [0, 1, 0] })))
+}
+
+fn case(tree: Value, points: &[(V3, f32)]) -> Case { Case { tree, points: points.to_vec() } }
+
+fn corpus() -> Vec<Case> {
+    let s3 = 3f32.sqrt();
+    let a = 1.0 / s3;
+    let (s5, c5) = (0.5f32.sin(), 0.5f32.cos());
+    let bx = |h: V3| leaf("Box3d", json!({ "half_size": h }));
+    let tpms = |ty: &str, scale: f32| leaf(ty, json!({ "scale": scale, "thickness": 0.1 }));
+    let deep = |ty: &str, params: Value| {
+        let mut p = params;
+        p["half_depth"] = json!(1.0);
+        leaf(ty, p)
+    };
+    vec![
+        // Primitives.
+        case(sphere(1.0), &[([2.0, 0.0, 0.0], 1.0), ([0.0, 0.25, 0.0], -0.75), ([1.0, 1.0, 1.0], s3 - 1.0)]),
+        case(bx([1.0, 2.0, 3.0]), &[([3.0, 0.0, 0.0], 2.0), ([0.0; 3], -1.0), ([2.0, 3.0, 0.0], SQRT_2), ([2.0, 3.0, 4.0], s3)]),
+        case(leaf("Cylinder", json!({ "radius": 0.5, "half_height": 1.0 })), &[([2.0, 0.0, 0.0], 1.5), ([0.0, 3.0, 0.0], 2.0), ([1.5, 2.0, 0.0], SQRT_2), ([0.0; 3], -0.5)]),
+        case(leaf("Torus", json!({ "major_radius": 1.0, "minor_radius": 0.25 })), &[([1.0, 0.0, 0.0], -0.25), ([0.0; 3], 0.75), ([3.0, 0.0, 4.0], 3.75)]),
+        case(leaf("Plane", json!({ "normal": [3, 0, 4], "distance": 0.5 })), &[([3.0, 0.0, 4.0], 5.5), ([0.0; 3], 0.5)]),
+        case(leaf("Plane", json!({})), &[([5.0, -2.0, 3.0], -2.0), ([0.0, 1.5, 0.0], 1.5)]),
+        case(leaf("Capsule", json!({ "radius": 0.3, "half_height": 0.5 })), &[([0.0, 2.0, 0.0], 1.2), ([1.0, 0.0, 0.0], 0.7), ([0.0; 3], -0.3)]),
+        case(leaf("Cone", json!({ "radius": 0.5, "height": 1.0 })), &[([0.0, 1.5, 0.0], 1.0), ([0.0, -1.5, 0.0], 1.0), ([0.0; 3], -0.5 / 5f32.sqrt()), ([1.0, -0.5, 0.0], 0.5)]),
+        case(leaf("RoundedBox", json!({ "half_size": [1, 1, 1], "radius": 0.2 })), &[([2.0, 0.0, 0.0], 1.0), ([2.0, 2.0, 0.0], SQRT_2 * 1.2 - 0.2)]),
+        case(leaf("Ellipsoid", json!({ "radii": [2, 1, 1] })), &[([3.0, 0.0, 0.0], 1.0), ([0.0, 2.0, 0.0], 1.0), ([0.0, 0.0, -1.5], 0.5)]),
+        case(leaf("Pyramid", json!({ "height": 1.0, "base": 1.0 })), &[([0.0, 2.0, 0.0], 1.0), ([0.0, -1.0, 0.0], 1.0), ([2.0, 0.0, 0.0], 1.5), ([0.0, 0.5, 0.0], -0.25 / 1.25f32.sqrt()), ([1.0, 1.0, 0.0], 2.0 / 5f32.sqrt())]),
+        case(leaf("Octahedron", json!({ "size": 1.0 })), &[([0.0; 3], -a), ([2.0, 0.0, 0.0], 1.0), ([1.0, 1.0, 1.0], 2.0 * a)]),
+        case(leaf("Tetrahedron", json!({ "size": 1.0 })), &[([0.0; 3], -a), ([0.0, 0.0, 2.0], a), ([0.0, 0.0, -2.0], a)]),
+        case(leaf("RoundedCone", json!({ "radius_bottom": 0.5, "radius_top": 0.25, "height": 1.0 })), &[([0.0, -2.0, 0.0], 1.0), ([0.0, 2.0, 0.0], 1.25), ([0.0, -0.5, 0.0], -0.5), ([0.0, 0.5, 0.0], -0.25)]),
+        case(leaf("HexPrism", json!({ "radius": 0.5, "half_depth": 0.5 })), &[([0.0; 3], -0.5), ([0.0, 2.0, 0.0], 1.5), ([0.0, 0.0, 2.0], 1.5), ([2.0, 0.0, 0.0], 2.0 - 0.5 / (PI / 6.0).cos())]),
+        case(leaf("Link", json!({ "half_length": 0.5, "radius": 0.5, "thickness": 0.1 })), &[([0.5, 0.0, 0.0], -0.1), ([0.0; 3], 0.4), ([0.0, 1.0, 0.0], -0.1), ([0.5, 0.0, 1.0], 0.9)]),
+        case(leaf("Triangle", json!({})), &[([0.0, 0.2, 1.0], 0.98), ([0.0, -1.0, 0.0], 0.98), ([0.0, 0.2, 0.0], -0.02)]),
+        case(leaf("Bezier", json!({ "radius": 0.1 })), &[([0.0, 0.5, 1.0], 0.9), ([0.0, 0.5, 0.0], -0.1), ([-2.0, 0.0, 0.0], 0.9), ([0.0, -1.0, 0.0], SQRT_2 - 0.1)]),
+        case(leaf("CappedCone", json!({ "half_height": 0.5, "radius_bottom": 0.5, "radius_top": 0.25 })), &[([0.0, 2.0, 0.0], 1.5), ([0.0, -2.0, 0.0], 1.5), ([0.0; 3], -0.375 / 1.0625f32.sqrt())]),
+        case(leaf("CappedTorus", json!({ "angle": 1.0, "major_radius": 1.0, "minor_radius": 0.25 })), &[([0.0, 1.0, 0.0], -0.25), ([0.0; 3], 0.75), ([0.0, -1.0, 0.0], (2.0 + 2.0 * 1f32.cos()).sqrt() - 0.25)]),
+        case(leaf("RoundedCylinder", json!({ "radius": 0.5, "round_radius": 0.1, "half_height": 1.0 })), &[([2.0, 0.0, 0.0], 1.5), ([0.0, 3.0, 0.0], 2.0), ([0.0; 3], -0.5)]),
+        case(leaf("TriangularPrism", json!({ "size": 1.0, "half_depth": 0.5 })), &[([0.0; 3], -0.5), ([0.0, -2.0, 0.0], 1.5), ([0.0, 0.0, 3.0], 2.5)]),
+        case(leaf("CutSphere", json!({ "radius": 1.0, "cut_height": 0.5 })), &[([0.0, 2.0, 0.0], 1.0), ([0.0, 0.75, 0.0], -0.25), ([0.0; 3], 0.5), ([2.0, 0.5, 0.0], 2.0 - 0.75f32.sqrt())]),
+        case(leaf("CutHollowSphere", json!({ "radius": 1.0, "cut_height": 0.5, "thickness": 0.05 })), &[([0.0, -1.0, 0.0], -0.05), ([0.0; 3], 0.95), ([0.0, 2.0, 0.0], s3 - 0.05)]),
+        case(leaf("DeathStar", json!({ "radius": 1.0, "cut_radius": 0.7, "cut_distance": 1.2 })), &[([-2.0, 0.0, 0.0], 1.0), ([0.0; 3], -0.5), ([0.0, 0.0, 2.0], 1.0)]),
+        case(leaf("SolidAngle", json!({ "angle": 0.5, "radius": 1.0 })), &[([0.0, 0.5, 0.0], -0.5 * s5), ([0.0, 2.0, 0.0], 1.0), ([0.0, -1.0, 0.0], 1.0)]),
+        case(leaf("Rhombus", json!({ "half_width": 0.6, "half_depth": 0.3, "half_height": 0.1, "radius": 0.05 })), &[([0.0, 1.0, 0.0], 0.9), ([2.0, 0.0, 0.0], 1.35), ([0.0; 3], -0.1)]),
+        case(leaf("Vesica", json!({ "radius": 1.0, "distance": 0.5 })), &[([0.0, 2.0, 0.0], 2.0 - 0.75f32.sqrt()), ([0.0; 3], -0.5), ([1.0, 0.0, 0.0], 0.5)]),
+        case(leaf("Egg", json!({ "radius": 0.5, "tip_radius": 0.1 })), &[([0.0, -1.0, 0.0], 0.5), ([0.0; 3], -0.5), ([2.0, 0.0, 0.0], 1.5), ([0.0, 2.0, 0.0], 2.0 - 0.4 * s3 - 0.1)]),
+        case(leaf("Tube", json!({ "radius": 0.5, "thickness": 0.1, "half_height": 1.0 })), &[([0.0; 3], 0.4), ([0.45, 0.0, 0.0], -0.05), ([0.45, 3.0, 0.0], 2.0), ([2.0, 0.0, 0.0], 1.5)]),
+        {
+            let k = 1.0 / 1.04f32.sqrt();
+            case(leaf("Barrel", json!({ "radius": 0.5, "half_height": 1.0, "bulge": 0.1 })), &[([2.0, 0.0, 0.0], 1.4 * k), ([0.0, 3.0, 0.0], 2.0 * k), ([0.0; 3], -0.6 * k)])
+        },
+        case(leaf("ChamferedCube", json!({ "half_size": [1, 1, 1], "chamfer": 0.2 })), &[([2.0, 0.0, 0.0], 1.0), ([0.0; 3], -1.0), ([2.0, 2.0, 0.0], 2.2 / SQRT_2)]),
+        case(leaf("Superellipsoid", json!({ "radii": [2, 2, 2] })), &[([4.0, 0.0, 0.0], 2.0), ([0.0; 3], -2.0)]),
+        case(leaf("Helix", json!({ "radius": 1.0, "thickness": 0.1, "pitch": 0.5, "half_height": 1.0 })), &[
+            ([1.0, 0.0, 0.0], -0.1), ([0.0; 3], 0.9), ([1.0, 3.0, 0.0], 2.0), ([1.0, 0.25, 0.0], 0.25 / (1.0 + (0.5 / (2.0 * PI)).powi(2)).sqrt() - 0.1),
+        ]),
+        case(leaf("BoxFrame", json!({ "half_size": [1, 1, 1], "thickness": 0.05 })), &[([0.0; 3], 1.62f32.sqrt()), ([0.95, 0.95, 0.0], -0.05), ([2.0, 0.0, 0.0], 1.81f32.sqrt())]),
+        case(leaf("InfiniteCylinder", json!({ "radius": 0.5 })), &[([2.0, 100.0, 0.0], 1.5), ([3.0, 0.0, 4.0], 4.5)]),
+        case(leaf("InfiniteCone", json!({ "angle": 0.5 })), &[([0.0, -1.0, 0.0], -s5), ([0.0, 1.0, 0.0], 1.0), ([1.0, 0.0, 0.0], c5)]),
+        case(leaf("Dodecahedron", json!({ "radius": 1.0 })), &[([0.0, 3.0 * 0.850_651, 3.0 * 0.525_731], 2.0), ([0.0; 3], -1.0)]),
+        case(leaf("Icosahedron", json!({ "radius": 1.0 })), &[([2.0 * a; 3], 1.0), ([0.0; 3], -1.0)]),
+        case(leaf("TruncatedOctahedron", json!({ "radius": 1.0 })), &[([3.0, 0.0, 0.0], 2.0), ([2.0; 3], 6.0 * a - 1.0)]),
+        case(leaf("TruncatedIcosahedron", json!({ "radius": 1.0 })), &[([3.0 * a; 3], 2.0), ([0.0; 3], -1.0)]),
+        // Triply periodic minimal surfaces.
+        case(tpms("Gyroid", 1.0), &[([0.0; 3], -0.1), ([FRAC_PI_2, 0.0, 0.0], 0.9), ([FRAC_PI_2; 3], -0.1)]),
+        case(tpms("SchwarzP", 1.0), &[([0.0; 3], 2.9), ([FRAC_PI_2; 3], -0.1)]),
+        case(tpms("SchwarzP", 2.0), &[([FRAC_PI_2, 0.0, 0.0], 0.4)]),
+        case(tpms("Diamond", 1.0), &[([0.0; 3], -0.1), ([FRAC_PI_2, 0.0, 0.0], 0.9), ([FRAC_PI_2; 3], 0.9)]),
+        case(tpms("DiamondSurface", 1.0), &[([0.0; 3], -0.1), ([FRAC_PI_2, 0.0, 0.0], 0.9), ([FRAC_PI_2; 3], 0.9)]),
+        case(tpms("Neovius", 1.0), &[([0.0; 3], 4.9), ([FRAC_PI_2; 3], -0.1)]),
+        case(tpms("Lidinoid", 1.0), &[([0.0; 3], 1.35 / 1.3 - 0.1), ([FRAC_PI_2, 0.0, 0.0], 0.4)]),
+        case(tpms("IWP", 1.0), &[([0.0; 3], 3.0 / 4.3 - 0.1), ([FRAC_PI_2, 0.0, 0.0], 1.0 / 4.3 - 0.1)]),
+        case(tpms("FRD", 1.0), &[([0.0; 3], 1.0 / 3.6 - 0.1), ([FRAC_PI_4; 3], SQRT_2 / 3.6 - 0.1)]),
+        case(tpms("FischerKochS", 1.0), &[([0.0; 3], -0.1), ([0.0, FRAC_PI_2, 0.0], 1.0 / 1.75 - 0.1)]),
+        case(tpms("PMY", 1.0), &[([0.0; 3], 2.0 / 1.95 - 0.1), ([FRAC_PI_2; 3], -0.1)]),
+        // Extruded 2D shapes: depth 1 keeps the Z cap out of the way at z = 0.
+        case(leaf("Horseshoe", json!({})), &[([0.0, -0.5, 0.0], -0.05), ([0.0, -1.0, 0.0], 0.45), ([0.0; 3], 0.45), ([0.0, -0.5, 1.0], 0.9)]),
+        case(deep("Heart", json!({ "size": 1.0 })), &[([0.0, -1.0, 0.0], 0.5), ([0.0; 3], -0.125f32.sqrt()), ([0.25, 0.5, 0.0], 0.25 - SQRT_2 / 4.0)]),
+        case(deep("RoundedX", json!({ "width": 1.0, "radius": 0.1 })), &[([0.0; 3], -0.1), ([0.5, 0.5, 0.0], -0.1), ([1.0, 0.0, 0.0], 0.5f32.sqrt() - 0.1)]),
+        case(deep("Pie", json!({ "angle": 0.8, "radius": 1.0 })), &[([0.0, 0.5, 0.0], -0.5 * 0.8f32.sin()), ([0.0, 2.0, 0.0], 1.0), ([0.0, -1.0, 0.0], 1.0)]),
+        case(deep("Trapezoid", json!({ "bottom_width": 0.5, "top_width": 0.25, "half_height": 0.5 })), &[([0.0, 2.0, 0.0], 1.5), ([0.0, -2.0, 0.0], 1.5), ([0.0; 3], -0.375 / 1.0625f32.sqrt())]),
+        case(deep("Parallelogram", json!({ "half_width": 0.5, "half_height": 0.3, "skew": 0.2 })), &[([0.0; 3], -0.3), ([0.0, 1.0, 0.0], 0.7), ([0.0, -1.0, 0.0], 0.7)]),
+        case(deep("Tunnel", json!({ "half_width": 0.5, "height": 1.0 })), &[([0.0; 3], -0.5), ([0.0, 1.0, 0.0], 0.5), ([0.0, -2.0, 0.0], 1.0), ([2.0, -0.5, 0.0], 1.5)]),
+        case(deep("UnevenCapsule", json!({ "radius_bottom": 0.3, "radius_top": 0.15, "height": 1.0 })), &[([0.0, -0.5, 0.0], -0.3), ([0.0, -2.0, 0.0], 1.2), ([0.0, 2.0, 0.0], 1.35)]),
+        case(deep("ArcShape", json!({ "angle": 1.0, "radius": 1.0, "thickness": 0.1 })), &[([0.0, 1.0, 0.0], -0.1), ([0.0; 3], 0.9), ([0.0, -1.0, 0.0], (2.0 + 2.0 * 1f32.cos()).sqrt() - 0.1)]),
+        case(deep("Moon", json!({ "radius": 1.0, "cut_radius": 0.8, "cut_distance": 0.5 })), &[([-2.0, 0.0, 0.0], 1.0), ([-0.9, 0.0, 0.0], -0.1), ([0.0; 3], 0.3)]),
+        case(deep("CrossShape", json!({ "half_length": 0.5, "half_width": 0.15 })), &[([0.0; 3], -0.15 * SQRT_2), ([1.0, 0.0, 0.0], 0.5), ([0.5, 0.5, 0.0], 0.35)]),
+        case(deep("BlobbyCross", json!({ "size": 1.0, "height": 0.5 })), &[([0.0; 3], 0.5 - 1.0 / SQRT_2), ([2.0, 2.0, 0.0], 5f32.sqrt())]),
+        case(deep("ParabolaSegment", json!({ "half_width": 0.5, "height": 1.0 })), &[([0.0, -1.0, 0.0], 1.0), ([0.0, 2.0, 0.0], 1.0), ([0.0, 0.5, 0.0], -0.109_375f32.sqrt())]),
+        case(leaf("StairsPrim", json!({})), &[([0.5, -0.5, 0.0], 0.5), ([2.0, 0.5, 0.0], 1.0), ([0.9, 0.1, 0.0], -0.1)]),
+        // 2D primitives, unbounded along Z.
+        case(leaf("Circle2d", json!({ "radius": 0.5 })), &[([2.0, 0.0, 7.0], 1.5), ([0.0; 3], -0.5)]),
+        case(leaf("Rect2d", json!({ "half_width": 0.5, "half_height": 0.5 })), &[([1.5, 1.5, 0.0], SQRT_2), ([0.0, 0.0, -3.0], -0.5)]),
+        case(leaf("RoundedRect2d", json!({ "half_width": 0.5, "half_height": 0.5, "radius": 0.1 })), &[([1.5, 1.5, 0.0], 1.1 * SQRT_2 - 0.1), ([2.0, 0.0, 0.0], 1.5)]),
+        case(leaf("Segment2d", json!({})), &[([0.0, 1.0, 5.0], 0.95), ([2.0, 0.0, 0.0], 1.45)]),
+        case(leaf("Polygon2d", json!({})), &[([0.0, -1.0, 0.0], 0.5), ([0.0, 2.0, 0.0], 1.5), ([0.0; 3], -0.25 / 1.25f32.sqrt())]),
+        case(leaf("Annular2d", json!({ "radius": 0.5, "thickness": 0.05 })), &[([0.5, 0.0, 0.0], -0.05), ([0.0; 3], 0.45)]),
+        case(leaf("RegularPolygon", json!({ "radius": 0.5, "sides": 4 })), &[([0.0, 2.0, 0.0], 1.5), ([0.0; 3], -0.5 * FRAC_PI_4.cos()), ([1.0, 1.0, 0.0], 1.5 / SQRT_2)]),
+        case(leaf("StarPolygon", json!({})), &[([0.0, 2.0, 0.0], 1.5), ([0.0; 3], -0.25)]),
+        case(leaf("MeshSdf", json!({ "vertices": [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1], "indices": [0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3] })), &[
+            ([-1.0, 0.0, 0.0], 1.0), ([0.1, 0.1, 0.1], -0.1), ([2.0, 2.0, 2.0], 5.0 * a),
+        ]),
+        // Operations on the half-spaces x <= 0 and y <= 0.
+        case(nary("Union", json!({}), vec![at([-2.0, 0.0, 0.0], sphere(0.5)), sphere(0.5), at([2.0, 0.0, 0.0], sphere(0.5))]), &[([2.0, 0.0, 0.0], -0.5), ([1.0, 0.0, 0.0], 0.5)]),
+        case(binary("Intersection", json!({}), bx([1.0; 3]), sphere(1.2)), &[([0.0; 3], -1.0), ([2.0, 0.0, 0.0], 1.0)]),
+        case(binary("Subtraction", json!({}), bx([1.0; 3]), sphere(0.5)), &[([0.0; 3], 0.5), ([0.9, 0.0, 0.0], -0.1)]),
+        case(planes("SmoothUnion", json!({ "k": 0.5 })), &[([0.0; 3], -0.125), ([1.0, 0.0, 0.0], 0.0), ([0.25, 0.0, 0.0], -0.03125)]),
+        case(planes("SmoothIntersection", json!({ "k": 0.5 })), &[([0.0; 3], 0.125), ([1.0, -1.0, 0.0], 1.0)]),
+        case(planes("SmoothSubtraction", json!({ "k": 0.5 })), &[([0.0; 3], 0.125), ([0.0, 1.0, 0.0], 0.0)]),
+        case(planes("ChamferUnion", json!({ "r": 0.2 })), &[([0.0; 3], -0.2 / SQRT_2), ([1.0, 2.0, 0.0], 1.0)]),
+        case(planes("ChamferIntersection", json!({ "r": 0.2 })), &[([0.0; 3], 0.2 / SQRT_2), ([1.0, 2.0, 0.0], 3.2 / SQRT_2)]),
+        case(planes("ChamferSubtraction", json!({ "r": 0.2 })), &[([0.0; 3], 0.2 / SQRT_2)]),
+        case(planes("StairsUnion", json!({ "r": 0.4, "steps": 4 })), &[([0.0; 3], -0.2), ([1.0, 1.0, 0.0], 0.8), ([2.0, 0.0, 0.0], 0.0)]),
+        case(planes("StairsIntersection", json!({ "r": 0.4, "steps": 4 })), &[([0.0; 3], 0.2)]),
+        case(planes("StairsSubtraction", json!({ "r": 0.4, "steps": 4 })), &[([0.0; 3], 0.2)]),
+        case(planes("ExpSmoothUnion", json!({ "k": 0.1 })), &[([0.0; 3], -0.1 * LN_2)]),
+        case(planes("ExpSmoothIntersection", json!({ "k": 0.1 })), &[([0.0; 3], 0.1 * LN_2)]),
+        case(planes("ExpSmoothSubtraction", json!({ "k": 0.1 })), &[([0.0; 3], 0.1 * LN_2)]),
+        case(planes("ColumnsUnion", json!({ "r": 0.4, "steps": 4 })), &[([1.0, 1.0, 0.0], 1.0), ([0.0; 3], -0.174_942)]),
+        case(planes("ColumnsIntersection", json!({ "r": 0.4, "steps": 4 })), &[([0.0; 3], 0.269_029)]),
+        case(planes("ColumnsSubtraction", json!({ "r": 0.4, "steps": 4 })), &[([0.0; 3], 0.269_029)]),
+        case(planes("Xor", json!({})), &[([1.0, 2.0, 0.0], 1.0), ([-1.0, -2.0, 0.0], 1.0), ([-1.0, 2.0, 0.0], -1.0)]),
+        case(planes("Morph", json!({ "t": 0.25 })), &[([1.0, 2.0, 0.0], 1.25)]),
+        case(planes("Pipe", json!({ "r": 0.1 })), &[([0.3, 0.4, 0.0], 0.4)]),
+        case(planes("Engrave", json!({ "r": 0.1 })), &[([0.0; 3], 0.1 / SQRT_2), ([-1.0, 0.0, 0.0], -0.9 / SQRT_2)]),
+        case(planes("Groove", json!({ "depth": 0.1, "width": 0.05 })), &[([0.0; 3], 0.05)]),
+        case(planes("Tongue", json!({ "height": 0.1, "width": 0.05 })), &[([0.0; 3], -0.05), ([0.5, 1.0, 0.0], 0.5)]),
+        // Transforms.
+        case(at([1.0, 2.0, 3.0], sphere(1.0)), &[([1.0, 2.0, 5.0], 1.0)]),
+        case(unary("RotateEuler", json!({ "angles": [0.0, 0.0, FRAC_PI_2] }), bx([2.0, 0.5, 0.5])), &[([0.0, 3.0, 0.0], 1.0), ([3.0, 0.0, 0.0], 2.5)]),
+        case(unary("RotateQuat", json!({ "quaternion": [0, 0, 1, 1] }), bx([2.0, 0.5, 0.5])), &[([0.0, 3.0, 0.0], 1.0), ([3.0, 0.0, 0.0], 2.5)]),
+        case(unary("Scale", json!({ "factor": 2.0 }), sphere(1.0)), &[([3.0, 0.0, 0.0], 1.0), ([0.0; 3], -2.0)]),
+        case(unary("ScaleNonUniform", json!({ "factors": [2, 1, 1] }), sphere(1.0)), &[([3.0, 0.0, 0.0], 0.5), ([0.0, 2.0, 0.0], 1.0)]),
+        case(unary("ProjectiveTransform", json!({ "matrix": [1, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1] }), sphere(1.0)), &[([3.0, 0.0, 0.0], 1.0)]),
+        case(unary("ProjectiveTransform", json!({ "matrix": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 2], "lipschitz": 2.0 }), sphere(1.0)), &[([1.0, 0.0, 0.0], 0.5)]),
+        // Deformations.
+        case(unary("Twist", json!({ "strength": 1.0 }), leaf("Cylinder", json!({}))), &[([2.0, 0.3, 0.0], 1.5)]),
+        case(unary("Twist", json!({ "strength": 1.0 }), bx([2.0, 1.0, 0.5])), &[([0.0, FRAC_PI_2, 1.5], FRAC_PI_2 - 1.0)]),
+        case(unary("Bend", json!({ "strength": 1.0 }), sphere(1.0)), &[([0.0, 2.0, 0.0], 1.0), ([FRAC_PI_2, 0.0, 0.0], FRAC_PI_2 - 1.0)]),
+        case(unary("Taper", json!({ "strength": 0.5 }), leaf("Cylinder", json!({}))), &[([0.0; 3], -0.5), ([2.0, 0.0, 0.0], 1.5 / SQRT_2), ([0.0, 3.0, 0.0], 2.0)]),
+        case(unary("LatticeDeform", json!({}), sphere(1.0)), &[([2.0, 0.0, 0.0], 1.0)]),
+        case(unary("LatticeDeform", json!({ "offsets": [0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0, 0.5, 0, 0] }), sphere(0.5)), &[([0.5, 0.0, 0.0], -0.5)]),
+        case(unary("SdfSkinning", json!({ "bones": [{ "offset": [1, 0, 0], "radius": 1.0 }] }), sphere(0.5)), &[([1.0, 0.3, 0.0], -0.2)]),
+        // Sketch modifiers.
+        case(unary("Extrude", json!({ "half_depth": 0.5 }), leaf("Circle2d", json!({ "radius": 1.0 }))), &[([0.0; 3], -0.5), ([2.0, 0.0, 1.5], SQRT_2)]),
+        case(unary("Revolution", json!({ "offset": 1.0 }), leaf("Circle2d", json!({ "radius": 0.25 }))), &[([1.0, 0.0, 0.0], -0.25), ([3.0, 0.0, 4.0], 3.75)]),
+        case(unary("Sweep", json!({}), leaf("Circle2d", json!({ "radius": 0.1 }))), &[([0.0, 0.05, 0.5], -0.05), ([0.0, 1.0, 0.5], 0.9)]),
+        // Patterns.
+        case(unary("Repeat", json!({ "spacing": [2, 2, 2] }), sphere(0.5)), &[([4.1, 0.0, 0.0], -0.4), ([1.0, 1.0, 1.0], s3 - 0.5), ([3.0, 0.0, -2.0], 0.5)]),
+        case(unary("RepeatFinite", json!({ "spacing": [2, 2, 2], "count": [3, 1, 1] }), sphere(0.5)), &[([2.25, 0.0, 0.0], -0.25), ([6.0, 0.0, 0.0], 3.5), ([0.0, 4.0, 0.0], 3.5)]),
+        case(unary("Mirror", json!({ "axis": [1, 0, 0] }), at([1.0, 0.0, 0.0], sphere(0.5))), &[([-1.0, 0.0, 0.0], -0.5), ([0.0; 3], 0.5)]),
+        case(unary("PolarRepeat", json!({ "count": 4, "radius": 1.0 }), sphere(0.5)), &[([0.0, 0.0, 1.0], -0.5), ([0.0, 0.0, -1.0], -0.5), ([0.0, 1.0, 0.0], SQRT_2 - 0.5)]),
+        case(unary("MirrorOctant", json!({}), at([1.0; 3], sphere(0.5))), &[([-1.0; 3], -0.5), ([1.0, -1.0, 1.0], -0.5)]),
+        case(unary("IcosahedralSymmetry", json!({}), sphere(1.0)), &[([2.0, 0.0, 0.0], 1.0), ([0.3, -0.4, 0.0], -0.5), ([1.0, 1.0, 1.0], s3 - 1.0)]),
+        case(unary("IFS", json!({ "iterations": 1, "scale": 3.0, "offset": [1, 1, 1] }), bx([1.0; 3])), &[([0.0; 3], SQRT_2 / 3.0), ([1.0; 3], 0.0), ([3.0, 0.0, 0.0], 37f32.sqrt() / 3.0)]),
+        // Surface modifiers; noise vanishes on the integer lattice.
+        case(unary("Noise", json!({ "amplitude": 0.1 }), sphere(1.0)), &[([2.0, 0.0, 0.0], 1.0), ([1.0, 1.0, 1.0], s3 - 1.0)]),
+        case(unary("SurfaceRoughness", json!({ "amplitude": 0.1, "octaves": 1 }), sphere(1.0)), &[([2.0, 0.0, 0.0], 1.0), ([0.0, -3.0, 0.0], 2.0)]),
+        case(unary("Displacement", json!({ "amplitude": 0.05 }), sphere(1.0)), &[([PI / 10.0; 3], s3 * PI / 10.0 - 1.0 + 0.05)]),
+        case(unary("HeightmapDisplacement", json!({ "heights": [0, 1, 0, 1], "columns": 2, "rows": 2, "amplitude": 0.1 }), leaf("Plane", json!({}))), &[([0.0; 3], -0.05), ([0.5, 1.0, 0.0], 0.925)]),
+        case(unary("Shell", json!({ "thickness": 0.1 }), sphere(1.0)), &[([0.0; 3], 0.9), ([0.95, 0.0, 0.0], -0.05)]),
+        case(unary("Onion", json!({ "thickness": 0.1 }), sphere(1.0)), &[([0.0; 3], 0.9), ([1.0, 0.0, 0.0], -0.1)]),
+    ]
+}
+
+fn compile(tree: &Value) -> CompiledSdf {
+    let node = SdfNode::from_json(tree).unwrap_or_else(|e| panic!("{tree}: {e}"));
+    compiler::compile(&node).unwrap_or_else(|e| panic!("{tree}: {e}"))
+}
+
+/// Shader distance at `p`, undoing the step scale the transpiler applies.
+fn shader_eval(prog: &Program, sdf: &CompiledSdf, p: V3) -> f32 { prog.call("sdf_eval", &[Val::vec3(p)]).float() / sdf.step_scale }
+
+fn close(got: f32, want: f32, tol: f32) -> bool { (got - want).abs() <= tol * want.abs().max(1.0) }
+
+fn check_all(path: &str, eval: impl Fn(&CompiledSdf, &Case) -> Vec<f32>) {
+    let mut bad = Vec::new();
+    for c in corpus() {
+        let got = eval(&compile(&c.tree), &c);
+        for (&(p, want), d) in c.points.iter().zip(got) {
+            if !close(d, want, 1e-4) { bad.push(format!("{} at {p:?}: {path} gave {d}, expected {want}", c.tree["type"])); }
+        }
+    }
+    assert!(bad.is_empty(), "{} mismatches:\n{}", bad.len(), bad.join("\n"));
+}
+
+#[test]
+fn corpus_covers_every_node_type() {
+    fn types(v: &Value, out: &mut Vec<String>) {
+        out.extend(v["type"].as_str().map(String::from));
+        for k in ["a", "b", "child"] { if let Some(c) = v.get(k) { types(c, out); } }
+        for c in v["children"].as_array().into_iter().flatten() { types(c, out); }
+    }
+    let mut seen = Vec::new();
+    for c in corpus() {
+        let errs = schema::check(&SdfNode::from_json(&c.tree).expect("corpus tree parses"));
+        assert!(errs.is_empty(), "{}: {errs:?}", c.tree);
+        types(&c.tree, &mut seen);
+    }
+    let missing: Vec<&str> = schema::NODES.iter().map(|n| n.name).filter(|n| !seen.iter().any(|s| s == n)).collect();
+    assert!(missing.is_empty(), "no golden case for {missing:?}");
+}
+
+#[test]
+fn scalar_matches_reference() {
+    check_all("eval", |sdf, c| c.points.iter().map(|&(p, _)| sdf.eval(p)).collect());
+}
+
+#[test]
+fn batch_matches_reference() {
+    // Enough repeats to span several batch chunks, so chunk boundaries are covered too.
+    check_all("eval_batch", |sdf, c| {
+        let pts: Vec<V3> = c.points.iter().map(|&(p, _)| p).cycle().take(c.points.len() * (2500 / c.points.len() + 1)).collect();
+        let d = sdf.eval_batch(&pts);
+        for (i, x) in d.iter().enumerate() { assert_eq!(x.to_bits(), d[i % c.points.len()].to_bits(), "{} batch entry {i} differs from its repeat", c.tree["type"]); }
+        d[..c.points.len()].to_vec()
+    });
+}
+
+#[test]
+fn shader_matches_reference() {
+    check_all("GLSL", |sdf, c| {
+        let prog = Program::parse(&shader::transpile(sdf, Target::Glsl));
+        c.points.iter().map(|&(p, _)| shader_eval(&prog, sdf, p)).collect()
+    });
+}
+
+#[test]
+fn parametric_shader_matches_reference() {
+    check_all("GLSL params", |sdf, c| {
+        let (src, params) = shader::transpile_parametric(sdf, Target::Glsl);
+        let items = params.iter().map(|v| if *v < 0.0 { format!("({v:?})") } else { format!("{v:?}") }).collect::<Vec<_>>().join(", ");
+        let data = format!("const float sdf_params[{n}] = float[{n}]({items});", n = params.len());
+        let prog = Program::parse(&src.replace("layout(std430, binding = 1) readonly buffer SdfParams { float sdf_params[]; };", &data));
+        c.points.iter().map(|&(p, _)| shader_eval(&prog, sdf, p)).collect()
+    });
+    let (a, pa) = shader::transpile_parametric(&compile(&at([1.0, 0.0, 0.0], sphere(1.0))), Target::Wgsl);
+    let (b, pb) = shader::transpile_parametric(&compile(&at([0.5, 2.0, 0.0], sphere(1.5))), Target::Wgsl);
+    assert_eq!(a, b);
+    assert_ne!(pa, pb);
+}
+
+#[test]
+fn paths_agree_on_random_points() {
+    let mut rng = Rng::new(44);
+    let mut bad = Vec::new();
+    for c in corpus() {
+        let sdf = compile(&c.tree);
+        let prog = Program::parse(&shader::transpile(&sdf, Target::Glsl));
+        let pts: Vec<V3> = (0..64).map(|_| rng.point_in([-3.0; 3], [3.0; 3])).collect();
+        let batch = sdf.eval_batch(&pts);
+        for (&p, b) in pts.iter().zip(batch) {
+            let (s, g) = (sdf.eval(p), shader_eval(&prog, &sdf, p));
+            if !close(b, s, 1e-4) || !close(g, s, 1e-4) { bad.push(format!("{} at {p:?}: eval {s}, batch {b}, GLSL {g}", c.tree["type"])); }
+        }
+    }
+    assert!(bad.is_empty(), "{} disagreements:\n{}", bad.len(), bad.join("\n"));
+}
+
+#[test]
+fn exact_primitives_are_1_lipschitz() {
+    let mut rng = Rng::new(45);
+    for ty in EXACT {
+        let sdf = compile(&leaf(ty, json!({})));
+        for i in 0..2000 {
+            let p = rng.point_in([-2.0; 3], [2.0; 3]);
+            // Half the pairs are close together, where a wrong gradient shows up first.
+            let q = if i % 2 == 0 { rng.point_in([-2.0; 3], [2.0; 3]) } else { math::add(p, rng.point_in([-0.5; 3], [0.5; 3])) };
+            let (dp, dq, gap) = (sdf.eval(p), sdf.eval(q), math::len(math::sub(p, q)));
+            assert!((dp - dq).abs() <= gap * (1.0 + 1e-3) + 1e-5, "{ty}: |d({p:?}) - d({q:?})| = |{dp} - {dq}| exceeds the distance {gap}");
+        }
+    }
+}
+
+#[test]
+fn lipschitz_bounds_hold() {
+    let mut rng = Rng::new(50);
+    let mut bad = Vec::new();
+    for c in corpus() {
+        let node = SdfNode::from_json(&c.tree).unwrap();
+        let (sdf, l) = (compile(&c.tree), lipschitz::analyze(&node).lipschitz);
+        let b = crate::bounds::bounds(&node).expand(1.0).clamp_to(3.0);
+        let (lo, hi) = if b.is_empty() { ([-3.0; 3], [3.0; 3]) } else { (b.min, b.max) };
+        for i in 0..500 {
+            let p = rng.point_in(lo, hi);
+            let q = if i % 2 == 0 { rng.point_in(lo, hi) } else { math::add(p, rng.point_in([-0.1; 3], [0.1; 3])) };
+            let (dp, dq, gap) = (sdf.eval(p), sdf.eval(q), math::len(math::sub(p, q)));
+            // Tracers step from outside; the bound is only claimed there.
+            if dp > 0.0 && dq > 0.0 && (dp - dq).abs() > l * gap * (1.0 + 1e-3) + 1e-4 { bad.push(format!("{}: |{dp} - {dq}| over {gap} exceeds L = {l}", c.tree["type"])); break; }
+        }
+    }
+    assert!(bad.is_empty(), "{} violations:\n{}", bad.len(), bad.join("\n"));
+
+    let analyze = |t: &Value| lipschitz::analyze(&SdfNode::from_json(t).unwrap());
+    let ball = analyze(&at([1.0, 0.0, 0.0], sphere(1.0)));
+    assert!(ball.exact && ball.lipschitz == 1.0 && ball.step_scale == 1.0 && ball.warnings.is_empty());
+    let twist = json!({ "type": "Twist", "params": { "strength": 2.0 }, "child": leaf("Box3d", json!({ "half_size": [1.0, 1.0, 1.0] })) });
+    let a = analyze(&twist);
+    assert!(a.step_scale < 0.5 && a.warnings.len() == 1 && a.warnings[0].path == "root" && a.warnings[0].step_scale == a.step_scale, "{a:?}");
+    assert!(shader::transpile(&compile(&twist), Target::Glsl).contains(&format!("* {:?};", a.step_scale)));
+    let blend = analyze(&binary("SmoothUnion", json!({ "k": 0.3 }), sphere(1.0), at([1.0, 0.0, 0.0], sphere(1.0))));
+    assert!(!blend.exact && blend.step_scale == 1.0 && blend.warnings.len() == 1 && blend.nodes.len() == 4, "{blend:?}");
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn jit_matches_interpreter() {
+    let mut rng = Rng::new(48);
+    let (mut native, mut bad) = (0, Vec::new());
+    for c in corpus() {
+        let sdf = compile(&c.tree);
+        let Ok(jit) = crate::jit::compile(&sdf) else { continue };
+        native += 1;
+        let pts: Vec<V3> = c.points.iter().map(|&(p, _)| p).chain((0..64).map(|_| rng.point_in([-3.0; 3], [3.0; 3]))).collect();
+        for (&p, j) in pts.iter().zip(jit.eval_batch(&pts)) {
+            let s = sdf.eval(p);
+            if j != s && !(j.is_nan() && s.is_nan()) { bad.push(format!("{} at {p:?}: eval {s}, jit {j}", c.tree["type"])); }
+        }
+    }
+    assert!(native >= 40, "only {native} corpus trees compiled natively");
+    assert!(bad.is_empty(), "{} disagreements:\n{}", bad.len(), bad.join("\n"));
+    let gyroid = SdfNode::from_json(&binary("Union", json!({}), leaf("Gyroid", json!({})), sphere(1.0))).unwrap();
+    assert_eq!(crate::jit::compile(&compiler::compile(&gyroid).unwrap()).err().as_deref(), Some("Tpms is not supported by the JIT"));
+    let (cache, ball) = (crate::jit::Cache::new(1), SdfNode::from_json(&sphere(1.0)).unwrap());
+    let sdf = compiler::compile(&ball).unwrap();
+    let (a, b) = (cache.get(&ball, &sdf).unwrap(), cache.get(&ball, &sdf).unwrap());
+    assert!(std::sync::Arc::ptr_eq(&a, &b));
+    assert!(cache.get(&gyroid, &compiler::compile(&gyroid).unwrap()).is_err());
+    assert_eq!(crate::eval::Field::eval(&*cache.get(&ball, &sdf).unwrap(), [2.0, 0.0, 0.0]), 1.0);
+}
+
+#[test]
+fn bvh_matches_interpreter() {
+    check_all("bvh", |_, c| crate::eval_bvh::BvhSdf::new(&SdfNode::from_json(&c.tree).unwrap()).unwrap().eval_batch(&c.points.iter().map(|&(p, _)| p).collect::<Vec<_>>()));
+    // A scene of a few hundred exact parts, some under a shared transform, plus an unbounded floor.
+    let mut rng = Rng::new(49);
+    let mut parts: Vec<Value> = (0..300).map(|i| {
+        let c = rng.point_in([-20.0; 3], [20.0; 3]);
+        at(c, if i % 2 == 0 { sphere(0.3 + i as f32 * 0.002) } else { leaf("Box3d", json!({ "half_size": [0.4, 0.2, 0.6] })) })
+    }).collect();
+    let group = nary("Union", json!({}), parts.split_off(250));
+    parts.push(unary("RotateEuler", json!({ "angles": [0.3, 0.7, 0.0] }), unary("Scale", json!({ "factor": 1.5 }), group)));
+    parts.push(leaf("Plane", json!({ "normal": [0.0, 1.0, 0.0], "distance": 25.0 })));
+    let tree = nary("Union", json!({}), parts);
+    let bvh = crate::eval_bvh::BvhSdf::new(&SdfNode::from_json(&tree).unwrap()).unwrap();
+    assert_eq!(bvh.parts(), 301);
+    let sdf = compile(&tree);
+    let pts: Vec<V3> = (0..4000).map(|_| rng.point_in([-30.0; 3], [30.0; 3])).collect();
+    for (&p, d) in pts.iter().zip(bvh.eval_batch(&pts)) { assert_eq!(d, sdf.eval(p), "at {p:?}"); }
+}
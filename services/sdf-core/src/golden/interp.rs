@@ -0,0 +1,439 @@
+//! Interpreter for the GLSL-like dialect `shader::transpile` writes, so tests
+//! can run generated shaders on the CPU. It is as strict as the strictest
+//! target: no implicit int/float conversion, no mixed vector/scalar builtin
+//! calls, declarations and assignments must keep their type. Anything outside
+//! the dialect panics with the offending token.
+
+use std::collections::HashMap;
+
+/// A float or vector (components, length 1..=4), int, uint or bool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Val { F([f32; 4], usize), I(i32), U(u32), B(bool) }
+
+impl Val {
+    pub fn vec3(v: [f32; 3]) -> Val { Val::F([v[0], v[1], v[2], 0.0], 3) }
+    pub fn float(self) -> f32 { match self { Val::F(c, 1) => c[0], v => panic!("expected float, got {v:?}") } }
+    fn ty(self) -> &'static str {
+        match self { Val::F(_, n) => ["", "float", "vec2", "vec3", "vec4"][n], Val::I(_) => "int", Val::U(_) => "uint", Val::B(_) => "bool" }
+    }
+}
+
+fn vector(n: usize, f: impl Fn(usize) -> f32) -> Val {
+    let mut c = [0.0; 4];
+    for (k, x) in c.iter_mut().enumerate().take(n) { *x = f(k); }
+    Val::F(c, n)
+}
+
+#[derive(Debug)]
+enum Expr { Lit(Val), Var(String), Neg(Box<Expr>), Not(Box<Expr>), Bin(String, Box<Expr>, Box<Expr>), Call(String, Vec<Expr>), Swizzle(Box<Expr>, String), Index(String, Box<Expr>) }
+
+#[derive(Debug)]
+enum Stmt { Decl(String, String, Expr), Assign(String, Option<String>, Expr), Inc(String), If(Expr, Vec<Stmt>, Vec<Stmt>), For(Box<Stmt>, Expr, Box<Stmt>, Vec<Stmt>), Return(Expr) }
+
+struct Func { ret: String, params: Vec<(String, String)>, body: Vec<Stmt> }
+
+pub struct Program { funcs: HashMap<String, Func>, arrays: HashMap<String, Vec<f32>> }
+
+const TYPES: [&str; 7] = ["float", "vec2", "vec3", "vec4", "int", "uint", "bool"];
+const PUNCT: [&str; 13] = ["<=", ">=", "==", "!=", "&&", "||", "++", ">>", "<<", "+=", "-=", "*=", "/="];
+/// Binary operators from loosest to tightest binding.
+const LEVELS: [&[&str]; 10] = [&["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+fn lex(src: &str) -> Vec<String> {
+    let b = src.as_bytes();
+    let (mut out, mut i) = (Vec::new(), 0);
+    while i < b.len() {
+        let c = b[i];
+        if c.is_ascii_whitespace() { i += 1; continue; }
+        if c == b'#' || src[i..].starts_with("//") { while i < b.len() && b[i] != b'\n' { i += 1; } continue; }
+        let start = i;
+        if c.is_ascii_digit() {
+            i += 1;
+            let hex = src[start..].starts_with("0x");
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'.' || (!hex && matches!(b[i], b'+' | b'-') && matches!(b[i - 1], b'e' | b'E'))) { i += 1; }
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'_') { i += 1; }
+        } else if PUNCT.iter().any(|p| src[i..].starts_with(p)) {
+            i += 2;
+        } else {
+            i += 1;
+        }
+        out.push(src[start..i].to_string());
+    }
+    out
+}
+
+fn number(t: &str) -> Val {
+    fn parse<T: std::str::FromStr>(t: &str, radix16: bool) -> T {
+        let v = if radix16 { u32::from_str_radix(t, 16).ok().and_then(|x| x.to_string().parse().ok()) } else { t.parse().ok() };
+        v.unwrap_or_else(|| panic!("bad number literal {t}"))
+    }
+    if let Some(h) = t.strip_prefix("0x") {
+        let v: u32 = parse(h.trim_end_matches('u'), true);
+        return if h.ends_with('u') { Val::U(v) } else { Val::I(v as i32) };
+    }
+    if let Some(u) = t.strip_suffix('u') { return Val::U(parse(u, false)); }
+    if t.contains(['.', 'e', 'E']) { Val::F([parse(t, false), 0.0, 0.0, 0.0], 1) } else { Val::I(parse(t, false)) }
+}
+
+struct Parser { t: Vec<String>, i: usize }
+
+impl Parser {
+    fn peek(&self) -> &str { self.t.get(self.i).map_or("", |s| s.as_str()) }
+    fn peek_at(&self, k: usize) -> &str { self.t.get(self.i + k).map_or("", |s| s.as_str()) }
+    fn next(&mut self) -> String { let s = self.peek().to_string(); self.i += 1; s }
+    fn eat(&mut self, s: &str) -> bool { if self.peek() == s { self.i += 1; true } else { false } }
+    fn expect(&mut self, s: &str) {
+        if !self.eat(s) { panic!("expected '{s}' at token {} ('{}') near: {}", self.i, self.peek(), self.t[self.i.saturating_sub(8)..(self.i + 8).min(self.t.len())].join(" ")); }
+    }
+    fn ident(&mut self) -> String {
+        let s = self.next();
+        assert!(s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'), "expected an identifier, got '{s}'");
+        s
+    }
+
+    fn program(&mut self) -> Program {
+        let (mut funcs, mut arrays) = (HashMap::new(), HashMap::new());
+        while self.i < self.t.len() {
+            if self.eat("const") {
+                self.expect("float");
+                let name = self.ident();
+                self.expect("[");
+                let n: usize = self.next().parse().expect("array length");
+                self.expect("]");
+                self.expect("=");
+                self.expect("float");
+                self.expect("[");
+                self.expect(&n.to_string());
+                self.expect("]");
+                self.expect("(");
+                let mut v = Vec::with_capacity(n);
+                loop {
+                    let neg = self.eat("(") && self.eat("-");
+                    let x = number(&self.next()).float();
+                    if neg { self.expect(")"); }
+                    v.push(if neg { -x } else { x });
+                    if !self.eat(",") { break; }
+                }
+                self.expect(")");
+                self.expect(";");
+                assert_eq!(v.len(), n, "array {name} length");
+                arrays.insert(name, v);
+                continue;
+            }
+            let ret = self.next();
+            assert!(TYPES.contains(&ret.as_str()), "expected a function or const array, got '{ret}'");
+            let name = self.ident();
+            self.expect("(");
+            let mut params = Vec::new();
+            while !self.eat(")") {
+                let ty = self.next();
+                assert!(TYPES.contains(&ty.as_str()), "bad parameter type '{ty}' in {name}");
+                params.push((ty, self.ident()));
+                self.eat(",");
+            }
+            let body = self.block();
+            assert!(funcs.insert(name.clone(), Func { ret, params, body }).is_none(), "function {name} defined twice");
+        }
+        Program { funcs, arrays }
+    }
+
+    fn block(&mut self) -> Vec<Stmt> {
+        if !self.eat("{") { return vec![self.stmt()]; }
+        let mut out = Vec::new();
+        while !self.eat("}") { out.push(self.stmt()); }
+        out
+    }
+
+    /// A statement without its trailing `;` where one is needed.
+    fn simple(&mut self) -> Stmt {
+        if TYPES.contains(&self.peek()) {
+            let ty = self.next();
+            let name = self.ident();
+            self.expect("=");
+            return Stmt::Decl(ty, name, self.expr(0));
+        }
+        let name = self.ident();
+        if self.eat("++") { return Stmt::Inc(name); }
+        let field = if self.eat(".") { Some(self.ident()) } else { None };
+        let op = self.next();
+        let e = self.expr(0);
+        let e = match op.as_str() {
+            "=" => e,
+            "+=" | "-=" | "*=" | "/=" => {
+                let cur = Expr::Var(name.clone());
+                let cur = match &field { Some(f) => Expr::Swizzle(Box::new(cur), f.clone()), None => cur };
+                Expr::Bin(op[..1].to_string(), Box::new(cur), Box::new(e))
+            }
+            _ => panic!("expected an assignment to {name}, got '{op}'"),
+        };
+        Stmt::Assign(name, field, e)
+    }
+
+    fn stmt(&mut self) -> Stmt {
+        if self.eat("if") {
+            self.expect("(");
+            let c = self.expr(0);
+            self.expect(")");
+            let then = self.block();
+            let other = if self.eat("else") { self.block() } else { vec![] };
+            return Stmt::If(c, then, other);
+        }
+        if self.eat("for") {
+            self.expect("(");
+            let init = self.simple();
+            self.expect(";");
+            let c = self.expr(0);
+            self.expect(";");
+            let step = self.simple();
+            self.expect(")");
+            return Stmt::For(Box::new(init), c, Box::new(step), self.block());
+        }
+        let s = if self.eat("return") { Stmt::Return(self.expr(0)) } else { self.simple() };
+        self.expect(";");
+        s
+    }
+
+    fn expr(&mut self, level: usize) -> Expr {
+        if level == LEVELS.len() { return self.unary(); }
+        let mut e = self.expr(level + 1);
+        while LEVELS[level].contains(&self.peek()) {
+            let op = self.next();
+            e = Expr::Bin(op, Box::new(e), Box::new(self.expr(level + 1)));
+        }
+        e
+    }
+
+    fn unary(&mut self) -> Expr {
+        if self.eat("-") { return Expr::Neg(Box::new(self.unary())); }
+        if self.eat("!") { return Expr::Not(Box::new(self.unary())); }
+        let mut e = self.primary();
+        while self.eat(".") { e = Expr::Swizzle(Box::new(e), self.ident()); }
+        e
+    }
+
+    fn primary(&mut self) -> Expr {
+        if self.eat("(") {
+            let e = self.expr(0);
+            self.expect(")");
+            return e;
+        }
+        if self.peek().starts_with(|c: char| c.is_ascii_digit()) { return Expr::Lit(number(&self.next())); }
+        let name = self.ident();
+        if self.peek() == "(" {
+            self.next();
+            let mut args = Vec::new();
+            while !self.eat(")") {
+                args.push(self.expr(0));
+                if self.peek() != ")" { self.expect(","); }
+            }
+            return Expr::Call(name, args);
+        }
+        if self.peek() == "[" && self.peek_at(1) != "]" {
+            self.next();
+            let i = self.expr(0);
+            self.expect("]");
+            return Expr::Index(name, Box::new(i));
+        }
+        match name.as_str() { "true" => Expr::Lit(Val::B(true)), "false" => Expr::Lit(Val::B(false)), _ => Expr::Var(name) }
+    }
+}
+
+fn lanes(s: &str, n: usize) -> Vec<usize> {
+    s.chars().map(|c| {
+        let k = "xyzw".find(c).or_else(|| "rgba".find(c)).unwrap_or_else(|| panic!("bad swizzle .{s}"));
+        assert!(k < n, "swizzle .{s} out of range for vec{n}");
+        k
+    }).collect()
+}
+
+fn binary(op: &str, a: Val, b: Val) -> Val {
+    use Val::*;
+    let cmp = |o: std::cmp::Ordering| B(match op {
+        "<" => o.is_lt(), ">" => o.is_gt(), "<=" => o.is_le(), ">=" => o.is_ge(), "==" => o.is_eq(), "!=" => o.is_ne(),
+        _ => panic!("operator {op} is not defined here"),
+    });
+    match (a, b) {
+        (F(x, n), F(y, m)) if n == m || n == 1 || m == 1 => {
+            if n == 1 && m == 1 && matches!(op, "<" | ">" | "<=" | ">=" | "==" | "!=") {
+                return match x[0].partial_cmp(&y[0]) { Some(o) => cmp(o), None => B(op == "!=") };
+            }
+            let at = |v: [f32; 4], w: usize, k: usize| if w == 1 { v[0] } else { v[k] };
+            let f: fn(f32, f32) -> f32 = match op { "+" => |a, b| a + b, "-" => |a, b| a - b, "*" => |a, b| a * b, "/" => |a, b| a / b, _ => panic!("operator {op} on {} and {}", a.ty(), b.ty()) };
+            vector(n.max(m), |k| f(at(x, n, k), at(y, m, k)))
+        }
+        (I(x), I(y)) => match op {
+            "+" => I(x.wrapping_add(y)), "-" => I(x.wrapping_sub(y)), "*" => I(x.wrapping_mul(y)), "/" => I(x / y), "%" => I(x % y),
+            _ => cmp(x.cmp(&y)),
+        },
+        (U(x), U(y)) => match op {
+            "+" => U(x.wrapping_add(y)), "-" => U(x.wrapping_sub(y)), "*" => U(x.wrapping_mul(y)), "/" => U(x / y), "%" => U(x % y),
+            "^" => U(x ^ y), "&" => U(x & y), "|" => U(x | y), ">>" => U(x >> y), "<<" => U(x << y),
+            _ => cmp(x.cmp(&y)),
+        },
+        (B(x), B(y)) if op == "==" || op == "!=" => B((x == y) == (op == "==")),
+        _ => panic!("operator {op} on {} and {}", a.ty(), b.ty()),
+    }
+}
+
+/// Component-wise builtin over same-length float arguments.
+fn zip(name: &str, a: &[Val], f: impl Fn(&[f32]) -> f32) -> Val {
+    let Val::F(_, n) = a[0] else { panic!("{name} needs float arguments, got {}", a[0].ty()) };
+    let cs: Vec<[f32; 4]> = a.iter().map(|v| match *v {
+        Val::F(c, m) if m == n => c,
+        v => panic!("{name} mixes {} and {}", a[0].ty(), v.ty()),
+    }).collect();
+    vector(n, |k| f(&cs.iter().map(|c| c[k]).collect::<Vec<_>>()))
+}
+
+fn builtin(name: &str, a: &[Val]) -> Val {
+    use Val::*;
+    let arity = |n: usize| assert_eq!(a.len(), n, "{name} takes {n} arguments");
+    let comps = |v: Val| match v { F(c, n) => c[..n].to_vec(), v => panic!("{name} needs float arguments, got {}", v.ty()) };
+    match name {
+        "abs" | "sign" | "floor" | "fract" | "sqrt" | "sin" | "cos" | "acos" | "exp" | "log" => {
+            arity(1);
+            let f: fn(f32) -> f32 = match name {
+                "abs" => f32::abs, "floor" => f32::floor, "fract" => |x| x - x.floor(), "sqrt" => f32::sqrt, "sin" => f32::sin,
+                "cos" => f32::cos, "acos" => f32::acos, "exp" => f32::exp, "log" => f32::ln,
+                _ => |x| if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 },
+            };
+            zip(name, a, |x| f(x[0]))
+        }
+        "pow" => { arity(2); zip(name, a, |x| x[0].powf(x[1])) }
+        "atan" | "atan2" => { arity(2); zip(name, a, |x| x[0].atan2(x[1])) }
+        "min" => { arity(2); zip(name, a, |x| x[0].min(x[1])) }
+        "max" => { arity(2); zip(name, a, |x| x[0].max(x[1])) }
+        "clamp" => { arity(3); zip(name, a, |x| x[0].max(x[1]).min(x[2])) }
+        "mix" => { arity(3); zip(name, a, |x| x[0] + (x[1] - x[0]) * x[2]) }
+        "dot" | "length" | "cross" => {
+            let (x, y) = (comps(a[0]), comps(*a.get(1).unwrap_or(&a[0])));
+            assert_eq!(x.len(), y.len(), "{name} of different lengths");
+            let d: f32 = x.iter().zip(&y).map(|(p, q)| p * q).sum();
+            match name {
+                "dot" => { arity(2); vector(1, |_| d) }
+                "length" => { arity(1); vector(1, |_| d.sqrt()) }
+                _ => { arity(2); assert_eq!(x.len(), 3, "cross needs vec3"); vector(3, |k| x[(k + 1) % 3] * y[(k + 2) % 3] - x[(k + 2) % 3] * y[(k + 1) % 3]) }
+            }
+        }
+        "vec2" | "vec3" | "vec4" => {
+            let n = name[3..].parse::<usize>().unwrap_or(0);
+            let c: Vec<f32> = a.iter().flat_map(|v| comps(*v)).collect();
+            let c = if c.len() == 1 { vec![c[0]; n] } else { c };
+            assert_eq!(c.len(), n, "{name} built from {} components", c.len());
+            vector(n, |k| c[k])
+        }
+        "float" | "int" | "uint" => {
+            arity(1);
+            match (name, a[0]) {
+                ("float", F(c, 1)) => vector(1, |_| c[0]), ("float", I(x)) => vector(1, |_| x as f32), ("float", U(x)) => vector(1, |_| x as f32),
+                ("int", F(c, 1)) => I(c[0] as i32), ("int", I(x)) => I(x), ("int", U(x)) => I(x as i32),
+                ("uint", F(c, 1)) => U(c[0] as u32), ("uint", I(x)) => U(x as u32), ("uint", U(x)) => U(x),
+                (_, v) => panic!("{name}({})", v.ty()),
+            }
+        }
+        _ => panic!("unknown function {name}"),
+    }
+}
+
+type Scopes = Vec<HashMap<String, Val>>;
+
+impl Program {
+    pub fn parse(src: &str) -> Program { Parser { t: lex(src), i: 0 }.program() }
+
+    /// Call a function of the program.
+    pub fn call(&self, name: &str, args: &[Val]) -> Val {
+        let f = self.funcs.get(name).unwrap_or_else(|| panic!("no function {name}"));
+        assert_eq!(f.params.len(), args.len(), "{name} takes {} arguments", f.params.len());
+        let mut frame = HashMap::new();
+        for ((ty, p), v) in f.params.iter().zip(args) {
+            assert_eq!(ty, v.ty(), "argument {p} of {name}");
+            frame.insert(p.clone(), *v);
+        }
+        let r = self.run(&f.body, &mut vec![frame]).unwrap_or_else(|| panic!("{name} ends without return"));
+        assert_eq!(f.ret, r.ty(), "return type of {name}");
+        r
+    }
+
+    fn run(&self, body: &[Stmt], s: &mut Scopes) -> Option<Val> {
+        s.push(HashMap::new());
+        let r = body.iter().find_map(|st| self.exec(st, s));
+        s.pop();
+        r
+    }
+
+    fn exec(&self, st: &Stmt, s: &mut Scopes) -> Option<Val> {
+        match st {
+            Stmt::Decl(ty, name, e) => {
+                let v = self.eval(e, s);
+                assert_eq!(ty, v.ty(), "declaration of {name}");
+                let top = s.last_mut().unwrap();
+                assert!(top.insert(name.clone(), v).is_none(), "{name} declared twice in one scope");
+            }
+            Stmt::Assign(name, field, e) => {
+                let v = self.eval(e, s);
+                let slot = s.iter_mut().rev().find_map(|m| m.get_mut(name)).unwrap_or_else(|| panic!("assignment to undeclared {name}"));
+                match (field, slot) {
+                    (None, slot) => { assert_eq!(slot.ty(), v.ty(), "assignment to {name}"); *slot = v; }
+                    (Some(f), Val::F(c, n)) => {
+                        let ks = lanes(f, *n);
+                        let Val::F(src, m) = v else { panic!("assigning {} to {name}.{f}", v.ty()) };
+                        assert_eq!(m, ks.len(), "assignment to {name}.{f}");
+                        for (j, k) in ks.into_iter().enumerate() { c[k] = src[j]; }
+                    }
+                    (Some(f), slot) => panic!("swizzle .{f} of {}", slot.ty()),
+                }
+            }
+            Stmt::Inc(name) => {
+                let slot = s.iter_mut().rev().find_map(|m| m.get_mut(name)).unwrap_or_else(|| panic!("{name}++ of undeclared {name}"));
+                *slot = match *slot { Val::I(x) => Val::I(x + 1), Val::U(x) => Val::U(x + 1), v => panic!("{name}++ on {}", v.ty()) };
+            }
+            Stmt::If(c, then, other) => {
+                let Val::B(b) = self.eval(c, s) else { panic!("if condition is not a bool") };
+                return self.run(if b { then } else { other }, s);
+            }
+            Stmt::For(init, c, step, body) => {
+                s.push(HashMap::new());
+                self.exec(init, s);
+                let mut r = None;
+                while r.is_none() && self.eval(c, s) == Val::B(true) {
+                    r = self.run(body, s);
+                    self.exec(step, s);
+                }
+                s.pop();
+                return r;
+            }
+            Stmt::Return(e) => return Some(self.eval(e, s)),
+        }
+        None
+    }
+
+    fn eval(&self, e: &Expr, s: &mut Scopes) -> Val {
+        match e {
+            Expr::Lit(v) => *v,
+            Expr::Var(n) => *s.iter().rev().find_map(|m| m.get(n)).unwrap_or_else(|| panic!("undeclared {n}")),
+            Expr::Neg(x) => match self.eval(x, s) { Val::F(c, n) => vector(n, |k| -c[k]), Val::I(x) => Val::I(x.wrapping_neg()), v => panic!("-{}", v.ty()) },
+            Expr::Not(x) => match self.eval(x, s) { Val::B(b) => Val::B(!b), v => panic!("!{}", v.ty()) },
+            Expr::Bin(op, a, b) if op == "&&" || op == "||" => {
+                let Val::B(x) = self.eval(a, s) else { panic!("{op} on a non-bool") };
+                if x == (op == "||") { return Val::B(x); }
+                let Val::B(y) = self.eval(b, s) else { panic!("{op} on a non-bool") };
+                Val::B(y)
+            }
+            Expr::Bin(op, a, b) => { let (x, y) = (self.eval(a, s), self.eval(b, s)); binary(op, x, y) }
+            Expr::Call(name, args) => {
+                let a: Vec<Val> = args.iter().map(|x| self.eval(x, s)).collect();
+                if self.funcs.contains_key(name) { self.call(name, &a) } else { builtin(name, &a) }
+            }
+            Expr::Swizzle(x, f) => match self.eval(x, s) {
+                Val::F(c, n) => { let ks = lanes(f, n); vector(ks.len(), |j| c[ks[j]]) }
+                v => panic!("swizzle .{f} of {}", v.ty()),
+            },
+            Expr::Index(name, i) => {
+                let a = self.arrays.get(name).unwrap_or_else(|| panic!("no array {name}"));
+                let Val::I(i) = self.eval(i, s) else { panic!("{name} indexed by a non-int") };
+                vector(1, |_| *a.get(i as usize).unwrap_or_else(|| panic!("{name}[{i}] out of bounds")))
+            }
+        }
+    }
+}
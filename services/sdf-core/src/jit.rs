@@ -0,0 +1,129 @@
+//! Native code for compiled trees, via Cranelift.
+//!
+//! `compile` turns a `CompiledSdf` into one `sdf_eval(x, y, z)` function that
+//! gives the interpreter's distances without its dispatch and stacks. Not every
+//! instruction has native code; for those `compile` fails with the reason and
+//! callers evaluate with the interpreter instead. `Cache` keeps compiled
+//! functions, and failures, by canonical tree hash.
+
+mod codegen;
+
+use crate::canon;
+use crate::compiler::CompiledSdf;
+use crate::eval::Field;
+use crate::math::V3;
+use crate::tree::SdfNode;
+use cranelift_codegen::ir::{types::F32, AbiParam, InstBuilder};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module};
+use rayon::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+extern "C" fn jit_sin(x: f32) -> f32 { x.sin() }
+extern "C" fn jit_cos(x: f32) -> f32 { x.cos() }
+
+/// A tree compiled to native code.
+pub struct JitSdf { f: extern "C" fn(f32, f32, f32) -> f32, module: Option<JITModule> }
+
+// The module is only touched again to free it on drop, and the function reads
+// nothing but its arguments, so both can move and be shared across threads.
+unsafe impl Send for JitSdf {}
+unsafe impl Sync for JitSdf {}
+
+impl Drop for JitSdf {
+    fn drop(&mut self) {
+        // SAFETY: `f` points into this module and goes away with `self`.
+        if let Some(m) = self.module.take() { unsafe { m.free_memory() } }
+    }
+}
+
+impl JitSdf {
+    pub fn eval(&self, p: V3) -> f32 { (self.f)(p[0], p[1], p[2]) }
+
+    pub fn eval_batch(&self, points: &[V3]) -> Vec<f32> {
+        points.par_chunks(1024).flat_map_iter(|c| c.iter().map(|&p| self.eval(p))).collect()
+    }
+}
+
+impl Field for JitSdf {
+    fn eval(&self, p: V3) -> f32 { JitSdf::eval(self, p) }
+    fn eval_batch(&self, points: &[V3]) -> Vec<f32> { JitSdf::eval_batch(self, points) }
+}
+
+/// Native code for `sdf`, or why there is none (an unsupported instruction or host).
+pub fn compile(sdf: &CompiledSdf) -> Result<JitSdf, String> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+    let isa = cranelift_native::builder().map_err(|e| format!("no JIT for this host: {e}"))?
+        .finish(settings::Flags::new(flags)).map_err(|e| e.to_string())?;
+    let mut jb = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    jb.symbol("sdf_jit_sin", jit_sin as *const u8);
+    jb.symbol("sdf_jit_cos", jit_cos as *const u8);
+    let mut module = JITModule::new(jb);
+    match define(&mut module, sdf) {
+        Ok(f) => Ok(JitSdf { f, module: Some(module) }),
+        Err(e) => {
+            // SAFETY: nothing from the module was handed out.
+            unsafe { module.free_memory() }
+            Err(e)
+        }
+    }
+}
+
+fn define(module: &mut JITModule, sdf: &CompiledSdf) -> Result<extern "C" fn(f32, f32, f32) -> f32, String> {
+    let mut unary = module.make_signature();
+    unary.params.push(AbiParam::new(F32));
+    unary.returns.push(AbiParam::new(F32));
+    let sin = module.declare_function("sdf_jit_sin", Linkage::Import, &unary).map_err(|e| e.to_string())?;
+    let cos = module.declare_function("sdf_jit_cos", Linkage::Import, &unary).map_err(|e| e.to_string())?;
+    let mut ctx = module.make_context();
+    ctx.func.signature.params.extend([AbiParam::new(F32); 3]);
+    ctx.func.signature.returns.push(AbiParam::new(F32));
+    let id = module.declare_function("sdf_eval", Linkage::Export, &ctx.func.signature).map_err(|e| e.to_string())?;
+    let mut fbc = FunctionBuilderContext::new();
+    let mut b = FunctionBuilder::new(&mut ctx.func, &mut fbc);
+    let (sin, cos) = (module.declare_func_in_func(sin, b.func), module.declare_func_in_func(cos, b.func));
+    let entry = b.create_block();
+    b.append_block_params_for_function_params(entry);
+    b.switch_to_block(entry);
+    b.seal_block(entry);
+    let p = b.block_params(entry);
+    let p = [p[0], p[1], p[2]];
+    let d = codegen::Emitter { b: &mut b, sin, cos }.program(&sdf.code, p)?;
+    b.ins().return_(&[d]);
+    b.finalize();
+    module.define_function(id, &mut ctx).map_err(|e| e.to_string())?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().map_err(|e| e.to_string())?;
+    // SAFETY: the function was declared above with exactly this signature.
+    Ok(unsafe { std::mem::transmute::<*const u8, extern "C" fn(f32, f32, f32) -> f32>(module.get_finalized_function(id)) })
+}
+
+type Entry = Result<Arc<JitSdf>, String>;
+
+/// Compiled trees by canonical SHA-256, least recently compiled evicted first.
+pub struct Cache { cap: usize, entries: Mutex<(HashMap<String, Entry>, VecDeque<String>)> }
+
+impl Default for Cache { fn default() -> Self { Cache::new(256) } }
+
+impl Cache {
+    pub fn new(cap: usize) -> Self { Cache { cap: cap.max(1), entries: Mutex::default() } }
+
+    /// Native code for `node`, compiled from `sdf` on first sight of its hash.
+    pub fn get(&self, node: &SdfNode, sdf: &CompiledSdf) -> Entry {
+        let key = canon::hash(&canon::text(&canon::canonical(node)), "sha256").unwrap_or_default();
+        if let Some(hit) = self.entries.lock().unwrap_or_else(|e| e.into_inner()).0.get(&key) { return hit.clone(); }
+        // Compile unlocked; two requests racing on one tree both compile and the second insert wins.
+        let v = compile(sdf).map(Arc::new);
+        let (map, order) = &mut *self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if map.insert(key.clone(), v.clone()).is_none() {
+            order.push_back(key);
+            while order.len() > self.cap { if let Some(old) = order.pop_front() { map.remove(&old); } }
+        }
+        v
+    }
+}
+
@@ -0,0 +1,410 @@
+//! Inst → Cranelift IR for `sdf_eval(x, y, z) -> f32`.
+//!
+//! Programs are straight-line, so a scene is one basic block: the value and
+//! point stacks become SSA values and the branches inside distance functions
+//! become `select`s. Node parameters are constants, so branches on them are
+//! decided here. Each function repeats the interpreter's arithmetic in the same
+//! order, which makes results match it bit for bit (NaN handling aside).
+
+use crate::compiler::{Inst, Op, PointOp, Post, Prim};
+use cranelift_codegen::ir::{condcodes::FloatCC, FuncRef, InstBuilder, Value};
+use cranelift_frontend::FunctionBuilder;
+use std::f32::consts::FRAC_1_SQRT_2;
+
+type P = [Value; 3];
+
+pub(super) struct Emitter<'a, 'b> { pub b: &'a mut FunctionBuilder<'b>, pub sin: FuncRef, pub cos: FuncRef }
+
+/// Error naming an instruction the JIT has no code for, e.g. `Tpms`.
+fn unsupported(x: &impl std::fmt::Debug) -> String {
+    let s = format!("{x:?}");
+    format!("{} is not supported by the JIT", s.split(['(', ' ', '{']).next().unwrap_or(&s))
+}
+
+impl Emitter<'_, '_> {
+    /// Distance of `code` at `p`, or the first instruction there is no code for.
+    pub fn program(&mut self, code: &[Inst], p: P) -> Result<Value, String> {
+        let (mut vs, mut ps) = (Vec::new(), vec![p]);
+        for i in code {
+            let p = *ps.last().unwrap();
+            match i {
+                Inst::Prim(pr) => { let d = self.prim(pr, p).ok_or_else(|| unsupported(pr))?; vs.push(d); }
+                Inst::Op(o) => { let b = vs.pop().unwrap(); let a = vs.pop().unwrap(); let d = self.op(*o, a, b).ok_or_else(|| unsupported(o))?; vs.push(d); }
+                Inst::Push(o) => { let q = self.point_op(o, p).ok_or_else(|| unsupported(o))?; ps.push(q); }
+                Inst::PopPoint => { ps.pop(); }
+                Inst::Post(o) => { let d = vs.pop().unwrap(); let d = self.post(o, d, p).ok_or_else(|| unsupported(o))?; vs.push(d); }
+            }
+        }
+        Ok(match vs.pop() { Some(d) => d, None => self.c(f32::INFINITY) })
+    }
+
+    // ── arithmetic ──────────────────────────────────────────────────────────
+
+    fn c(&mut self, x: f32) -> Value { self.b.ins().f32const(x) }
+    fn add(&mut self, a: Value, b: Value) -> Value { self.b.ins().fadd(a, b) }
+    fn sub(&mut self, a: Value, b: Value) -> Value { self.b.ins().fsub(a, b) }
+    fn mul(&mut self, a: Value, b: Value) -> Value { self.b.ins().fmul(a, b) }
+    fn div(&mut self, a: Value, b: Value) -> Value { self.b.ins().fdiv(a, b) }
+    fn min(&mut self, a: Value, b: Value) -> Value { self.b.ins().fmin(a, b) }
+    fn max(&mut self, a: Value, b: Value) -> Value { self.b.ins().fmax(a, b) }
+    fn abs(&mut self, a: Value) -> Value { self.b.ins().fabs(a) }
+    fn neg(&mut self, a: Value) -> Value { self.b.ins().fneg(a) }
+    fn sqrt(&mut self, a: Value) -> Value { self.b.ins().sqrt(a) }
+    fn floor(&mut self, a: Value) -> Value { self.b.ins().floor(a) }
+    fn addk(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.add(a, k) }
+    fn subk(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.sub(a, k) }
+    fn mulk(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.mul(a, k) }
+    fn mink(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.min(a, k) }
+    fn maxk(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.max(a, k) }
+    fn clamp(&mut self, x: Value, lo: f32, hi: f32) -> Value { let x = self.maxk(x, lo); self.mink(x, hi) }
+    fn lt(&mut self, a: Value, b: Value) -> Value { self.b.ins().fcmp(FloatCC::LessThan, a, b) }
+    fn ltk(&mut self, a: Value, k: f32) -> Value { let k = self.c(k); self.lt(a, k) }
+    fn select(&mut self, c: Value, a: Value, b: Value) -> Value { self.b.ins().select(c, a, b) }
+
+    fn call(&mut self, f: FuncRef, x: Value) -> Value {
+        let i = self.b.ins().call(f, &[x]);
+        self.b.inst_results(i)[0]
+    }
+
+    fn len2(&mut self, x: Value, y: Value) -> Value {
+        let (xx, yy) = (self.mul(x, x), self.mul(y, y));
+        let s = self.add(xx, yy);
+        self.sqrt(s)
+    }
+
+    fn dot(&mut self, a: P, b: P) -> Value {
+        let (x, y, z) = (self.mul(a[0], b[0]), self.mul(a[1], b[1]), self.mul(a[2], b[2]));
+        let xy = self.add(x, y);
+        self.add(xy, z)
+    }
+
+    fn len(&mut self, a: P) -> Value { let d = self.dot(a, a); self.sqrt(d) }
+
+    /// `w0.max(w1).min(0) + len2(w0.max(0), w1.max(0))`, as in `eval::extrude`.
+    fn join(&mut self, w0: Value, w1: Value) -> Value {
+        let m = self.max(w0, w1);
+        let inner = self.mink(m, 0.0);
+        let (a, b) = (self.maxk(w0, 0.0), self.maxk(w1, 0.0));
+        let outer = self.len2(a, b);
+        self.add(inner, outer)
+    }
+
+    // ── primitives ──────────────────────────────────────────────────────────
+
+    fn sd_box(&mut self, p: P, h: [f32; 3]) -> Value {
+        let q = [0, 1, 2].map(|i| { let a = self.abs(p[i]); self.subk(a, h[i]) });
+        let m = q.map(|x| self.maxk(x, 0.0));
+        let outer = self.len(m);
+        let xy = self.max(q[0], q[1]);
+        let mc = self.max(xy, q[2]);
+        let inner = self.mink(mc, 0.0);
+        self.add(outer, inner)
+    }
+
+    fn prim(&mut self, pr: &Prim, p: P) -> Option<Value> {
+        use Prim::*;
+        Some(match *pr {
+            Sphere(r) => { let l = self.len(p); self.subk(l, r) }
+            Box3d(h) => self.sd_box(p, h),
+            Cylinder(r, h) => {
+                let l = self.len2(p[0], p[2]);
+                let dx = self.subk(l, r);
+                let ay = self.abs(p[1]);
+                let dy = self.subk(ay, h);
+                self.join(dx, dy)
+            }
+            Torus(a, b) => {
+                let l = self.len2(p[0], p[2]);
+                let q = self.subk(l, a);
+                let l = self.len2(q, p[1]);
+                self.subk(l, b)
+            }
+            Plane(n, h) => { let n = n.map(|x| self.c(x)); let d = self.dot(p, n); self.addk(d, h) }
+            Capsule(r, h) => {
+                let cl = self.clamp(p[1], -h, h);
+                let y = self.sub(p[1], cl);
+                let l = self.len([p[0], y, p[2]]);
+                self.subk(l, r)
+            }
+            Cone(r, height) => {
+                let h = height * 0.5;
+                let (k1, k2) = ([0.0, h], [-r, 2.0 * h]);
+                let (q0, q1) = (self.len2(p[0], p[2]), p[1]);
+                let below = self.ltk(q1, 0.0);
+                let (kr, k0) = (self.c(r), self.c(0.0));
+                let rr = self.select(below, kr, k0);
+                let m = self.min(q0, rr);
+                let ca0 = self.sub(q0, m);
+                let aq1 = self.abs(q1);
+                let ca1 = self.subk(aq1, h);
+                let (k10, k11) = (self.c(k1[0]), self.c(k1[1]));
+                let (u, v) = (self.sub(k10, q0), self.sub(k11, q1));
+                let (u, v) = (self.mulk(u, k2[0]), self.mulk(v, k2[1]));
+                let num = self.add(u, v);
+                let den = self.c(k2[0] * k2[0] + k2[1] * k2[1]);
+                let t = self.div(num, den);
+                let t = self.clamp(t, 0.0, 1.0);
+                let (a, b) = (self.subk(q0, k1[0]), self.subk(q1, k1[1]));
+                let (ta, tb) = (self.mulk(t, k2[0]), self.mulk(t, k2[1]));
+                let (cb0, cb1) = (self.add(a, ta), self.add(b, tb));
+                let (c0, c1) = (self.ltk(cb0, 0.0), self.ltk(ca1, 0.0));
+                let inside = self.b.ins().band(c0, c1);
+                let (neg, pos) = (self.c(-1.0), self.c(1.0));
+                let s = self.select(inside, neg, pos);
+                let (a0, a1, b0, b1) = (self.mul(ca0, ca0), self.mul(ca1, ca1), self.mul(cb0, cb0), self.mul(cb1, cb1));
+                let (da, db) = (self.add(a0, a1), self.add(b0, b1));
+                let m = self.min(da, db);
+                let d = self.sqrt(m);
+                self.mul(s, d)
+            }
+            RoundedBox(h, r) => { let d = self.sd_box(p, [h[0] - r, h[1] - r, h[2] - r]); self.subk(d, r) }
+            Ellipsoid(r) => {
+                let rr = [r[0] * r[0], r[1] * r[1], r[2] * r[2]];
+                let a = [0, 1, 2].map(|i| { let k = self.c(r[i]); self.div(p[i], k) });
+                let b = [0, 1, 2].map(|i| { let k = self.c(rr[i]); self.div(p[i], k) });
+                let (k0, k1) = (self.len(a), self.len(b));
+                let k0m = self.subk(k0, 1.0);
+                let num = self.mul(k0, k0m);
+                let d = self.div(num, k1);
+                let zero = self.c(0.0);
+                let at_centre = self.b.ins().fcmp(FloatCC::Equal, k1, zero);
+                let centre = self.c(-r[0].min(r[1]).min(r[2]));
+                self.select(at_centre, centre, d)
+            }
+            Tetrahedron(s) => {
+                let s3 = 3f32.sqrt();
+                let (a, b) = (self.add(p[0], p[1]), self.sub(p[0], p[1]));
+                let (a, b) = (self.abs(a), self.abs(b));
+                let (a, b) = (self.sub(a, p[2]), self.add(b, p[2]));
+                let m = self.max(a, b);
+                let k = self.c(s3);
+                let d = self.div(m, k);
+                self.subk(d, s / s3)
+            }
+            InfiniteCylinder(r) => { let l = self.len2(p[0], p[2]); self.subk(l, r) }
+            Circle2d(r) => { let l = self.len2(p[0], p[1]); self.subk(l, r) }
+            Rect2d(w, h, r) => {
+                let (ax, ay) = (self.abs(p[0]), self.abs(p[1]));
+                let (qx, qy) = (self.subk(ax, w), self.subk(ay, h));
+                let (qx, qy) = (self.addk(qx, r), self.addk(qy, r));
+                let d = self.join(qx, qy);
+                self.subk(d, r)
+            }
+            Annular2d(r, t) => {
+                let l = self.len2(p[0], p[1]);
+                let d = self.subk(l, r);
+                let d = self.abs(d);
+                self.subk(d, t)
+            }
+            _ => return None,
+        })
+    }
+
+    // ── operations ──────────────────────────────────────────────────────────
+
+    fn smin(&mut self, a: Value, b: Value, k: f32) -> Value {
+        if k <= 0.0 { return self.min(a, b); }
+        let d = self.sub(a, b);
+        let d = self.abs(d);
+        let kk = self.c(k);
+        let h = self.sub(kk, d);
+        let h = self.maxk(h, 0.0);
+        let h = self.div(h, kk);
+        let m = self.min(a, b);
+        let hh = self.mul(h, h);
+        let hh = self.mulk(hh, k);
+        let hh = self.mulk(hh, 0.25);
+        self.sub(m, hh)
+    }
+
+    fn smax(&mut self, a: Value, b: Value, k: f32) -> Value {
+        let (na, nb) = (self.neg(a), self.neg(b));
+        let m = self.smin(na, nb, k);
+        self.neg(m)
+    }
+
+    fn stairs_union(&mut self, a: Value, b: Value, r: f32, n: f32) -> Value {
+        if r <= 0.0 { return self.min(a, b); }
+        let s = r / n;
+        let u = self.subk(b, r);
+        let m = self.sub(u, a);
+        let m = self.addk(m, s);
+        let k = self.c(2.0 * s);
+        let f = self.div(m, k);
+        let f = self.floor(f);
+        let f = self.mul(k, f);
+        let md = self.sub(m, f);
+        let ua = self.add(u, a);
+        let e = self.subk(md, s);
+        let e = self.abs(e);
+        let x = self.add(ua, e);
+        let x = self.mulk(x, 0.5);
+        let ab = self.min(a, b);
+        self.min(ab, x)
+    }
+
+    fn op(&mut self, o: Op, a: Value, b: Value) -> Option<Value> {
+        use Op::*;
+        Some(match o {
+            Union => self.min(a, b),
+            Intersection => self.max(a, b),
+            Subtraction => { let nb = self.neg(b); self.max(a, nb) }
+            SmoothUnion(k) => self.smin(a, b, k),
+            SmoothIntersection(k) => self.smax(a, b, k),
+            SmoothSubtraction(k) => { let nb = self.neg(b); self.smax(a, nb, k) }
+            ChamferUnion(r) => {
+                let m = self.min(a, b);
+                let s = self.add(a, b);
+                let s = self.subk(s, r);
+                let s = self.mulk(s, FRAC_1_SQRT_2);
+                self.min(m, s)
+            }
+            ChamferIntersection(r) => {
+                let m = self.max(a, b);
+                let s = self.add(a, b);
+                let s = self.addk(s, r);
+                let s = self.mulk(s, FRAC_1_SQRT_2);
+                self.max(m, s)
+            }
+            ChamferSubtraction(r) => {
+                let nb = self.neg(b);
+                let m = self.max(a, nb);
+                let s = self.sub(a, b);
+                let s = self.addk(s, r);
+                let s = self.mulk(s, FRAC_1_SQRT_2);
+                self.max(m, s)
+            }
+            StairsUnion(r, n) => self.stairs_union(a, b, r, n),
+            StairsIntersection(r, n) => { let (na, nb) = (self.neg(a), self.neg(b)); let d = self.stairs_union(na, nb, r, n); self.neg(d) }
+            StairsSubtraction(r, n) => { let na = self.neg(a); let d = self.stairs_union(na, b, r, n); self.neg(d) }
+            Xor => {
+                let (lo, hi) = (self.min(a, b), self.max(a, b));
+                let nhi = self.neg(hi);
+                self.max(lo, nhi)
+            }
+            Morph(t) => { let d = self.sub(b, a); let d = self.mulk(d, t); self.add(a, d) }
+            Pipe(r) => { let l = self.len2(a, b); self.subk(l, r) }
+            Engrave(r) => {
+                let ab = self.abs(b);
+                let s = self.addk(a, r);
+                let s = self.sub(s, ab);
+                let s = self.mulk(s, FRAC_1_SQRT_2);
+                self.max(a, s)
+            }
+            Groove(ra, rb) => {
+                let ab = self.abs(b);
+                let (x, kb) = (self.addk(a, ra), self.c(rb));
+                let y = self.sub(kb, ab);
+                let m = self.min(x, y);
+                self.max(a, m)
+            }
+            Tongue(ra, rb) => {
+                let ab = self.abs(b);
+                let (x, y) = (self.subk(a, ra), self.subk(ab, rb));
+                let m = self.max(x, y);
+                self.min(a, m)
+            }
+            _ => return None,
+        })
+    }
+
+    // ── point and distance ops ──────────────────────────────────────────────
+
+    /// `sin` and `cos` of `k * x`, through the same libm calls as the interpreter.
+    fn sin_cos(&mut self, x: Value, k: f32) -> (Value, Value) {
+        let a = self.mulk(x, k);
+        let (sin, cos) = (self.sin, self.cos);
+        (self.call(sin, a), self.call(cos, a))
+    }
+
+    fn point_op(&mut self, o: &PointOp, p: P) -> Option<P> {
+        Some(match *o {
+            PointOp::Translate(o) => [0, 1, 2].map(|i| self.subk(p[i], o[i])),
+            PointOp::Rotate(ref m) => [0, 3, 6].map(|r| {
+                let row = [self.c(m[r]), self.c(m[r + 1]), self.c(m[r + 2])];
+                self.dot(row, p)
+            }),
+            PointOp::Scale(s) => { let k = 1.0 / s; p.map(|x| self.mulk(x, k)) }
+            PointOp::ScaleNonUniform(f) => [0, 1, 2].map(|i| { let k = self.c(f[i]); self.div(p[i], k) }),
+            PointOp::Twist(k) => {
+                let (s, c) = self.sin_cos(p[1], k);
+                let (cx, sz, sx, cz) = (self.mul(c, p[0]), self.mul(s, p[2]), self.mul(s, p[0]), self.mul(c, p[2]));
+                [self.sub(cx, sz), p[1], self.add(sx, cz)]
+            }
+            PointOp::Bend(k) => {
+                let (s, c) = self.sin_cos(p[0], k);
+                let (cx, sy, sx, cy) = (self.mul(c, p[0]), self.mul(s, p[1]), self.mul(s, p[0]), self.mul(c, p[1]));
+                [self.sub(cx, sy), self.add(sx, cy), p[2]]
+            }
+            PointOp::Repeat(s) => [0, 1, 2].map(|i| {
+                if s[i] <= 0.0 { return p[i]; }
+                let k = self.c(s[i]);
+                let r = self.div(p[i], k);
+                let r = self.addk(r, 0.5);
+                let r = self.floor(r);
+                let r = self.mul(k, r);
+                self.sub(p[i], r)
+            }),
+            PointOp::RepeatFinite(s, n) => [0, 1, 2].map(|i| {
+                if s[i] <= 0.0 || n[i] < 1.0 { return p[i]; }
+                let (nr, k) = (n[i].round(), self.c(s[i]));
+                let c = (nr - 1.0) * 0.5;
+                let r = self.div(p[i], k);
+                let r = self.addk(r, c);
+                let r = self.addk(r, 0.5);
+                let r = self.floor(r);
+                let r = self.clamp(r, 0.0, nr - 1.0);
+                let r = self.subk(r, c);
+                let r = self.mul(k, r);
+                self.sub(p[i], r)
+            }),
+            PointOp::Mirror(m) => [0, 1, 2].map(|i| if m[i] { self.abs(p[i]) } else { p[i] }),
+            PointOp::Taper(k) => {
+                let s = self.mulk(p[1], k);
+                let one = self.c(1.0);
+                let s = self.add(one, s);
+                let s = self.maxk(s, 0.05);
+                [self.div(p[0], s), p[1], self.div(p[2], s)]
+            }
+            PointOp::Revolve(o) => { let l = self.len2(p[0], p[2]); [self.subk(l, o), p[1], self.c(0.0)] }
+            _ => return None,
+        })
+    }
+
+    fn post(&mut self, o: &Post, d: Value, p: P) -> Option<Value> {
+        Some(match *o {
+            Post::MulDist(s) => self.mulk(d, s),
+            Post::Shell(t) => { let n = self.neg(d); let n = self.subk(n, t); self.max(d, n) }
+            Post::Onion(t) => { let a = self.abs(d); self.subk(a, t) }
+            Post::Displacement(a, f) => {
+                let sin = self.sin;
+                let s = [0, 1, 2].map(|i| { let x = self.mulk(p[i], f); self.call(sin, x) });
+                let k = self.c(a);
+                let w = self.mul(k, s[0]);
+                let w = self.mul(w, s[1]);
+                let w = self.mul(w, s[2]);
+                self.add(d, w)
+            }
+            Post::Taper(k) => {
+                let s = self.mulk(p[1], k);
+                let one = self.c(1.0);
+                let s = self.add(one, s);
+                let s = self.maxk(s, 0.05);
+                let l = self.len2(p[0], p[2]);
+                let r = self.div(l, s);
+                let ss = self.mul(s, s);
+                let inv = self.div(one, ss);
+                let inv = self.maxk(inv, 1.0);
+                let w = self.c(k * k);
+                let w = self.mul(w, r);
+                let w = self.mul(w, r);
+                let w = self.div(w, ss);
+                let n = self.add(inv, w);
+                let n = self.sqrt(n);
+                self.div(d, n)
+            }
+            Post::Extrude(h) => { let az = self.abs(p[2]); let w1 = self.subk(az, h); self.join(d, w1) }
+            _ => return None,
+        })
+    }
+}
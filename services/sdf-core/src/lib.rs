@@ -0,0 +1,42 @@
+//! SDF core: tree types, schema, compiler and evaluator shared by the services
+//! and tools. Meshing (`mesh`), shader transpiling (`shader`) and tree analysis
+//! (`analysis`) are optional features, all on by default; native evaluation
+//! through Cranelift (`jit`) is opt-in.
+
+pub mod anim;
+pub mod bounds;
+pub mod canon;
+pub mod cave;
+pub mod compiler;
+pub mod diff;
+pub mod eval;
+pub mod eval_bvh;
+#[cfg(feature = "mesh")]
+pub mod export;
+pub mod expr;
+#[cfg(feature = "analysis")]
+pub mod fit;
+pub mod fracture;
+#[cfg(all(test, feature = "shader"))]
+mod golden;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod lipschitz;
+pub mod material;
+pub mod math;
+#[cfg(feature = "mesh")]
+pub mod mesh;
+pub mod meshsdf;
+pub mod noise;
+#[cfg(feature = "analysis")]
+pub mod optimize;
+#[cfg(feature = "mesh")]
+pub mod sample;
+pub mod schema;
+#[cfg(feature = "shader")]
+pub mod shader;
+#[cfg(feature = "analysis")]
+pub mod solve;
+pub mod tree;
+#[cfg(feature = "mesh")]
+pub mod voxel;
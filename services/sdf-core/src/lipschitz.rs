@@ -0,0 +1,186 @@
+//! Lipschitz bounds of tree fields, for safe sphere tracing.
+//!
+//! An exact field changes by at most the distance moved. Twists and bends
+//! stretch space and noise and displacements add slope, so their fields can
+//! overstate distances by up to their Lipschitz bound `L`; a tracer that steps
+//! `1/L` of the field never passes through the surface. Smooth blends and
+//! non-uniform scales keep `L <= 1` but understate distances instead, which is
+//! safe but slows tracing. `analyze` estimates both per node from parameters and
+//! child bounds, and warns at the node that introduces either. Estimates hold
+//! outside the surface, where a tracer steps, and over the ±5 domain previews
+//! and meshes use when a bound is infinite.
+
+use crate::bounds;
+use crate::math;
+use crate::tree::SdfNode;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Primitives whose field is the exact Euclidean distance.
+pub(crate) const EXACT: &[&str] = &[
+    "Sphere", "Box3d", "Cylinder", "Torus", "Plane", "Capsule", "Cone", "RoundedBox", "Pyramid", "Octahedron", "RoundedCone", "HexPrism",
+    "Link", "Triangle", "Bezier", "CappedCone", "CappedTorus", "RoundedCylinder", "CutSphere", "CutHollowSphere", "DeathStar", "SolidAngle",
+    "Rhombus", "Vesica", "Egg", "Tube", "BoxFrame", "InfiniteCylinder", "InfiniteCone", "Horseshoe", "Heart", "RoundedX", "Pie", "Trapezoid",
+    "Parallelogram", "Tunnel", "UnevenCapsule", "ArcShape", "Moon", "CrossShape", "ParabolaSegment", "StairsPrim", "Circle2d", "Rect2d",
+    "RoundedRect2d", "Segment2d", "Polygon2d", "Annular2d", "RegularPolygon", "StarPolygon",
+];
+
+/// Operations and transforms that keep exact operands exact.
+const KEEPS_EXACT: &[&str] = &[
+    "Union", "Translate", "RotateEuler", "RotateQuat", "Scale", "Mirror", "MirrorOctant", "Repeat", "RepeatFinite", "PolarRepeat",
+    "IcosahedralSymmetry", "Revolution", "Extrude", "Shell", "Onion",
+];
+
+/// Hard intersections and subtractions are bounds only in their corners; too common and too mild to warn about.
+const QUIET: &[&str] = &["Intersection", "Subtraction"];
+
+/// Largest gradient of `noise::noise3` at unit frequency, sampled over seeds.
+const NOISE_SLOPE: f32 = 2.7;
+
+/// Half-extent substituted for unbounded axes, as in previews and meshing.
+const DOMAIN: f32 = 5.0;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeBound {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub lipschitz: f32,
+    /// The field is the exact distance, not just a bound on it.
+    pub exact: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Warning {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub lipschitz: f32,
+    /// Suggested factor on sphere-tracing steps; 1 when distances only understate.
+    pub step_scale: f32,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Analysis {
+    pub lipschitz: f32,
+    pub step_scale: f32,
+    pub exact: bool,
+    /// Every node, parents before children.
+    pub nodes: Vec<NodeBound>,
+    pub warnings: Vec<Warning>,
+}
+
+/// Safe fraction of a step for bound `l`: `1/l` rounded down to hundredths, at least 0.01.
+pub fn step_scale(l: f32) -> f32 { if l <= 1.0 { 1.0 } else { ((100.0 / l).floor() / 100.0).max(0.01) } }
+
+pub fn analyze(tree: &SdfNode) -> Analysis {
+    let mut a = Analysis { lipschitz: 1.0, step_scale: 1.0, exact: true, nodes: Vec::new(), warnings: Vec::new() };
+    let (l, exact) = walk(tree, "root", &mut a);
+    a.lipschitz = l;
+    a.step_scale = step_scale(l);
+    a.exact = exact;
+    a
+}
+
+fn walk(n: &SdfNode, path: &str, a: &mut Analysis) -> (f32, bool) {
+    let at = a.nodes.len();
+    a.nodes.push(NodeBound { path: path.into(), ty: n.ty.clone(), lipschitz: 1.0, exact: true });
+    let kids: Vec<(f32, bool)> = n.children.iter().enumerate().map(|(i, c)| walk(c, &n.child_path(path, i), a)).collect();
+    let lc = if kids.is_empty() { 1.0 } else { kids.iter().fold(0.0f32, |m, k| m.max(k.0)) };
+    let ec = kids.iter().all(|k| k.1);
+    let exact = if kids.is_empty() { EXACT.contains(&n.ty.as_str()) } else { ec && KEEPS_EXACT.contains(&n.ty.as_str()) };
+    let l = match n.ty.as_str() {
+        "Gyroid" | "SchwarzP" | "Diamond" | "DiamondSurface" => 1.75,
+        "Neovius" => 2.7,
+        "Lidinoid" => 2.0,
+        "IWP" => 1.25,
+        "FRD" => 1.95,
+        "FischerKochS" => 1.4,
+        "PMY" => 2.1,
+        "Ellipsoid" => { let r = n.v3("radii", [1.0, 0.5, 0.5]); (math::max_c(r) / math::min_c(r).max(1e-6)).max(1.0) }
+        // Twisting by k·y shears points at radius r by k·r per unit of y.
+        "Twist" => lc * stretch(n.f("strength", 1.0).abs() * reach(n, [0, 2])),
+        "Bend" => lc * stretch(n.f("strength", 1.0).abs() * reach(n, [0, 1])),
+        "LatticeDeform" => lc * (1.0 + lattice_slope(n)),
+        "SdfSkinning" => lc * (1.0 + skin_slope(n)),
+        "Noise" => lc + n.f("amplitude", 0.1).abs() * n.f("frequency", 1.0).abs() * NOISE_SLOPE,
+        "SurfaceRoughness" => lc + n.f("amplitude", 0.02).abs() * n.f("frequency", 4.0).abs() * NOISE_SLOPE * n.u("octaves", 4).clamp(1, 8) as f32,
+        "Displacement" => lc + n.f("amplitude", 0.05).abs() * n.f("frequency", 5.0).abs() * 3f32.sqrt(),
+        "HeightmapDisplacement" => lc + n.f("amplitude", 0.1).abs() * heightmap_slope(n),
+        "Pipe" | "Engrave" => lc * std::f32::consts::SQRT_2,
+        "Morph" if kids.len() == 2 => { let t = n.f("t", 0.5); (1.0 - t).abs() * kids[0].0 + t.abs() * kids[1].0 }
+        _ => lc,
+    };
+    a.nodes[at].lipschitz = l;
+    a.nodes[at].exact = exact;
+    if l > lc.max(1.0) + 1e-4 {
+        let s = step_scale(l);
+        let message = format!("{} raises the Lipschitz bound to {l:.2}: distances may overstate by that factor, so scale sphere-tracing steps by {s}", n.ty);
+        a.warnings.push(Warning { path: path.into(), ty: n.ty.clone(), lipschitz: l, step_scale: s, message });
+    } else if !exact && ec && !kids.is_empty() && !QUIET.contains(&n.ty.as_str()) {
+        let message = format!("{} turns exact distances into a bound: they may understate, which is safe but slows sphere tracing", n.ty);
+        a.warnings.push(Warning { path: path.into(), ty: n.ty.clone(), lipschitz: l, step_scale: 1.0, message });
+    }
+    (l, exact)
+}
+
+/// Largest singular value of a shear of `a` next to a unit diagonal.
+fn stretch(a: f32) -> f32 { (a + (a * a + 4.0).sqrt()) * 0.5 }
+
+/// Largest distance from the deformation axis over the child's bounds.
+fn reach(n: &SdfNode, axes: [usize; 2]) -> f32 {
+    let b = n.children.first().map_or(bounds::Aabb::EMPTY, bounds::bounds).clamp_to(DOMAIN);
+    if b.is_empty() { return 0.0; }
+    let m = |i: usize| b.min[i].abs().max(b.max[i].abs());
+    math::len2(m(axes[0]), m(axes[1]))
+}
+
+fn floats(n: &SdfNode, k: &str) -> Vec<f32> {
+    n.params.get(k).and_then(Value::as_array).map(|a| a.iter().filter_map(Value::as_f64).map(|x| x as f32).collect()).unwrap_or_default()
+}
+
+/// Gradient bound of a bilinear heightmap from its steepest step along each axis.
+fn heightmap_slope(n: &SdfNode) -> f32 {
+    let (h, cols, rows) = (floats(n, "heights"), n.u("columns", 2) as usize, n.u("rows", 2) as usize);
+    if cols < 2 || rows < 2 || h.len() != cols * rows { return 0.0; }
+    let size = n.v3("size", [2.0, 0.0, 2.0]);
+    let at = |i: usize, j: usize| h[j * cols + i];
+    let dx = (0..rows).flat_map(|j| (1..cols).map(move |i| (j, i))).fold(0.0f32, |m, (j, i)| m.max((at(i, j) - at(i - 1, j)).abs()));
+    let dz = (1..rows).flat_map(|j| (0..cols).map(move |i| (j, i))).fold(0.0f32, |m, (j, i)| m.max((at(i, j) - at(i, j - 1)).abs()));
+    math::len2(dx * (cols - 1) as f32 / size[0].abs().max(1e-6), dz * (rows - 1) as f32 / size[2].abs().max(1e-6))
+}
+
+/// Gradient bound of the bone weights times how far each bone moves points. A
+/// bone's weight against the identity weight of 1e-4 turns over at √ln(1e4)
+/// radii from its centre, where it changes by √ln(1e4)/2 per radius.
+fn skin_slope(n: &SdfNode) -> f32 {
+    let s = 1e4f32.ln().sqrt();
+    let bones = n.params.get("bones").and_then(Value::as_array).cloned().unwrap_or_default();
+    bones.iter().map(|b| {
+        let b = SdfNode { params: b.as_object().cloned().unwrap_or_default(), ..SdfNode::leaf("Bone", Value::Null) };
+        let (m, r) = (math::euler_xyz(b.v3("angles", [0.0; 3])), b.f("radius", 1.0).abs().max(1e-6));
+        // |R - I| = 2 sin(θ/2), with cos θ from the trace.
+        let turn = (2.0 - 2.0 * ((m[0] + m[4] + m[8] - 1.0) * 0.5).clamp(-1.0, 1.0)).sqrt();
+        s * 0.5 * (math::len(b.v3("offset", [0.0; 3])) + turn * s * r) / r
+    }).sum()
+}
+
+/// Gradient bound of a trilinear lattice offset from its steepest step along each axis.
+fn lattice_slope(n: &SdfNode) -> f32 {
+    let d = n.v3("divisions", [2.0; 3]).map(|x| x.round().max(2.0) as usize);
+    let flat = floats(n, "offsets");
+    if d.iter().try_fold(3usize, |a, &x| a.checked_mul(x)) != Some(flat.len()) { return 0.0; }
+    let (min, max) = (n.v3("min", [-1.0; 3]), n.v3("max", [1.0; 3]));
+    let at = |x: usize, y: usize, z: usize| { let i = ((z * d[1] + y) * d[0] + x) * 3; [flat[i], flat[i + 1], flat[i + 2]] };
+    let axis = |k: usize| {
+        let mut m = 0.0f32;
+        for z in 0..d[2] { for y in 0..d[1] { for x in 0..d[0] {
+            let mut q = [x, y, z];
+            q[k] += 1;
+            if q[k] < d[k] { m = m.max(math::len(math::sub(at(q[0], q[1], q[2]), at(x, y, z)))); }
+        }}}
+        m * (d[k] - 1) as f32 / (max[k] - min[k]).abs().max(1e-6)
+    };
+    (axis(0).powi(2) + axis(1).powi(2) + axis(2).powi(2)).sqrt()
+}
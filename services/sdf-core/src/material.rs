@@ -0,0 +1,74 @@
+//! Surface materials: an optional `material` object on any tree node.
+//!
+//! A leaf takes the material of its nearest ancestor-or-self that has one, or
+//! the default grey. Hard operations pick the operand whose surface wins, smooth
+//! ones blend across the seam, subtractions keep the material of the first operand.
+
+use crate::compiler::Op;
+use crate::math::V3;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Material {
+    #[serde(default = "grey")] pub base_color: V3,
+    #[serde(default)] pub metallic: f32,
+    #[serde(default = "half")] pub roughness: f32,
+    #[serde(default)] pub emissive: V3,
+}
+
+fn grey() -> V3 { [0.8; 3] }
+fn half() -> f32 { 0.5 }
+
+impl Default for Material {
+    fn default() -> Self { Material { base_color: grey(), metallic: 0.0, roughness: half(), emissive: [0.0; 3] } }
+}
+
+impl Material {
+    pub fn parse(v: &Value, path: &str) -> Result<Material, String> {
+        let m: Material = serde_json::from_value(v.clone()).map_err(|e| format!("{path}: material {e}"))?;
+        let unit = |x: f32| (0.0..=1.0).contains(&x);
+        if !m.base_color.into_iter().all(unit) { return Err(format!("{path}: material base_color components must be in [0, 1]")); }
+        if !unit(m.metallic) || !unit(m.roughness) { return Err(format!("{path}: material metallic and roughness must be in [0, 1]")); }
+        if !m.emissive.into_iter().all(|x| x >= 0.0) { return Err(format!("{path}: material emissive components must be >= 0")); }
+        Ok(m)
+    }
+
+    pub fn pack(&self) -> Packed {
+        let (c, e) = (self.base_color, self.emissive);
+        [c[0], c[1], c[2], self.metallic, self.roughness, e[0], e[1], e[2]]
+    }
+}
+
+/// Material as blendable numbers: base color, metallic, roughness, emissive.
+pub type Packed = [f32; 8];
+
+/// Material at a point: the dominant table id plus the blended values.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample { pub id: u32, pub value: Packed }
+
+impl Sample {
+    pub fn mix(a: &Sample, b: &Sample, w: f32) -> Sample {
+        if w <= 0.0 { return *a; }
+        if w >= 1.0 { return *b; }
+        Sample { id: if w > 0.5 { b.id } else { a.id }, value: std::array::from_fn(|i| a.value[i] + (b.value[i] - a.value[i]) * w) }
+    }
+}
+
+/// How much of `b`'s material shows where `op` combines distances `a` and `b`.
+pub fn weight(o: Op, a: f32, b: f32) -> f32 {
+    use Op::*;
+    let pick = |c: bool| if c { 1.0 } else { 0.0 };
+    let blend = |x: f32, k: f32| if k > 0.0 { (0.5 + 0.5 * x / k).clamp(0.0, 1.0) } else { pick(x > 0.0) };
+    match o {
+        Union | ChamferUnion(_) | StairsUnion(..) | ColumnsUnion(..) => pick(b < a),
+        Intersection | ChamferIntersection(_) | StairsIntersection(..) | ColumnsIntersection(..) => pick(b > a),
+        SmoothUnion(k) | ExpSmoothUnion(k) => blend(a - b, k),
+        SmoothIntersection(k) | ExpSmoothIntersection(k) => blend(b - a, k),
+        Xor => pick(b.abs() < a.abs()),
+        Morph(t) => t,
+        Subtraction | SmoothSubtraction(_) | ChamferSubtraction(_) | StairsSubtraction(..) | ExpSmoothSubtraction(_) | ColumnsSubtraction(..)
+        | Pipe(_) | Engrave(_) | Groove(..) | Tongue(..) => 0.0,
+    }
+}
@@ -0,0 +1,50 @@
+//! Small fixed-size vector helpers shared by the evaluator, bounds and mesher.
+
+pub type V3 = [f32; 3];
+
+#[inline] pub fn add(a: V3, b: V3) -> V3 { [a[0]+b[0], a[1]+b[1], a[2]+b[2]] }
+#[inline] pub fn sub(a: V3, b: V3) -> V3 { [a[0]-b[0], a[1]-b[1], a[2]-b[2]] }
+#[inline] pub fn mul(a: V3, s: f32) -> V3 { [a[0]*s, a[1]*s, a[2]*s] }
+#[inline] pub fn mul3(a: V3, b: V3) -> V3 { [a[0]*b[0], a[1]*b[1], a[2]*b[2]] }
+#[inline] pub fn div3(a: V3, b: V3) -> V3 { [a[0]/b[0], a[1]/b[1], a[2]/b[2]] }
+#[inline] pub fn dot(a: V3, b: V3) -> f32 { a[0]*b[0] + a[1]*b[1] + a[2]*b[2] }
+#[inline] pub fn cross(a: V3, b: V3) -> V3 { [a[1]*b[2]-a[2]*b[1], a[2]*b[0]-a[0]*b[2], a[0]*b[1]-a[1]*b[0]] }
+#[inline] pub fn len(a: V3) -> f32 { dot(a, a).sqrt() }
+#[inline] pub fn abs(a: V3) -> V3 { [a[0].abs(), a[1].abs(), a[2].abs()] }
+#[inline] pub fn max0(a: V3) -> V3 { [a[0].max(0.0), a[1].max(0.0), a[2].max(0.0)] }
+#[inline] pub fn max_c(a: V3) -> f32 { a[0].max(a[1]).max(a[2]) }
+#[inline] pub fn min_c(a: V3) -> f32 { a[0].min(a[1]).min(a[2]) }
+#[inline] pub fn lerp(a: f32, b: f32, t: f32) -> f32 { a + (b - a) * t }
+#[inline] pub fn len2(x: f32, y: f32) -> f32 { (x*x + y*y).sqrt() }
+
+/// `x` as the shortest decimal that reads back to it, for compact JSON.
+pub fn short(x: f32) -> f64 { x.to_string().parse().unwrap_or(0.0) }
+
+pub fn normalize(a: V3) -> V3 {
+    let l = len(a);
+    if l > 1e-12 { mul(a, 1.0 / l) } else { [0.0, 1.0, 0.0] }
+}
+
+/// Row-major 3x3 matrix.
+pub type M3 = [f32; 9];
+
+#[inline] pub fn mat_vec(m: &M3, v: V3) -> V3 {
+    [m[0]*v[0]+m[1]*v[1]+m[2]*v[2], m[3]*v[0]+m[4]*v[1]+m[5]*v[2], m[6]*v[0]+m[7]*v[1]+m[8]*v[2]]
+}
+
+pub fn mat_mul(a: &M3, b: &M3) -> M3 {
+    let mut r = [0.0; 9];
+    for i in 0..3 { for j in 0..3 { r[i*3+j] = (0..3).map(|k| a[i*3+k] * b[k*3+j]).sum(); } }
+    r
+}
+
+pub fn transpose(m: &M3) -> M3 { [m[0], m[3], m[6], m[1], m[4], m[7], m[2], m[5], m[8]] }
+
+/// Object rotation for XYZ Euler angles in radians (applied X, then Y, then Z).
+pub fn euler_xyz(a: V3) -> M3 {
+    let (sx, cx) = a[0].sin_cos(); let (sy, cy) = a[1].sin_cos(); let (sz, cz) = a[2].sin_cos();
+    let rx = [1.0, 0.0, 0.0, 0.0, cx, -sx, 0.0, sx, cx];
+    let ry = [cy, 0.0, sy, 0.0, 1.0, 0.0, -sy, 0.0, cy];
+    let rz = [cz, -sz, 0.0, sz, cz, 0.0, 0.0, 0.0, 1.0];
+    mat_mul(&rz, &mat_mul(&ry, &rx))
+}
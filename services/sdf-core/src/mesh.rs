@@ -0,0 +1,161 @@
+//! Iso-surface extraction by marching tetrahedra on a regular grid.
+//!
+//! Each grid cube is split into six tetrahedra along its main diagonal, the same
+//! way for every cube, so neighbouring cells agree on shared faces and the output
+//! is a closed 2-manifold. The field is clipped against the sampling domain, which
+//! also closes surfaces that would otherwise leave it (planes, TPMS, repeats).
+
+use crate::bounds::Aabb;
+use crate::compiler::CompiledSdf;
+use crate::eval::{sd_box, Field};
+use crate::material::Material;
+use crate::math::{self, V3};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<V3>,
+    pub normals: Vec<V3>,
+    pub indices: Vec<[u32; 3]>,
+    /// Per-vertex base color; empty for trees without materials.
+    pub colors: Vec<V3>,
+    /// Material id per face, indexing `materials`; empty for trees without materials.
+    pub face_materials: Vec<u32>,
+    pub materials: Vec<Material>,
+}
+
+impl Mesh {
+    pub fn vertex_count(&self) -> usize { self.positions.len() }
+    pub fn face_count(&self) -> usize { self.indices.len() }
+
+    /// Material ids that own at least one face, ascending.
+    pub fn used_materials(&self) -> Vec<u32> {
+        let mut ids = self.face_materials.clone();
+        ids.sort_unstable(); ids.dedup();
+        ids
+    }
+
+    /// Every edge is shared by exactly two faces with opposite winding.
+    pub fn is_watertight(&self) -> bool {
+        let mut edges: HashMap<(u32, u32), i32> = HashMap::with_capacity(self.indices.len() * 3);
+        for t in &self.indices {
+            for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+                *edges.entry((a, b)).or_default() += 1;
+            }
+        }
+        edges.iter().all(|(&(a, b), &n)| n == 1 && edges.get(&(b, a)) == Some(&1))
+    }
+}
+
+/// Six tetrahedra of a cube, as corner indices (bit 0 = +x, bit 1 = +y, bit 2 = +z).
+const TETS: [[usize; 4]; 6] = [[0, 1, 3, 7], [0, 1, 5, 7], [0, 2, 3, 7], [0, 2, 6, 7], [0, 4, 5, 7], [0, 4, 6, 7]];
+
+pub struct Grid { pub origin: V3, pub step: f32, pub dims: [usize; 3] }
+
+impl Grid {
+    /// `res` cells along the longest axis of `domain`, plus one padding cell per side.
+    pub fn new(domain: &Aabb, res: usize) -> Grid {
+        let size = domain.size();
+        let step = math::max_c(size).max(1e-6) / res.max(1) as f32;
+        let dims = size.map(|s| (s / step).ceil().max(1.0) as usize + 2);
+        Grid { origin: math::sub(domain.min, [step; 3]), step, dims }
+    }
+    pub fn point(&self, i: usize, j: usize, k: usize) -> V3 {
+        math::add(self.origin, [i as f32 * self.step, j as f32 * self.step, k as f32 * self.step])
+    }
+}
+
+/// Marching progress after a slab of cells along Z.
+#[derive(Clone, Copy, Debug)]
+pub struct Progress { pub slabs_done: usize, pub slabs_total: usize, pub triangles: usize }
+
+/// Surface mesh of `sdf` over `domain`, calling `progress` after every slab of
+/// cells; `None` once it returns false.
+pub fn generate(sdf: &dyn Field, domain: &Aabb, res: usize, progress: &mut dyn FnMut(Progress) -> bool) -> Option<Mesh> {
+    let grid = Grid::new(domain, res);
+    let clip_c = domain.center();
+    let clip_h = math::add(math::mul(domain.size(), 0.5), [grid.step * 0.5; 3]);
+    let field = |p: V3| sdf.eval(p).max(sd_box(math::sub(p, clip_c), clip_h));
+    let [nx, ny, nz] = grid.dims;
+    let layer = |k: usize| -> Vec<f32> {
+        let pts: Vec<V3> = (0..=ny).flat_map(|j| (0..=nx).map(move |i| (i, j))).map(|(i, j)| grid.point(i, j, k)).collect();
+        let d = sdf.eval_batch(&pts);
+        pts.iter().zip(d).map(|(&p, d)| d.max(sd_box(math::sub(p, clip_c), clip_h))).collect()
+    };
+
+    let mut mesh = Mesh::default();
+    let mut cache: HashMap<(u64, u64), u32> = HashMap::new();
+    let gid = |i: usize, j: usize, k: usize| (i + (nx + 1) * (j + (ny + 1) * k)) as u64;
+    let mut lo = layer(0);
+    for k in 0..nz {
+        let hi = layer(k + 1);
+        for j in 0..ny {
+            for i in 0..nx {
+                let mut v = [0.0f32; 8];
+                let mut ids = [0u64; 8];
+                let mut pos = [[0.0f32; 3]; 8];
+                for c in 0..8 {
+                    let (ci, cj, ck) = (i + (c & 1), j + ((c >> 1) & 1), k + ((c >> 2) & 1));
+                    let l = if ck == k { &lo } else { &hi };
+                    v[c] = l[ci + (nx + 1) * cj];
+                    ids[c] = gid(ci, cj, ck);
+                    pos[c] = grid.point(ci, cj, ck);
+                }
+                let inside = v.iter().filter(|d| **d < 0.0).count();
+                if inside == 0 || inside == 8 { continue; }
+                for t in &TETS { polygonize(t, &v, &ids, &pos, &mut mesh, &mut cache); }
+            }
+        }
+        lo = hi;
+        if !progress(Progress { slabs_done: k + 1, slabs_total: nz, triangles: mesh.face_count() }) { return None; }
+    }
+    mesh.normals = mesh.positions.par_iter().map(|&p| {
+        let e = grid.step * 0.25;
+        let g = |i: usize| { let mut a = p; let mut b = p; a[i] += e; b[i] -= e; field(a) - field(b) };
+        math::normalize([g(0), g(1), g(2)])
+    }).collect();
+    Some(mesh)
+}
+
+/// Color vertices with the blended base color and give each face the dominant
+/// material at its centroid. Trees without materials are left uncolored.
+pub fn paint(mesh: &mut Mesh, sdf: &CompiledSdf) {
+    if !sdf.has_materials() { return; }
+    mesh.colors = sdf.eval_material_batch(&mesh.positions).iter().map(|(_, s)| [s.value[0], s.value[1], s.value[2]]).collect();
+    let centroids: Vec<V3> = mesh.indices.iter().map(|t| math::mul(t.iter().fold([0.0; 3], |a, &i| math::add(a, mesh.positions[i as usize])), 1.0 / 3.0)).collect();
+    mesh.face_materials = sdf.eval_material_batch(&centroids).iter().map(|(_, s)| s.id).collect();
+    mesh.materials = sdf.materials.clone();
+}
+
+fn polygonize(t: &[usize; 4], v: &[f32; 8], ids: &[u64; 8], pos: &[V3; 8], mesh: &mut Mesh, cache: &mut HashMap<(u64, u64), u32>) {
+    let (ins, outs): (Vec<usize>, Vec<usize>) = t.iter().partition(|&&c| v[c] < 0.0);
+    if ins.is_empty() || outs.is_empty() { return; }
+    let mut vert = |a: usize, b: usize| -> u32 {
+        let (a, b) = if ids[a] < ids[b] { (a, b) } else { (b, a) };
+        *cache.entry((ids[a], ids[b])).or_insert_with(|| {
+            let s = v[a] / (v[a] - v[b]);
+            mesh.positions.push(math::add(pos[a], math::mul(math::sub(pos[b], pos[a]), s)));
+            (mesh.positions.len() - 1) as u32
+        })
+    };
+    // Winding is decided on edge midpoints rather than the interpolated vertices:
+    // the sign is the same for any crossing, but midpoints never degenerate when
+    // the surface passes exactly through grid corners.
+    let mid = |a: usize, b: usize| math::mul(math::add(pos[a], pos[b]), 0.5);
+    let centroid = |cs: &[usize]| math::mul(cs.iter().fold([0.0; 3], |acc, &c| math::add(acc, pos[c])), 1.0 / cs.len() as f32);
+    let outward = math::sub(centroid(&outs), centroid(&ins));
+    let tris: Vec<([u32; 3], [V3; 3])> = match (ins.len(), outs.len()) {
+        (1, 3) => vec![([vert(ins[0], outs[0]), vert(ins[0], outs[1]), vert(ins[0], outs[2])], [mid(ins[0], outs[0]), mid(ins[0], outs[1]), mid(ins[0], outs[2])])],
+        (3, 1) => vec![([vert(ins[0], outs[0]), vert(ins[1], outs[0]), vert(ins[2], outs[0])], [mid(ins[0], outs[0]), mid(ins[1], outs[0]), mid(ins[2], outs[0])])],
+        _ => {
+            let q = [vert(ins[0], outs[0]), vert(ins[0], outs[1]), vert(ins[1], outs[1]), vert(ins[1], outs[0])];
+            let m = [mid(ins[0], outs[0]), mid(ins[0], outs[1]), mid(ins[1], outs[1]), mid(ins[1], outs[0])];
+            vec![([q[0], q[1], q[2]], [m[0], m[1], m[2]]), ([q[0], q[2], q[3]], [m[0], m[2], m[3]])]
+        }
+    };
+    for (mut tri, [a, b, c]) in tris {
+        if math::dot(math::cross(math::sub(b, a), math::sub(c, a)), outward) < 0.0 { tri.swap(1, 2); }
+        mesh.indices.push(tri);
+    }
+}
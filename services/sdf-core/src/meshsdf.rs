@@ -0,0 +1,373 @@
+//! Triangle meshes as distance fields: the `MeshSdf` primitive and OBJ/STL import.
+//!
+//! Exact nodes keep the triangles. The distance is to the nearest triangle through
+//! a BVH; the sign comes from the generalized winding number, summed exactly over
+//! near triangles and with one dipole per BVH node far away (Barill et al., "Fast
+//! Winding Numbers for Soups and Clouds"), so meshes with small holes, duplicate
+//! shells or flipped patches still split inside from outside. Baked nodes sample
+//! that field at grid points grouped into 4³ bricks; bricks wholly beyond the
+//! narrow band store only their sign and read as ±band.
+
+use crate::eval::sd_triangle;
+use crate::math::{self, short, V3};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// Triangles per BVH leaf.
+const LEAF: usize = 4;
+/// A BVH node is replaced by its dipole when the query point is more than this
+/// many node radii from the node's centre.
+const BETA: f32 = 2.0;
+/// Grid samples per brick edge.
+pub const BRICK: usize = 4;
+/// Grid samples per axis; well above what the largest bake resolution produces.
+pub const MAX_GRID_DIM: usize = 2048;
+/// Brick map entries for bricks entirely outside / inside the band.
+pub const FAR_OUTSIDE: i32 = -1;
+pub const FAR_INSIDE: i32 = -2;
+
+#[derive(Debug)]
+pub enum MeshSdf { Exact(TriMesh), Baked(Grid) }
+
+impl MeshSdf {
+    pub fn distance(&self, p: V3) -> f32 {
+        match self { MeshSdf::Exact(m) => m.distance(p), MeshSdf::Baked(g) => g.distance(p) }
+    }
+}
+
+/// BVH node. Leaves hold `count > 0` triangles from `first`; inner nodes have
+/// their left child right after them and the right child at `first`. `normal`
+/// is the area-weighted normal sum and `centre` the area centroid of the subtree.
+#[derive(Debug)]
+struct Node { lo: V3, hi: V3, first: u32, count: u32, centre: V3, radius: f32, normal: V3 }
+
+#[derive(Debug)]
+pub struct TriMesh { pub tris: Vec<[V3; 3]>, nodes: Vec<Node> }
+
+fn centroid(t: &[V3; 3]) -> V3 { math::mul(math::add(math::add(t[0], t[1]), t[2]), 1.0 / 3.0) }
+
+fn box_dist(p: V3, lo: V3, hi: V3) -> f32 {
+    math::len(math::max0([(lo[0] - p[0]).max(p[0] - hi[0]), (lo[1] - p[1]).max(p[1] - hi[1]), (lo[2] - p[2]).max(p[2] - hi[2])]))
+}
+
+fn build(tris: &mut [[V3; 3]], first: usize, nodes: &mut Vec<Node>) {
+    let (mut lo, mut hi, mut clo, mut chi) = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3], [f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+    let (mut normal, mut centre, mut area) = ([0.0; 3], [0.0; 3], 0.0);
+    for t in tris.iter() {
+        for v in t { for i in 0..3 { lo[i] = lo[i].min(v[i]); hi[i] = hi[i].max(v[i]); } }
+        let c = centroid(t);
+        for i in 0..3 { clo[i] = clo[i].min(c[i]); chi[i] = chi[i].max(c[i]); }
+        let n = math::mul(math::cross(math::sub(t[1], t[0]), math::sub(t[2], t[0])), 0.5);
+        let a = math::len(n);
+        normal = math::add(normal, n);
+        centre = math::add(centre, math::mul(c, a));
+        area += a;
+    }
+    let centre = if area > 1e-20 { math::mul(centre, 1.0 / area) } else { math::mul(math::add(lo, hi), 0.5) };
+    let radius = tris.iter().flatten().map(|v| math::len(math::sub(*v, centre))).fold(0.0, f32::max);
+    let i = nodes.len();
+    nodes.push(Node { lo, hi, first: first as u32, count: tris.len() as u32, centre, radius, normal });
+    if tris.len() <= LEAF { return; }
+    let ext = math::sub(chi, clo);
+    let axis = if ext[0] >= ext[1] && ext[0] >= ext[2] { 0 } else if ext[1] >= ext[2] { 1 } else { 2 };
+    let mid = tris.len() / 2;
+    tris.select_nth_unstable_by(mid, |a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+    let (l, r) = tris.split_at_mut(mid);
+    build(l, first, nodes);
+    nodes[i].first = nodes.len() as u32;
+    nodes[i].count = 0;
+    build(r, first + mid, nodes);
+}
+
+/// Solid angle of triangle `t` seen from `p` (Van Oosterom and Strackee), positive
+/// from behind a counter-clockwise face.
+fn solid_angle(t: &[V3; 3], p: V3) -> f32 {
+    let (a, b, c) = (math::sub(t[0], p), math::sub(t[1], p), math::sub(t[2], p));
+    let (la, lb, lc) = (math::len(a), math::len(b), math::len(c));
+    let det = math::dot(a, math::cross(b, c));
+    2.0 * det.atan2(la * lb * lc + math::dot(a, b) * lc + math::dot(b, c) * la + math::dot(c, a) * lb)
+}
+
+impl TriMesh {
+    pub fn new(mut tris: Vec<[V3; 3]>) -> TriMesh {
+        let mut nodes = Vec::with_capacity(tris.len() / 2 + 1);
+        if !tris.is_empty() { build(&mut tris, 0, &mut nodes); }
+        TriMesh { tris, nodes }
+    }
+
+    pub fn bounds(&self) -> (V3, V3) { self.nodes.first().map_or(([0.0; 3], [0.0; 3]), |n| (n.lo, n.hi)) }
+
+    /// Distance to the nearest triangle.
+    pub fn unsigned(&self, p: V3) -> f32 {
+        let mut best = f32::INFINITY;
+        let mut stack = [0u32; 64];
+        let mut top = usize::from(!self.nodes.is_empty());
+        while top > 0 {
+            top -= 1;
+            let n = &self.nodes[stack[top] as usize];
+            if box_dist(p, n.lo, n.hi) >= best { continue; }
+            if n.count > 0 {
+                for t in &self.tris[n.first as usize..(n.first + n.count) as usize] { best = best.min(sd_triangle(p, t[0], t[1], t[2], 0.0)); }
+                continue;
+            }
+            let (a, b) = (stack[top] + 1, n.first);
+            let (na, nb) = (&self.nodes[a as usize], &self.nodes[b as usize]);
+            // Push the nearer child last so it is searched first.
+            let (near, far) = if box_dist(p, na.lo, na.hi) <= box_dist(p, nb.lo, nb.hi) { (a, b) } else { (b, a) };
+            stack[top] = far;
+            stack[top + 1] = near;
+            top += 2;
+        }
+        best
+    }
+
+    /// Generalized winding number: about 1 inside a closed counter-clockwise mesh, 0 outside.
+    pub fn winding(&self, p: V3) -> f32 {
+        let mut w = 0.0;
+        let mut stack = [0u32; 64];
+        let mut top = usize::from(!self.nodes.is_empty());
+        while top > 0 {
+            top -= 1;
+            let i = stack[top];
+            let n = &self.nodes[i as usize];
+            let d = math::sub(n.centre, p);
+            let r = math::len(d);
+            if n.count > 0 {
+                w += self.tris[n.first as usize..(n.first + n.count) as usize].iter().map(|t| solid_angle(t, p)).sum::<f32>();
+            } else if r > BETA * n.radius {
+                w += math::dot(d, n.normal) / (r * r * r);
+            } else {
+                stack[top] = n.first;
+                stack[top + 1] = i + 1;
+                top += 2;
+            }
+        }
+        w / (4.0 * PI)
+    }
+
+    pub fn distance(&self, p: V3) -> f32 {
+        let d = self.unsigned(p);
+        if self.winding(p).abs() > 0.5 { -d } else { d }
+    }
+}
+
+/// Baked field: `dims` samples per axis (multiples of [`BRICK`]) spaced `cell` apart
+/// from `origin`. `bricks` has one entry per brick in x-fastest order, either an
+/// index into `values` (64 samples, x fastest) or [`FAR_OUTSIDE`] / [`FAR_INSIDE`].
+#[derive(Debug)]
+pub struct Grid { pub origin: V3, pub cell: f32, pub dims: [usize; 3], pub band: f32, pub bricks: Vec<i32>, pub values: Vec<f32> }
+
+impl Grid {
+    /// Checks the sizes of the parts read from a node.
+    pub fn check(&self) -> Result<(), String> {
+        if self.cell.is_nan() || self.band.is_nan() || self.cell <= 0.0 || self.band <= 0.0 { return Err("grid_cell and grid_band must be positive".into()); }
+        if self.dims.iter().any(|&d| d < BRICK || d % BRICK != 0) { return Err(format!("grid_dims must be positive multiples of {BRICK}")); }
+        if self.dims.iter().any(|&d| d > MAX_GRID_DIM) { return Err(format!("grid_dims must be <= {MAX_GRID_DIM} per axis")); }
+        let count = self.dims.iter().try_fold(1usize, |n, d| n.checked_mul(d / BRICK)).ok_or("grid_dims overflow")?;
+        if self.bricks.len() != count { return Err(format!("grid_bricks must hold {count} entries")); }
+        let cap = (self.values.len() / BRICK.pow(3)) as i32;
+        if self.values.len() % BRICK.pow(3) != 0 || self.bricks.iter().any(|&b| b < FAR_INSIDE || b >= cap) {
+            return Err(format!("grid_bricks entries must be -1, -2 or one of the {cap} bricks in grid_values"));
+        }
+        Ok(())
+    }
+
+    fn at(&self, i: usize, j: usize, k: usize) -> f32 {
+        let (bx, by) = (self.dims[0] / BRICK, self.dims[1] / BRICK);
+        match self.bricks[(k / BRICK * by + j / BRICK) * bx + i / BRICK] {
+            FAR_OUTSIDE => self.band,
+            FAR_INSIDE => -self.band,
+            b => self.values[b as usize * BRICK.pow(3) + ((k % BRICK) * BRICK + j % BRICK) * BRICK + i % BRICK],
+        }
+    }
+
+    /// Trilinear sample, plus the distance to the grid box for points outside it.
+    pub fn distance(&self, p: V3) -> f32 {
+        let q = math::sub(p, self.origin);
+        let mut u = [0.0; 3];
+        let mut ix = [0usize; 3];
+        let mut out = [0.0; 3];
+        for a in 0..3 {
+            let max = (self.dims[a] - 1) as f32 * self.cell;
+            let c = q[a].clamp(0.0, max);
+            out[a] = q[a] - c;
+            let g = c / self.cell;
+            ix[a] = (g.floor() as usize).min(self.dims[a] - 2);
+            u[a] = g - ix[a] as f32;
+        }
+        let [i, j, k] = ix;
+        let x00 = math::lerp(self.at(i, j, k), self.at(i + 1, j, k), u[0]);
+        let x10 = math::lerp(self.at(i, j + 1, k), self.at(i + 1, j + 1, k), u[0]);
+        let x01 = math::lerp(self.at(i, j, k + 1), self.at(i + 1, j, k + 1), u[0]);
+        let x11 = math::lerp(self.at(i, j + 1, k + 1), self.at(i + 1, j + 1, k + 1), u[0]);
+        math::lerp(math::lerp(x00, x10, u[1]), math::lerp(x01, x11, u[1]), u[2]) + math::len(out)
+    }
+
+    /// Samples `mesh` with `resolution` cells along its longest side and a band of
+    /// `band` cells. `None` once `expired` reports the deadline passed.
+    pub fn bake(mesh: &TriMesh, resolution: usize, band: f32, expired: &(dyn Fn() -> bool + Sync)) -> Option<Grid> {
+        use rayon::prelude::*;
+        let (lo, hi) = mesh.bounds();
+        let cell = math::max_c(math::sub(hi, lo)).max(1e-6) / resolution.max(1) as f32;
+        let band = band.max(1.0) * cell;
+        let origin = math::sub(lo, [band + cell; 3]);
+        let dims = [0, 1, 2].map(|a| ((((hi[a] - lo[a]) + 2.0 * (band + cell)) / cell).ceil() as usize + 1).div_ceil(BRICK) * BRICK);
+        let nb = dims.map(|d| d / BRICK);
+        let half = 0.5 * (BRICK - 1) as f32 * cell * 3f32.sqrt();
+        let bricks: Vec<Option<Result<i32, Vec<f32>>>> = (0..nb[0] * nb[1] * nb[2]).into_par_iter().map(|b| {
+            if expired() { return None; }
+            let base = [b % nb[0], b / nb[0] % nb[1], b / (nb[0] * nb[1])].map(|x| x * BRICK);
+            let at = |i: usize, j: usize, k: usize| math::add(origin, math::mul([(base[0] + i) as f32, (base[1] + j) as f32, (base[2] + k) as f32], cell));
+            let mid = 0.5 * (BRICK - 1) as f32;
+            let d = mesh.distance(math::add(origin, math::mul([base[0] as f32 + mid, base[1] as f32 + mid, base[2] as f32 + mid], cell)));
+            if d.abs() - half > band { return Some(Ok(if d > 0.0 { FAR_OUTSIDE } else { FAR_INSIDE })); }
+            let mut v = Vec::with_capacity(BRICK.pow(3));
+            for k in 0..BRICK { for j in 0..BRICK { for i in 0..BRICK { v.push(mesh.distance(at(i, j, k)).clamp(-band, band)); } } }
+            Some(Err(v))
+        }).collect();
+        let mut grid = Grid { origin, cell, dims, band, bricks: Vec::with_capacity(bricks.len()), values: vec![] };
+        for b in bricks {
+            match b? {
+                Ok(flag) => grid.bricks.push(flag),
+                Err(v) => { grid.bricks.push((grid.values.len() / BRICK.pow(3)) as i32); grid.values.extend(v); }
+            }
+        }
+        Some(grid)
+    }
+}
+
+// ── nodes ───────────────────────────────────────────────────────────────────
+
+/// Exact `MeshSdf` node holding `s`.
+pub fn exact_node(s: &Soup) -> Value {
+    let vertices: Vec<f64> = s.vertices.iter().flatten().map(|&x| short(x)).collect();
+    json!({ "type": "MeshSdf", "params": { "vertices": vertices, "indices": s.indices.iter().flatten().collect::<Vec<_>>() } })
+}
+
+impl Grid {
+    /// Baked `MeshSdf` node holding this grid.
+    pub fn node(&self) -> Value {
+        json!({ "type": "MeshSdf", "params": {
+            "grid_origin": self.origin.map(short), "grid_cell": short(self.cell), "grid_dims": self.dims, "grid_band": short(self.band),
+            "grid_bricks": self.bricks, "grid_values": self.values.iter().map(|&x| short(x)).collect::<Vec<_>>(),
+        } })
+    }
+}
+
+// ── import ──────────────────────────────────────────────────────────────────
+
+/// Indexed triangles read from an upload.
+pub struct Soup { pub vertices: Vec<V3>, pub indices: Vec<[u32; 3]> }
+
+impl Soup {
+    pub fn triangles(&self) -> Vec<[V3; 3]> { self.indices.iter().map(|t| t.map(|i| self.vertices[i as usize])).collect() }
+
+    /// Every edge shared by exactly two faces with opposite orientation.
+    pub fn watertight(&self) -> bool {
+        let mut edges: HashMap<(u32, u32), [u32; 2]> = HashMap::new();
+        for t in &self.indices {
+            for (a, b) in [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] { edges.entry((a.min(b), a.max(b))).or_default()[usize::from(a > b)] += 1; }
+        }
+        !edges.is_empty() && edges.values().all(|&c| c == [1, 1])
+    }
+}
+
+/// Wavefront OBJ: `v` and `f` records; polygons are fanned, `v/vt/vn` and
+/// negative (relative) indices are accepted, everything else is ignored.
+pub fn parse_obj(text: &str) -> Result<Soup, String> {
+    let mut s = Soup { vertices: vec![], indices: vec![] };
+    for (no, line) in text.lines().enumerate() {
+        let mut it = line.split_whitespace();
+        match it.next() {
+            Some("v") => {
+                let v: Vec<f32> = it.take(3).map(|x| x.parse::<f32>()).collect::<Result<_, _>>().map_err(|_| format!("line {}: bad vertex", no + 1))?;
+                if v.len() != 3 || v.iter().any(|x| !x.is_finite()) { return Err(format!("line {}: a vertex needs three finite coordinates", no + 1)); }
+                s.vertices.push([v[0], v[1], v[2]]);
+            }
+            Some("f") => {
+                let n = s.vertices.len() as i64;
+                let face = it.map(|w| {
+                    let i: i64 = w.split('/').next().unwrap_or("").parse().map_err(|_| format!("line {}: bad face index '{w}'", no + 1))?;
+                    let i = if i < 0 { n + i } else { i - 1 };
+                    if (0..n).contains(&i) { Ok(i as u32) } else { Err(format!("line {}: face index '{w}' is out of range", no + 1)) }
+                }).collect::<Result<Vec<u32>, String>>()?;
+                if face.len() < 3 { return Err(format!("line {}: a face needs at least three vertices", no + 1)); }
+                s.indices.extend((1..face.len() - 1).map(|k| [face[0], face[k], face[k + 1]]));
+            }
+            _ => {}
+        }
+    }
+    Ok(s)
+}
+
+/// STL, binary or ASCII; coincident corners are merged into shared vertices.
+pub fn parse_stl(data: &[u8]) -> Result<Soup, String> {
+    let mut corners: Vec<V3> = vec![];
+    let binary = data.len() >= 84 && 84 + 50 * u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize == data.len();
+    if binary {
+        for f in data[84..].chunks_exact(50) {
+            for c in 0..3 {
+                let o = 12 + c * 12;
+                corners.push([0, 1, 2].map(|a| f32::from_le_bytes([f[o + a * 4], f[o + a * 4 + 1], f[o + a * 4 + 2], f[o + a * 4 + 3]])));
+            }
+        }
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| "STL is neither binary nor ASCII")?;
+        if !text.trim_start().starts_with("solid") { return Err("STL is neither binary nor ASCII".into()); }
+        for (no, line) in text.lines().enumerate() {
+            let mut it = line.split_whitespace();
+            if it.next() != Some("vertex") { continue; }
+            let v: Vec<f32> = it.take(3).map(|x| x.parse::<f32>()).collect::<Result<_, _>>().map_err(|_| format!("line {}: bad vertex", no + 1))?;
+            if v.len() != 3 { return Err(format!("line {}: a vertex needs three coordinates", no + 1)); }
+            corners.push([v[0], v[1], v[2]]);
+        }
+        if corners.len() % 3 != 0 { return Err("STL facets must have three vertices each".into()); }
+    }
+    if corners.iter().flatten().any(|x| !x.is_finite()) { return Err("STL has non-finite coordinates".into()); }
+    let mut s = Soup { vertices: vec![], indices: vec![] };
+    let mut seen: HashMap<[u32; 3], u32> = HashMap::new();
+    let ids: Vec<u32> = corners.iter().map(|v| *seen.entry(v.map(f32::to_bits)).or_insert_with(|| { s.vertices.push(*v); s.vertices.len() as u32 - 1 })).collect();
+    s.indices = ids.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, schema, tree::SdfNode};
+
+    const CUBE: &str = "v -1 -1 -1\nv 1 -1 -1\nv 1 1 -1\nv -1 1 -1\nv -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+        f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\nf 2 3 7 6\nf 3 4 8 7\nf 4 1 5 8\n";
+
+    #[test]
+    fn baked_grids_round_trip() {
+        let soup = parse_obj(CUBE).unwrap();
+        assert!(soup.watertight());
+        let grid = Grid::bake(&TriMesh::new(soup.triangles()), 16, 2.0, &|| false).unwrap();
+        grid.check().unwrap();
+        let node = grid.node();
+        assert!(schema::validate(&node).is_empty());
+        let sdf = compiler::compile(&SdfNode::from_json(&node).unwrap()).unwrap();
+        // Distances are clamped to the band, here two cells of 0.125.
+        for (p, want) in [([0.0, 0.0, 0.0], -0.25), ([1.1, 0.0, 0.0], 0.1), ([0.0, -0.95, 0.2], -0.05)] {
+            assert!((sdf.eval(p) - want).abs() < 0.02, "{p:?}: {}", sdf.eval(p));
+        }
+    }
+
+    #[test]
+    fn oversized_grids_are_rejected() {
+        // 2^42 samples per axis: the brick count overflows unless the dims are capped first.
+        let huge = 4_398_046_511_104f64;
+        let node = json!({"type": "MeshSdf", "params": {"grid_cell": 0.1, "grid_band": 0.2, "grid_dims": [huge, huge, 4], "grid_bricks": [], "grid_values": []}});
+        let errs = schema::validate(&node);
+        assert!(errs.iter().any(|e| e.contains("param 'grid_dims' must be <= 2048")), "{errs:?}");
+        let e = compiler::compile(&SdfNode::from_json(&node).unwrap()).unwrap_err();
+        assert_eq!(e, format!("root: MeshSdf grid_dims must be <= {MAX_GRID_DIM} per axis"));
+        let grid = |dims: [usize; 3], bricks: Vec<i32>| Grid { origin: [0.0; 3], cell: 0.1, dims, band: 0.2, bricks, values: vec![] };
+        assert_eq!(grid([8, 8, 6], vec![]).check().unwrap_err(), "grid_dims must be positive multiples of 4");
+        assert_eq!(grid([8, 8, 4], vec![-1; 3]).check().unwrap_err(), "grid_bricks must hold 4 entries");
+        assert_eq!(grid([8, 8, 4], vec![-1, -2, 0, -1]).check().unwrap_err(), "grid_bricks entries must be -1, -2 or one of the 0 bricks in grid_values");
+        assert!(grid([8, 8, 4], vec![-1, -2, -2, -1]).check().is_ok());
+    }
+}
@@ -0,0 +1,64 @@
+//! Seeded lattice noise and a deterministic PRNG.
+//!
+//! Everything here is integer-hash based so the same seed gives the same field on
+//! every platform, and the functions can be transcribed 1:1 into shader code.
+
+use crate::math::V3;
+
+#[inline]
+pub fn hash3(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9E37_79B1)
+        ^ (x as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (y as u32).wrapping_mul(0xC2B2_AE35)
+        ^ (z as u32).wrapping_mul(0x27D4_EB2F);
+    h ^= h >> 15; h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12; h = h.wrapping_mul(0x297A_2D39);
+    h ^ (h >> 15)
+}
+
+#[inline]
+fn grad(h: u32, d: V3) -> f32 {
+    let g = [(h & 0xff) as f32 / 127.5 - 1.0, ((h >> 8) & 0xff) as f32 / 127.5 - 1.0, ((h >> 16) & 0xff) as f32 / 127.5 - 1.0];
+    g[0]*d[0] + g[1]*d[1] + g[2]*d[2]
+}
+
+#[inline]
+fn fade(t: f32) -> f32 { t * t * t * (t * (t * 6.0 - 15.0) + 10.0) }
+
+/// Gradient noise in roughly [-1, 1].
+pub fn noise3(p: V3, seed: u32) -> f32 {
+    let i = [p[0].floor(), p[1].floor(), p[2].floor()];
+    let f = [p[0]-i[0], p[1]-i[1], p[2]-i[2]];
+    let (ix, iy, iz) = (i[0] as i32, i[1] as i32, i[2] as i32);
+    let u = [fade(f[0]), fade(f[1]), fade(f[2])];
+    let c = |dx: i32, dy: i32, dz: i32| grad(hash3(ix+dx, iy+dy, iz+dz, seed), [f[0]-dx as f32, f[1]-dy as f32, f[2]-dz as f32]);
+    let l = crate::math::lerp;
+    let x00 = l(c(0,0,0), c(1,0,0), u[0]); let x10 = l(c(0,1,0), c(1,1,0), u[0]);
+    let x01 = l(c(0,0,1), c(1,0,1), u[0]); let x11 = l(c(0,1,1), c(1,1,1), u[0]);
+    l(l(x00, x10, u[1]), l(x01, x11, u[1]), u[2])
+}
+
+/// Fractal sum of `octaves` noise layers, each at twice the frequency and half the amplitude.
+pub fn fbm(p: V3, octaves: u32, seed: u32) -> f32 {
+    (0..octaves).map(|i| { let s = (1u32 << i) as f32; noise3(crate::math::mul(p, s), seed.wrapping_add(i)) / s }).sum()
+}
+
+/// SplitMix64: tiny, fast and stable across releases, which matters more here than quality.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self { Rng(seed) }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+    /// Uniform in [0, 1).
+    pub fn f32(&mut self) -> f32 { (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32 }
+    pub fn range(&mut self, lo: f32, hi: f32) -> f32 { lo + (hi - lo) * self.f32() }
+    /// Standard normal, by Box–Muller.
+    pub fn gaussian(&mut self) -> f32 { (-2.0 * (1.0 - self.f32()).ln()).sqrt() * (std::f32::consts::TAU * self.f32()).cos() }
+    pub fn point_in(&mut self, lo: V3, hi: V3) -> V3 { [self.range(lo[0], hi[0]), self.range(lo[1], hi[1]), self.range(lo[2], hi[2])] }
+}
@@ -0,0 +1,252 @@
+//! Tree optimizer: rewrites that keep the zero set (the modeled surface) unchanged.
+//!
+//! Passes run bottom-up and repeat until nothing changes:
+//! - constant folding of no-op params (zero offsets, unit scales, `k = 0` smooth ops, `t = 0|1` morphs, ...)
+//! - transform fusion (Translate∘Translate, Scale∘Scale, RotateEuler∘RotateEuler)
+//! - flattening of nested or single-child Union/Intersection and removal of duplicate operands
+//! - pruning with bounds: provably empty subtrees, subtractions that miss, and
+//!   half-spaces that already contain the rest of an Intersection
+
+use crate::bounds::{bounds, Aabb};
+use crate::math::{self, M3};
+use crate::tree::{SdfNode, Slots};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Change { pub path: String, pub rule: &'static str, pub detail: String }
+
+pub struct Optimized { pub tree: SdfNode, pub changes: Vec<Change>, pub empty: bool }
+
+const MAX_PASSES: usize = 8;
+
+pub fn optimize(tree: &SdfNode) -> Optimized {
+    let mut changes = Vec::new();
+    let mut cur = tree.clone();
+    for _ in 0..MAX_PASSES {
+        let before = changes.len();
+        cur = pass(cur, "root", &mut changes);
+        if changes.len() == before { break; }
+    }
+    let empty = bounds(&cur).is_empty();
+    Optimized { tree: cur, changes, empty }
+}
+
+fn note(ch: &mut Vec<Change>, path: &str, rule: &'static str, detail: String) {
+    ch.push(Change { path: path.into(), rule, detail });
+}
+
+fn is_zero(v: [f32; 3]) -> bool { v.iter().all(|x| *x == 0.0) }
+
+/// Optimize one node, children first. Subtrees whose bounds come out empty are
+/// dropped by the nearest Union or Subtraction above them.
+fn pass(n: SdfNode, path: &str, ch: &mut Vec<Change>) -> SdfNode {
+    let kids: Vec<SdfNode> = n.children.iter().enumerate().map(|(i, c)| pass(c.clone(), &n.child_path(path, i), ch)).collect();
+    let n = SdfNode { children: kids, ..n };
+    let ty = n.ty.clone();
+
+    match ty.as_str() {
+        "Union" => {
+            let mut kids = flatten_and_dedup(&n, path, ch);
+            if kids.iter().any(|k| !bounds(k).is_empty()) {
+                let before = kids.len();
+                kids.retain(|k| !bounds(k).is_empty());
+                for _ in kids.len()..before { note(ch, path, "prune_empty", "removed operand with empty bounds".into()); }
+            }
+            collapse(n, kids, path, ch)
+        }
+        "Intersection" => {
+            let mut kids = flatten_and_dedup(&n, path, ch);
+            // Drop half-spaces that contain the bounds of all the other operands.
+            let mut i = 0;
+            while kids.len() > 1 && i < kids.len() {
+                let rest = kids.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, k)| bounds(k)).fold(Aabb::EVERYTHING, |a, b| a.intersect(&b));
+                if kids[i].ty == "Plane" && rest.is_finite() && !rest.is_empty() && plane_contains(&kids[i], &rest) {
+                    note(ch, path, "prune_dominated", "removed half-space that contains the rest of the intersection".into());
+                    kids.remove(i);
+                } else { i += 1; }
+            }
+            collapse(n, kids, path, ch)
+        }
+        "Subtraction" | "SmoothSubtraction" => {
+            let pad = if ty == "SmoothSubtraction" { n.f("k", 0.1).max(0.0) } else { 0.0 };
+            let a = bounds(&n.children[0]);
+            let mut kids = n.children.clone();
+            let before = kids.len();
+            let head = kids.remove(0);
+            kids.retain(|k| !a.intersect(&bounds(k).expand(pad)).is_empty());
+            for _ in kids.len() + 1..before { note(ch, path, "prune_dominated", "removed subtrahend that does not overlap the minuend".into()); }
+            kids.insert(0, head);
+            if kids.len() == 1 { return kids.pop().unwrap(); }
+            if ty == "SmoothSubtraction" && pad == 0.0 {
+                note(ch, path, "fold_constant", "SmoothSubtraction with k <= 0 is Subtraction".into());
+                return collapse(SdfNode { ty: "Subtraction".into(), params: Default::default(), ..n }, kids, path, ch);
+            }
+            collapse(n, kids, path, ch)
+        }
+        "SmoothUnion" | "SmoothIntersection" => {
+            if n.f("k", 0.1) <= 0.0 {
+                let hard = if ty == "SmoothUnion" { "Union" } else { "Intersection" };
+                note(ch, path, "fold_constant", format!("{ty} with k <= 0 is {hard}"));
+                return SdfNode { ty: hard.into(), params: Default::default(), ..n };
+            }
+            n
+        }
+        "Morph" => {
+            let t = n.f("t", 0.5);
+            if t == 0.0 || t == 1.0 {
+                note(ch, path, "fold_constant", format!("Morph with t = {t} selects one operand"));
+                return n.children[if t == 0.0 { 0 } else { 1 }].clone();
+            }
+            n
+        }
+        "Translate" | "RotateEuler" | "Scale" | "ScaleNonUniform" => fold_transform(n, path, ch),
+        "Twist" | "Bend" | "Noise" | "Repeat" | "RepeatFinite" | "Mirror" => {
+            let identity = match ty.as_str() {
+                "Twist" | "Bend" => n.f("strength", 1.0) == 0.0,
+                "Noise" => n.f("amplitude", 0.1) == 0.0,
+                "Repeat" => n.v3("spacing", [2.0; 3]).iter().all(|s| *s <= 0.0),
+                "RepeatFinite" => n.v3("count", [3.0; 3]).iter().all(|c| c.round() <= 1.0),
+                _ => is_zero(n.v3("axis", [1.0, 0.0, 0.0])),
+            };
+            if identity {
+                note(ch, path, "fold_constant", format!("{ty} with these params is the identity"));
+                return n.children[0].clone();
+            }
+            n
+        }
+        _ => n,
+    }
+}
+
+fn plane_contains(plane: &SdfNode, b: &Aabb) -> bool {
+    let nv = math::normalize(plane.v3("normal", [0.0, 1.0, 0.0]));
+    let h = plane.f("distance", 0.0);
+    (0..8).all(|c| {
+        let p = [if c & 1 == 0 { b.min[0] } else { b.max[0] }, if c & 2 == 0 { b.min[1] } else { b.max[1] }, if c & 4 == 0 { b.min[2] } else { b.max[2] }];
+        math::dot(p, nv) + h <= 0.0
+    })
+}
+
+fn flatten_and_dedup(n: &SdfNode, path: &str, ch: &mut Vec<Change>) -> Vec<SdfNode> {
+    let mut out: Vec<SdfNode> = Vec::new();
+    let mut seen: Vec<Value> = Vec::new();
+    for (i, c) in n.children.iter().enumerate() {
+        let parts = if c.ty == n.ty {
+            note(ch, &n.child_path(path, i), "flatten", format!("merged nested {} into its parent", n.ty));
+            c.children.clone()
+        } else { vec![c.clone()] };
+        for p in parts {
+            let key = p.to_json();
+            if seen.contains(&key) {
+                note(ch, path, "dedup", format!("removed duplicate {} operand", p.ty));
+                continue;
+            }
+            seen.push(key);
+            out.push(p);
+        }
+    }
+    out
+}
+
+fn collapse(n: SdfNode, kids: Vec<SdfNode>, path: &str, ch: &mut Vec<Change>) -> SdfNode {
+    match kids.len() {
+        0 => n,
+        1 => { note(ch, path, "flatten", format!("{} with a single operand replaced by it", n.ty)); kids.into_iter().next().unwrap() }
+        2 if n.slots == Slots::Pair || n.children.len() == 2 => SdfNode { children: kids, ..n },
+        2 => SdfNode { slots: Slots::Pair, children: kids, ..n },
+        _ => SdfNode { slots: Slots::List, children: kids, ..n },
+    }
+}
+
+fn rotation_to_euler(m: &M3) -> [f32; 3] {
+    let sy = (-m[6]).clamp(-1.0, 1.0);
+    let y = sy.asin();
+    if sy.abs() < 0.999_999 { [m[7].atan2(m[8]), y, m[3].atan2(m[0])] } else { [0.0, y, (-m[1]).atan2(m[4])] }
+}
+
+fn fold_transform(n: SdfNode, path: &str, ch: &mut Vec<Change>) -> SdfNode {
+    let c = &n.children[0];
+    match n.ty.as_str() {
+        "Translate" => {
+            let o = n.v3("offset", [0.0; 3]);
+            if is_zero(o) { note(ch, path, "fold_constant", "zero Translate removed".into()); return c.clone(); }
+            if c.ty == "Translate" {
+                let sum = math::add(o, c.v3("offset", [0.0; 3]));
+                note(ch, path, "fuse_transform", "nested Translates fused".into());
+                return SdfNode::unary("Translate", json!({ "offset": sum }), c.children[0].clone());
+            }
+        }
+        "Scale" => {
+            let s = n.f("factor", 1.0);
+            if s == 1.0 { note(ch, path, "fold_constant", "identity Scale removed".into()); return c.clone(); }
+            if c.ty == "Scale" {
+                note(ch, path, "fuse_transform", "nested Scales fused".into());
+                return SdfNode::unary("Scale", json!({ "factor": s * c.f("factor", 1.0) }), c.children[0].clone());
+            }
+        }
+        "ScaleNonUniform" => {
+            let f = n.v3("factors", [1.0; 3]);
+            if f == [1.0; 3] { note(ch, path, "fold_constant", "identity ScaleNonUniform removed".into()); return c.clone(); }
+            if f[0] == f[1] && f[1] == f[2] {
+                note(ch, path, "fold_constant", "uniform ScaleNonUniform rewritten as Scale".into());
+                return SdfNode::unary("Scale", json!({ "factor": f[0] }), c.clone());
+            }
+        }
+        "RotateEuler" => {
+            let a = n.v3("angles", [0.0; 3]);
+            if is_zero(a) { note(ch, path, "fold_constant", "zero RotateEuler removed".into()); return c.clone(); }
+            if c.ty == "RotateEuler" {
+                let m = math::mat_mul(&math::euler_xyz(a), &math::euler_xyz(c.v3("angles", [0.0; 3])));
+                note(ch, path, "fuse_transform", "nested RotateEulers fused".into());
+                return SdfNode::unary("RotateEuler", json!({ "angles": rotation_to_euler(&m) }), c.children[0].clone());
+            }
+        }
+        _ => {}
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler;
+    use crate::noise::Rng;
+
+    fn sphere(r: f32, at: [f32; 3]) -> SdfNode { SdfNode::unary("Translate", json!({ "offset": at }), SdfNode::leaf("Sphere", json!({ "radius": r }))) }
+
+    #[test]
+    fn rewrites_keep_the_zero_set() {
+        let rot = |a: [f32; 3], c| SdfNode::unary("RotateEuler", json!({ "angles": a }), c);
+        let boxed = SdfNode::leaf("Box3d", json!({ "half_size": [0.6, 0.4, 0.5] }));
+        let tree = SdfNode::list("Union", json!({}), vec![
+            SdfNode::binary("Union", json!({}), sphere(1.0, [0.0; 3]), sphere(1.0, [0.0; 3])),
+            SdfNode::unary("Translate", json!({ "offset": [1.0, 0.0, 0.0] }), SdfNode::unary("Translate", json!({ "offset": [0.0, 0.5, 0.0] }), boxed.clone())),
+            rot([0.3, 0.0, 0.0], rot([0.0, 0.4, 0.2], boxed.clone())),
+            SdfNode::unary("Scale", json!({ "factor": 1.0 }), SdfNode::unary("Twist", json!({ "strength": 0.0 }), sphere(0.5, [0.0, 1.5, 0.0]))),
+            SdfNode::binary("Subtraction", json!({}), sphere(0.5, [-2.0, 0.0, 0.0]), sphere(0.2, [5.0, 0.0, 0.0])),
+            SdfNode::binary("Intersection", json!({}), sphere(0.7, [0.0, -1.5, 0.0]), SdfNode::leaf("Plane", json!({ "normal": [0.0, 1.0, 0.0], "distance": -3.0 }))),
+            SdfNode::binary("SmoothUnion", json!({ "k": 0.0 }), sphere(0.3, [0.0, 0.0, 2.0]), sphere(0.3, [0.0, 0.0, -2.0])),
+        ]);
+        let o = optimize(&tree);
+        let rules: Vec<&str> = o.changes.iter().map(|c| c.rule).collect();
+        for r in ["dedup", "flatten", "fuse_transform", "fold_constant", "prune_dominated"] { assert!(rules.contains(&r), "no {r} in {rules:?}"); }
+        assert!(!o.empty);
+        let (a, b) = (compiler::compile(&tree).unwrap(), compiler::compile(&o.tree).unwrap());
+        assert!(b.code.len() < a.code.len());
+        let mut rng = Rng::new(28);
+        for _ in 0..5000 {
+            let p = rng.point_in([-3.0; 3], [3.0; 3]);
+            let (da, db) = (a.eval(p), b.eval(p));
+            if da.abs() > 1e-3 { assert_eq!(da < 0.0, db < 0.0, "{p:?}: {da} before, {db} after"); }
+        }
+    }
+
+    #[test]
+    fn empty_trees_are_reported() {
+        let gone = SdfNode::binary("Intersection", json!({}), sphere(0.5, [-3.0, 0.0, 0.0]), sphere(0.5, [3.0, 0.0, 0.0]));
+        assert!(optimize(&gone).empty);
+        let o = optimize(&SdfNode::binary("Union", json!({}), gone, sphere(1.0, [0.0; 3])));
+        assert_eq!(o.tree.ty, "Sphere");
+    }
+}
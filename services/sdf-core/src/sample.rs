@@ -0,0 +1,223 @@
+//! Seeded sample sets for training on SDFs (`/api/v1/sdf/sample`).
+//!
+//! Three kinds, each drawn from its own stream of the seed so changing one count
+//! leaves the others untouched: uniform points in the domain, surface points
+//! jittered by a Gaussian, and Poisson-disk surface points. Surface points start
+//! on a marching mesh (area weighted) and are projected onto the exact surface
+//! along the gradient. The Poisson set keeps `n` of `5n` such candidates by
+//! weighted sample elimination (Yuksel 2015), which gives exactly `n` points with
+//! blue-noise spacing instead of dart throwing's unpredictable count.
+
+use crate::bounds::Aabb;
+use crate::compiler::CompiledSdf;
+use crate::math::{self, V3};
+use crate::mesh;
+use crate::noise::Rng;
+use rayon::prelude::*;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Candidates drawn per Poisson sample kept.
+const OVERSAMPLE: usize = 5;
+
+pub struct Plan { pub uniform: usize, pub near_surface: usize, pub sigma: f32, pub surface: usize, pub resolution: usize, pub seed: u64 }
+
+/// Samples in plan order (uniform, near-surface, surface); normals are the
+/// normalised field gradient at each point.
+#[derive(Default)]
+pub struct Samples { pub points: Vec<V3>, pub distances: Vec<f32>, pub normals: Vec<V3> }
+
+impl Samples {
+    /// Little-endian f32 records of `x y z distance nx ny nz`.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.points.len() * 28);
+        for ((p, d), n) in self.points.iter().zip(&self.distances).zip(&self.normals) {
+            for x in p.iter().chain([d]).chain(n) { out.extend_from_slice(&x.to_le_bytes()); }
+        }
+        out
+    }
+
+    /// ASCII PLY point cloud with normals and a `distance` property; comments give the counts per kind.
+    pub fn to_ply(&self, p: &Plan) -> String {
+        let mut s = format!("ply\nformat ascii 1.0\ncomment seed {}\ncomment uniform {}\ncomment near_surface {}\ncomment surface {}\n", p.seed, p.uniform, p.near_surface, p.surface);
+        s += &format!("element vertex {}\nproperty float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\nproperty float distance\nend_header\n", self.points.len());
+        for ((v, d), n) in self.points.iter().zip(&self.distances).zip(&self.normals) {
+            s += &format!("{:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6}\n", v[0], v[1], v[2], n[0], n[1], n[2], d);
+        }
+        s
+    }
+}
+
+/// Draw `plan` from `sdf` over `domain`; `None` once `expired` says so.
+pub fn sample(sdf: &CompiledSdf, domain: &Aabb, plan: &Plan, expired: &dyn Fn() -> bool) -> Option<Result<Samples, String>> {
+    let stream = |k: u64| Rng::new(plan.seed ^ k.wrapping_mul(0xD6E8_FEB8_6659_FD93));
+    let mut points: Vec<V3> = {
+        let mut rng = stream(1);
+        (0..plan.uniform).map(|_| rng.point_in(domain.min, domain.max)).collect()
+    };
+    if plan.near_surface + plan.surface > 0 {
+        let surface = match Surface::new(sdf, domain, plan.resolution, expired)? {
+            Some(s) => s,
+            None => return Some(Err("the tree has no surface inside the domain".into())),
+        };
+        let mut rng = stream(2);
+        let base = surface.draw(sdf, plan.near_surface, &mut rng);
+        if base.len() < plan.near_surface { return Some(Err("too few surface points could be projected onto the surface".into())); }
+        points.extend(base.into_iter().map(|p| math::add(p, [rng.gaussian() * plan.sigma, rng.gaussian() * plan.sigma, rng.gaussian() * plan.sigma])));
+        if expired() { return None; }
+        if plan.surface > 0 {
+            let candidates = surface.draw(sdf, plan.surface * OVERSAMPLE, &mut stream(3));
+            if candidates.len() < plan.surface { return Some(Err("too few surface points could be projected onto the surface".into())); }
+            points.extend(eliminate(&candidates, plan.surface, surface.area));
+        }
+    }
+    if expired() { return None; }
+    let e = math::max_c(domain.size()) * 1e-4;
+    let (distances, normals) = points.par_iter().map(|&p| (sdf.eval(p), gradient(sdf, p, e))).unzip();
+    Some(Ok(Samples { points, distances, normals }))
+}
+
+fn gradient(sdf: &CompiledSdf, p: V3, e: f32) -> V3 {
+    let g = |i: usize| { let mut a = p; let mut b = p; a[i] += e; b[i] -= e; sdf.eval(a) - sdf.eval(b) };
+    math::normalize([g(0), g(1), g(2)])
+}
+
+/// Marching mesh of the surface, kept as triangles with a cumulative area table.
+struct Surface { tris: Vec<[V3; 3]>, cdf: Vec<f32>, area: f32, step: f32 }
+
+impl Surface {
+    /// `Some(None)` when the domain holds no surface. Faces the mesher adds where
+    /// the solid meets the domain boundary are dropped: they are not on the surface.
+    fn new(sdf: &CompiledSdf, domain: &Aabb, resolution: usize, expired: &dyn Fn() -> bool) -> Option<Option<Surface>> {
+        let m = mesh::generate(sdf, domain, resolution, &mut |_| !expired())?;
+        let step = math::max_c(domain.size()).max(1e-6) / resolution as f32;
+        let tris: Vec<[V3; 3]> = m.indices.par_iter().map(|t| t.map(|i| m.positions[i as usize]))
+            .filter(|t| sdf.eval(math::mul(math::add(math::add(t[0], t[1]), t[2]), 1.0 / 3.0)).abs() < step * 0.5).collect();
+        let mut area = 0.0;
+        let cdf: Vec<f32> = tris.iter().map(|t| { area += 0.5 * math::len(math::cross(math::sub(t[1], t[0]), math::sub(t[2], t[0]))); area }).collect();
+        Some((area > 0.0).then_some(Surface { tris, cdf, area, step }))
+    }
+
+    /// Up to `n` area-weighted points projected onto the zero set. Points that do
+    /// not converge are dropped and redrawn, a few rounds at most.
+    fn draw(&self, sdf: &CompiledSdf, n: usize, rng: &mut Rng) -> Vec<V3> {
+        let e = self.step * 0.01;
+        let mut out = Vec::with_capacity(n);
+        for _ in 0..4 {
+            let need = n - out.len();
+            if need == 0 { break; }
+            let raw: Vec<V3> = (0..need + need / 8 + 8).map(|_| {
+                let at = rng.f32() * self.area;
+                let t = &self.tris[self.cdf.partition_point(|&c| c < at).min(self.tris.len() - 1)];
+                let (r1, r2) = (rng.f32().sqrt(), rng.f32());
+                math::add(math::add(math::mul(t[0], 1.0 - r1), math::mul(t[1], r1 * (1.0 - r2))), math::mul(t[2], r1 * r2))
+            }).collect();
+            out.par_extend(raw.into_par_iter().filter_map(|mut p| {
+                for _ in 0..4 { p = math::sub(p, math::mul(gradient(sdf, p, e), sdf.eval(p))); }
+                (sdf.eval(p).abs() < self.step * 1e-3).then_some(p)
+            }));
+            out.truncate(n);
+        }
+        out
+    }
+}
+
+/// Weighted sample elimination: repeatedly drop the candidate most crowded by
+/// its neighbours within `2 r_max` until `n` remain, in candidate order.
+fn eliminate(c: &[V3], n: usize, area: f32) -> Vec<V3> {
+    let r_max = (area / (2.0 * 3f32.sqrt() * n as f32)).sqrt();
+    let r_min = r_max * 0.65 * (1.0 - (n as f32 / c.len() as f32).powf(1.5));
+    let reach = 2.0 * r_max;
+    let weight = |a: V3, b: V3| { let d = math::len(math::sub(a, b)); if d < reach { (1.0 - d.max(r_min) / reach).powi(8) } else { 0.0 } };
+    let cell = |p: V3| p.map(|x| (x / reach).floor() as i32);
+    let mut grid: HashMap<[i32; 3], Vec<u32>> = HashMap::new();
+    for (i, &p) in c.iter().enumerate() { grid.entry(cell(p)).or_default().push(i as u32); }
+    let grid = &grid;
+    let neighbours = |i: usize| {
+        let k = cell(c[i]);
+        (0..27).flat_map(move |o| grid.get(&[k[0] + o % 3 - 1, k[1] + o / 3 % 3 - 1, k[2] + o / 9 - 1])).flatten().map(|&j| j as usize).filter(move |&j| j != i)
+    };
+    let mut w: Vec<f32> = (0..c.len()).into_par_iter().map(|i| neighbours(i).map(|j| weight(c[i], c[j])).sum()).collect();
+    // Non-negative f32s order like their bit patterns, which keeps the heap exact and deterministic.
+    let mut heap: BinaryHeap<(u32, usize)> = w.iter().enumerate().map(|(i, x)| (x.to_bits(), i)).collect();
+    let mut alive = vec![true; c.len()];
+    let mut left = c.len();
+    while left > n {
+        let Some((bits, i)) = heap.pop() else { break };
+        if !alive[i] || bits != w[i].to_bits() { continue; }
+        alive[i] = false;
+        left -= 1;
+        for j in neighbours(i) {
+            if !alive[j] { continue; }
+            w[j] = (w[j] - weight(c[i], c[j])).max(0.0);
+            heap.push((w[j].to_bits(), j));
+        }
+    }
+    c.iter().zip(&alive).filter(|(_, &a)| a).map(|(&p, _)| p).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, tree::SdfNode};
+    use serde_json::json;
+
+    fn sphere() -> CompiledSdf { compiler::compile(&SdfNode::leaf("Sphere", json!({"radius": 1.0}))).unwrap() }
+
+    fn plan(uniform: usize, near_surface: usize, surface: usize, seed: u64) -> Plan {
+        Plan { uniform, near_surface, sigma: 0.02, surface, resolution: 32, seed }
+    }
+
+    fn draw(p: &Plan) -> Samples { sample(&sphere(), &Aabb::sym([1.5; 3]), p, &|| false).unwrap().unwrap() }
+
+    #[test]
+    fn same_seed_same_samples() {
+        let p = plan(200, 100, 50, 7);
+        let (a, b) = (draw(&p), draw(&p));
+        assert_eq!(a.to_binary(), b.to_binary());
+        assert_eq!(a.to_ply(&p), b.to_ply(&p));
+        assert_ne!(a.points, draw(&plan(200, 100, 50, 8)).points);
+    }
+
+    #[test]
+    fn counts_are_exact_and_streams_independent() {
+        let p = plan(123, 45, 67, 3);
+        let s = draw(&p);
+        assert_eq!((s.points.len(), s.distances.len(), s.normals.len()), (235, 235, 235));
+        let domain = Aabb::sym([1.5; 3]);
+        assert!(s.points[..123].iter().all(|p| (0..3).all(|i| p[i] >= domain.min[i] && p[i] <= domain.max[i])));
+        // Changing one count leaves the other kinds untouched.
+        let t = draw(&plan(10, 45, 67, 3));
+        assert_eq!(s.points[123..], t.points[10..]);
+        assert_eq!(s.points[..10], t.points[..10]);
+        let bin = s.to_binary();
+        assert_eq!(bin.len(), 235 * 28);
+        assert_eq!(bin[12..16], s.distances[0].to_le_bytes());
+        assert!(s.to_ply(&p).contains("comment uniform 123\ncomment near_surface 45\ncomment surface 67\nelement vertex 235\n"));
+    }
+
+    #[test]
+    fn surface_samples_lie_on_the_surface() {
+        let s = draw(&plan(0, 300, 200, 11));
+        for (i, (&d, n)) in s.distances.iter().zip(&s.normals).enumerate() {
+            assert!((math::len(*n) - 1.0).abs() < 1e-3);
+            // Near-surface jitter has sigma 0.02; surface points are projected to the zero set.
+            let tol = if i < 300 { 0.1 } else { 1e-3 };
+            assert!(d.abs() < tol, "sample {i}: {d}");
+        }
+        // Poisson-disk points keep their distance from each other.
+        let poisson = &s.points[300..];
+        let closest = poisson.iter().enumerate().map(|(i, a)| poisson[i + 1..].iter().fold(f32::MAX, |m, b| m.min(math::len(math::sub(*a, *b))))).fold(f32::MAX, f32::min);
+        let r_max = (4.0 * std::f32::consts::PI / (2.0 * 3f32.sqrt() * 200.0)).sqrt();
+        assert!(closest > 0.3 * r_max, "closest pair {closest}, r_max {r_max}");
+    }
+
+    #[test]
+    fn empty_domains_and_deadlines() {
+        let far = Aabb { min: [5.0; 3], max: [6.0; 3] };
+        let e = sample(&sphere(), &far, &plan(10, 10, 0, 1), &|| false).unwrap().err();
+        assert_eq!(e.as_deref(), Some("the tree has no surface inside the domain"));
+        // Uniform points alone need no surface.
+        assert_eq!(sample(&sphere(), &far, &plan(10, 0, 0, 1), &|| false).unwrap().unwrap().points.len(), 10);
+        assert!(sample(&sphere(), &Aabb::sym([1.5; 3]), &plan(10, 10, 10, 1), &|| true).is_none());
+    }
+}
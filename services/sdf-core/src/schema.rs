@@ -0,0 +1,553 @@
+//! Node vocabulary: arity and parameter schema of every node type.
+//!
+//! This table is what `/api/v1/primitives` publishes and what `/validate` checks
+//! trees against. Defaults match the ones the compiler falls back to.
+
+use crate::math::V3;
+use crate::tree::SdfNode;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Group { Primitive, Operation, Transform, Modifier }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arity { Leaf, Unary, Binary, Nary }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    F32, U32, Vec3,
+    #[serde(rename = "f32[]")] F32Array,
+    /// Array of `{pivot, offset, angles, radius}` objects (SdfSkinning).
+    #[serde(rename = "bone[]")] Bones,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(untagged)]
+pub enum ParamDefault { Scalar(f32), Int(u32), Vec3(V3), List(&'static [f32]) }
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct ParamSchema {
+    pub name: &'static str,
+    #[serde(rename = "type")] pub ty: ParamType,
+    pub unit: &'static str,
+    pub default: ParamDefault,
+    #[serde(skip_serializing_if = "Option::is_none")] pub min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")] pub max: Option<f32>,
+    /// `min` itself is not allowed.
+    #[serde(skip_serializing_if = "is_false")] pub exclusive_min: bool,
+    /// Required element count of an `f32[]` param.
+    #[serde(skip_serializing_if = "Option::is_none")] pub length: Option<usize>,
+    pub description: &'static str,
+}
+
+fn is_false(b: &bool) -> bool { !*b }
+
+impl ParamSchema {
+    const fn new(name: &'static str, ty: ParamType, unit: &'static str, default: ParamDefault, description: &'static str) -> Self {
+        ParamSchema { name, ty, unit, default, min: None, max: None, exclusive_min: false, length: None, description }
+    }
+    const fn at_least(self, v: f32) -> Self { ParamSchema { min: Some(v), ..self } }
+    const fn above(self, v: f32) -> Self { ParamSchema { min: Some(v), exclusive_min: true, ..self } }
+    const fn at_most(self, v: f32) -> Self { ParamSchema { max: Some(v), ..self } }
+    const fn len(self, n: usize) -> Self { ParamSchema { length: Some(n), ..self } }
+}
+
+const fn f(name: &'static str, unit: &'static str, d: f32, desc: &'static str) -> ParamSchema { ParamSchema::new(name, ParamType::F32, unit, ParamDefault::Scalar(d), desc) }
+const fn u(name: &'static str, unit: &'static str, d: u32, desc: &'static str) -> ParamSchema { ParamSchema::new(name, ParamType::U32, unit, ParamDefault::Int(d), desc).at_least(0.0) }
+const fn list(name: &'static str, unit: &'static str, d: &'static [f32], desc: &'static str) -> ParamSchema { ParamSchema::new(name, ParamType::F32Array, unit, ParamDefault::List(d), desc) }
+const fn v(name: &'static str, unit: &'static str, d: V3, desc: &'static str) -> ParamSchema { ParamSchema::new(name, ParamType::Vec3, unit, ParamDefault::Vec3(d), desc) }
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct NodeSchema {
+    pub name: &'static str,
+    #[serde(skip)] pub group: Group,
+    pub category: &'static str,
+    pub arity: Arity,
+    pub description: &'static str,
+    pub params: &'static [ParamSchema],
+}
+
+const fn node(name: &'static str, group: Group, category: &'static str, arity: Arity, description: &'static str, params: &'static [ParamSchema]) -> NodeSchema {
+    NodeSchema { name, group, category, arity, description, params }
+}
+
+use Arity::*;
+use Group::*;
+
+const TPMS: &[ParamSchema] = &[
+    f("scale", "1/length", 1.0, "Spatial frequency of the lattice").above(0.0),
+    f("thickness", "length", 0.1, "Half-thickness of the walls").at_least(0.0),
+];
+const SMOOTH_K: &[ParamSchema] = &[f("k", "length", 0.1, "Blend radius; 0 gives the sharp operation").at_least(0.0)];
+const CHAMFER_R: &[ParamSchema] = &[f("r", "length", 0.1, "Chamfer size").at_least(0.0)];
+const STEPPED: &[ParamSchema] = &[
+    f("r", "length", 0.1, "Blend radius").at_least(0.0),
+    u("steps", "count", 4, "Number of steps or columns in the blend").at_least(1.0),
+];
+const HALF_DEPTH: ParamSchema = f("half_depth", "length", 0.1, "Half of the extrusion depth along Z").at_least(0.0);
+const POLY_RADIUS: &[ParamSchema] = &[f("radius", "length", 1.0, "Center to face distance").at_least(0.0)];
+
+pub static NODES: &[NodeSchema] = &[
+    node("Sphere", Primitive, "basic", Leaf, "Sphere at the origin", &[f("radius", "length", 1.0, "Radius").at_least(0.0)]),
+    node("Box3d", Primitive, "basic", Leaf, "Axis-aligned box", &[v("half_size", "length", [0.5; 3], "Half extent along each axis").at_least(0.0)]),
+    node("Cylinder", Primitive, "basic", Leaf, "Capped cylinder along Y", &[
+        f("radius", "length", 0.5, "Radius").at_least(0.0),
+        f("half_height", "length", 1.0, "Half of the height").at_least(0.0),
+    ]),
+    node("Torus", Primitive, "basic", Leaf, "Torus in the XZ plane", &[
+        f("major_radius", "length", 1.0, "Distance from the center to the tube center").at_least(0.0),
+        f("minor_radius", "length", 0.25, "Tube radius").at_least(0.0),
+    ]),
+    node("Plane", Primitive, "basic", Leaf, "Half-space dot(p, normal) + distance <= 0", &[
+        v("normal", "direction", [0.0, 1.0, 0.0], "Outward normal; normalized before use"),
+        f("distance", "length", 0.0, "Offset along the normal"),
+    ]),
+    node("Capsule", Primitive, "basic", Leaf, "Capsule along Y", &[
+        f("radius", "length", 0.3, "Radius").at_least(0.0),
+        f("half_height", "length", 0.5, "Half length of the core segment").at_least(0.0),
+    ]),
+    node("Cone", Primitive, "basic", Leaf, "Cone along Y, apex up, centered on the origin", &[
+        f("radius", "length", 0.5, "Base radius").at_least(0.0),
+        f("height", "length", 1.0, "Height").at_least(0.0),
+    ]),
+    node("RoundedBox", Primitive, "extended", Leaf, "Box with rounded edges", &[
+        v("half_size", "length", [0.5; 3], "Half extent along each axis, rounding included").at_least(0.0),
+        f("radius", "length", 0.1, "Edge rounding radius").at_least(0.0),
+    ]),
+    node("Ellipsoid", Primitive, "extended", Leaf, "Axis-aligned ellipsoid", &[v("radii", "length", [1.0, 0.5, 0.5], "Semi-axis lengths").above(0.0)]),
+    node("Pyramid", Primitive, "extended", Leaf, "Square pyramid with its base on y = 0", &[
+        f("height", "length", 1.0, "Apex height").at_least(0.0),
+        f("base", "length", 1.0, "Base side length").above(0.0),
+    ]),
+    node("Octahedron", Primitive, "platonic", Leaf, "Regular octahedron", &[f("size", "length", 1.0, "Center to vertex distance").at_least(0.0)]),
+    node("Tetrahedron", Primitive, "platonic", Leaf, "Regular tetrahedron", &[f("size", "length", 1.0, "Size").at_least(0.0)]),
+    node("Gyroid", Primitive, "tpms", Leaf, "Gyroid lattice, unbounded", TPMS),
+    node("SchwarzP", Primitive, "tpms", Leaf, "Schwarz P lattice, unbounded", TPMS),
+    node("RoundedCone", Primitive, "extended", Leaf, "Cone with spherical ends along Y, centered on the origin", &[
+        f("radius_bottom", "length", 0.5, "Radius of the lower sphere").at_least(0.0),
+        f("radius_top", "length", 0.25, "Radius of the upper sphere").at_least(0.0),
+        f("height", "length", 1.0, "Distance between the sphere centers").above(0.0),
+    ]),
+    node("HexPrism", Primitive, "extended", Leaf, "Hexagonal prism along Z", &[
+        f("radius", "length", 0.5, "Center to side distance").at_least(0.0),
+        f("half_depth", "length", 0.5, "Half of the length along Z").at_least(0.0),
+    ]),
+    node("Link", Primitive, "extended", Leaf, "Chain link in the XY plane, elongated along Y", &[
+        f("half_length", "length", 0.5, "Half length of the straight sides").at_least(0.0),
+        f("radius", "length", 0.5, "Radius of the rounded ends").at_least(0.0),
+        f("thickness", "length", 0.1, "Wire radius").at_least(0.0),
+    ]),
+    node("Triangle", Primitive, "extended", Leaf, "Triangle with corners a, b, c, thickened into a slab", &[
+        v("a", "length", [-0.5, 0.0, 0.0], "First corner"),
+        v("b", "length", [0.5, 0.0, 0.0], "Second corner"),
+        v("c", "length", [0.0, 0.8, 0.0], "Third corner"),
+        f("thickness", "length", 0.02, "Half thickness of the slab").at_least(0.0),
+    ]),
+    node("Bezier", Primitive, "extended", Leaf, "Tube along a quadratic Bezier curve", &[
+        v("a", "length", [-1.0, 0.0, 0.0], "Start point"),
+        v("b", "length", [0.0, 1.0, 0.0], "Control point"),
+        v("c", "length", [1.0, 0.0, 0.0], "End point"),
+        f("radius", "length", 0.1, "Tube radius").at_least(0.0),
+    ]),
+    node("CappedCone", Primitive, "extended", Leaf, "Cone frustum along Y, centered on the origin", &[
+        f("half_height", "length", 0.5, "Half of the height").at_least(0.0),
+        f("radius_bottom", "length", 0.5, "Radius at y = -half_height").at_least(0.0),
+        f("radius_top", "length", 0.25, "Radius at y = half_height").at_least(0.0),
+    ]),
+    node("CappedTorus", Primitive, "extended", Leaf, "Torus arc in the XY plane spanning +-angle around +Y", &[
+        f("angle", "radians", 1.0, "Half of the arc angle").at_least(0.0).at_most(std::f32::consts::PI),
+        f("major_radius", "length", 1.0, "Arc radius").at_least(0.0),
+        f("minor_radius", "length", 0.25, "Tube radius").at_least(0.0),
+    ]),
+    node("RoundedCylinder", Primitive, "extended", Leaf, "Cylinder along Y with rounded rims", &[
+        f("radius", "length", 0.5, "Radius, rounding included").at_least(0.0),
+        f("round_radius", "length", 0.1, "Rim rounding radius").at_least(0.0),
+        f("half_height", "length", 1.0, "Half of the height, rounding included").at_least(0.0),
+    ]),
+    node("TriangularPrism", Primitive, "extended", Leaf, "Equilateral triangle in XY extruded along Z", &[
+        f("size", "length", 1.0, "Twice the inradius of the triangle").at_least(0.0),
+        f("half_depth", "length", 0.5, "Half of the length along Z").at_least(0.0),
+    ]),
+    node("CutSphere", Primitive, "extended", Leaf, "Cap of a sphere above the plane y = cut_height", &[
+        f("radius", "length", 1.0, "Sphere radius").at_least(0.0),
+        f("cut_height", "length", 0.5, "Height of the cutting plane; clamped to +-radius"),
+    ]),
+    node("CutHollowSphere", Primitive, "extended", Leaf, "Spherical bowl below the plane y = cut_height", &[
+        f("radius", "length", 1.0, "Sphere radius").at_least(0.0),
+        f("cut_height", "length", 0.5, "Height of the cutting plane; clamped to +-radius"),
+        f("thickness", "length", 0.05, "Half thickness of the wall").at_least(0.0),
+    ]),
+    node("DeathStar", Primitive, "extended", Leaf, "Sphere with a spherical dent on its +X side", &[
+        f("radius", "length", 1.0, "Sphere radius").at_least(0.0),
+        f("cut_radius", "length", 0.7, "Radius of the carving sphere").at_least(0.0),
+        f("cut_distance", "length", 1.2, "Distance of the carving sphere along X").above(0.0),
+    ]),
+    node("SolidAngle", Primitive, "extended", Leaf, "Spherical sector around +Y", &[
+        f("angle", "radians", 0.5, "Half of the opening angle").at_least(0.0).at_most(std::f32::consts::PI),
+        f("radius", "length", 1.0, "Sphere radius").at_least(0.0),
+    ]),
+    node("Rhombus", Primitive, "extended", Leaf, "Rhombic slab in the XZ plane with rounded edges", &[
+        f("half_width", "length", 0.6, "Half diagonal along X").at_least(0.0),
+        f("half_depth", "length", 0.3, "Half diagonal along Z").at_least(0.0),
+        f("half_height", "length", 0.1, "Half thickness along Y").at_least(0.0),
+        f("radius", "length", 0.05, "Edge rounding radius").at_least(0.0),
+    ]),
+    node("Vesica", Primitive, "extended", Leaf, "Lens pointed along Y, revolved around Y", &[
+        f("radius", "length", 1.0, "Radius of the two arcs").at_least(0.0),
+        f("distance", "length", 0.5, "Arc center offset from the axis; must be below radius").at_least(0.0),
+    ]),
+    node("Egg", Primitive, "extended", Leaf, "Egg pointing +Y, revolved around Y", &[
+        f("radius", "length", 0.5, "Radius of the blunt end").at_least(0.0),
+        f("tip_radius", "length", 0.1, "Rounding of the tip").at_least(0.0),
+    ]),
+    node("Tube", Primitive, "extended", Leaf, "Open-ended pipe along Y", &[
+        f("radius", "length", 0.5, "Outer radius").at_least(0.0),
+        f("thickness", "length", 0.1, "Wall thickness").at_least(0.0),
+        f("half_height", "length", 1.0, "Half of the height").at_least(0.0),
+    ]),
+    node("Barrel", Primitive, "extended", Leaf, "Cylinder along Y bulging at mid height", &[
+        f("radius", "length", 0.5, "Radius at the ends").at_least(0.0),
+        f("half_height", "length", 1.0, "Half of the height").above(0.0),
+        f("bulge", "length", 0.1, "Extra radius at mid height"),
+    ]),
+    node("ChamferedCube", Primitive, "extended", Leaf, "Box with 45-degree chamfered edges", &[
+        v("half_size", "length", [0.5; 3], "Half extent along each axis").at_least(0.0),
+        f("chamfer", "length", 0.1, "Chamfer size").at_least(0.0),
+    ]),
+    node("Superellipsoid", Primitive, "extended", Leaf, "Superquadric; exponents below 1 are boxy, above 1 pinched", &[
+        v("radii", "length", [1.0; 3], "Semi-axis lengths").above(0.0),
+        f("exponent_1", "none", 1.0, "North-south exponent").above(0.0),
+        f("exponent_2", "none", 1.0, "East-west exponent").above(0.0),
+    ]),
+    node("Helix", Primitive, "extended", Leaf, "Coil around Y", &[
+        f("radius", "length", 1.0, "Coil radius").at_least(0.0),
+        f("thickness", "length", 0.1, "Wire radius").at_least(0.0),
+        f("pitch", "length", 0.5, "Rise per turn").above(0.0),
+        f("half_height", "length", 1.0, "Half of the height").at_least(0.0),
+    ]),
+    node("BoxFrame", Primitive, "extended", Leaf, "Edges of a box", &[
+        v("half_size", "length", [0.5; 3], "Half extent along each axis").at_least(0.0),
+        f("thickness", "length", 0.05, "Edge thickness").at_least(0.0),
+    ]),
+    node("InfiniteCylinder", Primitive, "extended", Leaf, "Cylinder along Y, unbounded", &[f("radius", "length", 0.5, "Radius").at_least(0.0)]),
+    node("InfiniteCone", Primitive, "extended", Leaf, "Cone with its apex at the origin opening toward -Y, unbounded", &[
+        f("angle", "radians", 0.5, "Half of the opening angle").above(0.0).at_most(std::f32::consts::FRAC_PI_2),
+    ]),
+    node("Dodecahedron", Primitive, "platonic", Leaf, "Regular dodecahedron", POLY_RADIUS),
+    node("Icosahedron", Primitive, "platonic", Leaf, "Regular icosahedron", POLY_RADIUS),
+    node("TruncatedOctahedron", Primitive, "platonic", Leaf, "Truncated octahedron", POLY_RADIUS),
+    node("TruncatedIcosahedron", Primitive, "platonic", Leaf, "Truncated icosahedron (soccer ball)", POLY_RADIUS),
+    node("Diamond", Primitive, "tpms", Leaf, "Schwarz D lattice, unbounded", TPMS),
+    node("DiamondSurface", Primitive, "tpms", Leaf, "Schwarz D lattice, unbounded; same as Diamond", TPMS),
+    node("Neovius", Primitive, "tpms", Leaf, "Neovius lattice, unbounded", TPMS),
+    node("Lidinoid", Primitive, "tpms", Leaf, "Lidinoid lattice, unbounded", TPMS),
+    node("IWP", Primitive, "tpms", Leaf, "Schoen I-WP lattice, unbounded", TPMS),
+    node("FRD", Primitive, "tpms", Leaf, "Schoen F-RD lattice, unbounded", TPMS),
+    node("FischerKochS", Primitive, "tpms", Leaf, "Fischer-Koch S lattice, unbounded", TPMS),
+    node("PMY", Primitive, "tpms", Leaf, "PMY lattice, unbounded", TPMS),
+    node("Horseshoe", Primitive, "extruded", Leaf, "Horseshoe in XY extruded along Z", &[
+        f("angle", "radians", 1.2, "Half of the opening angle of the arc"),
+        f("radius", "length", 0.5, "Arc radius").at_least(0.0),
+        f("length", "length", 0.25, "Length of the straight legs").at_least(0.0),
+        f("thickness", "length", 0.05, "Half thickness of the band").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("Heart", Primitive, "extruded", Leaf, "Heart in XY extruded along Z", &[f("size", "length", 1.0, "Overall size").above(0.0), HALF_DEPTH]),
+    node("RoundedX", Primitive, "extruded", Leaf, "Rounded X in XY extruded along Z", &[
+        f("width", "length", 1.0, "Length of each arm").at_least(0.0),
+        f("radius", "length", 0.1, "Arm radius").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("Pie", Primitive, "extruded", Leaf, "Circular sector around +Y in XY extruded along Z", &[
+        f("angle", "radians", 0.8, "Half of the opening angle").at_least(0.0).at_most(std::f32::consts::PI),
+        f("radius", "length", 1.0, "Radius").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("Trapezoid", Primitive, "extruded", Leaf, "Isosceles trapezoid in XY extruded along Z", &[
+        f("bottom_width", "length", 0.5, "Half width of the bottom edge").at_least(0.0),
+        f("top_width", "length", 0.25, "Half width of the top edge").at_least(0.0),
+        f("half_height", "length", 0.5, "Half of the height").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("Parallelogram", Primitive, "extruded", Leaf, "Parallelogram in XY extruded along Z", &[
+        f("half_width", "length", 0.5, "Half width").at_least(0.0),
+        f("half_height", "length", 0.3, "Half height").at_least(0.0),
+        f("skew", "length", 0.2, "Horizontal shift of the top edge"),
+        HALF_DEPTH,
+    ]),
+    node("Tunnel", Primitive, "extruded", Leaf, "Arch (slot with a round top) in XY extruded along Z", &[
+        f("half_width", "length", 0.5, "Half width, also the arch radius").at_least(0.0),
+        f("height", "length", 1.0, "Height of the straight part below y = 0").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("UnevenCapsule", Primitive, "extruded", Leaf, "2D capsule with different end radii, extruded along Z", &[
+        f("radius_bottom", "length", 0.3, "Radius of the lower end").at_least(0.0),
+        f("radius_top", "length", 0.15, "Radius of the upper end").at_least(0.0),
+        f("height", "length", 1.0, "Distance between the end centers").above(0.0),
+        HALF_DEPTH,
+    ]),
+    node("ArcShape", Primitive, "extruded", Leaf, "Circular arc around +Y in XY extruded along Z", &[
+        f("angle", "radians", 1.0, "Half of the arc angle").at_least(0.0).at_most(std::f32::consts::PI),
+        f("radius", "length", 1.0, "Arc radius").at_least(0.0),
+        f("thickness", "length", 0.1, "Half thickness of the band").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("Moon", Primitive, "extruded", Leaf, "Crescent in XY extruded along Z", &[
+        f("radius", "length", 1.0, "Outer disk radius").at_least(0.0),
+        f("cut_radius", "length", 0.8, "Radius of the removed disk").at_least(0.0),
+        f("cut_distance", "length", 0.5, "Offset of the removed disk along X").above(0.0),
+        HALF_DEPTH,
+    ]),
+    node("CrossShape", Primitive, "extruded", Leaf, "Plus sign in XY extruded along Z", &[
+        f("half_length", "length", 0.5, "Half length of each bar").at_least(0.0),
+        f("half_width", "length", 0.15, "Half width of each bar").at_least(0.0),
+        HALF_DEPTH,
+    ]),
+    node("BlobbyCross", Primitive, "extruded", Leaf, "Cross with concave parabolic sides in XY, extruded along Z", &[
+        f("size", "length", 1.0, "Overall size").above(0.0),
+        f("height", "none", 0.5, "Curvature of the sides").above(0.0),
+        HALF_DEPTH,
+    ]),
+    node("ParabolaSegment", Primitive, "extruded", Leaf, "Region under a parabola above y = 0, extruded along Z", &[
+        f("half_width", "length", 0.5, "Half width at the base").above(0.0),
+        f("height", "length", 1.0, "Apex height").above(0.0),
+        HALF_DEPTH,
+    ]),
+    node("StairsPrim", Primitive, "extruded", Leaf, "Staircase profile climbing +X+Y from the origin, extruded along Z", &[
+        f("step_width", "length", 0.2, "Tread depth").above(0.0),
+        f("step_height", "length", 0.2, "Riser height").above(0.0),
+        u("steps", "count", 5, "Number of steps").at_least(1.0),
+        f("half_depth", "length", 0.5, "Half of the extrusion depth along Z").at_least(0.0),
+    ]),
+    node("Circle2d", Primitive, "2d", Leaf, "Circle in XY, unbounded along Z", &[f("radius", "length", 0.5, "Radius").at_least(0.0)]),
+    node("Rect2d", Primitive, "2d", Leaf, "Rectangle in XY, unbounded along Z", &[
+        f("half_width", "length", 0.5, "Half of the size along X").at_least(0.0),
+        f("half_height", "length", 0.5, "Half of the size along Y").at_least(0.0),
+    ]),
+    node("RoundedRect2d", Primitive, "2d", Leaf, "Rectangle with rounded corners in XY, unbounded along Z", &[
+        f("half_width", "length", 0.5, "Half of the size along X").at_least(0.0),
+        f("half_height", "length", 0.5, "Half of the size along Y").at_least(0.0),
+        f("radius", "length", 0.1, "Corner radius; clamped to the smaller half size").at_least(0.0),
+    ]),
+    node("Segment2d", Primitive, "2d", Leaf, "Thickened line segment in XY, unbounded along Z", &[
+        list("a", "length", &[-0.5, 0.0], "Start point [x, y]").len(2),
+        list("b", "length", &[0.5, 0.0], "End point [x, y]").len(2),
+        f("thickness", "length", 0.05, "Half thickness").at_least(0.0),
+    ]),
+    node("Polygon2d", Primitive, "2d", Leaf, "Closed polygon in XY, unbounded along Z", &[
+        list("points", "length", &[-0.5, -0.5, 0.5, -0.5, 0.0, 0.5], "Flat [x, y, ...] vertex list, at least 3 vertices, either winding"),
+    ]),
+    node("Annular2d", Primitive, "2d", Leaf, "Ring in XY, unbounded along Z", &[
+        f("radius", "length", 0.5, "Radius of the ring's center line").at_least(0.0),
+        f("thickness", "length", 0.05, "Half width of the ring").at_least(0.0),
+    ]),
+    node("RegularPolygon", Primitive, "2d", Leaf, "Regular polygon in XY with a vertex on +Y, unbounded along Z", &[
+        f("radius", "length", 0.5, "Circumradius").at_least(0.0),
+        u("sides", "count", 6, "Number of sides").at_least(3.0),
+    ]),
+    node("StarPolygon", Primitive, "2d", Leaf, "Star in XY with a tip on +Y, unbounded along Z", &[
+        f("radius", "length", 0.5, "Radius of the tips").at_least(0.0),
+        f("inner_radius", "length", 0.25, "Radius of the inner vertices; clamped to radius").at_least(0.0),
+        u("points", "count", 5, "Number of tips").at_least(3.0),
+    ]),
+    node("MeshSdf", Primitive, "imported", Leaf, "Triangle mesh, exact with a winding-number sign or baked into a sparse grid; built by /sdf/from-mesh", &[
+        list("vertices", "length", &[], "Flat [x, y, z, ...] vertex positions (exact mode)"),
+        list("indices", "none", &[], "Flat [a, b, c, ...] zero-based triangle corners, counter-clockwise from outside (exact mode)"),
+        v("grid_origin", "length", [0.0; 3], "Position of the first grid sample (baked mode)"),
+        f("grid_cell", "length", 0.0, "Grid spacing; 0 selects exact mode").at_least(0.0),
+        list("grid_dims", "count", &[0.0, 0.0, 0.0], "Samples along each axis, multiples of 4").len(3).at_most(crate::meshsdf::MAX_GRID_DIM as f32),
+        f("grid_band", "length", 0.0, "Narrow-band half width; samples are clamped to it").at_least(0.0),
+        list("grid_bricks", "none", &[], "Per 4x4x4 brick, x fastest: index into grid_values, -1 far outside or -2 far inside"),
+        list("grid_values", "length", &[], "64 distance samples per stored brick, x fastest"),
+    ]),
+
+    node("Union", Operation, "standard", Nary, "Union of all operands", &[]),
+    node("Intersection", Operation, "standard", Nary, "Intersection of all operands", &[]),
+    node("Subtraction", Operation, "standard", Nary, "First operand minus all the others", &[]),
+    node("SmoothUnion", Operation, "smooth", Nary, "Union with a blended seam", SMOOTH_K),
+    node("SmoothIntersection", Operation, "smooth", Nary, "Intersection with a blended seam", SMOOTH_K),
+    node("SmoothSubtraction", Operation, "smooth", Nary, "Subtraction with a blended seam", SMOOTH_K),
+    node("ChamferUnion", Operation, "chamfer", Nary, "Union with a 45-degree chamfer at the seam", CHAMFER_R),
+    node("ChamferIntersection", Operation, "chamfer", Nary, "Intersection with a 45-degree chamfer at the seam", CHAMFER_R),
+    node("ChamferSubtraction", Operation, "chamfer", Nary, "Subtraction with a 45-degree chamfer at the seam", CHAMFER_R),
+    node("StairsUnion", Operation, "stairs", Nary, "Union with a stepped seam", STEPPED),
+    node("StairsIntersection", Operation, "stairs", Nary, "Intersection with a stepped seam", STEPPED),
+    node("StairsSubtraction", Operation, "stairs", Nary, "Subtraction with a stepped seam", STEPPED),
+    node("ExpSmoothUnion", Operation, "smooth", Nary, "Union blended with an exponential smooth minimum", SMOOTH_K),
+    node("ExpSmoothIntersection", Operation, "smooth", Nary, "Intersection blended with an exponential smooth maximum", SMOOTH_K),
+    node("ExpSmoothSubtraction", Operation, "smooth", Nary, "Subtraction blended with an exponential smooth maximum", SMOOTH_K),
+    node("ColumnsUnion", Operation, "columns", Nary, "Union with a seam of round columns", STEPPED),
+    node("ColumnsIntersection", Operation, "columns", Nary, "Intersection with a seam of round columns", STEPPED),
+    node("ColumnsSubtraction", Operation, "columns", Nary, "Subtraction with a seam of round columns", STEPPED),
+    node("Pipe", Operation, "special", Nary, "Tube along the intersection curve of the operand surfaces", &[f("r", "length", 0.1, "Tube radius").at_least(0.0)]),
+    node("Engrave", Operation, "special", Nary, "V-groove cut into the first operand along the others' surfaces", &[f("r", "length", 0.1, "Groove depth").at_least(0.0)]),
+    node("Groove", Operation, "special", Nary, "Rectangular groove cut into the first operand along the others' surfaces", &[
+        f("depth", "length", 0.1, "Groove depth").at_least(0.0),
+        f("width", "length", 0.05, "Half width of the groove").at_least(0.0),
+    ]),
+    node("Tongue", Operation, "special", Nary, "Rectangular ridge raised on the first operand along the others' surfaces", &[
+        f("height", "length", 0.1, "Ridge height").at_least(0.0),
+        f("width", "length", 0.05, "Half width of the ridge").at_least(0.0),
+    ]),
+    node("Xor", Operation, "special", Nary, "Regions inside exactly one operand", &[]),
+    node("Morph", Operation, "special", Binary, "Linear blend between two distance fields", &[f("t", "none", 0.5, "Blend factor: 0 = a, 1 = b").at_least(0.0).at_most(1.0)]),
+
+    node("Translate", Transform, "spatial", Unary, "Move the child", &[v("offset", "length", [0.0; 3], "Translation")]),
+    node("RotateEuler", Transform, "spatial", Unary, "Rotate the child about X, then Y, then Z", &[v("angles", "radians", [0.0; 3], "Rotation angles")]),
+    node("RotateQuat", Transform, "spatial", Unary, "Rotate the child by a quaternion", &[
+        list("quaternion", "none", &[0.0, 0.0, 0.0, 1.0], "Rotation as [x, y, z, w]; normalized before use").len(4),
+    ]),
+    node("Scale", Transform, "spatial", Unary, "Uniform scale", &[f("factor", "factor", 1.0, "Scale factor").above(0.0)]),
+    node("ScaleNonUniform", Transform, "spatial", Unary, "Per-axis scale; distances are bounds, not exact", &[v("factors", "factor", [1.0; 3], "Scale factor per axis").above(0.0)]),
+
+    node("ProjectiveTransform", Transform, "spatial", Unary, "Apply a 4x4 projective matrix; distances are bounds, not exact", &[
+        list("matrix", "none", &[1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0], "Row-major matrix; must be invertible").len(16),
+        f("lipschitz", "factor", 1.0, "Upper bound on how much the inverse map stretches distances").above(0.0),
+    ]),
+
+    node("Twist", Modifier, "deform", Unary, "Twist about Y", &[f("strength", "radians/length", 1.0, "Rotation per unit of height")]),
+    node("Bend", Modifier, "deform", Unary, "Bend in the XY plane", &[f("strength", "radians/length", 1.0, "Bend angle per unit of length along X")]),
+    node("Taper", Modifier, "deform", Unary, "Scale the XZ cross-section linearly with height", &[f("strength", "1/length", 0.5, "Relative growth of the cross-section per unit of height")]),
+    node("LatticeDeform", Modifier, "deform", Unary, "Free-form deformation by a lattice of control-point offsets", &[
+        v("divisions", "count", [2.0; 3], "Control points per axis").at_least(2.0).at_most(crate::compiler::MAX_LATTICE_DIVISIONS as f32),
+        v("min", "length", [-1.0; 3], "Lower corner of the lattice"),
+        v("max", "length", [1.0; 3], "Upper corner of the lattice"),
+        list("offsets", "length", &[], "Flat [x, y, z, ...] offset per control point, X fastest; empty means no deformation"),
+    ]),
+    node("SdfSkinning", Modifier, "deform", Unary, "Blend rigid bone poses with Gaussian weights; distances are approximate", &[
+        ParamSchema::new("bones", ParamType::Bones, "none", ParamDefault::List(&[]), "Bones as {pivot, offset, angles, radius}: rotate by angles about pivot, then move by offset; radius sets the weight falloff"),
+    ]),
+    node("Extrude", Modifier, "sketch", Unary, "Cap a 2D child along Z", &[f("half_depth", "length", 0.5, "Half of the extrusion depth along Z").at_least(0.0)]),
+    node("Revolution", Modifier, "sketch", Unary, "Revolve a 2D child's XY profile around the Y axis", &[f("offset", "length", 0.0, "Distance of the profile's x = 0 line from the axis")]),
+    node("Sweep", Modifier, "sketch", Unary, "Sweep a 2D child's XY profile along a quadratic Bezier path, capped at both ends", &[
+        v("a", "length", [-1.0, 0.0, 0.0], "Start point"),
+        v("b", "length", [0.0, 0.0, 1.0], "Control point"),
+        v("c", "length", [1.0, 0.0, 0.0], "End point"),
+    ]),
+    node("Repeat", Modifier, "pattern", Unary, "Infinite repetition", &[v("spacing", "length", [2.0; 3], "Cell size per axis; 0 leaves the axis unrepeated").at_least(0.0)]),
+    node("RepeatFinite", Modifier, "pattern", Unary, "Finite repetition centered on the origin", &[
+        v("spacing", "length", [2.0; 3], "Cell size per axis; 0 leaves the axis unrepeated").at_least(0.0),
+        v("count", "count", [3.0; 3], "Number of copies per axis").at_least(1.0),
+    ]),
+    node("Mirror", Modifier, "pattern", Unary, "Mirror across the coordinate planes", &[v("axis", "none", [1.0, 0.0, 0.0], "Non-zero components mirror that axis")]),
+    node("PolarRepeat", Modifier, "pattern", Unary, "Copies arranged around the Y axis", &[
+        u("count", "count", 6, "Number of copies").at_least(1.0),
+        f("radius", "length", 0.0, "Distance of the copies from the axis"),
+    ]),
+    node("MirrorOctant", Modifier, "pattern", Unary, "Mirror across all three coordinate planes", &[]),
+    node("IcosahedralSymmetry", Modifier, "pattern", Unary, "Fold space into one cell of the icosahedral group", &[]),
+    node("IFS", Modifier, "pattern", Unary, "Kaleidoscopic iterated function system (Menger-style fold, scale, shift)", &[
+        u("iterations", "count", 4, "Number of fold steps").at_most(16.0),
+        f("scale", "factor", 3.0, "Scale per step").above(1.0),
+        v("offset", "length", [1.0; 3], "Shift per step"),
+    ]),
+    node("Noise", Modifier, "surface", Unary, "Gradient-noise surface displacement", &[
+        f("amplitude", "length", 0.1, "Displacement amplitude; negative values invert the pattern"),
+        f("frequency", "1/length", 1.0, "Noise frequency").above(0.0),
+        u("seed", "none", 0, "Noise seed"),
+    ]),
+    node("Displacement", Modifier, "surface", Unary, "Sinusoidal surface displacement", &[
+        f("amplitude", "length", 0.05, "Displacement amplitude"),
+        f("frequency", "1/length", 5.0, "Angular frequency").above(0.0),
+    ]),
+    node("SurfaceRoughness", Modifier, "surface", Unary, "Fractal noise surface displacement", &[
+        f("amplitude", "length", 0.02, "Displacement amplitude"),
+        f("frequency", "1/length", 4.0, "Base noise frequency").above(0.0),
+        u("octaves", "count", 4, "Noise layers").at_least(1.0).at_most(8.0),
+        u("seed", "none", 0, "Noise seed"),
+    ]),
+    node("HeightmapDisplacement", Modifier, "surface", Unary, "Raise the surface by a height grid sampled over XZ", &[
+        list("heights", "none", &[], "Row-major grid of columns x rows samples"),
+        u("columns", "count", 2, "Samples along X").at_least(2.0),
+        u("rows", "count", 2, "Samples along Z").at_least(2.0),
+        v("size", "length", [2.0, 0.0, 2.0], "Extent of the grid in X and Z, centered on the origin; y is unused"),
+        f("amplitude", "length", 0.1, "Height scale"),
+    ]),
+    node("Shell", Modifier, "surface", Unary, "Hollow the solid, keeping a wall inside the surface", &[f("thickness", "length", 0.1, "Wall thickness").at_least(0.0)]),
+    node("Onion", Modifier, "surface", Unary, "Layer of constant thickness around the surface", &[f("thickness", "length", 0.1, "Half-thickness of the layer").at_least(0.0)]),
+];
+
+pub fn lookup(name: &str) -> Option<&'static NodeSchema> { NODES.iter().find(|n| n.name == name) }
+
+/// Everything `/validate` reports for a tree's JSON: the parse error, else the
+/// schema problems, else the compile error; empty when the tree is usable.
+pub fn validate(tree: &Value) -> Vec<String> {
+    match SdfNode::from_json(tree) {
+        Err(e) => vec![e],
+        Ok(node) => {
+            let errs = check(&node);
+            if errs.is_empty() { crate::compiler::compile(&node).err().into_iter().collect() } else { errs }
+        }
+    }
+}
+
+/// Check a tree against the schema; returns one message per problem, prefixed by node path.
+pub fn check(tree: &SdfNode) -> Vec<String> {
+    let mut errs = Vec::new();
+    check_node(tree, "root", &mut errs);
+    errs
+}
+
+fn check_node(n: &SdfNode, path: &str, errs: &mut Vec<String>) {
+    for (i, c) in n.children.iter().enumerate() { check_node(c, &n.child_path(path, i), errs); }
+    let Some(s) = lookup(&n.ty) else { errs.push(format!("{path}: unknown node type '{}'", n.ty)); return };
+    let k = n.children.len();
+    let ok = match s.arity { Leaf => k == 0, Unary => k == 1, Binary => k == 2, Nary => k >= 2 };
+    if !ok {
+        let want = match s.arity { Leaf => "no children", Unary => "1 child", Binary => "2 children", Nary => "at least 2 children" };
+        errs.push(format!("{path}: {} expects {want}, got {k}", n.ty));
+    }
+    for (key, val) in &n.params {
+        let Some(p) = s.params.iter().find(|p| p.name == key) else {
+            errs.push(format!("{path}: {} has no param '{key}'", n.ty));
+            continue;
+        };
+        if let Err(e) = check_value(p, val) { errs.push(format!("{path}: param '{key}' {e}")); }
+    }
+}
+
+fn check_value(p: &ParamSchema, val: &Value) -> Result<(), String> {
+    let comps: Vec<f64> = match (p.ty, val) {
+        (ParamType::Bones, Value::Array(a)) => {
+            for (i, b) in a.iter().enumerate() {
+                let o = b.as_object().ok_or_else(|| format!("[{i}] must be an object"))?;
+                for (k, x) in o {
+                    let bp = BONE.iter().find(|p| p.name == k).ok_or_else(|| format!("[{i}] has no field '{k}'"))?;
+                    check_value(bp, x).map_err(|e| format!("[{i}].{k} {e}"))?;
+                }
+            }
+            return Ok(());
+        }
+        (ParamType::Bones, _) => return Err("must be an array of bone objects".into()),
+        (ParamType::F32Array, Value::Array(a)) => {
+            if let Some(n) = p.length { if a.len() != n { return Err(format!("must have {n} elements, got {}", a.len())); } }
+            a.iter().map(|x| x.as_f64()).collect::<Option<_>>().ok_or("must be an array of numbers")?
+        }
+        (ParamType::F32Array, _) => return Err("must be an array of numbers".into()),
+        (ParamType::Vec3, Value::Array(a)) if a.len() == 3 => a.iter().map(|x| x.as_f64()).collect::<Option<_>>().ok_or("must be an array of 3 numbers")?,
+        (ParamType::Vec3, Value::Number(x)) => vec![x.as_f64().unwrap_or(0.0)],
+        (ParamType::Vec3, _) => return Err("must be an array of 3 numbers (or one number for all axes)".into()),
+        (_, Value::Number(x)) => vec![x.as_f64().unwrap_or(0.0)],
+        _ => return Err("must be a number".into()),
+    };
+    for x in comps {
+        if p.ty == ParamType::U32 && (x.fract() != 0.0 || x > u32::MAX as f64) { return Err(format!("must be a non-negative integer, got {x}")); }
+        if let Some(m) = p.min {
+            let m = m as f64;
+            if x < m || (p.exclusive_min && x == m) { return Err(format!("must be {} {m}, got {x}", if p.exclusive_min { ">" } else { ">=" })); }
+        }
+        if let Some(m) = p.max { if x > m as f64 { return Err(format!("must be <= {m}, got {x}")); } }
+    }
+    Ok(())
+}
+
+/// Fields of one SdfSkinning bone.
+pub const BONE: &[ParamSchema] = &[
+    v("pivot", "length", [0.0; 3], "Rotation center"),
+    v("offset", "length", [0.0; 3], "Translation applied after the rotation"),
+    v("angles", "radians", [0.0; 3], "XYZ Euler rotation"),
+    f("radius", "length", 1.0, "Influence radius").above(0.0),
+];
@@ -0,0 +1,1262 @@
+//! CompiledSdf → WGSL / GLSL / HLSL: a `sdf_eval(p)` function per scene.
+//!
+//! The scene is written once, SSA style, in a small GLSL-like dialect and then
+//! translated per target. The dialect keeps to what all three languages agree
+//! on: one declaration per line, no overloads, no ternaries, no mixed
+//! vector/scalar builtin calls, no assignments to parameters. Helpers are only
+//! emitted when the scene uses them; per-node data (lattice offsets, heightmaps,
+//! polygon vertices, mesh triangles and grids) becomes constant arrays.
+
+use crate::compiler::{Bone, CompiledSdf, Heightmap, Inst, Lattice, Op, PointOp, Post, Prim, Tpms};
+use crate::eval::{GDF, ICOSA_FOLD};
+use crate::math::V3;
+use crate::meshsdf::{Grid, MeshSdf, BRICK, FAR_INSIDE, FAR_OUTSIDE};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target { Wgsl, Glsl, Hlsl }
+
+impl Target {
+    pub fn parse(s: &str) -> Option<Target> {
+        match s { "wgsl" => Some(Target::Wgsl), "glsl" => Some(Target::Glsl), "hlsl" => Some(Target::Hlsl), _ => None }
+    }
+}
+
+/// (name, dependencies, source). Dependencies come earlier in the list.
+const HELPERS: &[(&str, &[&str], &str)] = &[
+    ("sd_cap2", &[], "
+float sd_cap2(vec2 d) {
+    return min(max(d.x, d.y), 0.0) + length(max(d, vec2(0.0, 0.0)));
+}"),
+    ("extrude", &["sd_cap2"], "
+float extrude(float d, float z, float h) {
+    return sd_cap2(vec2(d, abs(z) - h));
+}"),
+    ("box_part", &[], "
+float box_part(vec3 q) {
+    return length(max(q, vec3(0.0, 0.0, 0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}"),
+    ("sd_box", &["box_part"], "
+float sd_box(vec3 p, vec3 h) {
+    return box_part(abs(p) - h);
+}"),
+    ("sd_cylinder", &["sd_cap2"], "
+float sd_cylinder(vec3 p, float r, float h) {
+    return sd_cap2(vec2(length(p.xz) - r, abs(p.y) - h));
+}"),
+    ("sd_ellipsoid", &[], "
+float sd_ellipsoid(vec3 p, vec3 r) {
+    float k0 = length(p / r);
+    float k1 = length(p / (r * r));
+    if (k1 == 0.0) { return -min(r.x, min(r.y, r.z)); }
+    return k0 * (k0 - 1.0) / k1;
+}"),
+    ("sd_pyramid", &[], "
+float sd_pyramid(vec3 p0, float height, float base) {
+    float h = height / base;
+    vec3 p = p0 / base;
+    if (p.y < 0.0) { return length(vec3(max(abs(p.x) - 0.5, 0.0), p.y, max(abs(p.z) - 0.5, 0.0))) * base; }
+    float m2 = h * h + 0.25;
+    p.x = abs(p.x);
+    p.z = abs(p.z);
+    if (p.z > p.x) {
+        float tmp = p.x;
+        p.x = p.z;
+        p.z = tmp;
+    }
+    p.x = p.x - 0.5;
+    p.z = p.z - 0.5;
+    vec3 q = vec3(p.z, h * p.y - 0.5 * p.x, h * p.x + 0.5 * p.y);
+    float s = max(-q.x, 0.0);
+    float t = clamp((q.y - 0.5 * p.z) / (m2 + 0.25), 0.0, 1.0);
+    float a = m2 * (q.x + s) * (q.x + s) + q.y * q.y;
+    float b = m2 * (q.x + 0.5 * t) * (q.x + 0.5 * t) + (q.y - m2 * t) * (q.y - m2 * t);
+    float d2 = min(a, b);
+    if (min(q.y, -q.x * m2 - q.y * 0.5) > 0.0) { d2 = 0.0; }
+    float sg = 1.0;
+    if (max(q.z, -p.y) < 0.0) { sg = -1.0; }
+    return max(sqrt((d2 + q.z * q.z) / m2) * sg, -p.y) * base;
+}"),
+    ("sd_octahedron", &[], "
+float sd_octahedron(vec3 p0, float s) {
+    vec3 p = abs(p0);
+    float m = p.x + p.y + p.z - s;
+    vec3 q = p;
+    if (3.0 * p.x < m) { q = p; } else if (3.0 * p.y < m) { q = p.yzx; } else if (3.0 * p.z < m) { q = p.zxy; } else { return m * 0.57735026; }
+    float k = clamp(0.5 * (q.z - q.y + s), 0.0, s);
+    return length(vec3(q.x, q.y - s + k, q.z - k));
+}"),
+    ("sd_hex_prism", &["sd_cap2"], "
+float sd_hex_prism(vec3 p, float r, float hd) {
+    vec3 k = vec3(-0.8660254, 0.5, 0.57735026);
+    vec3 q = abs(p);
+    float t = 2.0 * min(k.x * q.x + k.y * q.y, 0.0);
+    q.x = q.x - t * k.x;
+    q.y = q.y - t * k.y;
+    float dx = length(vec2(q.x - clamp(q.x, -k.z * r, k.z * r), q.y - r)) * sign(q.y - r);
+    return sd_cap2(vec2(dx, q.z - hd));
+}"),
+    ("sd_link", &[], "
+float sd_link(vec3 p, float le, float r1, float r2) {
+    vec3 q = vec3(p.x, max(abs(p.y) - le, 0.0), p.z);
+    return length(vec2(length(q.xy) - r1, q.z)) - r2;
+}"),
+    ("seg_dist2", &[], "
+float seg_dist2(vec3 v, vec3 pv) {
+    float t = clamp(dot(v, pv) / max(dot(v, v), 1e-12), 0.0, 1.0);
+    vec3 d = v * t - pv;
+    return dot(d, d);
+}"),
+    ("sd_triangle", &["seg_dist2"], "
+float sd_triangle(vec3 p, vec3 a, vec3 b, vec3 c, float t) {
+    vec3 ba = b - a;
+    vec3 pa = p - a;
+    vec3 cb = c - b;
+    vec3 pb = p - b;
+    vec3 ac = a - c;
+    vec3 pc = p - c;
+    vec3 nor = cross(ba, ac);
+    float nn = dot(nor, nor);
+    float inside = sign(dot(cross(ba, nor), pa)) + sign(dot(cross(cb, nor), pb)) + sign(dot(cross(ac, nor), pc));
+    float d2 = 0.0;
+    if (inside < 2.0 || nn < 1e-12) {
+        d2 = min(min(seg_dist2(ba, pa), seg_dist2(cb, pb)), seg_dist2(ac, pc));
+    } else {
+        d2 = dot(nor, pa) * dot(nor, pa) / nn;
+    }
+    return sqrt(d2) - t;
+}"),
+    ("cbrt_s", &[], "
+float cbrt_s(float x) {
+    return sign(x) * pow(abs(x), 0.33333334);
+}"),
+    ("bezier_d2", &[], "
+float bezier_d2(vec3 d, vec3 cc, vec3 bb, float t) {
+    vec3 v = d + (cc + bb * t) * t;
+    return dot(v, v);
+}"),
+    ("bezier_t", &["cbrt_s", "bezier_d2"], "
+float bezier_t(vec3 p, vec3 a, vec3 b, vec3 c) {
+    vec3 aa = b - a;
+    vec3 bb = a - b * 2.0 + c;
+    vec3 cc = aa * 2.0;
+    vec3 d = a - p;
+    float bb2 = dot(bb, bb);
+    if (bb2 < 1e-10) { return clamp(dot(c - a, p - a) / max(dot(c - a, c - a), 1e-12), 0.0, 1.0); }
+    float kk = 1.0 / bb2;
+    float kx = kk * dot(aa, bb);
+    float ky = kk * (2.0 * dot(aa, aa) + dot(d, bb)) / 3.0;
+    float kz = kk * dot(d, aa);
+    float pp = ky - kx * kx;
+    float q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    float h = q * q + 4.0 * pp * pp * pp;
+    if (h >= 0.0) {
+        float hs = sqrt(h);
+        return clamp(cbrt_s((hs - q) * 0.5) + cbrt_s((-hs - q) * 0.5) - kx, 0.0, 1.0);
+    }
+    float z = sqrt(-pp);
+    float v = acos(clamp(q / (pp * z * 2.0), -1.0, 1.0)) / 3.0;
+    float m = cos(v);
+    float n = sin(v) * 1.7320508;
+    float t1 = clamp((m + m) * z - kx, 0.0, 1.0);
+    float t2 = clamp((-n - m) * z - kx, 0.0, 1.0);
+    if (bezier_d2(d, cc, bb, t1) <= bezier_d2(d, cc, bb, t2)) { return t1; }
+    return t2;
+}"),
+    ("bezier_pos", &[], "
+vec3 bezier_pos(vec3 a, vec3 b, vec3 c, float t) {
+    return a + ((b - a) * 2.0 + (a - b * 2.0 + c) * t) * t;
+}"),
+    ("sd_bezier", &["bezier_t", "bezier_pos"], "
+float sd_bezier(vec3 p, vec3 a, vec3 b, vec3 c, float r) {
+    return length(bezier_pos(a, b, c, bezier_t(p, a, b, c)) - p) - r;
+}"),
+    ("sd_capped_torus", &[], "
+float sd_capped_torus(vec3 p, float angle, float ra, float rb) {
+    float s = sin(angle);
+    float c = cos(angle);
+    float px = abs(p.x);
+    float k = length(vec2(px, p.y));
+    if (c * px > s * p.y) { k = px * s + p.y * c; }
+    return sqrt(max(dot(p, p) + ra * ra - 2.0 * ra * k, 0.0)) - rb;
+}"),
+    ("sd_tri_prism", &[], "
+float sd_tri_prism(vec3 p, float size, float hd) {
+    vec3 q = abs(p);
+    return max(q.z - hd, max(q.x * 0.8660254 + p.y * 0.5, -p.y) - size * 0.5);
+}"),
+    ("sd_cut_sphere", &[], "
+float sd_cut_sphere(vec3 p, float r, float h) {
+    float w = sqrt(max(r * r - h * h, 0.0));
+    vec2 q = vec2(length(p.xz), p.y);
+    float s = max((h - r) * q.x * q.x + w * w * (h + r - 2.0 * q.y), h * q.x - w * q.y);
+    if (s < 0.0) { return length(q) - r; }
+    if (q.x < w) { return h - q.y; }
+    return length(q - vec2(w, h));
+}"),
+    ("sd_cut_hollow_sphere", &[], "
+float sd_cut_hollow_sphere(vec3 p, float r, float h, float t) {
+    float w = sqrt(max(r * r - h * h, 0.0));
+    vec2 q = vec2(length(p.xz), p.y);
+    if (h * q.x < w * q.y) { return length(q - vec2(w, h)) - t; }
+    return abs(length(q) - r) - t;
+}"),
+    ("sd_rhombus", &["sd_cap2"], "
+float sd_rhombus(vec3 p0, float la, float lb, float h, float ra) {
+    vec3 p = abs(p0);
+    vec2 b = vec2(la, lb);
+    float f = clamp((b.x * (b.x - 2.0 * p.x) - b.y * (b.y - 2.0 * p.z)) / dot(b, b), -1.0, 1.0);
+    float dx = length(vec2(p.x - 0.5 * la * (1.0 - f), p.z - 0.5 * lb * (1.0 + f))) * sign(p.x * lb + p.z * la - la * lb) - ra;
+    return sd_cap2(vec2(dx, p.y - h));
+}"),
+    ("sd_vesica", &[], "
+float sd_vesica(vec3 p, float r, float d) {
+    vec2 q = vec2(length(p.xz), abs(p.y));
+    float b = sqrt(max(r * r - d * d, 0.0));
+    if ((q.y - b) * d > q.x * b) { return length(vec2(q.x, q.y - b)); }
+    return length(vec2(q.x + d, q.y)) - r;
+}"),
+    ("sd_egg", &[], "
+float sd_egg(vec3 p, float ra, float rb) {
+    float k = 1.7320508;
+    vec2 q = vec2(length(p.xz), p.y);
+    float r = ra - rb;
+    if (q.y < 0.0) { return length(q) - r - rb; }
+    if (k * (q.x + r) < q.y) { return length(vec2(q.x, q.y - k * r)) - rb; }
+    return length(vec2(q.x + r, q.y)) - 2.0 * r - rb;
+}"),
+    ("sd_barrel", &["sd_cap2"], "
+float sd_barrel(vec3 p, float r, float h, float bulge) {
+    float y = p.y / h;
+    float g = 2.0 * bulge / h;
+    float k = 1.0 / sqrt(1.0 + g * g);
+    return sd_cap2(vec2(length(p.xz) - (r + bulge * (1.0 - min(y * y, 1.0))), abs(p.y) - h)) * k;
+}"),
+    ("sd_chamfered_cube", &["sd_box"], "
+float sd_chamfered_cube(vec3 p, vec3 h, float c) {
+    vec3 q = abs(p);
+    float d = sd_box(p, h);
+    d = max(d, (q.x + q.y - (h.x + h.y - c)) * 0.70710677);
+    d = max(d, (q.y + q.z - (h.y + h.z - c)) * 0.70710677);
+    return max(d, (q.z + q.x - (h.z + h.x - c)) * 0.70710677);
+}"),
+    ("sd_superellipsoid", &[], "
+float sd_superellipsoid(vec3 p, vec3 r, float e1, float e2) {
+    vec3 q = abs(p / r);
+    float f = pow(pow(q.x, 2.0 / e2) + pow(q.z, 2.0 / e2), e2 / e1) + pow(q.y, 2.0 / e1);
+    return (pow(f, e1 * 0.5) - 1.0) * min(r.x, min(r.y, r.z));
+}"),
+    ("sd_helix", &[], "
+float sd_helix(vec3 p, float big, float t, float pitch, float h) {
+    float a = atan2(p.z, p.x);
+    float y0 = p.y - pitch * a / 6.2831855;
+    float y = y0 - pitch * floor(y0 / pitch + 0.5);
+    float g = pitch / (6.2831855 * max(big, 1e-6));
+    float cosa = 1.0 / sqrt(1.0 + g * g);
+    return max(length(vec2(length(p.xz) - big, y * cosa)) - t, abs(p.y) - h);
+}"),
+    ("sd_box_frame", &["box_part"], "
+float sd_box_frame(vec3 p0, vec3 b, float e) {
+    vec3 p = abs(p0) - b;
+    vec3 q = abs(p + vec3(e, e, e)) - vec3(e, e, e);
+    return min(min(box_part(vec3(p.x, q.y, q.z)), box_part(vec3(q.x, p.y, q.z))), box_part(vec3(q.x, q.y, p.z)));
+}"),
+    ("sd_infinite_cone", &[], "
+float sd_infinite_cone(vec3 p, float angle) {
+    vec2 c = vec2(sin(angle), cos(angle));
+    vec2 q = vec2(length(p.xz), -p.y);
+    float d = length(q - c * max(dot(q, c), 0.0));
+    if (q.x * c.y - q.y * c.x < 0.0) { return -d; }
+    return d;
+}"),
+    ("sd2_horseshoe", &[], "
+float sd2_horseshoe(vec2 p, float angle, float r, float le, float th) {
+    float s = sin(angle);
+    float c = cos(angle);
+    float px = abs(p.x);
+    float l = length(vec2(px, p.y));
+    vec2 q = vec2(-c * px + s * p.y, s * px + c * p.y);
+    vec2 w = q;
+    if (!(q.y > 0.0 || q.x > 0.0)) { w.x = l * sign(-c); }
+    if (!(q.x > 0.0)) { w.y = l; }
+    w = vec2(w.x - le, abs(w.y - r) - th);
+    return length(max(w, vec2(0.0, 0.0))) + min(max(w.x, w.y), 0.0);
+}"),
+    ("sd2_heart", &[], "
+float sd2_heart(vec2 p, float size) {
+    float x = abs(p.x / size);
+    float y = p.y / size + 0.5;
+    if (y + x > 1.0) { return (length(vec2(x - 0.25, y - 0.75)) - 0.35355338) * size; }
+    float m = 0.5 * max(x + y, 0.0);
+    return sqrt(min(x * x + (y - 1.0) * (y - 1.0), (x - m) * (x - m) + (y - m) * (y - m))) * sign(x - y) * size;
+}"),
+    ("sd2_rounded_x", &[], "
+float sd2_rounded_x(vec2 p, float w, float r) {
+    vec2 q = abs(p);
+    float m = min(q.x + q.y, w) * 0.5;
+    return length(q - vec2(m, m)) - r;
+}"),
+    ("sd2_pie", &[], "
+float sd2_pie(vec2 p, float angle, float r) {
+    vec2 c = vec2(sin(angle), cos(angle));
+    vec2 q = vec2(abs(p.x), p.y);
+    float t = clamp(dot(q, c), 0.0, r);
+    return max(length(q) - r, length(q - c * t) * sign(c.y * q.x - c.x * q.y));
+}"),
+    ("sd2_trapezoid", &[], "
+float sd2_trapezoid(vec2 p, float r1, float r2, float he) {
+    vec2 k2 = vec2(r2 - r1, 2.0 * he);
+    float px = abs(p.x);
+    float rr = r2;
+    if (p.y < 0.0) { rr = r1; }
+    vec2 ca = vec2(px - min(px, rr), abs(p.y) - he);
+    float t = clamp(dot(vec2(r2 - px, he - p.y), k2) / dot(k2, k2), 0.0, 1.0);
+    vec2 cb = vec2(px - r2 + k2.x * t, p.y - he + k2.y * t);
+    float s = 1.0;
+    if (cb.x < 0.0 && ca.y < 0.0) { s = -1.0; }
+    return s * sqrt(min(dot(ca, ca), dot(cb, cb)));
+}"),
+    ("sd2_parallelogram", &[], "
+float sd2_parallelogram(vec2 p, float wi, float he, float sk) {
+    vec2 e = vec2(sk, he);
+    vec2 q = p;
+    if (p.y < 0.0) { q = -p; }
+    vec2 w = q - e;
+    w.x = w.x - clamp(w.x, -wi, wi);
+    vec2 d = vec2(dot(w, w), -w.y);
+    float s = q.x * e.y - q.y * e.x;
+    if (s < 0.0) { q = -q; }
+    vec2 v = q - vec2(wi, 0.0);
+    v = v - e * clamp(dot(v, e) / dot(e, e), -1.0, 1.0);
+    d = min(d, vec2(dot(v, v), wi * he - abs(s)));
+    return sqrt(d.x) * sign(-d.y);
+}"),
+    ("sd2_tunnel", &[], "
+float sd2_tunnel(vec2 p, float w, float h) {
+    float px = abs(p.x);
+    float py = -p.y;
+    float qy = py - h;
+    float a = max(px - w, 0.0);
+    float qx = length(vec2(px, py)) - w;
+    if (py > 0.0) { qx = px - w; }
+    float b = max(qy, 0.0);
+    float d = sqrt(min(a * a + qy * qy, qx * qx + b * b));
+    if (max(qx, qy) < 0.0) { return -d; }
+    return d;
+}"),
+    ("sd2_uneven_capsule", &[], "
+float sd2_uneven_capsule(vec2 p, float r1, float r2, float h) {
+    vec2 q = vec2(abs(p.x), p.y + h * 0.5);
+    float b = clamp((r1 - r2) / h, -1.0, 1.0);
+    float a = sqrt(1.0 - b * b);
+    float k = -b * q.x + a * q.y;
+    if (k < 0.0) { return length(q) - r1; }
+    if (k > a * h) { return length(vec2(q.x, q.y - h)) - r2; }
+    return a * q.x + b * q.y - r1;
+}"),
+    ("sd2_arc", &[], "
+float sd2_arc(vec2 p, float angle, float ra, float rb) {
+    vec2 sc = vec2(sin(angle), cos(angle));
+    vec2 q = vec2(abs(p.x), p.y);
+    if (sc.y * q.x > sc.x * q.y) { return length(q - sc * ra) - rb; }
+    return abs(length(q) - ra) - rb;
+}"),
+    ("sd2_moon", &[], "
+float sd2_moon(vec2 p, float ra, float rb, float d) {
+    vec2 q = vec2(p.x, abs(p.y));
+    float a = (ra * ra - rb * rb + d * d) / (2.0 * d);
+    float b = sqrt(max(ra * ra - a * a, 0.0));
+    if (d * (q.x * b - q.y * a) > d * d * max(b - q.y, 0.0)) { return length(q - vec2(a, b)); }
+    return max(length(q) - ra, -(length(q - vec2(d, 0.0)) - rb));
+}"),
+    ("sd2_cross", &[], "
+float sd2_cross(vec2 p, float bx, float by) {
+    vec2 a = abs(p);
+    if (a.y > a.x) { a = a.yx; }
+    vec2 q = a - vec2(bx, by);
+    float k = max(q.x, q.y);
+    vec2 w = q;
+    if (k <= 0.0) { w = vec2(by - a.x, -k); }
+    return sign(k) * length(max(w, vec2(0.0, 0.0)));
+}"),
+    ("sd2_blobby_cross", &["cbrt_s"], "
+float sd2_blobby_cross(vec2 p, float size, float he) {
+    vec2 a = abs(p / size);
+    vec2 q = vec2(abs(a.x - a.y), 1.0 - a.x - a.y) * 0.70710677;
+    float pp = (he - q.y - 0.25 / he) / (6.0 * he);
+    float qq = q.x / (he * he * 16.0);
+    float h = qq * qq - pp * pp * pp;
+    float x = 0.0;
+    if (h > 0.0) {
+        float r = sqrt(h);
+        x = cbrt_s(qq + r) - pow(abs(qq - r), 0.33333334) * sign(r - qq);
+    } else {
+        float r = sqrt(pp);
+        x = 2.0 * r * cos(acos(clamp(qq / (pp * r), -1.0, 1.0)) / 3.0);
+    }
+    x = min(x, 0.70710677);
+    vec2 z = vec2(x - q.x, he * (1.0 - 2.0 * x * x) - q.y);
+    return length(z) * sign(z.y) * size;
+}"),
+    ("sd2_parabola_segment", &["cbrt_s"], "
+float sd2_parabola_segment(vec2 p, float wi, float he) {
+    float px = abs(p.x);
+    float py = p.y;
+    if (py < 0.0) { return length(vec2(max(px - wi, 0.0), py)); }
+    float ik = wi * wi / he;
+    float pp = ik * (he - py - 0.5 * ik) / 3.0;
+    float q = px * ik * ik * 0.25;
+    float h = q * q - pp * pp * pp;
+    float r = sqrt(abs(h));
+    float x = 2.0 * cos(atan2(r, q) / 3.0) * sqrt(max(pp, 0.0));
+    if (h > 0.0) { x = cbrt_s(q + r) - pow(abs(q - r), 0.33333334) * sign(r - q); }
+    x = min(x, wi);
+    return max(length(vec2(px - x, py - (he - x * x / ik))) * sign(ik * (py - he) + px * px), -py);
+}"),
+    ("sd2_stairs", &[], "
+float sd2_stairs(vec2 p, float w, float h, float n) {
+    vec2 ba = vec2(w * n, h * n);
+    vec2 e1 = vec2(p.x - clamp(p.x, 0.0, ba.x), p.y);
+    vec2 e2 = vec2(p.x - ba.x, p.y - clamp(p.y, 0.0, ba.y));
+    float d = min(dot(e1, e1), dot(e2, e2));
+    float s = sign(max(-p.y, p.x - ba.x));
+    float dia = length(vec2(w, h));
+    vec2 q = vec2(w * p.x + h * p.y, -h * p.x + w * p.y) / dia;
+    float id = clamp(floor(q.x / dia + 0.5), 0.0, n - 1.0);
+    float q0 = q.x - id * dia;
+    q = vec2(w * q0 - h * q.y, h * q0 + w * q.y) / dia;
+    float hh = h * 0.5;
+    q.y = q.y - hh;
+    if (q.y > hh * sign(q.x)) { s = 1.0; }
+    if (!(id < 0.5 || q.x > 0.0)) { q = -q; }
+    vec2 e3 = vec2(q.x, q.y - clamp(q.y, -hh, hh));
+    vec2 e4 = vec2(q.x - clamp(q.x, 0.0, w), q.y - hh);
+    d = min(d, min(dot(e3, e3), dot(e4, e4)));
+    return sqrt(d) * s;
+}"),
+    ("sd2_segment", &[], "
+float sd2_segment(vec2 p, vec2 a, vec2 b) {
+    vec2 pa = p - a;
+    vec2 ba = b - a;
+    float h = clamp(dot(pa, ba) / max(dot(ba, ba), 1e-12), 0.0, 1.0);
+    return length(pa - ba * h);
+}"),
+    ("sd2_star", &[], "
+float sd2_star(vec2 p, float r, float ri, float n) {
+    float an = 3.1415927 / n;
+    float a = atan2(p.x, p.y);
+    a = abs(a - 2.0 * an * floor(a / (2.0 * an) + 0.5));
+    float l = length(p);
+    vec2 w = vec2(l * cos(a) - r, l * sin(a));
+    vec2 e = vec2(ri * cos(an) - r, ri * sin(an));
+    float h = clamp(dot(w, e) / max(dot(e, e), 1e-12), 0.0, 1.0);
+    float d = length(w - e * h);
+    if (e.x * w.y - e.y * w.x > 0.0) { return -d; }
+    return d;
+}"),
+    ("op_smin", &[], "
+float op_smin(float a, float b, float k) {
+    if (k <= 0.0) { return min(a, b); }
+    float h = max(k - abs(a - b), 0.0) / k;
+    return min(a, b) - h * h * k * 0.25;
+}"),
+    ("op_exp_smin", &[], "
+float op_exp_smin(float a, float b, float k) {
+    if (k <= 0.0) { return min(a, b); }
+    float m = min(a, b);
+    return m - k * log(exp(-(a - m) / k) + exp(-(b - m) / k));
+}"),
+    ("op_stairs_union", &[], "
+float op_stairs_union(float a, float b, float r, float n) {
+    if (r <= 0.0) { return min(a, b); }
+    float s = r / n;
+    float u = b - r;
+    float m = u - a + s;
+    float md = m - 2.0 * s * floor(m / (2.0 * s));
+    return min(min(a, b), 0.5 * (u + a + abs(md - s)));
+}"),
+    ("mod_centered", &[], "
+float mod_centered(float x, float size) {
+    float h = size * 0.5;
+    return (x + h) - size * floor((x + h) / size) - h;
+}"),
+    ("op_columns_union", &["mod_centered"], "
+float op_columns_union(float a, float b, float r, float n) {
+    if (r <= 0.0 || !(a < r && b < r)) { return min(a, b); }
+    float cr = r * 1.4142135 / ((n - 1.0) * 2.0 + 1.4142135);
+    vec2 p = vec2(a + b, b - a) * 0.70710677;
+    p.x = p.x + cr * 1.4142135 - 0.70710677 * r;
+    if (fract(n * 0.5) > 0.25) { p.y = p.y + cr; }
+    p.y = mod_centered(p.y, cr * 2.0);
+    return min(min(min(length(p) - cr, p.x), a), b);
+}"),
+    ("op_columns_sub", &["mod_centered"], "
+float op_columns_sub(float a0, float b, float r, float n) {
+    float a = -a0;
+    if (r <= 0.0 || !(a < r && b < r)) { return -min(a, b); }
+    float cr = r * 1.4142135 / ((n - 1.0) * 2.0 + 1.4142135);
+    vec2 p = vec2(a + b, b - a) * 0.70710677;
+    p.y = p.y + cr;
+    p.x = p.x - (0.70710677 * r + cr * 0.70710677);
+    if (fract(n * 0.5) > 0.25) { p.y = p.y + cr; }
+    p.y = mod_centered(p.y, cr * 2.0);
+    return -min(min(max(cr - length(p), p.x), a), b);
+}"),
+    ("op_twist", &[], "
+vec3 op_twist(vec3 p, float k) {
+    float s = sin(k * p.y);
+    float c = cos(k * p.y);
+    return vec3(c * p.x - s * p.z, p.y, s * p.x + c * p.z);
+}"),
+    ("op_bend", &[], "
+vec3 op_bend(vec3 p, float k) {
+    float s = sin(k * p.x);
+    float c = cos(k * p.x);
+    return vec3(c * p.x - s * p.y, s * p.x + c * p.y, p.z);
+}"),
+    ("rep_finite", &[], "
+float rep_finite(float x, float s, float n) {
+    float c = (n - 1.0) * 0.5;
+    return x - s * (clamp(floor(x / s + c + 0.5), 0.0, n - 1.0) - c);
+}"),
+    ("op_polar", &[], "
+vec3 op_polar(vec3 p, float n, float r) {
+    float sector = 6.2831855 / n;
+    float a0 = atan2(p.z, p.x);
+    float a = a0 - sector * floor(a0 / sector + 0.5);
+    float l = length(p.xz);
+    return vec3(cos(a) * l - r, p.y, sin(a) * l);
+}"),
+    ("op_projective", &[], "
+vec3 op_projective(vec3 p, vec4 r0, vec4 r1, vec4 r2, vec4 r3) {
+    vec4 h = vec4(p.x, p.y, p.z, 1.0);
+    float w = dot(r3, h);
+    if (abs(w) < 1e-6) {
+        if (w < 0.0) { w = -1e-6; } else { w = 1e-6; }
+    }
+    return vec3(dot(r0, h), dot(r1, h), dot(r2, h)) / w;
+}"),
+    ("op_ifs", &[], "
+vec3 op_ifs(vec3 p0, int n, float s, vec3 o) {
+    vec3 p = p0;
+    for (int i = 0; i < n; i++) {
+        p = abs(p);
+        if (p.x < p.y) { p = p.yxz; }
+        if (p.x < p.z) { p = p.zyx; }
+        if (p.y < p.z) { p = p.xzy; }
+        p = p * s - o * (s - 1.0);
+        if (p.z < -0.5 * o.z * (s - 1.0)) { p.z = p.z + o.z * (s - 1.0); }
+    }
+    return p;
+}"),
+    ("op_taper", &[], "
+vec3 op_taper(vec3 p, float k) {
+    float s = max(1.0 + k * p.y, 0.05);
+    return vec3(p.x / s, p.y, p.z / s);
+}"),
+    ("op_sweep", &["bezier_t", "bezier_pos"], "
+vec3 op_sweep(vec3 p, vec3 a, vec3 b, vec3 c, vec3 up, float plen) {
+    float t = bezier_t(p, a, b, c);
+    vec3 g = b - a + (a - b * 2.0 + c) * t;
+    vec3 tn = vec3(0.0, 1.0, 0.0);
+    if (dot(g, g) > 1e-24) { tn = g / length(g); }
+    vec3 d = p - bezier_pos(a, b, c, t);
+    return vec3(dot(d, cross(tn, up)), dot(d, up), (t - 0.5) * plen + dot(d, tn));
+}"),
+    ("post_taper", &[], "
+float post_taper(float d, vec3 p, float k) {
+    float s = max(1.0 + k * p.y, 0.05);
+    float r = length(p.xz) / s;
+    return d / sqrt(max(1.0 / (s * s), 1.0) + k * k * r * r / (s * s));
+}"),
+    ("sdf_hash", &[], "
+uint sdf_hash(int x, int y, int z, uint seed) {
+    uint h = (seed * 0x9E3779B1u) ^ (uint(x) * 0x85EBCA6Bu) ^ (uint(y) * 0xC2B2AE35u) ^ (uint(z) * 0x27D4EB2Fu);
+    h = h ^ (h >> 15u);
+    h = h * 0x2C1B3C6Du;
+    h = h ^ (h >> 12u);
+    h = h * 0x297A2D39u;
+    return h ^ (h >> 15u);
+}"),
+    ("sdf_grad", &[], "
+float sdf_grad(uint h, vec3 d) {
+    vec3 g = vec3(float(h & 255u), float((h >> 8u) & 255u), float((h >> 16u) & 255u)) / 127.5 - vec3(1.0, 1.0, 1.0);
+    return dot(g, d);
+}"),
+    ("sdf_fade", &[], "
+float sdf_fade(float t) {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}"),
+    ("sdf_noise", &["sdf_hash", "sdf_grad", "sdf_fade"], "
+float sdf_noise(vec3 p, uint seed) {
+    vec3 i = floor(p);
+    vec3 f = p - i;
+    int ix = int(i.x);
+    int iy = int(i.y);
+    int iz = int(i.z);
+    vec3 u = vec3(sdf_fade(f.x), sdf_fade(f.y), sdf_fade(f.z));
+    float c000 = sdf_grad(sdf_hash(ix, iy, iz, seed), f);
+    float c100 = sdf_grad(sdf_hash(ix + 1, iy, iz, seed), f - vec3(1.0, 0.0, 0.0));
+    float c010 = sdf_grad(sdf_hash(ix, iy + 1, iz, seed), f - vec3(0.0, 1.0, 0.0));
+    float c110 = sdf_grad(sdf_hash(ix + 1, iy + 1, iz, seed), f - vec3(1.0, 1.0, 0.0));
+    float c001 = sdf_grad(sdf_hash(ix, iy, iz + 1, seed), f - vec3(0.0, 0.0, 1.0));
+    float c101 = sdf_grad(sdf_hash(ix + 1, iy, iz + 1, seed), f - vec3(1.0, 0.0, 1.0));
+    float c011 = sdf_grad(sdf_hash(ix, iy + 1, iz + 1, seed), f - vec3(0.0, 1.0, 1.0));
+    float c111 = sdf_grad(sdf_hash(ix + 1, iy + 1, iz + 1, seed), f - vec3(1.0, 1.0, 1.0));
+    float x00 = mix(c000, c100, u.x);
+    float x10 = mix(c010, c110, u.x);
+    float x01 = mix(c001, c101, u.x);
+    float x11 = mix(c011, c111, u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}"),
+    ("sdf_fbm", &["sdf_noise"], "
+float sdf_fbm(vec3 p, int octaves, uint seed) {
+    float sum = 0.0;
+    float s = 1.0;
+    for (int i = 0; i < octaves; i++) {
+        sum = sum + sdf_noise(p * s, seed + uint(i)) / s;
+        s = s * 2.0;
+    }
+    return sum;
+}"),
+];
+
+fn lit(x: f32) -> String {
+    let x = if x.is_nan() { 0.0 } else { x.clamp(f32::MIN, f32::MAX) };
+    if x < 0.0 { format!("({x:?})") } else { format!("{x:?}") }
+}
+
+fn v3(v: V3) -> String { format!("vec3({}, {}, {})", lit(v[0]), lit(v[1]), lit(v[2])) }
+
+fn v2(v: [f32; 2]) -> String { format!("vec2({}, {})", lit(v[0]), lit(v[1])) }
+
+fn v4(v: &[f32]) -> String { format!("vec4({}, {}, {}, {})", lit(v[0]), lit(v[1]), lit(v[2]), lit(v[3])) }
+
+#[derive(Default)]
+struct Gen {
+    used: Vec<&'static str>,
+    arrays: Vec<(String, Vec<f32>)>,
+    fns: Vec<(String, String)>,
+    body: Vec<String>,
+    next: usize,
+}
+
+impl Gen {
+    fn need(&mut self, name: &'static str) {
+        if self.used.contains(&name) { return; }
+        let (_, deps, _) = HELPERS.iter().find(|h| h.0 == name).expect("unknown shader helper");
+        for d in deps.iter() { self.need(d); }
+        self.used.push(name);
+    }
+
+    fn call(&mut self, name: &'static str, args: &[String]) -> String {
+        self.need(name);
+        format!("{name}({})", args.join(", "))
+    }
+
+    /// Per-scene function, emitted once per name.
+    fn func(&mut self, name: String, src: impl FnOnce() -> String) -> String {
+        if !self.fns.iter().any(|f| f.0 == name) { let s = src(); self.fns.push((name.clone(), s)); }
+        name
+    }
+
+    fn var(&mut self, ty: &str, prefix: &str, e: String) -> String {
+        let name = format!("{prefix}{}", self.next);
+        self.next += 1;
+        self.body.push(format!("    {ty} {name} = {e};"));
+        name
+    }
+
+    fn prim(&mut self, pr: &Prim, p: &str) -> String {
+        use Prim::*;
+        let l = lit;
+        let xy = format!("{p}.xy");
+        let rev = format!("vec2(length({p}.xz), {p}.y)");
+        let ext = |g: &mut Gen, name: &'static str, args: &[f32], hd: f32| {
+            let mut a = vec![xy.clone()];
+            a.extend(args.iter().map(|x| l(*x)));
+            let d = g.call(name, &a);
+            g.call("extrude", &[d, format!("{p}.z"), l(hd)])
+        };
+        match *pr {
+            Sphere(r) => format!("length({p}) - {}", l(r)),
+            Box3d(h) => self.call("sd_box", &[p.into(), v3(h)]),
+            Cylinder(r, h) => self.call("sd_cylinder", &[p.into(), l(r), l(h)]),
+            Torus(a, b) => format!("length(vec2(length({p}.xz) - {}, {p}.y)) - {}", l(a), l(b)),
+            Plane(n, h) => format!("dot({p}, {}) + {}", v3(n), l(h)),
+            Capsule(r, h) => format!("length(vec3({p}.x, {p}.y - clamp({p}.y, {}, {}), {p}.z)) - {}", l(-h), l(h), l(r)),
+            Cone(r, h) => self.call("sd2_trapezoid", &[rev, l(r), l(0.0), l(h * 0.5)]),
+            RoundedBox(h, r) => format!("{} - {}", self.call("sd_box", &[p.into(), v3(h.map(|x| x - r))]), l(r)),
+            Ellipsoid(r) => self.call("sd_ellipsoid", &[p.into(), v3(r)]),
+            Pyramid(h, b) => self.call("sd_pyramid", &[p.into(), l(h), l(b)]),
+            Octahedron(s) => self.call("sd_octahedron", &[p.into(), l(s)]),
+            Tetrahedron(s) => format!("(max(abs({p}.x + {p}.y) - {p}.z, abs({p}.x - {p}.y) + {p}.z) - {}) * 0.57735026", l(s)),
+            RoundedCone(r1, r2, h) => self.call("sd2_uneven_capsule", &[rev, l(r1), l(r2), l(h)]),
+            HexPrism(r, hd) => self.call("sd_hex_prism", &[p.into(), l(r), l(hd)]),
+            Link(le, r1, r2) => self.call("sd_link", &[p.into(), l(le), l(r1), l(r2)]),
+            Triangle(a, b, c, t) => self.call("sd_triangle", &[p.into(), v3(a), v3(b), v3(c), l(t)]),
+            Bezier(a, b, c, r) => self.call("sd_bezier", &[p.into(), v3(a), v3(b), v3(c), l(r)]),
+            CappedCone(h, r1, r2) => self.call("sd2_trapezoid", &[rev, l(r1), l(r2), l(h)]),
+            CappedTorus(an, ra, rb) => self.call("sd_capped_torus", &[p.into(), l(an), l(ra), l(rb)]),
+            RoundedCylinder(r, rb, h) => format!("{} - {}", self.call("sd_cap2", &[format!("vec2(length({p}.xz) - {}, abs({p}.y) - {})", l(r - rb), l(h - rb))]), l(rb)),
+            TriangularPrism(s, hd) => self.call("sd_tri_prism", &[p.into(), l(s), l(hd)]),
+            CutSphere(r, h) => self.call("sd_cut_sphere", &[p.into(), l(r), l(h)]),
+            CutHollowSphere(r, h, t) => self.call("sd_cut_hollow_sphere", &[p.into(), l(r), l(h), l(t)]),
+            DeathStar(ra, rb, d) => self.call("sd2_moon", &[format!("vec2({p}.x, length({p}.yz))"), l(ra), l(rb), l(d)]),
+            SolidAngle(an, r) => self.call("sd2_pie", &[rev, l(an), l(r)]),
+            Rhombus(la, lb, h, ra) => self.call("sd_rhombus", &[p.into(), l(la), l(lb), l(h), l(ra)]),
+            Vesica(r, d) => self.call("sd_vesica", &[p.into(), l(r), l(d)]),
+            Egg(ra, rb) => self.call("sd_egg", &[p.into(), l(ra), l(rb)]),
+            Tube(r, t, h) => self.call("sd_cap2", &[format!("vec2(abs(length({p}.xz) - {}) - {}, abs({p}.y) - {})", l(r - t * 0.5), l(t * 0.5), l(h))]),
+            Barrel(r, h, b) => self.call("sd_barrel", &[p.into(), l(r), l(h), l(b)]),
+            ChamferedCube(h, c) => self.call("sd_chamfered_cube", &[p.into(), v3(h), l(c)]),
+            Superellipsoid(r, e1, e2) => self.call("sd_superellipsoid", &[p.into(), v3(r), l(e1), l(e2)]),
+            Helix(big, t, pitch, h) => self.call("sd_helix", &[p.into(), l(big), l(t), l(pitch), l(h)]),
+            BoxFrame(h, e) => self.call("sd_box_frame", &[p.into(), v3(h), l(e)]),
+            InfiniteCylinder(r) => format!("length({p}.xz) - {}", l(r)),
+            InfiniteCone(an) => self.call("sd_infinite_cone", &[p.into(), l(an)]),
+            Gdf(b, e, r) => {
+                let name = self.func(format!("sd_gdf_{b}_{e}"), || {
+                    let mut s = format!("float sd_gdf_{b}_{e}(vec3 p, float r) {{\n    float d = abs(dot(p, {}));\n", v3(GDF[b]));
+                    for v in &GDF[b + 1..e] { s += &format!("    d = max(d, abs(dot(p, {})));\n", v3(*v)); }
+                    s + "    return d - r;\n}"
+                });
+                format!("{name}({p}, {})", l(r))
+            }
+            Tpms(kind, s, t) => {
+                let kn = format!("{kind:?}").to_lowercase();
+                let name = self.func(format!("sd_tpms_{kn}"), || tpms_fn(kind, &kn));
+                format!("{name}({p}, {}, {})", l(s), l(t))
+            }
+            Horseshoe(an, r, le, th, hd) => ext(self, "sd2_horseshoe", &[an, r, le, th], hd),
+            Heart(s, hd) => ext(self, "sd2_heart", &[s], hd),
+            RoundedX(w, r, hd) => ext(self, "sd2_rounded_x", &[w, r], hd),
+            Pie(an, r, hd) => ext(self, "sd2_pie", &[an, r], hd),
+            Trapezoid(r1, r2, he, hd) => ext(self, "sd2_trapezoid", &[r1, r2, he], hd),
+            Parallelogram(wi, he, sk, hd) => ext(self, "sd2_parallelogram", &[wi, he, sk], hd),
+            Tunnel(w, h, hd) => ext(self, "sd2_tunnel", &[w, h], hd),
+            UnevenCapsule(r1, r2, h, hd) => ext(self, "sd2_uneven_capsule", &[r1, r2, h], hd),
+            ArcShape(an, ra, rb, hd) => ext(self, "sd2_arc", &[an, ra, rb], hd),
+            Moon(ra, rb, d, hd) => ext(self, "sd2_moon", &[ra, rb, d], hd),
+            CrossShape(bx, by, hd) => ext(self, "sd2_cross", &[bx, by], hd),
+            BlobbyCross(s, he, hd) => ext(self, "sd2_blobby_cross", &[s, he], hd),
+            ParabolaSegment(wi, he, hd) => ext(self, "sd2_parabola_segment", &[wi, he], hd),
+            Stairs(w, h, n, hd) => ext(self, "sd2_stairs", &[w, h, n], hd),
+            Circle2d(r) => format!("length({xy}) - {}", l(r)),
+            Rect2d(w, h, r) => format!("{} - {}", self.call("sd_cap2", &[format!("abs({xy}) - vec2({}, {})", l(w - r), l(h - r))]), l(r)),
+            Segment2d(a, b, t) => format!("{} - {}", self.call("sd2_segment", &[xy, v2(a), v2(b)]), l(t)),
+            Polygon2d(ref v) => {
+                let k = self.fns.len();
+                let name = self.func(format!("polygon_{k}"), || polygon_fn(k, v.len()));
+                self.arrays.push((format!("POLYGON_{k}"), v.iter().flatten().copied().collect()));
+                format!("{name}({xy})")
+            }
+            Annular2d(r, t) => format!("abs(length({xy}) - {}) - {}", l(r), l(t)),
+            Star(r, ri, n) => self.call("sd2_star", &[xy, l(r), l(ri), l(n)]),
+            Mesh(ref m) => {
+                let k = self.fns.len();
+                match **m {
+                    MeshSdf::Exact(ref t) => {
+                        self.need("sd_triangle");
+                        let name = self.func(format!("mesh_{k}"), || mesh_fn(k, t.tris.len()));
+                        self.arrays.push((format!("MESH_{k}"), t.tris.iter().flatten().flatten().copied().collect()));
+                        format!("{name}({p})")
+                    }
+                    MeshSdf::Baked(ref g) => {
+                        let name = self.func(format!("mesh_{k}"), || mesh_grid_fn(k, g));
+                        self.arrays.push((format!("MESH_BRICKS_{k}"), g.bricks.iter().map(|&b| b as f32).collect()));
+                        self.arrays.push((format!("MESH_{k}"), if g.values.is_empty() { vec![0.0] } else { g.values.clone() }));
+                        format!("{name}({p})")
+                    }
+                }
+            }
+        }
+    }
+
+    fn op(&mut self, o: Op, a: &str, b: &str) -> String {
+        use Op::*;
+        let l = lit;
+        let (a, b) = (a.to_string(), b.to_string());
+        let neg = |s: &str| format!("-{s}");
+        match o {
+            Union => format!("min({a}, {b})"),
+            Intersection => format!("max({a}, {b})"),
+            Subtraction => format!("max({a}, -{b})"),
+            SmoothUnion(k) => self.call("op_smin", &[a, b, l(k)]),
+            SmoothIntersection(k) => neg(&self.call("op_smin", &[neg(&a), neg(&b), l(k)])),
+            SmoothSubtraction(k) => neg(&self.call("op_smin", &[neg(&a), b, l(k)])),
+            ChamferUnion(r) => format!("min(min({a}, {b}), ({a} + {b} - {}) * 0.70710677)", l(r)),
+            ChamferIntersection(r) => format!("max(max({a}, {b}), ({a} + {b} + {}) * 0.70710677)", l(r)),
+            ChamferSubtraction(r) => format!("max(max({a}, -{b}), ({a} - {b} + {}) * 0.70710677)", l(r)),
+            StairsUnion(r, n) => self.call("op_stairs_union", &[a, b, l(r), l(n)]),
+            StairsIntersection(r, n) => neg(&self.call("op_stairs_union", &[neg(&a), neg(&b), l(r), l(n)])),
+            StairsSubtraction(r, n) => neg(&self.call("op_stairs_union", &[neg(&a), b, l(r), l(n)])),
+            ExpSmoothUnion(k) => self.call("op_exp_smin", &[a, b, l(k)]),
+            ExpSmoothIntersection(k) => neg(&self.call("op_exp_smin", &[neg(&a), neg(&b), l(k)])),
+            ExpSmoothSubtraction(k) => neg(&self.call("op_exp_smin", &[neg(&a), b, l(k)])),
+            ColumnsUnion(r, n) => self.call("op_columns_union", &[a, b, l(r), l(n)]),
+            ColumnsIntersection(r, n) => self.call("op_columns_sub", &[a, neg(&b), l(r), l(n)]),
+            ColumnsSubtraction(r, n) => self.call("op_columns_sub", &[a, b, l(r), l(n)]),
+            Xor => format!("max(min({a}, {b}), -max({a}, {b}))"),
+            Morph(t) => format!("mix({a}, {b}, {})", l(t)),
+            Pipe(r) => format!("length(vec2({a}, {b})) - {}", l(r)),
+            Engrave(r) => format!("max({a}, ({a} + {} - abs({b})) * 0.70710677)", l(r)),
+            Groove(ra, rb) => format!("max({a}, min({a} + {}, {} - abs({b})))", l(ra), l(rb)),
+            Tongue(ra, rb) => format!("min({a}, max({a} - {}, abs({b}) - {}))", l(ra), l(rb)),
+        }
+    }
+
+    fn point_op(&mut self, o: &PointOp, p: &str) -> String {
+        let l = lit;
+        let axes = ["x", "y", "z"];
+        match *o {
+            PointOp::Translate(o) => format!("{p} - {}", v3(o)),
+            PointOp::Rotate(ref m) => format!("vec3(dot({}, {p}), dot({}, {p}), dot({}, {p}))", v3([m[0], m[1], m[2]]), v3([m[3], m[4], m[5]]), v3([m[6], m[7], m[8]])),
+            PointOp::Scale(s) => format!("{p} * {}", l(1.0 / s)),
+            PointOp::ScaleNonUniform(f) => format!("{p} / {}", v3(f)),
+            PointOp::Twist(k) => self.call("op_twist", &[p.into(), l(k)]),
+            PointOp::Bend(k) => self.call("op_bend", &[p.into(), l(k)]),
+            PointOp::Repeat(s) => {
+                let c: Vec<String> = (0..3).map(|i| {
+                    let x = format!("{p}.{}", axes[i]);
+                    if s[i] > 0.0 { format!("{x} - {} * floor({x} / {} + 0.5)", l(s[i]), l(s[i])) } else { x }
+                }).collect();
+                format!("vec3({})", c.join(", "))
+            }
+            PointOp::RepeatFinite(s, n) => {
+                let c: Vec<String> = (0..3).map(|i| {
+                    let x = format!("{p}.{}", axes[i]);
+                    if s[i] > 0.0 && n[i] >= 1.0 { self.call("rep_finite", &[x, l(s[i]), l(n[i].round())]) } else { x }
+                }).collect();
+                format!("vec3({})", c.join(", "))
+            }
+            PointOp::Mirror(m) => {
+                let c: Vec<String> = (0..3).map(|i| if m[i] { format!("abs({p}.{})", axes[i]) } else { format!("{p}.{}", axes[i]) }).collect();
+                format!("vec3({})", c.join(", "))
+            }
+            PointOp::PolarRepeat(n, r) => self.call("op_polar", &[p.into(), l(n), l(r)]),
+            PointOp::Projective(ref m) => self.call("op_projective", &[p.into(), v4(&m[0..4]), v4(&m[4..8]), v4(&m[8..12]), v4(&m[12..16])]),
+            PointOp::Lattice(ref lat) => {
+                let k = self.fns.len();
+                let name = self.func(format!("lattice_{k}"), || lattice_fn(k, lat));
+                self.arrays.push((format!("LATTICE_{k}"), lat.offsets.iter().flatten().copied().collect()));
+                format!("{name}({p})")
+            }
+            PointOp::Skin(ref bones) => {
+                let k = self.fns.len();
+                let name = self.func(format!("skin_{k}"), || skin_fn(k, bones));
+                format!("{name}({p})")
+            }
+            PointOp::Ifs(n, s, o) => self.call("op_ifs", &[p.into(), n.to_string(), l(s), v3(o)]),
+            PointOp::Icosahedral => {
+                let name = self.func("op_icosahedral".into(), || format!("vec3 op_icosahedral(vec3 p0) {{
+    vec3 n = {};
+    vec3 p = p0;
+    for (int i = 0; i < 5; i++) {{
+        p.x = abs(p.x);
+        p.y = abs(p.y);
+        float t = dot(p, n);
+        if (t < 0.0) {{ p = p - n * (2.0 * t); }}
+    }}
+    return p;
+}}", v3(ICOSA_FOLD)));
+                format!("{name}({p})")
+            }
+            PointOp::Taper(k) => self.call("op_taper", &[p.into(), l(k)]),
+            PointOp::Revolve(o) => format!("vec3(length({p}.xz) - {}, {p}.y, 0.0)", l(o)),
+            PointOp::Sweep(ref s) => self.call("op_sweep", &[p.into(), v3(s.a), v3(s.b), v3(s.c), v3(s.up), l(s.len)]),
+        }
+    }
+
+    fn post(&mut self, o: &Post, d: &str, p: &str) -> String {
+        let l = lit;
+        match *o {
+            Post::MulDist(s) => format!("{d} * {}", l(s)),
+            Post::Noise(a, f, seed) => format!("{d} + {} * {}", l(a), self.call("sdf_noise", &[format!("{p} * {}", l(f)), format!("{seed}u")])),
+            Post::Shell(t) => format!("max({d}, -{d} - {})", l(t)),
+            Post::Onion(t) => format!("abs({d}) - {}", l(t)),
+            Post::Displacement(a, f) => format!("{d} + {} * sin({f} * {p}.x) * sin({f} * {p}.y) * sin({f} * {p}.z)", l(a), f = l(f)),
+            Post::Heightmap(ref h) => {
+                let k = self.fns.len();
+                let name = self.func(format!("heightmap_{k}"), || heightmap_fn(k, h));
+                self.arrays.push((format!("HEIGHTMAP_{k}"), h.data.clone()));
+                format!("{d} - {} * {name}({p})", l(h.amplitude))
+            }
+            Post::Roughness(a, f, oct, seed) => format!("{d} + {} * {}", l(a), self.call("sdf_fbm", &[format!("{p} * {}", l(f)), oct.to_string(), format!("{seed}u")])),
+            Post::Taper(k) => self.call("post_taper", &[d.into(), p.into(), l(k)]),
+            Post::Extrude(h) => self.call("extrude", &[d.into(), format!("{p}.z"), l(h)]),
+        }
+    }
+}
+
+fn tpms_fn(kind: Tpms, kn: &str) -> String {
+    let (f, norm) = match kind {
+        Tpms::Gyroid => ("s.x * c.y + s.y * c.z + s.z * c.x", 1.0),
+        Tpms::SchwarzP => ("c.x + c.y + c.z", 1.0),
+        Tpms::Diamond => ("s.x * s.y * s.z + s.x * c.y * c.z + c.x * s.y * c.z + c.x * c.y * s.z", 1.0),
+        Tpms::Neovius => ("3.0 * (c.x + c.y + c.z) + 4.0 * c.x * c.y * c.z", 2.6),
+        Tpms::Lidinoid => ("0.5 * (s2.x * c.y * s.z + s2.y * c.z * s.x + s2.z * c.x * s.y) - 0.5 * (c2.x * c2.y + c2.y * c2.z + c2.z * c2.x) + 0.15", 1.3),
+        Tpms::Iwp => ("2.0 * (c.x * c.y + c.y * c.z + c.z * c.x) - (c2.x + c2.y + c2.z)", 4.3),
+        Tpms::Frd => ("4.0 * c.x * c.y * c.z - (c2.x * c2.y + c2.y * c2.z + c2.z * c2.x)", 3.6),
+        Tpms::FischerKochS => ("c2.x * s.y * c.z + c.x * c2.y * s.z + s.x * c.y * c2.z", 1.75),
+        Tpms::Pmy => ("2.0 * c.x * c.y * c.z + s2.x * s.y + s.x * s2.z + s2.y * s.z", 1.95),
+    };
+    format!("float sd_tpms_{kn}(vec3 p, float scale, float t) {{
+    vec3 q = p * scale;
+    vec3 s = sin(q);
+    vec3 c = cos(q);
+    vec3 s2 = sin(q * 2.0);
+    vec3 c2 = cos(q * 2.0);
+    return abs({f}) / (scale * {}) - t;
+}}", lit(norm))
+}
+
+fn lattice_fn(k: usize, l: &Lattice) -> String {
+    let [nx, ny, nz] = l.divisions;
+    let size: V3 = std::array::from_fn(|i| l.max[i] - l.min[i]);
+    let mut s = format!("vec3 lattice_{k}_at(int x, int y, int z) {{
+    int i = ((z * {ny} + y) * {nx} + x) * 3;
+    return vec3(LATTICE_{k}[i], LATTICE_{k}[i + 1], LATTICE_{k}[i + 2]);
+}}
+
+vec3 lattice_{k}(vec3 p) {{
+    vec3 t = clamp((p - {}) / {}, vec3(0.0, 0.0, 0.0), vec3(1.0, 1.0, 1.0)) * {};
+    vec3 cell = min(floor(t), {});
+    vec3 f = t - cell;
+    int ix = int(cell.x);
+    int iy = int(cell.y);
+    int iz = int(cell.z);
+    vec3 o = vec3(0.0, 0.0, 0.0);
+", v3(l.min), v3(size), v3([(nx - 1) as f32, (ny - 1) as f32, (nz - 1) as f32]), v3([(nx - 2) as f32, (ny - 2) as f32, (nz - 2) as f32]));
+    for c in 0..8 {
+        let (dx, dy, dz) = (c & 1, (c >> 1) & 1, (c >> 2) & 1);
+        let w = |d: usize, a: &str| if d == 1 { format!("f.{a}") } else { format!("(1.0 - f.{a})") };
+        s += &format!("    o = o + lattice_{k}_at(ix + {dx}, iy + {dy}, iz + {dz}) * ({} * {} * {});\n", w(dx, "x"), w(dy, "y"), w(dz, "z"));
+    }
+    s + "    return p - o;\n}"
+}
+
+fn skin_fn(k: usize, bones: &[Bone]) -> String {
+    let mut s = format!("vec3 skin_{k}(vec3 p) {{\n    vec3 acc = p * 1e-4;\n    float ws = 1e-4;\n");
+    for (i, b) in bones.iter().enumerate() {
+        let c: V3 = std::array::from_fn(|j| b.pivot[j] + b.offset[j]);
+        let m = &b.rot;
+        s += &format!("    vec3 d{i} = p - {};
+    float w{i} = exp(-dot(d{i}, d{i}) / {});
+    acc = acc + (vec3(dot({}, d{i}), dot({}, d{i}), dot({}, d{i})) + {}) * w{i};
+    ws = ws + w{i};
+", v3(c), lit(b.radius * b.radius), v3([m[0], m[1], m[2]]), v3([m[3], m[4], m[5]]), v3([m[6], m[7], m[8]]), v3(b.pivot));
+    }
+    s + "    return acc / ws;\n}"
+}
+
+fn polygon_fn(k: usize, n: usize) -> String {
+    format!("float polygon_{k}(vec2 p) {{
+    vec2 v0 = vec2(POLYGON_{k}[0], POLYGON_{k}[1]);
+    float d = dot(p - v0, p - v0);
+    float s = 1.0;
+    for (int i = 0; i < {n}; i++) {{
+        int j = (i + {m}) % {n};
+        vec2 vi = vec2(POLYGON_{k}[i * 2], POLYGON_{k}[i * 2 + 1]);
+        vec2 vj = vec2(POLYGON_{k}[j * 2], POLYGON_{k}[j * 2 + 1]);
+        vec2 e = vj - vi;
+        vec2 w = p - vi;
+        vec2 b = w - e * clamp(dot(w, e) / max(dot(e, e), 1e-12), 0.0, 1.0);
+        d = min(d, dot(b, b));
+        bool c1 = p.y >= vi.y;
+        bool c2 = p.y < vj.y;
+        bool c3 = e.x * w.y > e.y * w.x;
+        if ((c1 && c2 && c3) || (!c1 && !c2 && !c3)) {{ s = -s; }}
+    }}
+    return s * sqrt(d);
+}}", m = n - 1)
+}
+
+/// Brute force over all triangles: nearest distance, signed by the winding number
+/// (the sum of half solid angles passes pi inside).
+fn mesh_fn(k: usize, n: usize) -> String {
+    format!("float mesh_{k}(vec3 p) {{
+    float d = 1e30;
+    float w = 0.0;
+    for (int i = 0; i < {n}; i++) {{
+        vec3 a = vec3(MESH_{k}[i * 9], MESH_{k}[i * 9 + 1], MESH_{k}[i * 9 + 2]);
+        vec3 b = vec3(MESH_{k}[i * 9 + 3], MESH_{k}[i * 9 + 4], MESH_{k}[i * 9 + 5]);
+        vec3 c = vec3(MESH_{k}[i * 9 + 6], MESH_{k}[i * 9 + 7], MESH_{k}[i * 9 + 8]);
+        d = min(d, sd_triangle(p, a, b, c, 0.0));
+        vec3 pa = a - p;
+        vec3 pb = b - p;
+        vec3 pc = c - p;
+        float la = length(pa);
+        float lb = length(pb);
+        float lc = length(pc);
+        w = w + atan2(dot(pa, cross(pb, pc)), la * lb * lc + dot(pa, pb) * lc + dot(pb, pc) * la + dot(pc, pa) * lb);
+    }}
+    if (abs(w) > 3.14159265) {{ d = -d; }}
+    return d;
+}}")
+}
+
+fn mesh_grid_fn(k: usize, g: &Grid) -> String {
+    let [bx, by, _] = g.dims.map(|d| d / BRICK);
+    let size = g.dims.map(|d| (d - 1) as f32 * g.cell);
+    format!("float mesh_{k}_at(int x, int y, int z) {{
+    int b = int(MESH_BRICKS_{k}[((z / {BRICK}) * {by} + y / {BRICK}) * {bx} + x / {BRICK}]);
+    if (b == {FAR_OUTSIDE}) {{ return {band}; }}
+    if (b == {FAR_INSIDE}) {{ return {nband}; }}
+    return MESH_{k}[b * {cube} + ((z % {BRICK}) * {BRICK} + y % {BRICK}) * {BRICK} + x % {BRICK}];
+}}
+
+float mesh_{k}(vec3 p) {{
+    vec3 q = p - {};
+    vec3 c = clamp(q, vec3(0.0, 0.0, 0.0), {});
+    vec3 t = c / {};
+    vec3 cell = min(floor(t), {});
+    vec3 f = t - cell;
+    int x = int(cell.x);
+    int y = int(cell.y);
+    int z = int(cell.z);
+    float x00 = mix(mesh_{k}_at(x, y, z), mesh_{k}_at(x + 1, y, z), f.x);
+    float x10 = mix(mesh_{k}_at(x, y + 1, z), mesh_{k}_at(x + 1, y + 1, z), f.x);
+    float x01 = mix(mesh_{k}_at(x, y, z + 1), mesh_{k}_at(x + 1, y, z + 1), f.x);
+    float x11 = mix(mesh_{k}_at(x, y + 1, z + 1), mesh_{k}_at(x + 1, y + 1, z + 1), f.x);
+    return mix(mix(x00, x10, f.y), mix(x01, x11, f.y), f.z) + length(q - c);
+}}", v3(g.origin), v3(size), lit(g.cell), v3(g.dims.map(|d| (d - 2) as f32)), band = lit(g.band), nband = lit(-g.band), cube = BRICK.pow(3))
+}
+
+fn heightmap_fn(k: usize, h: &Heightmap) -> String {
+    let (c, r) = (h.cols, h.rows);
+    format!("float heightmap_{k}(vec3 p) {{
+    float u = min(clamp(p.x / {} + 0.5, 0.0, 1.0) * {cm}, {cm} - 1e-4);
+    float v = min(clamp(p.z / {} + 0.5, 0.0, 1.0) * {rm}, {rm} - 1e-4);
+    float fi = floor(u);
+    float fj = floor(v);
+    int i = int(fi);
+    int j = int(fj);
+    float a = mix(HEIGHTMAP_{k}[j * {c} + i], HEIGHTMAP_{k}[j * {c} + i + 1], u - fi);
+    float b = mix(HEIGHTMAP_{k}[(j + 1) * {c} + i], HEIGHTMAP_{k}[(j + 1) * {c} + i + 1], u - fi);
+    return mix(a, b, v - fj);
+}}", lit(h.size[0]), lit(h.size[1]), cm = lit((c - 1) as f32), rm = lit((r - 1) as f32))
+}
+
+// ── dialect → target ────────────────────────────────────────────────────────
+
+const TYPES: [&str; 7] = ["float", "vec2", "vec3", "vec4", "int", "uint", "bool"];
+
+fn map_ident(t: Target, w: &str) -> &str {
+    match (t, w) {
+        (Target::Wgsl, "float") => "f32",
+        (Target::Wgsl, "int") => "i32",
+        (Target::Wgsl, "uint") => "u32",
+        (Target::Wgsl, "vec2") => "vec2<f32>",
+        (Target::Wgsl, "vec3") => "vec3<f32>",
+        (Target::Wgsl, "vec4") => "vec4<f32>",
+        (Target::Glsl, "atan2") => "atan",
+        (Target::Hlsl, "vec2") => "float2",
+        (Target::Hlsl, "vec3") => "float3",
+        (Target::Hlsl, "vec4") => "float4",
+        (Target::Hlsl, "mix") => "lerp",
+        (Target::Hlsl, "fract") => "frac",
+        _ => w,
+    }
+}
+
+/// Rename identifiers outside of numeric literals.
+fn map_idents(t: Target, line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + 16);
+    let mut it = line.char_indices().peekable();
+    while let Some((i, ch)) = it.next() {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            let mut j = i + ch.len_utf8();
+            while let Some(&(k, c)) = it.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' { j = k + c.len_utf8(); it.next(); } else { break; }
+            }
+            let w = &line[i..j];
+            out.push_str(if ch.is_ascii_digit() { w } else { map_ident(t, w) });
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// `T name = e;` → `var name: T = e;` and `T f(T a, ...) {` → `fn f(a: T, ...) -> T {`.
+fn wgsl_line(line: &str) -> String {
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    if let Some(rest) = body.strip_prefix("for (int ") {
+        return format!("{indent}for (var {}", rest.replacen(" = ", ": int = ", 1));
+    }
+    let Some((ty, rest)) = body.split_once(' ') else { return line.into() };
+    if !TYPES.contains(&ty) { return line.into(); }
+    if let Some((name, init)) = rest.split_once(" = ") {
+        if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') { return format!("{indent}var {name}: {ty} = {init}"); }
+    }
+    if indent.is_empty() {
+        if let Some((name, params)) = rest.strip_suffix(") {").and_then(|r| r.split_once('(')) {
+            let params: Vec<String> = params.split(", ").filter(|s| !s.is_empty()).map(|a| {
+                let (t, n) = a.split_once(' ').unwrap_or((a, ""));
+                format!("{n}: {t}")
+            }).collect();
+            return format!("fn {name}({}) -> {ty} {{", params.join(", "));
+        }
+    }
+    line.into()
+}
+
+fn translate(t: Target, src: &str) -> String {
+    src.lines().map(|l| map_idents(t, &if t == Target::Wgsl { wgsl_line(l) } else { l.to_string() })).collect::<Vec<_>>().join("\n")
+}
+
+fn array(t: Target, name: &str, v: &[f32]) -> String {
+    let items = v.iter().map(|x| lit(*x)).collect::<Vec<_>>().join(", ");
+    match t {
+        Target::Wgsl => format!("var<private> {name}: array<f32, {n}> = array<f32, {n}>({items});", n = v.len()),
+        Target::Glsl => format!("const float {name}[{n}] = float[{n}]({items});", n = v.len()),
+        Target::Hlsl => format!("static const float {name}[{n}] = {{ {items} }};", n = v.len()),
+    }
+}
+
+/// Full shader source for `sdf`: helpers, data, `sdf_eval(p)` and, for WGSL, a
+/// debug fragment entry point shading by distance. Fields that can overstate
+/// distances return them times `sdf.step_scale`, so sphere tracing stays safe.
+pub fn transpile(sdf: &CompiledSdf, target: Target) -> String { source(sdf, target, sdf.step_scale) }
+
+fn source(sdf: &CompiledSdf, target: Target, step_scale: f32) -> String {
+    let mut g = Gen::default();
+    let (mut ps, mut vs) = (vec!["p".to_string()], Vec::<String>::new());
+    for i in &sdf.code {
+        let p = ps.last().unwrap().clone();
+        match i {
+            Inst::Prim(pr) => { let e = g.prim(pr, &p); vs.push(g.var("float", "d", e)); }
+            Inst::Op(o) => { let b = vs.pop().unwrap(); let a = vs.pop().unwrap(); let e = g.op(*o, &a, &b); vs.push(g.var("float", "d", e)); }
+            Inst::Push(o) => { let e = g.point_op(o, &p); ps.push(g.var("vec3", "p", e)); }
+            Inst::PopPoint => { ps.pop(); }
+            Inst::Post(o) => { let d = vs.pop().unwrap(); let e = g.post(o, &d, &p); vs.push(g.var("float", "d", e)); }
+        }
+    }
+    let result = vs.pop().unwrap_or_else(|| lit(f32::MAX));
+
+    let mut out = vec![match target {
+        Target::Wgsl => "// WGSL - generated by sdf-engine".to_string(),
+        Target::Glsl => "#version 450\n// GLSL - generated by sdf-engine".to_string(),
+        Target::Hlsl => "// HLSL - generated by sdf-engine".to_string(),
+    }];
+    for (_, _, src) in HELPERS.iter().filter(|h| g.used.contains(&h.0)) { out.push(translate(target, src.trim_start())); }
+    for (name, data) in &g.arrays { out.push(array(target, name, data)); }
+    for (_, src) in &g.fns { out.push(translate(target, src)); }
+    let mut scene = String::from("float sdf_eval(vec3 p) {\n");
+    for l in &g.body { scene += l; scene.push('\n'); }
+    scene += &if step_scale < 1.0 { format!("    return {result} * {};\n}}", lit(step_scale)) } else { format!("    return {result};\n}}") };
+    out.push(translate(target, &scene));
+    if target == Target::Wgsl {
+        out.push("@fragment fn fs(@location(0) wp: vec3<f32>) -> @location(0) vec4<f32> { let d = sdf_eval(wp); return vec4<f32>(vec3<f32>(0.5 - d * 0.5), 1.0); }".into());
+    }
+    out.join("\n\n") + "\n"
+}
+
+// ── animation ───────────────────────────────────────────────────────────────
+
+/// Sampled track values a shader may carry (tracks × samples).
+const MAX_TRACK_VALUES: usize = 65536;
+
+/// Shader for a tree sampled at times `t0 + i·dt`. Literals that differ between
+/// the samples become `sdf_track(k, sdf_time)`, linear between samples, where
+/// `sdf_time` is a uniform the host sets each frame. Every sample takes the
+/// smallest step scale, so the scaling never changes the shader's structure.
+pub fn transpile_animated(samples: &[CompiledSdf], t0: f32, dt: f32, target: Target) -> Result<String, String> {
+    let step_scale = samples.iter().fold(1.0f32, |m, s| m.min(s.step_scale));
+    let srcs: Vec<String> = samples.iter().map(|s| source(s, target, step_scale)).collect();
+    let toks: Vec<Vec<&str>> = srcs.iter().map(|s| tokens(s)).collect();
+    let base = &toks[0];
+    if toks.iter().any(|t| t.len() != base.len()) { return Err("the tree changes structure over time; only numeric parameters can be animated in shaders".into()); }
+    let (mut out, mut tracks, mut slots) = (String::with_capacity(srcs[0].len()), Vec::new(), 0);
+    for (i, &tok) in base.iter().enumerate() {
+        if toks.iter().all(|t| t[i] == tok) { out.push_str(tok); continue; }
+        let values = toks.iter().map(|t| literal(t[i])).collect::<Option<Vec<f32>>>()
+            .ok_or("the tree changes structure over time; only numeric parameters can be animated in shaders")?;
+        let line = &out[out.rfind('\n').map_or(0, |k| k + 1)..];
+        if ["var<private> ", "const float ", "static const float "].iter().any(|p| line.starts_with(p)) {
+            return Err("values stored in data arrays (polygon points, heightmaps, lattices, meshes) can't be animated in shaders".into());
+        }
+        out += &format!("sdf_track({slots}, sdf_time)");
+        tracks.extend(values);
+        slots += 1;
+    }
+    if slots == 0 { return Ok(out); }
+    if tracks.len() > MAX_TRACK_VALUES { return Err(format!("{slots} animated values x {} samples exceeds {MAX_TRACK_VALUES}; lower fps or shorten time_range", samples.len())); }
+    let n = samples.len();
+    let uniform = match target {
+        Target::Wgsl => "@group(0) @binding(0) var<uniform> sdf_time: f32;",
+        Target::Glsl => "layout(std140, binding = 0) uniform SdfTime { float sdf_time; };",
+        Target::Hlsl => "cbuffer SdfTime : register(b0) { float sdf_time; };",
+    };
+    let track = format!("float sdf_track(int k, float t) {{
+    float u = clamp((t - {}) / {}, 0.0, {nm1});
+    float fi = min(floor(u), {nm2});
+    int i = k * {n} + int(fi);
+    return mix(SDF_TRACKS[i], SDF_TRACKS[i + 1], u - fi);
+}}", lit(t0), lit(if dt > 0.0 { dt } else { 1.0 }), nm1 = lit((n - 1) as f32), nm2 = lit((n - 2) as f32));
+    let at = out.find("\n\n").map_or(out.len(), |k| k + 2);
+    out.insert_str(at, &format!("{uniform}\n\n{}\n\n{}\n\n", array(target, "SDF_TRACKS", &tracks), translate(target, &track)));
+    Ok(out)
+}
+
+/// Split shader source into identifiers, numbers (a negative literal keeps its
+/// parentheses) and single characters; concatenating the pieces gives `src` back.
+fn tokens(src: &str) -> Vec<&str> {
+    let b = src.as_bytes();
+    let (mut out, mut i) = (Vec::new(), 0);
+    let number = |mut j: usize| {
+        while j < b.len() && (b[j].is_ascii_alphanumeric() || b[j] == b'.' || (matches!(b[j], b'+' | b'-') && matches!(b[j - 1], b'e' | b'E'))) { j += 1; }
+        j
+    };
+    while i < b.len() {
+        let start = i;
+        let c = b[i];
+        if c.is_ascii_digit() {
+            i = number(i);
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'_') { i += 1; }
+        } else if c == b'(' && b.get(i + 1) == Some(&b'-') && b.get(i + 2).is_some_and(|d| d.is_ascii_digit()) && b.get(number(i + 2)) == Some(&b')') {
+            i = number(i + 2) + 1;
+        } else {
+            i += src[i..].chars().next().map_or(1, |c| c.len_utf8());
+        }
+        out.push(&src[start..i]);
+    }
+    out
+}
+
+/// Value of a float literal as `lit` writes it; integers and other tokens are structure.
+fn literal(tok: &str) -> Option<f32> {
+    let t = tok.strip_prefix('(').and_then(|t| t.strip_suffix(')')).unwrap_or(tok);
+    let digits = t.strip_prefix('-').unwrap_or(t);
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) || digits.starts_with("0x") || !digits.contains(['.', 'e']) { return None; }
+    t.parse().ok()
+}
+
+// ── live preview ────────────────────────────────────────────────────────────
+
+/// Shader whose scene literals read from an `sdf_params` storage buffer, and the
+/// buffer's contents. Trees that differ only in numeric parameters give the same
+/// source, so an editor can rewrite the buffer instead of rebuilding its
+/// pipeline. Helper constants and data arrays stay baked in.
+pub fn transpile_parametric(sdf: &CompiledSdf, target: Target) -> (String, Vec<f32>) {
+    let src = transpile(sdf, target);
+    let start = src.find(if target == Target::Wgsl { "fn sdf_eval(" } else { "float sdf_eval(" }).unwrap_or(src.len());
+    let end = src[start..].find("\n}").map_or(src.len(), |k| start + k);
+    let (mut out, mut params) = (String::with_capacity(src.len() + 1024), Vec::new());
+    for tok in tokens(&src[start..end]) {
+        match literal(tok) {
+            Some(v) => { out += &format!("sdf_params[{}]", params.len()); params.push(v); }
+            None => out.push_str(tok),
+        }
+    }
+    if params.is_empty() { return (src, params); }
+    let buffer = match target {
+        Target::Wgsl => "@group(0) @binding(1) var<storage, read> sdf_params: array<f32>;",
+        Target::Glsl => "layout(std430, binding = 1) readonly buffer SdfParams { float sdf_params[]; };",
+        Target::Hlsl => "StructuredBuffer<float> sdf_params : register(t0);",
+    };
+    let at = src.find("\n\n").map_or(0, |k| k + 2);
+    (format!("{}{buffer}\n\n{}{out}{}", &src[..at], &src[at..start], &src[end..]), params)
+}
@@ -0,0 +1,441 @@
+//! Constraint solver over tree parameters (`/api/v1/sdf/solve`).
+//!
+//! A free parameter is one scalar of the tree JSON: a param, one component of a
+//! vec3/list param, or a root variable. Constraints measure the nodes they name
+//! in world space, i.e. wrapped in the transforms and modifiers of their unary
+//! ancestors. Levenberg–Marquardt drives the residuals to zero using central
+//! difference Jacobians; every evaluation writes the parameters into the JSON,
+//! resolves it and compiles the measured subtrees.
+//!
+//! The gap between two surfaces is `min_p dA(p) + dB(p)`: exact for convex shapes
+//! with exact distances, and negative (the overlap depth) when they intersect.
+//! Volumes count smooth occupancy on a lattice anchored at the origin, so the
+//! estimate moves continuously with the parameters.
+
+use crate::bounds::{self, Aabb};
+use crate::compiler::{self, CompiledSdf};
+use crate::diff::{parse_path, Step};
+use crate::expr::Vars;
+use crate::math::{self, V3};
+use crate::schema::{self, ParamDefault, ParamType};
+use crate::tree::SdfNode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// A parameter to solve for: `path` + `param` (+ `component` for vec3 and list
+/// params), or a root `variable`. `min`/`max` narrow the schema range.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Free {
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub param: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub component: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub variable: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] pub max: Option<f64>,
+}
+
+fn one() -> f64 { 1.0 }
+fn root() -> String { "root".into() }
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Constraint {
+    /// Gap between the surfaces of nodes `a` and `b`; negative when they overlap.
+    Distance { a: String, b: String, target: f64, #[serde(default = "one")] weight: f64 },
+    /// Surfaces of `a` and `b` touching: a gap of 0.
+    Tangent { a: String, b: String, #[serde(default = "one")] weight: f64 },
+    /// Enclosed volume of the node at `path`.
+    Volume { #[serde(default = "root")] path: String, target: f64, #[serde(default = "one")] weight: f64 },
+    /// `point` lies on the surface of the node at `path`.
+    OnSurface { #[serde(default = "root")] path: String, point: V3, #[serde(default = "one")] weight: f64 },
+}
+
+impl Constraint {
+    fn kind(&self) -> &'static str {
+        match self { Constraint::Distance { .. } => "distance", Constraint::Tangent { .. } => "tangent", Constraint::Volume { .. } => "volume", Constraint::OnSurface { .. } => "on_surface" }
+    }
+    fn target(&self) -> f64 { match self { Constraint::Distance { target, .. } | Constraint::Volume { target, .. } => *target, _ => 0.0 } }
+    fn weight(&self) -> f64 { match self { Constraint::Distance { weight, .. } | Constraint::Tangent { weight, .. } | Constraint::Volume { weight, .. } | Constraint::OnSurface { weight, .. } => *weight } }
+    /// Residual in length units: volumes compare cube roots.
+    fn residual(&self, value: f64) -> f64 {
+        match self { Constraint::Volume { target, .. } => value.cbrt() - target.cbrt(), _ => value - self.target() }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Solved { #[serde(flatten)] pub free: Free, pub value: f64, pub initial: f64 }
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Measured { #[serde(rename = "type")] pub kind: &'static str, pub value: f64, pub target: f64, pub residual: f64 }
+
+pub struct Solution { pub tree: Value, pub parameters: Vec<Solved>, pub constraints: Vec<Measured>, pub iterations: usize, pub converged: bool }
+
+/// One free scalar: a JSON pointer into the tree and its allowed range.
+struct Slot { ptr: String, lo: f64, hi: f64, x0: f64 }
+
+pub struct Problem { base: Value, free: Vec<Free>, slots: Vec<Slot>, constraints: Vec<Constraint>, cells: Vec<f32> }
+
+fn pointer(steps: &[Step]) -> String {
+    steps.iter().map(|s| match s { Step::Slot(k) => format!("/{k}"), Step::Index(i) => format!("/{i}") }).collect()
+}
+
+/// The node at `path` wrapped in its unary ancestors, outermost last.
+fn world(tree: &Value, path: &str) -> Result<Value, String> {
+    let steps = parse_path(path)?;
+    let mut node = tree.pointer(&pointer(&steps)).cloned().ok_or_else(|| format!("{path}: no node at this path"))?;
+    for i in (0..steps.len()).rev() {
+        if steps[i] != Step::Slot("child".into()) { continue; }
+        let mut up = tree.pointer(&pointer(&steps[..i])).cloned().unwrap_or_default();
+        up["child"] = node;
+        node = up;
+    }
+    Ok(node)
+}
+
+fn compile_at(tree: &Value, vars: &Vars, path: &str) -> Result<(SdfNode, CompiledSdf), String> {
+    let node = SdfNode::from_json_with(&world(tree, path)?, vars).map_err(|e| format!("{path}: {e}"))?;
+    let sdf = compiler::compile(&node)?;
+    Ok((node, sdf))
+}
+
+impl Problem {
+    pub fn new(tree: &Value, free: Vec<Free>, constraints: Vec<Constraint>) -> Result<Problem, String> {
+        if free.is_empty() || free.len() > 32 { return Err("free must list 1 to 32 parameters".into()); }
+        if constraints.is_empty() || constraints.len() > 64 { return Err("constraints must list 1 to 64 constraints".into()); }
+        let mut base = tree.clone();
+        let vars = Vars::declare(tree.get("variables"), &Default::default(), 0.0)?;
+        let mut slots = Vec::new();
+        for (i, f) in free.iter().enumerate() {
+            let mut s = match (&f.variable, &f.path, &f.param) {
+                (Some(v), None, None) => {
+                    let x0 = *vars.values.get(v).ok_or_else(|| format!("free[{i}]: the tree declares no variable '{v}'"))?;
+                    Slot { ptr: format!("/variables/{v}"), lo: f64::NEG_INFINITY, hi: f64::INFINITY, x0 }
+                }
+                (None, Some(path), Some(param)) => param_slot(&mut base, path, param, f.component).map_err(|e| format!("free[{i}]: {e}"))?,
+                _ => return Err(format!("free[{i}]: give either 'variable', or 'path' and 'param'")),
+            };
+            s.lo = s.lo.max(f.min.unwrap_or(f64::NEG_INFINITY));
+            s.hi = s.hi.min(f.max.unwrap_or(f64::INFINITY));
+            if s.lo > s.hi { return Err(format!("free[{i}]: empty range [{}, {}]", s.lo, s.hi)); }
+            if slots.iter().any(|o: &Slot| o.ptr == s.ptr) { return Err(format!("free[{i}]: listed twice")); }
+            s.x0 = s.x0.clamp(s.lo, s.hi);
+            slots.push(s);
+        }
+        let mut cells = Vec::new();
+        for (i, c) in constraints.iter().enumerate() {
+            if c.weight() <= 0.0 { return Err(format!("constraints[{i}]: weight must be > 0")); }
+            let paths = match c {
+                Constraint::Distance { a, b, .. } | Constraint::Tangent { a, b, .. } => vec![a, b],
+                Constraint::Volume { path, target, .. } => {
+                    if *target <= 0.0 { return Err(format!("constraints[{i}]: volume target must be > 0")); }
+                    vec![path]
+                }
+                Constraint::OnSurface { path, .. } => vec![path],
+            };
+            for p in paths {
+                let (node, _) = compile_at(&base, &vars, p).map_err(|e| format!("constraints[{i}]: {e}"))?;
+                if let Constraint::Volume { .. } = c {
+                    let b = bounds::bounds(&node);
+                    if !b.is_finite() || b.is_empty() { return Err(format!("constraints[{i}]: volume needs a bounded, non-empty node")); }
+                    cells.push(math::max_c(b.size()) / 48.0);
+                }
+            }
+        }
+        Ok(Problem { base, free, slots, constraints, cells })
+    }
+
+    fn tree_at(&self, x: &[f64]) -> Value {
+        let mut t = self.base.clone();
+        for (s, &v) in self.slots.iter().zip(x) { if let Some(dst) = t.pointer_mut(&s.ptr) { *dst = json!(v); } }
+        t
+    }
+
+    /// Raw measurement of every constraint at parameters `x`.
+    fn measure(&self, x: &[f64]) -> Result<Vec<f64>, String> {
+        let t = self.tree_at(x);
+        let vars = Vars::declare(t.get("variables"), &Default::default(), 0.0)?;
+        let mut cells = self.cells.iter();
+        self.constraints.iter().map(|c| Ok(match c {
+            Constraint::Distance { a, b, .. } | Constraint::Tangent { a, b, .. } => {
+                let ((na, sa), (nb, sb)) = (compile_at(&t, &vars, a)?, compile_at(&t, &vars, b)?);
+                let domain = bounds::bounds(&na).union(&bounds::bounds(&nb)).clamp_to(5.0);
+                if domain.is_empty() { return Err(format!("{a}, {b}: empty node")); }
+                gap(&sa, &sb, &domain) as f64
+            }
+            Constraint::Volume { path, .. } => {
+                let (n, s) = compile_at(&t, &vars, path)?;
+                volume(&s, &bounds::bounds(&n), *cells.next().unwrap_or(&0.1))? as f64
+            }
+            Constraint::OnSurface { path, point, .. } => compile_at(&t, &vars, path)?.1.eval(*point) as f64,
+        })).collect()
+    }
+
+    fn residuals(&self, x: &[f64]) -> Result<Vec<f64>, String> {
+        Ok(self.measure(x)?.iter().zip(&self.constraints).map(|(&v, c)| c.residual(v) * c.weight()).collect())
+    }
+
+    /// Levenberg–Marquardt from the initial values; stops early once `expired` says so.
+    pub fn solve(&self, max_iterations: usize, tolerance: f64, expired: &dyn Fn() -> bool) -> Result<Solution, String> {
+        let x0: Vec<f64> = self.slots.iter().map(|s| s.x0).collect();
+        let range: Vec<(f64, f64)> = self.slots.iter().map(|s| (s.lo, s.hi)).collect();
+        let done = |r: &[f64]| r.iter().zip(&self.constraints).all(|(v, c)| (v / c.weight()).abs() <= tolerance);
+        let lm = levenberg_marquardt(x0, &range, &|x| self.residuals(x), &done, max_iterations, expired)?;
+        let x = lm.x;
+        let values = self.measure(&x)?;
+        Ok(Solution {
+            tree: self.tree_at(&x),
+            parameters: self.free.iter().zip(&self.slots).zip(&x).map(|((f, s), &v)| Solved { free: f.clone(), value: v, initial: s.x0 }).collect(),
+            constraints: self.constraints.iter().zip(values).map(|(c, v)| Measured { kind: c.kind(), value: v, target: c.target(), residual: v - c.target() }).collect(),
+            iterations: lm.iterations, converged: lm.converged,
+        })
+    }
+}
+
+/// Residuals of the unknowns, or why they cannot be evaluated.
+pub type Residuals<'a> = dyn Fn(&[f64]) -> Result<Vec<f64>, String> + 'a;
+
+pub struct Lm { pub x: Vec<f64>, pub r: Vec<f64>, pub iterations: usize, pub converged: bool }
+
+/// Levenberg–Marquardt on `residuals` with every unknown kept in its `range`,
+/// until `done` accepts the residuals, no step lowers the cost, `max_iterations`
+/// pass or `expired` says so.
+pub fn levenberg_marquardt(
+    mut x: Vec<f64>, range: &[(f64, f64)], residuals: &Residuals<'_>,
+    done: &dyn Fn(&[f64]) -> bool, max_iterations: usize, expired: &dyn Fn() -> bool,
+) -> Result<Lm, String> {
+    let n = x.len();
+    let mut r = residuals(&x)?;
+    let cost = |r: &[f64]| r.iter().map(|v| v * v).sum::<f64>();
+    let (mut lambda, mut iterations, mut converged) = (1e-3, 0, done(&r));
+    while !converged && iterations < max_iterations && !expired() {
+        iterations += 1;
+        let jac = jacobian(&x, range, r.len(), residuals)?;
+        let m = r.len();
+        let a: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| (0..m).map(|k| jac[k][i] * jac[k][j]).sum()).collect()).collect();
+        let g: Vec<f64> = (0..n).map(|i| -(0..m).map(|k| jac[k][i] * r[k]).sum::<f64>()).collect();
+        let mut accepted = false;
+        for _ in 0..12 {
+            let damped: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { a[i][j] * (1.0 + lambda) + 1e-12 } else { a[i][j] }).collect()).collect();
+            let Some(d) = solve_linear(damped, g.clone()) else { lambda *= 4.0; continue };
+            let y: Vec<f64> = x.iter().zip(&d).zip(range).map(|((v, d), &(lo, hi))| (v + d).clamp(lo, hi)).collect();
+            if let Ok(ry) = residuals(&y) {
+                if cost(&ry) < cost(&r) {
+                    let moved = x.iter().zip(&y).any(|(a, b)| (a - b).abs() > 1e-12 * a.abs().max(1.0));
+                    (x, r, lambda, accepted) = (y, ry, (lambda / 3.0).max(1e-9), moved);
+                    break;
+                }
+            }
+            lambda *= 4.0;
+        }
+        converged = done(&r);
+        if !accepted { break; }
+    }
+    Ok(Lm { x, r, iterations, converged })
+}
+
+/// Central differences, one-sided at the edge of a parameter's range.
+fn jacobian(x: &[f64], range: &[(f64, f64)], m: usize, residuals: &Residuals<'_>) -> Result<Vec<Vec<f64>>, String> {
+    let mut cols = Vec::with_capacity(x.len());
+    for (j, &(lo, hi)) in range.iter().enumerate() {
+        let e = 1e-3 * x[j].abs().max(1.0);
+        let (lo, hi) = ((x[j] - e).max(lo), (x[j] + e).min(hi));
+        if hi <= lo { cols.push(vec![0.0; m]); continue; }
+        let at = |v: f64| { let mut y = x.to_vec(); y[j] = v; residuals(&y) };
+        let (rl, rh) = (at(lo)?, at(hi)?);
+        cols.push(rl.iter().zip(&rh).map(|(a, b)| (b - a) / (hi - lo)).collect());
+    }
+    Ok((0..m).map(|k| cols.iter().map(|c| c[k]).collect()).collect())
+}
+
+/// Slot for `param` of the node at `path`. Vec3 and list params are written
+/// out as full arrays first so a single component can be replaced.
+fn param_slot(tree: &mut Value, path: &str, param: &str, component: Option<usize>) -> Result<Slot, String> {
+    let ptr = pointer(&parse_path(path)?);
+    let node = tree.pointer_mut(&ptr).ok_or_else(|| format!("{path}: no node at this path"))?;
+    let ty = node.get("type").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+    let s = schema::lookup(&ty).ok_or_else(|| format!("{path}: unknown node type '{ty}'"))?;
+    let p = s.params.iter().find(|p| p.name == param).ok_or_else(|| format!("{path}: {ty} has no param '{param}'"))?;
+    if !node.get("params").is_some_and(|v| v.is_object()) { node["params"] = json!({}); }
+    let cur = node["params"].get(param).cloned();
+    if let Some(Value::String(e)) = &cur { return Err(format!("{path}: param '{param}' is the expression '{e}'; free the variable it uses instead")); }
+    let num = |v: Option<&Value>, d: f32| v.and_then(|v| v.as_f64()).unwrap_or(d as f64);
+    let (value, x0) = match (p.ty, p.default, component) {
+        (ParamType::F32, ParamDefault::Scalar(d), None) => { let x = num(cur.as_ref(), d); (json!(x), x) }
+        (ParamType::Vec3, ParamDefault::Vec3(d), Some(c)) if c < 3 => {
+            let v: Vec<f64> = match &cur { Some(Value::Array(a)) => (0..3).map(|i| num(a.get(i), d[i])).collect(), Some(v) => vec![num(Some(v), 0.0); 3], None => d.iter().map(|&x| x as f64).collect() };
+            (json!(v), v[c])
+        }
+        (ParamType::F32Array, ParamDefault::List(d), Some(c)) => {
+            let v: Vec<f64> = match &cur { Some(Value::Array(a)) => a.iter().map(|x| num(Some(x), 0.0)).collect(), _ => d.iter().map(|&x| x as f64).collect() };
+            if c >= v.len() { return Err(format!("{path}: param '{param}' has {} elements, component {c} is out of range", v.len())); }
+            (json!(v), v[c])
+        }
+        (ParamType::Vec3, _, Some(c)) => return Err(format!("{path}: component {c} is out of range for vec3 param '{param}'")),
+        (ParamType::Vec3 | ParamType::F32Array, _, None) => return Err(format!("{path}: param '{param}' needs a 'component'")),
+        (ParamType::F32, _, Some(_)) => return Err(format!("{path}: param '{param}' is a scalar and takes no 'component'")),
+        _ => return Err(format!("{path}: param '{param}' is not continuous and cannot be solved for")),
+    };
+    node["params"][param] = value;
+    let lo = p.min.map_or(f64::NEG_INFINITY, |m| if p.exclusive_min { m as f64 + 1e-6 * (m as f64).abs().max(1.0) } else { m as f64 });
+    let suffix = component.filter(|_| p.ty != ParamType::F32).map_or(String::new(), |c| format!("/{c}"));
+    Ok(Slot { ptr: format!("{ptr}/params/{param}{suffix}"), lo, hi: p.max.map_or(f64::INFINITY, |m| m as f64), x0 })
+}
+
+fn grad(s: &CompiledSdf, p: V3) -> V3 {
+    let e = 1e-4;
+    std::array::from_fn(|i| { let mut a = p; let mut b = p; a[i] += e; b[i] -= e; (s.eval(a) - s.eval(b)) / (2.0 * e) })
+}
+
+/// `min_p dA(p) + dB(p)`: the best of a seed grid, then gradient descent with
+/// an adaptive step. For disjoint shapes the minimum is the whole segment
+/// between the closest points, for overlapping ones the deepest overlap.
+fn gap(a: &CompiledSdf, b: &CompiledSdf, domain: &Aabb) -> f32 {
+    let f = |p: V3| a.eval(p) + b.eval(p);
+    let (size, k) = (domain.size(), 6usize);
+    let mut p = domain.center();
+    for c in 0..k * k * k {
+        let q: V3 = std::array::from_fn(|i| domain.min[i] + size[i] * (((c / k.pow(i as u32)) % k) as f32 + 0.5) / k as f32);
+        if f(q) < f(p) { p = q; }
+    }
+    let (mut fp, mut step) = (f(p), math::max_c(size) / k as f32);
+    for _ in 0..400 {
+        if step < 1e-6 { break; }
+        let g = math::add(grad(a, p), grad(b, p));
+        if math::len(g) < 1e-6 { break; }
+        let q = math::sub(p, math::mul(math::normalize(g), step));
+        let fq = f(q);
+        if fq < fp { (p, fp, step) = (q, fq, step * 1.5); } else { step *= 0.5; }
+    }
+    fp
+}
+
+/// Smooth occupancy `clamp(0.5 - d/h, 0, 1)` summed over the lattice `h·Z³`.
+fn volume(s: &CompiledSdf, b: &Aabb, h: f32) -> Result<f32, String> {
+    if !b.is_finite() { return Err("volume needs a bounded node".into()); }
+    if b.is_empty() { return Ok(0.0); }
+    let lo = b.min.map(|v| (v / h).floor() as i64 - 1);
+    let hi = b.max.map(|v| (v / h).ceil() as i64 + 1);
+    let dims: [i64; 3] = std::array::from_fn(|i| hi[i] - lo[i] + 1);
+    if dims.iter().product::<i64>() > 8_000_000 { return Err("volume lattice exceeds 8M points; the node grew too large".into()); }
+    let pts: Vec<V3> = (0..dims[2]).flat_map(|k| (0..dims[1]).flat_map(move |j| (0..dims[0]).map(move |i| [(lo[0] + i) as f32 * h, (lo[1] + j) as f32 * h, (lo[2] + k) as f32 * h]))).collect();
+    Ok(s.eval_batch(&pts).iter().map(|d| (0.5 - d / h).clamp(0.0, 1.0)).sum::<f32>() * h * h * h)
+}
+
+/// Gaussian elimination with partial pivoting; `None` for a singular system.
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for c in 0..n {
+        let p = (c..n).max_by(|&i, &j| a[i][c].abs().total_cmp(&a[j][c].abs()))?;
+        if a[p][c].abs() < 1e-300 { return None; }
+        a.swap(c, p); b.swap(c, p);
+        let pivot = a[c].clone();
+        for r in c + 1..n {
+            let f = a[r][c] / pivot[c];
+            for (x, y) in a[r][c..].iter_mut().zip(&pivot[c..]) { *x -= f * y; }
+            b[r] -= f * b[c];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for c in (0..n).rev() { x[c] = (b[c] - (c + 1..n).map(|k| a[c][k] * x[k]).sum::<f64>()) / a[c][c]; }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(tree: Value, free: Value, constraints: Value) -> Result<Problem, String> {
+        Problem::new(&tree, serde_json::from_value(free).unwrap(), serde_json::from_value(constraints).unwrap())
+    }
+
+    fn two_spheres() -> Value {
+        json!({"type": "Union", "children": [
+            {"type": "Sphere", "params": {"radius": 1.0}},
+            {"type": "Translate", "params": {"offset": [3.0, 0.0, 0.0]}, "child": {"type": "Sphere", "params": {"radius": 0.5}}},
+        ]})
+    }
+
+    #[test]
+    fn tangent_spheres_converge() {
+        let p = problem(two_spheres(), json!([{"path": "root.children[0]", "param": "radius"}]), json!([{"type": "tangent", "a": "root.children[0]", "b": "root.children[1].child"}])).unwrap();
+        let s = p.solve(50, 1e-4, &|| false).unwrap();
+        assert!(s.converged, "{} iterations", s.iterations);
+        assert!((s.parameters[0].value - 2.5).abs() < 1e-3, "radius {}", s.parameters[0].value);
+        assert_eq!(s.parameters[0].initial, 1.0);
+        assert!(s.constraints[0].residual.abs() <= 1e-4);
+        assert_eq!(s.tree["children"][0]["params"]["radius"].as_f64(), Some(s.parameters[0].value));
+    }
+
+    #[test]
+    fn distance_and_volume_through_variables() {
+        let tree = json!({"variables": {"r": 1}, "type": "Union", "children": [
+            {"type": "Sphere", "params": {"radius": "r"}},
+            {"type": "Translate", "params": {"offset": [4.0, 0.0, 0.0]}, "child": {"type": "Sphere", "params": {"radius": 1.0}}},
+        ]});
+        let free = json!([{"variable": "r", "min": 0.1}, {"path": "root.children[1]", "param": "offset", "component": 1, "min": 0}]);
+        let target = 4.0 / 3.0 * std::f64::consts::PI * 8.0;
+        let c = json!([{"type": "volume", "path": "root.children[0]", "target": target}, {"type": "distance", "a": "root.children[0]", "b": "root.children[1].child", "target": 3.0}]);
+        let s = problem(tree, free, c).unwrap().solve(100, 1e-3, &|| false).unwrap();
+        assert!(s.converged, "{:?}", s.constraints);
+        // The lattice estimate of the volume is within a fraction of a cell of the exact one.
+        let (r, y) = (s.parameters[0].value, s.parameters[1].value);
+        assert!((r - 2.0).abs() < 0.02, "r {r}");
+        assert!(((16.0 + y * y).sqrt() - r - 1.0 - 3.0).abs() < 2e-3, "y {y}");
+        assert_eq!(s.tree["variables"]["r"].as_f64(), Some(r));
+        assert_eq!(s.tree["children"][0]["params"]["radius"], json!("r"));
+    }
+
+    #[test]
+    fn infeasible_constraints_report_their_residuals() {
+        // The radius may not reach the point: the solver stops at the bound and says so.
+        let tree = json!({"type": "Sphere", "params": {"radius": 1.0}});
+        let s = problem(tree.clone(), json!([{"path": "root", "param": "radius", "max": 1.2}]), json!([{"type": "on_surface", "point": [2.0, 0.0, 0.0]}])).unwrap().solve(50, 1e-4, &|| false).unwrap();
+        assert!(!s.converged);
+        assert!((s.parameters[0].value - 1.2).abs() < 1e-9);
+        assert_eq!(s.constraints[0].kind, "on_surface");
+        assert!((s.constraints[0].residual - 0.8).abs() < 1e-4, "residual {}", s.constraints[0].residual);
+        // Contradicting constraints settle on the least-squares compromise.
+        let c = json!([{"type": "on_surface", "point": [1.0, 0.0, 0.0]}, {"type": "on_surface", "point": [0.0, 2.0, 0.0]}]);
+        let s = problem(tree, json!([{"path": "root", "param": "radius"}]), c).unwrap().solve(50, 1e-4, &|| false).unwrap();
+        assert!(!s.converged);
+        assert!((s.parameters[0].value - 1.5).abs() < 1e-3);
+        assert!((s.constraints[0].residual + 0.5).abs() < 1e-3 && (s.constraints[1].residual - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn expiry_stops_the_solve() {
+        let p = problem(two_spheres(), json!([{"path": "root.children[0]", "param": "radius"}]), json!([{"type": "tangent", "a": "root.children[0]", "b": "root.children[1].child"}])).unwrap();
+        let s = p.solve(50, 1e-4, &|| true).unwrap();
+        assert_eq!((s.iterations, s.converged, s.parameters[0].value), (0, false, 1.0));
+    }
+
+    #[test]
+    fn bad_problems_are_rejected() {
+        let on = json!([{"type": "on_surface", "path": "root.children[0]", "point": [1, 0, 0]}]);
+        let err = |free: Value, c: Value| problem(two_spheres(), free, c).err().unwrap();
+        assert_eq!(err(json!([]), on.clone()), "free must list 1 to 32 parameters");
+        assert_eq!(err(json!([{"variable": "r"}]), on.clone()), "free[0]: the tree declares no variable 'r'");
+        assert_eq!(err(json!([{"path": "root.children[0]"}]), on.clone()), "free[0]: give either 'variable', or 'path' and 'param'");
+        let twice = json!([{"path": "root.children[0]", "param": "radius"}, {"path": "root.children[0]", "param": "radius", "max": 3}]);
+        assert_eq!(err(twice, on.clone()), "free[1]: listed twice");
+        assert_eq!(err(json!([{"path": "root.children[0]", "param": "radius", "min": 2, "max": 1}]), on.clone()), "free[0]: empty range [2, 1]");
+        assert_eq!(err(json!([{"path": "root.children[1]", "param": "offset"}]), on.clone()), "free[0]: root.children[1]: param 'offset' needs a 'component'");
+        assert_eq!(err(json!([{"path": "root.children[0]", "param": "size"}]), on.clone()), "free[0]: root.children[0]: Sphere has no param 'size'");
+        let free = json!([{"path": "root.children[0]", "param": "radius"}]);
+        assert_eq!(err(free.clone(), json!([{"type": "volume", "path": "root.children[0]", "target": 0}])), "constraints[0]: volume target must be > 0");
+        assert_eq!(err(free.clone(), json!([{"type": "tangent", "a": "root.children[5]", "b": "root", "weight": 1}])), "constraints[0]: root.children[5]: no node at this path");
+        let tree = json!({"variables": {"r": 1}, "type": "Sphere", "params": {"radius": "r * 2"}});
+        let e = problem(tree, json!([{"path": "root", "param": "radius"}]), on).err().unwrap();
+        assert_eq!(e, "free[0]: root: param 'radius' is the expression 'r * 2'; free the variable it uses instead");
+    }
+
+    #[test]
+    fn levenberg_marquardt_respects_ranges() {
+        let r = |x: &[f64]| Ok(vec![x[0] * x[0] - 2.0, x[1] - 3.0]);
+        let lm = levenberg_marquardt(vec![1.0, 0.0], &[(0.0, 10.0), (0.0, 1.0)], &r, &|r| r[0].abs() < 1e-10 && r[1].abs() < 1e-10, 100, &|| false).unwrap();
+        assert!((lm.x[0] - 2f64.sqrt()).abs() < 1e-6);
+        assert_eq!(lm.x[1], 1.0);
+        assert!(!lm.converged);
+    }
+}
@@ -0,0 +1,150 @@
+//! SdfNode: the parsed form of the JSON tree the API speaks.
+//!
+//! `{"type": "SmoothUnion", "params": {"k": 0.3}, "a": {...}, "b": {...}}`
+//! Children live under `a`/`b` (binary), `child` (unary) or `children` (n-ary).
+//! Any node may carry a `material` object (see `material.rs`); the root may
+//! declare `variables` that params refer to (see `expr.rs`). Parsing resolves
+//! them, so an `SdfNode` only holds numbers.
+
+use crate::expr::Vars;
+use crate::material::Material;
+use crate::math::V3;
+use serde_json::{json, Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Slots { None, Child, Pair, List }
+
+#[derive(Clone, Debug)]
+pub struct SdfNode {
+    pub ty: String,
+    pub params: Map<String, Value>,
+    pub children: Vec<SdfNode>,
+    pub slots: Slots,
+    pub material: Option<Material>,
+}
+
+impl SdfNode {
+    pub fn new(ty: &str, params: Value, children: Vec<SdfNode>) -> Self {
+        let slots = match children.len() { 0 => Slots::None, 1 => Slots::Child, 2 => Slots::Pair, _ => Slots::List };
+        SdfNode { ty: ty.into(), params: params.as_object().cloned().unwrap_or_default(), children, slots, material: None }
+    }
+
+    pub fn with_material(self, m: Option<Material>) -> Self { SdfNode { material: m, ..self } }
+
+    pub fn has_materials(&self) -> bool { self.material.is_some() || self.children.iter().any(|c| c.has_materials()) }
+
+    /// Move every material onto the leaves it applies to, so interior nodes can be
+    /// rewritten or dropped without changing any surface's material.
+    pub fn materials_to_leaves(&self, inherited: Option<Material>) -> SdfNode {
+        let m = self.material.or(inherited);
+        if self.children.is_empty() { return self.clone().with_material(m); }
+        SdfNode { children: self.children.iter().map(|c| c.materials_to_leaves(m)).collect(), material: None, ..self.clone() }
+    }
+
+    pub fn leaf(ty: &str, params: Value) -> Self { Self::new(ty, params, vec![]) }
+    pub fn unary(ty: &str, params: Value, child: SdfNode) -> Self { Self::new(ty, params, vec![child]) }
+    pub fn binary(ty: &str, params: Value, a: SdfNode, b: SdfNode) -> Self { Self::new(ty, params, vec![a, b]) }
+    pub fn list(ty: &str, params: Value, children: Vec<SdfNode>) -> Self {
+        SdfNode { slots: Slots::List, ..Self::new(ty, params, children) }
+    }
+
+    pub fn from_json(v: &Value) -> Result<Self, String> { Self::from_json_at(v, 0.0) }
+
+    /// Parse with keyframe curves sampled at `time`.
+    pub fn from_json_at(v: &Value, time: f64) -> Result<Self, String> {
+        Self::from_json_with(v, &Vars::declare(v.get("variables"), &Map::new(), time)?)
+    }
+
+    /// Parse with already resolved root variables (e.g. after overrides).
+    pub fn from_json_with(v: &Value, vars: &Vars) -> Result<Self, String> { parse(v, "root", vars) }
+
+    pub fn to_json(&self) -> Value {
+        let mut o = Map::new();
+        o.insert("type".into(), json!(self.ty));
+        o.insert("params".into(), Value::Object(self.params.clone()));
+        // Through text so f32 fields print as written (0.8, not 0.800000011920929).
+        if let Some(m) = &self.material { o.insert("material".into(), serde_json::to_string(m).ok().and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()); }
+        match self.slots {
+            Slots::None => {}
+            Slots::Child => { o.insert("child".into(), self.children[0].to_json()); }
+            Slots::Pair => { o.insert("a".into(), self.children[0].to_json()); o.insert("b".into(), self.children[1].to_json()); }
+            Slots::List => { o.insert("children".into(), Value::Array(self.children.iter().map(|c| c.to_json()).collect())); }
+        }
+        Value::Object(o)
+    }
+
+    /// Path of child `i` in the error/patch syntax: `root.a`, `root.child`, `root.children[2]`.
+    pub fn child_path(&self, path: &str, i: usize) -> String {
+        match self.slots {
+            Slots::Pair => format!("{path}.{}", if i == 0 { "a" } else { "b" }),
+            Slots::List => format!("{path}.children[{i}]"),
+            _ => format!("{path}.child"),
+        }
+    }
+
+    pub fn f(&self, k: &str, d: f32) -> f32 { self.params.get(k).and_then(|v| v.as_f64()).map_or(d, |x| x as f32) }
+    pub fn u(&self, k: &str, d: u32) -> u32 { self.params.get(k).and_then(|v| v.as_f64()).map_or(d, |x| x.max(0.0) as u32) }
+    /// A vec3 param; a bare number is splatted to all three components.
+    pub fn v3(&self, k: &str, d: V3) -> V3 {
+        match self.params.get(k) {
+            Some(Value::Array(a)) if a.len() == 3 => {
+                let c = |i: usize| a[i].as_f64().map_or(d[i], |x| x as f32);
+                [c(0), c(1), c(2)]
+            }
+            Some(v) => v.as_f64().map_or(d, |x| [x as f32; 3]),
+            None => d,
+        }
+    }
+}
+
+/// Node count, depth and types (pre-order) of a tree's JSON, however malformed.
+pub fn count_nodes(t: &Value) -> usize {
+    let mut c = 1;
+    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { c += count_nodes(ch); } }
+    if let Some(arr) = t.get("children").and_then(|v| v.as_array()) { for ch in arr { c += count_nodes(ch); } }
+    c
+}
+
+pub fn tree_depth(t: &Value) -> usize {
+    let mut mx = 0;
+    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { mx = mx.max(tree_depth(ch)); } }
+    if let Some(arr) = t.get("children").and_then(|v| v.as_array()) { for ch in arr { mx = mx.max(tree_depth(ch)); } }
+    1 + mx
+}
+
+pub fn collect_types(t: &Value) -> Vec<String> {
+    let mut v = Vec::new();
+    if let Some(s) = t.get("type").and_then(|x| x.as_str()) { v.push(s.into()); }
+    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { v.extend(collect_types(ch)); } }
+    if let Some(arr) = t.get("children").and_then(|x| x.as_array()) { for ch in arr { v.extend(collect_types(ch)); } }
+    v
+}
+
+fn parse(v: &Value, path: &str, vars: &Vars) -> Result<SdfNode, String> {
+    let o = v.as_object().ok_or_else(|| format!("{path}: node must be an object"))?;
+    let ty = o.get("type").and_then(|t| t.as_str()).ok_or_else(|| format!("{path}: missing 'type'"))?;
+    if path != "root" && o.contains_key("variables") { return Err(format!("{path}: only the root node may declare 'variables'")); }
+    let params = match o.get("params") {
+        None | Some(Value::Null) => Map::new(),
+        Some(Value::Object(m)) => m.iter().map(|(k, x)| Ok((k.clone(), vars.substitute(x).map_err(|e| format!("{path}: param '{k}' {e}"))?))).collect::<Result<_, String>>()?,
+        Some(_) => return Err(format!("{path}: 'params' must be an object")),
+    };
+    let (children, slots) = if let Some(arr) = o.get("children") {
+        let arr = arr.as_array().ok_or_else(|| format!("{path}: 'children' must be an array"))?;
+        let cs = arr.iter().enumerate().map(|(i, c)| parse(c, &format!("{path}.children[{i}]"), vars)).collect::<Result<Vec<_>, _>>()?;
+        (cs, Slots::List)
+    } else if let Some(c) = o.get("child") {
+        (vec![parse(c, &format!("{path}.child"), vars)?], Slots::Child)
+    } else if o.contains_key("a") || o.contains_key("b") {
+        let a = o.get("a").ok_or_else(|| format!("{path}: missing 'a'"))?;
+        let b = o.get("b").ok_or_else(|| format!("{path}: missing 'b'"))?;
+        (vec![parse(a, &format!("{path}.a"), vars)?, parse(b, &format!("{path}.b"), vars)?], Slots::Pair)
+    } else {
+        (vec![], Slots::None)
+    };
+    let material = match o.get("material") {
+        None | Some(Value::Null) => None,
+        Some(m) => Some(Material::parse(&vars.substitute(m).map_err(|e| format!("{path}: material {e}"))?, path)?),
+    };
+    Ok(SdfNode { ty: ty.into(), params, children, slots, material })
+}
@@ -0,0 +1,304 @@
+//! Baked distance volumes for game engines (`/api/v1/sdf/voxelize`).
+//!
+//! Samples sit at cell centres, so a 3D texture spanning the grid bounds filters
+//! to the field with `uvw = (p - min) / size`. The sparse layout cuts the grid
+//! into bricks of `BRICK`³ samples that overlap their neighbours by one sample,
+//! so trilinear filtering inside a brick never needs another one. Bricks whose
+//! centre is further from the surface than the band plus the brick's radius are
+//! not stored; like the mesh bake this trusts the tree's distance not to
+//! overestimate.
+
+use crate::bounds::Aabb;
+use crate::compiler::CompiledSdf;
+use crate::math::{self, V3};
+use rayon::prelude::*;
+
+/// Samples per brick side; neighbouring bricks share one layer, so a brick spans `BRICK - 1` cells.
+pub const BRICK: usize = 8;
+pub const FAR_OUTSIDE: i32 = -1;
+pub const FAR_INSIDE: i32 = -2;
+
+/// Row pitch alignment D3D12 and WebGPU require for texture uploads.
+const ROW_ALIGN: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Precision { F32, F16 }
+
+impl Precision {
+    pub fn parse(s: &str) -> Option<Precision> { match s { "f32" => Some(Precision::F32), "f16" => Some(Precision::F16), _ => None } }
+    pub fn bytes(self) -> usize { if self == Precision::F32 { 4 } else { 2 } }
+    pub fn texture_format(self) -> &'static str { if self == Precision::F32 { "r32float" } else { "r16float" } }
+    fn put(self, out: &mut Vec<u8>, x: f32) {
+        match self { Precision::F32 => out.extend_from_slice(&x.to_le_bytes()), Precision::F16 => out.extend_from_slice(&f16_bits(x).to_le_bytes()) }
+    }
+}
+
+/// IEEE half, rounding to nearest even; overflow goes to infinity.
+pub fn f16_bits(x: f32) -> u16 {
+    let b = x.to_bits();
+    let sign = ((b >> 16) & 0x8000) as u16;
+    let exp = ((b >> 23) & 0xff) as i32;
+    let man = b & 0x7f_ffff;
+    if exp == 0xff { return sign | 0x7c00 | if man != 0 { 0x200 } else { 0 }; }
+    let e = exp - 127 + 15;
+    if e >= 0x1f { return sign | 0x7c00; }
+    if e <= 0 {
+        if e < -10 { return sign; }
+        let m = man | 0x80_0000;
+        let shift = (14 - e) as u32;
+        let (h, rest, half) = (m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1));
+        return sign | (h + u32::from(rest > half || (rest == half && h & 1 == 1))) as u16;
+    }
+    let (h, rest) = (((e as u32) << 10) | (man >> 13), man & 0x1fff);
+    sign | (h + u32::from(rest > 0x1000 || (rest == 0x1000 && h & 1 == 1))) as u16
+}
+
+/// Sample lattice: sample `(i, j, k)` is at `origin + (i, j, k) * voxel`.
+#[derive(Clone, Copy)]
+pub struct Lattice { pub origin: V3, pub voxel: f32, pub dims: [usize; 3] }
+
+impl Lattice {
+    /// `resolution` cells along the longest side of `b`, one sample per cell centre.
+    pub fn over(b: &Aabb, resolution: usize) -> Lattice {
+        let voxel = math::max_c(b.size()).max(1e-6) / resolution.max(1) as f32;
+        let dims = b.size().map(|s| ((s / voxel - 1e-3).ceil() as usize).max(1));
+        Lattice { origin: math::add(b.min, [0.5 * voxel; 3]), voxel, dims }
+    }
+    /// Widened so the samples split into whole overlapping bricks.
+    pub fn bricked(self) -> Lattice { Lattice { dims: self.brick_dims().map(|n| n * (BRICK - 1) + 1), ..self } }
+    pub fn brick_dims(&self) -> [usize; 3] { self.dims.map(|d| (d.max(2) - 1).div_ceil(BRICK - 1)) }
+    pub fn count(&self) -> usize { self.dims.iter().product() }
+    /// Box the samples' cells cover.
+    pub fn bounds(&self) -> Aabb {
+        let min = math::sub(self.origin, [0.5 * self.voxel; 3]);
+        Aabb { min, max: math::add(min, self.dims.map(|d| d as f32 * self.voxel)) }
+    }
+    fn at(&self, i: usize, j: usize, k: usize) -> V3 { math::add(self.origin, math::mul([i as f32, j as f32, k as f32], self.voxel)) }
+}
+
+/// Every sample, x fastest, clamped to `±band` when given. `None` once `expired`.
+pub fn dense(sdf: &CompiledSdf, l: &Lattice, band: Option<f32>, expired: &(dyn Fn() -> bool + Sync)) -> Option<Vec<f32>> {
+    let [nx, ny, nz] = l.dims;
+    let slices: Vec<Option<Vec<f32>>> = (0..nz).into_par_iter().map(|k| {
+        if expired() { return None; }
+        let pts: Vec<V3> = (0..ny).flat_map(|j| (0..nx).map(move |i| (i, j))).map(|(i, j)| l.at(i, j, k)).collect();
+        let d = sdf.eval_batch(&pts);
+        Some(match band { Some(b) => d.into_iter().map(|x| x.clamp(-b, b)).collect(), None => d })
+    }).collect();
+    let mut out = Vec::with_capacity(l.count());
+    for s in slices { out.extend(s?); }
+    Some(out)
+}
+
+/// Narrow-band bricks: `index` holds, per brick cell (x fastest), the brick's
+/// number in `values` or `FAR_OUTSIDE` / `FAR_INSIDE`; `values` holds the stored
+/// bricks one after another, `BRICK`³ samples each, x fastest, clamped to `±band`.
+pub struct Sparse { pub index: Vec<i32>, pub values: Vec<f32>, pub stored: usize }
+
+/// `None` once `expired`. `l` must come from [`Lattice::bricked`].
+pub fn sparse(sdf: &CompiledSdf, l: &Lattice, band: f32, expired: &(dyn Fn() -> bool + Sync)) -> Option<Sparse> {
+    let nb = l.brick_dims();
+    let span = BRICK - 1;
+    let mid = 0.5 * span as f32;
+    let radius = mid * l.voxel * 3f32.sqrt();
+    let bricks: Vec<Option<Result<i32, Vec<f32>>>> = (0..nb[0] * nb[1] * nb[2]).into_par_iter().map(|b| {
+        if expired() { return None; }
+        let base = [b % nb[0], b / nb[0] % nb[1], b / (nb[0] * nb[1])].map(|x| x * span);
+        let d = sdf.eval(math::add(l.at(base[0], base[1], base[2]), [mid * l.voxel; 3]));
+        if d.abs() - radius > band { return Some(Ok(if d > 0.0 { FAR_OUTSIDE } else { FAR_INSIDE })); }
+        let pts: Vec<V3> = (0..BRICK.pow(3)).map(|s| l.at(base[0] + s % BRICK, base[1] + s / BRICK % BRICK, base[2] + s / (BRICK * BRICK))).collect();
+        Some(Err(sdf.eval_batch(&pts).into_iter().map(|x| x.clamp(-band, band)).collect()))
+    }).collect();
+    let mut s = Sparse { index: Vec::with_capacity(bricks.len()), values: vec![], stored: 0 };
+    for b in bricks {
+        match b? {
+            Ok(flag) => s.index.push(flag),
+            Err(v) => { s.index.push(s.stored as i32); s.stored += 1; s.values.extend(v); }
+        }
+    }
+    Some(s)
+}
+
+/// Raw little-endian values in the given order.
+pub fn raw(values: &[f32], p: Precision) -> Vec<u8> {
+    let mut out = Vec::with_capacity(values.len() * p.bytes());
+    for &x in values { p.put(&mut out, x); }
+    out
+}
+
+/// Attached NRRD of a dense f32 grid, with the lattice as its space.
+pub fn nrrd(values: &[f32], l: &Lattice) -> Vec<u8> {
+    let [x, y, z] = l.dims;
+    let (o, v) = (l.origin, l.voxel);
+    let mut out = format!(
+        "NRRD0004\n# SDF distance field\ntype: float\ndimension: 3\nsizes: {x} {y} {z}\nspace: right-anterior-superior\nspace origin: ({},{},{})\nspace directions: ({v},0,0) (0,{v},0) (0,0,{v})\nkinds: domain domain domain\nendian: little\nencoding: raw\n\n",
+        o[0], o[1], o[2],
+    ).into_bytes();
+    out.extend(raw(values, Precision::F32));
+    out
+}
+
+/// Bytes per texture row holding `width` texels of `texel` bytes, padded to the upload alignment.
+pub fn row_pitch(width: usize, texel: usize) -> usize { (width * texel).div_ceil(ROW_ALIGN) * ROW_ALIGN }
+
+/// Dense 3D texture upload: rows padded to [`row_pitch`], rows then slices in order.
+pub fn texture(values: &[f32], dims: [usize; 3], p: Precision) -> Vec<u8> {
+    let pitch = row_pitch(dims[0], p.bytes());
+    let mut out = Vec::with_capacity(pitch * dims[1] * dims[2]);
+    for row in values.chunks(dims[0]) {
+        for &x in row { p.put(&mut out, x); }
+        out.resize(out.len().div_ceil(pitch) * pitch, 0);
+    }
+    out
+}
+
+/// Bricks per side of the atlas holding `n` bricks: as cubic as possible.
+pub fn atlas_bricks(n: usize) -> [usize; 3] {
+    let a = (1..).find(|a| a * a * a >= n.max(1)).unwrap_or(1);
+    [a, a, n.max(1).div_ceil(a * a)]
+}
+
+/// Sparse grid as 3D textures: the stored bricks packed into an atlas of
+/// [`atlas_bricks`] (x fastest, unused slots at `band`), and an RGBA8 index
+/// texture with one texel per brick cell holding the brick's atlas slot and a
+/// state of 1 (stored), 0 (far outside) or 2 (far inside).
+pub fn atlas(s: &Sparse, brick_dims: [usize; 3], band: f32, p: Precision) -> (Vec<u8>, Vec<u8>) {
+    let ab = atlas_bricks(s.stored);
+    let dims = ab.map(|n| n * BRICK);
+    let mut texels = vec![band; dims.iter().product()];
+    for b in 0..s.stored {
+        let slot = [b % ab[0], b / ab[0] % ab[1], b / (ab[0] * ab[1])].map(|x| x * BRICK);
+        for (i, &v) in s.values[b * BRICK.pow(3)..(b + 1) * BRICK.pow(3)].iter().enumerate() {
+            let (x, y, z) = (slot[0] + i % BRICK, slot[1] + i / BRICK % BRICK, slot[2] + i / (BRICK * BRICK));
+            texels[(z * dims[1] + y) * dims[0] + x] = v;
+        }
+    }
+    let pitch = row_pitch(brick_dims[0], 4);
+    let mut index = Vec::with_capacity(pitch * brick_dims[1] * brick_dims[2]);
+    for row in s.index.chunks(brick_dims[0]) {
+        for &b in row {
+            index.extend_from_slice(&match b {
+                FAR_OUTSIDE => [0, 0, 0, 0],
+                FAR_INSIDE => [0, 0, 0, 2],
+                b => { let b = b as usize; [b % ab[0], b / ab[0] % ab[1], b / (ab[0] * ab[1]), 1].map(|x| x as u8) }
+            });
+        }
+        index.resize(index.len().div_ceil(pitch) * pitch, 0);
+    }
+    (texture(&texels, dims, p), index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler, tree::SdfNode};
+    use serde_json::json;
+
+    fn sdf() -> CompiledSdf {
+        let tree = json!({"type": "Union", "children": [
+            {"type": "Sphere", "params": {"radius": 0.8}},
+            {"type": "Translate", "params": {"offset": [0.9, 0.0, 0.0]}, "child": {"type": "Box3d", "params": {"half_size": [0.3, 0.5, 0.4]}}},
+        ]});
+        compiler::compile(&SdfNode::from_json(&tree).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn dense_and_sparse_agree() {
+        let sdf = sdf();
+        let l = Lattice::over(&Aabb { min: [-1.0, -1.0, -1.0], max: [1.4, 1.0, 1.0] }, 40).bricked();
+        let band = 3.0 * l.voxel;
+        let dense = dense(&sdf, &l, Some(band), &|| false).unwrap();
+        let s = sparse(&sdf, &l, band, &|| false).unwrap();
+        let nb = l.brick_dims();
+        assert_eq!(s.index.len(), nb.iter().product::<usize>());
+        assert_eq!(s.values.len(), s.stored * BRICK.pow(3));
+        assert!(s.stored > 0 && s.stored < s.index.len());
+        let at = |x: usize, y: usize, z: usize| dense[(z * l.dims[1] + y) * l.dims[0] + x];
+        for (b, &e) in s.index.iter().enumerate() {
+            let base = [b % nb[0], b / nb[0] % nb[1], b / (nb[0] * nb[1])].map(|x| x * (BRICK - 1));
+            for i in 0..BRICK.pow(3) {
+                let d = at(base[0] + i % BRICK, base[1] + i / BRICK % BRICK, base[2] + i / (BRICK * BRICK));
+                match e {
+                    // Bricks beyond the band read as ±band, which is what dense clamps to there.
+                    FAR_OUTSIDE => assert_eq!(d, band),
+                    FAR_INSIDE => assert_eq!(d, -band),
+                    e => assert_eq!(s.values[e as usize * BRICK.pow(3) + i], d),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lattices_cover_the_box() {
+        let b = Aabb { min: [-1.0, -0.5, 0.0], max: [1.0, 0.5, 0.25] };
+        let l = Lattice::over(&b, 16);
+        assert_eq!((l.dims, l.voxel, l.count()), ([16, 8, 2], 0.125, 256));
+        assert_eq!(l.bounds().min, b.min);
+        assert_eq!(l.bounds().max, b.max);
+        // Bricks of 8 samples share a layer: 16 samples need 3 bricks, i.e. 22 samples.
+        let k = l.bricked();
+        assert_eq!((k.brick_dims(), k.dims), ([3, 1, 1], [22, 8, 8]));
+    }
+
+    #[test]
+    fn texture_rows_are_padded_to_the_pitch() {
+        assert_eq!((row_pitch(1, 4), row_pitch(64, 4), row_pitch(65, 4), row_pitch(100, 2), row_pitch(128, 2)), (256, 256, 512, 256, 256));
+        let dims = [3, 2, 2];
+        let values: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        for p in [Precision::F32, Precision::F16] {
+            let t = texture(&values, dims, p);
+            assert_eq!(t.len(), 256 * 2 * 2);
+            for (r, row) in t.chunks(256).enumerate() {
+                let n = 3 * p.bytes();
+                assert_eq!(row[..n], raw(&values[r * 3..r * 3 + 3], p));
+                assert!(row[n..].iter().all(|&b| b == 0));
+            }
+        }
+        assert_eq!(raw(&[1.0, -2.0], Precision::F32), [0, 0, 0x80, 0x3f, 0, 0, 0, 0xc0]);
+    }
+
+    #[test]
+    fn atlas_textures_hold_every_stored_brick() {
+        let sdf = sdf();
+        let l = Lattice::over(&Aabb::sym([1.3; 3]), 24).bricked();
+        let band = 2.0 * l.voxel;
+        let s = sparse(&sdf, &l, band, &|| false).unwrap();
+        let nb = l.brick_dims();
+        let (data, index) = atlas(&s, nb, band, Precision::F32);
+        let ab = atlas_bricks(s.stored);
+        assert!(ab.iter().product::<usize>() >= s.stored);
+        let dims = ab.map(|n| n * BRICK);
+        let pitch = row_pitch(dims[0], 4);
+        assert_eq!(data.len(), pitch * dims[1] * dims[2]);
+        let ipitch = row_pitch(nb[0], 4);
+        assert_eq!(index.len(), ipitch * nb[1] * nb[2]);
+        let texel = |x: usize, y: usize, z: usize| { let o = (z * dims[1] + y) * pitch + x * 4; f32::from_le_bytes(data[o..o + 4].try_into().unwrap()) };
+        for (b, &e) in s.index.iter().enumerate() {
+            let (x, y, z) = (b % nb[0], b / nb[0] % nb[1], b / (nb[0] * nb[1]));
+            let t = &index[(z * nb[1] + y) * ipitch + x * 4..][..4];
+            match e {
+                FAR_OUTSIDE => assert_eq!(t, [0, 0, 0, 0]),
+                FAR_INSIDE => assert_eq!(t, [0, 0, 0, 2]),
+                e => {
+                    assert_eq!(t[3], 1);
+                    let slot = [t[0], t[1], t[2]].map(|c| c as usize * BRICK);
+                    for i in [0, 7, 100, BRICK.pow(3) - 1] {
+                        assert_eq!(texel(slot[0] + i % BRICK, slot[1] + i / BRICK % BRICK, slot[2] + i / (BRICK * BRICK)), s.values[e as usize * BRICK.pow(3) + i]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn half_floats_round_to_nearest_even() {
+        for (x, h) in [(0.0, 0x0000), (-0.0, 0x8000), (1.0, 0x3c00), (-2.0, 0xc000), (0.1, 0x2e66), (65504.0, 0x7bff), (65520.0, 0x7c00), (2f32.powi(-24), 0x0001), (1e-8, 0x0000)] {
+            assert_eq!(f16_bits(x), h, "{x}");
+        }
+        assert_eq!(f16_bits(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f16_bits(f32::NAN) & 0x7e00, 0x7e00);
+        // 1 + 2^-11 lies halfway between 1 and the next half; ties go to the even mantissa.
+        assert_eq!(f16_bits(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f16_bits(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+    }
+}
@@ -0,0 +1,104 @@
+//! The `sdf` binary end to end: exit codes and byte-identical output across runs.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const SPHERE: &str = r#"{"type": "Sphere", "params": {"radius": 1}}"#;
+const ANIMATED: &str = r#"{"type": "SmoothUnion", "params": {"k": 0.2},
+  "a": {"type": "Sphere", "params": {"radius": {"keys": [{"t": 0, "value": 0.5}, {"t": 1, "value": 1}]}}},
+  "b": {"type": "Translate", "params": {"offset": [0.8, 0, 0]}, "child": {"type": "Box3d", "params": {"half_size": 0.4}}}}"#;
+
+/// Scratch directory for one test, emptied first.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sdf-cli-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn tree_file(dir: &std::path::Path, name: &str, text: &str) -> String {
+    let p = dir.join(name);
+    std::fs::write(&p, text).unwrap();
+    p.to_string_lossy().into_owned()
+}
+
+fn sdf(args: &[&str]) -> Output { Command::new(env!("CARGO_BIN_EXE_sdf")).args(args).output().unwrap() }
+
+fn code(o: &Output) -> i32 { o.status.code().unwrap() }
+
+#[test]
+fn exit_codes() {
+    let dir = scratch("codes");
+    let ok = tree_file(&dir, "ok.json", SPHERE);
+    let unknown = tree_file(&dir, "unknown.json", r#"{"type": "Blob", "params": {}}"#);
+    let broken = tree_file(&dir, "broken.json", "{\"type\": ");
+    let missing = dir.join("missing.json").to_string_lossy().into_owned();
+
+    assert_eq!(code(&sdf(&["--help"])), 0);
+    assert_eq!(code(&sdf(&["validate", &ok])), 0);
+    assert_eq!(code(&sdf(&["eval", &ok, "--point", "2,0,0"])), 0);
+
+    for args in [vec!["validate", unknown.as_str()], vec!["validate", broken.as_str()], vec!["eval", unknown.as_str(), "--point", "0,0,0"]] {
+        let o = sdf(&args);
+        assert_eq!(code(&o), 1, "{args:?}");
+        assert!(String::from_utf8_lossy(&o.stderr).starts_with("sdf: "));
+    }
+
+    for args in [vec![], vec!["frobnicate", ok.as_str()], vec!["validate"], vec!["validate", ok.as_str(), "--bogus"], vec!["eval", ok.as_str()],
+                 vec!["eval", ok.as_str(), "--point", "1,2"], vec!["mesh", ok.as_str(), "--format", "fbx"], vec!["transpile", ok.as_str(), "--target", "msl"],
+                 vec!["render", ok.as_str(), "--size", "0x10"], vec!["export", ok.as_str()], vec!["validate", ok.as_str(), "extra"]] {
+        let o = sdf(&args);
+        assert_eq!(code(&o), 2, "{args:?}");
+        assert!(String::from_utf8_lossy(&o.stderr).contains("sdf --help"));
+    }
+
+    assert_eq!(code(&sdf(&["validate", &missing])), 3);
+    let no_dir = dir.join("nope").join("out.obj").to_string_lossy().into_owned();
+    assert_eq!(code(&sdf(&["mesh", &ok, "--resolution", "8", "-o", &no_dir])), 3);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn outputs_are_byte_identical_across_runs() {
+    let dir = scratch("repeat");
+    let tree = tree_file(&dir, "tree.json", ANIMATED);
+    let runs: [&[&str]; 7] = [
+        &["validate", &tree, "--json"],
+        &["eval", &tree, "--time", "0.5", "--point", "0,0,0", "--point", "1.5,0.2,-0.1"],
+        &["mesh", &tree, "--resolution", "24"],
+        &["mesh", &tree, "--resolution", "24", "--format", "glb", "--time", "1"],
+        &["transpile", &tree, "--target", "glsl"],
+        &["render", &tree, "--size", "48x32", "--view", "45,10"],
+        &["mesh", &tree, "--resolution", "24", "--format", "ply"],
+    ];
+    for args in runs {
+        let (a, b) = (sdf(args), sdf(args));
+        assert_eq!(code(&a), 0, "{args:?}: {}", String::from_utf8_lossy(&a.stderr));
+        assert!(!a.stdout.is_empty(), "{args:?}");
+        assert!(a.stdout == b.stdout, "{args:?} differs between runs");
+    }
+    let (png, mesh) = (sdf(runs[5]).stdout, sdf(runs[2]).stdout);
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    assert!(String::from_utf8(mesh).unwrap().lines().any(|l| l.starts_with("f ")));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn export_sequence_is_reproducible() {
+    let dir = scratch("export");
+    let tree = tree_file(&dir, "tree.json", ANIMATED);
+    let files = |run: &str| {
+        let out = dir.join(run);
+        std::fs::create_dir_all(&out).unwrap();
+        let o = sdf(&["export", &tree, "--resolution", "16", "--frames", "0:1:3", "-o", &out.join("anim.obj").to_string_lossy()]);
+        assert_eq!(code(&o), 0, "{}", String::from_utf8_lossy(&o.stderr));
+        let mut names: Vec<_> = std::fs::read_dir(&out).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names.into_iter().map(|n| { let bytes = std::fs::read(out.join(&n)).unwrap(); (n, bytes) }).collect::<Vec<_>>()
+    };
+    let (a, b) = (files("a"), files("b"));
+    assert_eq!(a.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), ["anim_0000.obj", "anim_0001.obj", "anim_0002.obj"]);
+    assert!(a == b, "export differs between runs");
+    assert!(a[0].1 != a[2].1, "frames should follow the keyframed radius");
+    let _ = std::fs::remove_dir_all(&dir);
+}
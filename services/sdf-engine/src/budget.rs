@@ -0,0 +1,119 @@
+//! Per-request resource budgets.
+//!
+//! The gateway passes the caller's plan limits in the trusted `X-SDF-Budget`
+//! header, e.g. `max_resolution=128, max_nodes=50, max_points=100000, timeout_ms=10000`.
+//! Keys missing from the header (or a missing header) take the engine defaults,
+//! which `SDF_BUDGET` overrides with the same syntax. Sizes are checked up front;
+//! the timeout is checked between units of work (mesh slabs, solver iterations,
+//! shader samples).
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+pub const HEADER: &str = "x-sdf-budget";
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Limits { pub max_resolution: usize, pub max_nodes: usize, pub max_points: usize, pub timeout_ms: u64 }
+
+impl Default for Limits {
+    fn default() -> Self { Limits { max_resolution: 256, max_nodes: 1000, max_points: 1_000_000, timeout_ms: 30_000 } }
+}
+
+impl Limits {
+    /// `key=value` pairs separated by commas, overriding `base`.
+    pub fn parse(s: &str, base: Limits) -> Result<Limits, String> {
+        let mut l = base;
+        for pair in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (k, v) = pair.split_once('=').ok_or_else(|| format!("'{pair}' is not key=value"))?;
+            let n: u64 = v.trim().parse().map_err(|_| format!("{}: '{}' is not a non-negative integer", k.trim(), v.trim()))?;
+            match k.trim() {
+                "max_resolution" => l.max_resolution = n as usize,
+                "max_nodes" => l.max_nodes = n as usize,
+                "max_points" => l.max_points = n as usize,
+                "timeout_ms" => l.timeout_ms = n,
+                k => return Err(format!("unknown limit '{k}'")),
+            }
+        }
+        Ok(l)
+    }
+}
+
+/// The limit a request went over.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Exceeded { pub name: &'static str, pub max: u64, pub requested: u64 }
+
+/// Deadline used when `timeout_ms` overflows `Instant`: about a century.
+const FAR: Duration = Duration::from_secs(100 * 365 * 24 * 3600);
+
+/// Limits plus the deadline of one request.
+#[derive(Clone, Copy, Debug)]
+pub struct Budget { pub limits: Limits, deadline: Instant }
+
+impl Budget {
+    /// A timeout past what `Instant` can hold (e.g. `timeout_ms=u64::MAX`) never expires in practice.
+    pub fn new(limits: Limits) -> Budget {
+        let now = Instant::now();
+        let deadline = now.checked_add(Duration::from_millis(limits.timeout_ms)).unwrap_or_else(|| now + FAR);
+        Budget { limits, deadline }
+    }
+
+    pub fn nodes(&self, n: usize) -> Result<(), Exceeded> { check("max_nodes", self.limits.max_nodes, n) }
+    pub fn resolution(&self, r: usize) -> Result<(), Exceeded> { check("max_resolution", self.limits.max_resolution, r) }
+    pub fn points(&self, n: usize) -> Result<(), Exceeded> { check("max_points", self.limits.max_points, n) }
+
+    pub fn expired(&self) -> bool { Instant::now() >= self.deadline }
+
+    /// Timeout error, with the time spent so far as `requested`.
+    pub fn timed_out(&self) -> Exceeded {
+        let spent = self.limits.timeout_ms.saturating_add(Instant::now().saturating_duration_since(self.deadline).as_millis() as u64);
+        Exceeded { name: "timeout_ms", max: self.limits.timeout_ms, requested: spent }
+    }
+}
+
+fn check(name: &'static str, max: usize, requested: usize) -> Result<(), Exceeded> {
+    if requested > max { Err(Exceeded { name, max: max as u64, requested: requested as u64 }) } else { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_overrides_the_base() {
+        let l = Limits::parse(" max_resolution=128, max_nodes = 50,timeout_ms=10 ,", Limits::default()).unwrap();
+        assert_eq!((l.max_resolution, l.max_nodes, l.max_points, l.timeout_ms), (128, 50, 1_000_000, 10));
+        let l = Limits::parse("", l).unwrap();
+        assert_eq!((l.max_resolution, l.timeout_ms), (128, 10));
+    }
+
+    #[test]
+    fn malformed_headers_are_errors() {
+        let err = |s| Limits::parse(s, Limits::default()).unwrap_err();
+        assert_eq!(err("max_nodes"), "'max_nodes' is not key=value");
+        assert_eq!(err("max_nodes=-1"), "max_nodes: '-1' is not a non-negative integer");
+        assert_eq!(err("max_points=1e6"), "max_points: '1e6' is not a non-negative integer");
+        assert_eq!(err("max_depth=3"), "unknown limit 'max_depth'");
+    }
+
+    #[test]
+    fn checks_name_the_limit() {
+        let b = Budget::new(Limits { max_resolution: 64, max_nodes: 10, max_points: 100, timeout_ms: 60_000 });
+        assert!(b.resolution(64).is_ok() && b.nodes(10).is_ok() && b.points(0).is_ok());
+        let e = b.points(101).unwrap_err();
+        assert_eq!((e.name, e.max, e.requested), ("max_points", 100, 101));
+        assert_eq!(b.resolution(65).unwrap_err().name, "max_resolution");
+        assert!(!b.expired());
+        let b = Budget::new(Limits { timeout_ms: 0, ..Limits::default() });
+        assert!(b.expired());
+        let e = b.timed_out();
+        assert_eq!((e.name, e.max), ("timeout_ms", 0));
+    }
+
+    #[test]
+    fn huge_timeouts_do_not_overflow_the_deadline() {
+        let l = Limits::parse(&format!("timeout_ms={}", u64::MAX), Limits::default()).unwrap();
+        let b = Budget::new(l);
+        assert!(!b.expired());
+        assert_eq!(b.timed_out().requested, u64::MAX);
+    }
+}
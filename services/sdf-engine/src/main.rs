@@ -1,11 +1,18 @@
-use axum::{extract::State, http::StatusCode, response::Json, routing::{get, post}, Router};
+mod budget;
+mod preview;
+
+use sdf_core::{anim, bounds, canon, cave, compiler, diff, eval::Field, eval_bvh, export, expr, fit, fracture, jit, lipschitz, material, math, mesh, meshsdf, optimize, sample, schema, shader, solve, tree, voxel};
+
+use axum::{extract::{DefaultBodyLimit, FromRequestParts, State}, http::{request::Parts, StatusCode}, response::sse::{Event, KeepAlive, Sse}, response::Json, routing::{get, post}, Router};
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tree::{collect_types, count_nodes, tree_depth};
 use std::sync::Arc;
 use std::time::Instant;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
-struct AppState { start_time: Instant }
+struct AppState { start_time: Instant, budget: budget::Limits, jit: jit::Cache }
 
 #[derive(Serialize)]
 struct Health { status: String, version: String, uptime_secs: u64, engine: String }
@@ -16,31 +23,222 @@ struct CompileReq { tree: serde_json::Value }
 struct CompileResp { success: bool, node_count: usize, depth: usize, compile_time_ms: f64 }
 
 #[derive(Deserialize)]
-struct EvalReq { tree: serde_json::Value, points: Vec<[f32; 3]>, #[serde(default = "default_mode")] mode: String }
+struct EvalReq { tree: serde_json::Value, points: Vec<[f32; 3]>, #[serde(default = "default_mode")] mode: String, #[serde(default)] materials: bool, #[serde(default)] time: f64 }
 fn default_mode() -> String { "compiled".into() }
 #[derive(Serialize)]
-struct EvalResp { distances: Vec<f32>, eval_time_ms: f64, point_count: usize, mode: String }
+struct EvalResp {
+    distances: Vec<f32>, eval_time_ms: f64, point_count: usize, mode: String,
+    /// Why the `jit` or `bvh` mode ran on the interpreter instead.
+    #[serde(skip_serializing_if = "Option::is_none")] fallback: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] material_ids: Option<Vec<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")] materials: Option<Vec<material::Material>>,
+}
 
 #[derive(Deserialize)]
 struct ValidateReq { tree: serde_json::Value }
 #[derive(Serialize)]
-struct ValidateResp { valid: bool, node_count: usize, depth: usize, node_types: Vec<String>, errors: Vec<String> }
+struct ValidateResp {
+    valid: bool, node_count: usize, depth: usize, node_types: Vec<String>, errors: Vec<String>,
+    /// Lipschitz bounds, step scale and warnings, for valid trees.
+    #[serde(flatten)] lipschitz: Option<lipschitz::Analysis>,
+}
 
 #[derive(Deserialize)]
-struct MeshReq { tree: serde_json::Value, #[serde(default = "d128")] resolution: usize, #[serde(default = "d_obj")] format: String }
+struct MeshReq {
+    tree: serde_json::Value, #[serde(default = "d128")] resolution: usize, #[serde(default = "d_obj")] format: String,
+    #[serde(default)] time: f64, #[serde(default)] frames: Option<FramesReq>, #[serde(default = "default_mode")] mode: String,
+}
 fn d128() -> usize { 128 }
 fn d_obj() -> String { "obj".into() }
+#[derive(Deserialize)]
+struct FramesReq { start: f64, end: f64, count: usize }
 #[derive(Serialize)]
-struct MeshResp { vertex_count: usize, face_count: usize, format: String, generation_time_ms: f64, #[serde(skip_serializing_if = "Option::is_none")] data_text: Option<String> }
+struct MeshResp {
+    vertex_count: usize, face_count: usize, format: String, generation_time_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")] fallback: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] data_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] data_base64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] mtl_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] frames: Option<Vec<MeshFrame>>,
+}
+#[derive(Serialize)]
+struct MeshFrame {
+    time: f64, vertex_count: usize, face_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")] data_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] mtl_text: Option<String>,
+}
 
 #[derive(Deserialize)]
-struct ShaderReq { tree: serde_json::Value, #[serde(default = "d_wgsl")] target: String }
+struct ShaderReq {
+    tree: serde_json::Value, #[serde(default = "d_wgsl")] target: String,
+    #[serde(default = "d_fps")] fps: f64, #[serde(default)] time_range: Option<[f64; 2]>,
+}
 fn d_wgsl() -> String { "wgsl".into() }
+fn d_fps() -> f64 { 60.0 }
+#[derive(Serialize)]
+struct ShaderResp {
+    target: String, source: String, transpile_time_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")] time_range: Option<[f64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")] samples: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct FractureReq {
+    tree: serde_json::Value,
+    #[serde(default)] seeds: Option<Vec<[f32; 3]>>,
+    #[serde(default = "d8")] seed_count: usize,
+    #[serde(default)] seed: u64,
+    #[serde(default)] inner_noise: Option<InnerNoiseReq>,
+    #[serde(default)] mesh: bool,
+    #[serde(default = "d64")] resolution: usize,
+    #[serde(default = "d_obj")] format: String,
+}
+#[derive(Deserialize)]
+struct InnerNoiseReq { amplitude: f32, #[serde(default = "d1")] frequency: f32, #[serde(default)] seed: u32 }
+fn d8() -> usize { 8 }
+fn d64() -> usize { 64 }
+fn d1() -> f32 { 1.0 }
+#[derive(Serialize)]
+struct FracturePiece { index: usize, seed_point: [f32; 3], tree: serde_json::Value, bounds: bounds::Aabb, #[serde(skip_serializing_if = "Option::is_none")] mesh: Option<PieceMesh> }
+#[derive(Serialize)]
+struct PieceMesh { vertex_count: usize, face_count: usize, watertight: bool, #[serde(skip_serializing_if = "Option::is_none")] data_text: Option<String> }
 #[derive(Serialize)]
-struct ShaderResp { target: String, source: String, transpile_time_ms: f64 }
+struct FractureResp { cell_count: usize, pieces: Vec<FracturePiece>, #[serde(skip_serializing_if = "Option::is_none")] format: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] data_base64: Option<String>, fracture_time_ms: f64 }
 
+#[derive(Deserialize)]
+struct CaveReq {
+    #[serde(default)] bounds: Option<bounds::Aabb>,
+    #[serde(default)] seed: u64,
+    #[serde(default = "d_density")] density: f32,
+    #[serde(default = "d_tunnel")] tunnel_radius: f32,
+    #[serde(default)] chamber_radius: Option<f32>,
+    #[serde(default = "d_conn")] connectivity: f32,
+    #[serde(default)] roughness: Option<f32>,
+    #[serde(default)] target: Option<serde_json::Value>,
+    #[serde(default)] smooth: Option<f32>,
+}
+fn d_density() -> f32 { 0.02 }
+fn d_tunnel() -> f32 { 0.3 }
+fn d_conn() -> f32 { 0.3 }
+#[derive(Serialize)]
+struct CaveResp { cave: serde_json::Value, #[serde(skip_serializing_if = "Option::is_none")] carved: Option<serde_json::Value>, chamber_count: usize, tunnel_count: usize, segment_count: usize, node_count: usize, bounds: bounds::Aabb, generation_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct OptimizeReq { tree: serde_json::Value }
+#[derive(Serialize)]
+struct TreeStats { node_count: usize, depth: usize }
 #[derive(Serialize)]
-struct Err { error: String, #[serde(skip_serializing_if = "Option::is_none")] details: Option<String> }
+struct OptimizeResp { tree: serde_json::Value, changes: Vec<optimize::Change>, empty: bool, before: TreeStats, after: TreeStats, optimize_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct DiffReq { old: serde_json::Value, new: serde_json::Value }
+#[derive(Serialize)]
+struct DiffResp { identical: bool, op_count: usize, patch: diff::TreePatch, diff_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct PatchReq { tree: serde_json::Value, patch: diff::TreePatch, #[serde(default = "d_true")] strict: bool }
+fn d_true() -> bool { true }
+#[derive(Serialize)]
+struct PatchResp { tree: serde_json::Value, applied: usize, conflicts: Vec<diff::Conflict>, valid: bool, errors: Vec<String>, patch_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct ResolveReq { tree: serde_json::Value, #[serde(default)] overrides: serde_json::Map<String, serde_json::Value>, #[serde(default)] time: f64 }
+#[derive(Serialize)]
+struct ResolveResp { tree: serde_json::Value, template: serde_json::Value, variables: std::collections::BTreeMap<String, f64>, resolve_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct SolveReq {
+    tree: serde_json::Value, free: Vec<solve::Free>, constraints: Vec<solve::Constraint>,
+    #[serde(default = "d100")] max_iterations: usize, #[serde(default = "d_tol")] tolerance: f64,
+}
+fn d100() -> usize { 100 }
+fn d_tol() -> f64 { 1e-4 }
+#[derive(Serialize)]
+struct SolveResp { tree: serde_json::Value, converged: bool, iterations: usize, parameters: Vec<solve::Solved>, constraints: Vec<solve::Measured>, solve_time_ms: f64 }
+
+#[derive(Deserialize)]
+struct FromMeshReq {
+    #[serde(default = "d_obj")] format: String, #[serde(default)] data: Option<String>, #[serde(default)] data_base64: Option<String>,
+    #[serde(default = "d_exact")] mode: String, #[serde(default = "d64")] resolution: usize, #[serde(default = "d_band")] band: f32,
+}
+fn d_exact() -> String { "exact".into() }
+fn d_band() -> f32 { 3.0 }
+#[derive(Serialize)]
+struct FromMeshResp {
+    node: serde_json::Value, mode: String, vertex_count: usize, triangle_count: usize, watertight: bool, bounds: bounds::Aabb,
+    #[serde(skip_serializing_if = "Option::is_none")] grid_dims: Option<[usize; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")] stored_bricks: Option<usize>,
+    import_time_ms: f64,
+}
+#[derive(Deserialize)]
+struct FitReq {
+    #[serde(default)] points: Option<Vec<[f32; 3]>>, #[serde(default)] ply: Option<String>, #[serde(default)] ply_base64: Option<String>,
+    #[serde(default = "d_fit_mode")] mode: String, #[serde(default)] primitive: Option<String>, #[serde(default)] primitives: Option<Vec<String>>,
+    #[serde(default = "d4")] max_primitives: usize, #[serde(default = "d50")] max_iterations: usize,
+}
+fn d_fit_mode() -> String { "primitive".into() }
+fn d4() -> usize { 4 }
+fn d50() -> usize { 50 }
+#[derive(Serialize)]
+struct FitResp {
+    tree: serde_json::Value, mode: String, primitive_count: usize, point_count: usize,
+    rms_error: f32, mean_error: f32, max_error: f32, errors: Vec<f32>, iterations: usize, converged: bool, fit_time_ms: f64,
+}
+#[derive(Deserialize)]
+struct SampleReq {
+    tree: serde_json::Value, #[serde(default)] time: f64, #[serde(default)] bounds: Option<bounds::Aabb>, #[serde(default)] seed: u64,
+    #[serde(default)] uniform: usize, #[serde(default)] near_surface: usize, #[serde(default)] sigma: Option<f32>, #[serde(default)] surface: usize,
+    #[serde(default = "d128")] resolution: usize, #[serde(default = "d_json")] format: String,
+}
+fn d_json() -> String { "json".into() }
+#[derive(Serialize)]
+struct SampleResp {
+    format: String, seed: u64, bounds: bounds::Aabb, sigma: f32, uniform_count: usize, near_surface_count: usize, surface_count: usize, sample_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")] points: Option<Vec<[f32; 3]>>,
+    #[serde(skip_serializing_if = "Option::is_none")] distances: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")] normals: Option<Vec<[f32; 3]>>,
+    #[serde(skip_serializing_if = "Option::is_none")] data_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")] data_base64: Option<String>,
+    sample_time_ms: f64,
+}
+#[derive(Deserialize)]
+struct VoxelizeReq {
+    tree: serde_json::Value, #[serde(default)] time: f64, #[serde(default)] bounds: Option<bounds::Aabb>,
+    #[serde(default = "d64")] resolution: usize, #[serde(default = "d_dense")] layout: String, #[serde(default = "d_f32")] precision: String,
+    #[serde(default = "d_raw")] format: String, #[serde(default)] band: Option<f32>,
+}
+fn d_dense() -> String { "dense".into() }
+fn d_f32() -> String { "f32".into() }
+fn d_raw() -> String { "raw".into() }
+#[derive(Serialize)]
+struct VoxelizeResp {
+    layout: String, format: String, precision: String, dims: [usize; 3], origin: [f32; 3], voxel_size: f32, bounds: bounds::Aabb,
+    #[serde(skip_serializing_if = "Option::is_none")] band: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")] brick_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")] brick_dims: Option<[usize; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")] stored_bricks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")] texture_format: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")] texture_dims: Option<[usize; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")] row_pitch: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")] index_row_pitch: Option<usize>,
+    byte_length: usize, data_base64: String,
+    #[serde(skip_serializing_if = "Option::is_none")] index_base64: Option<String>,
+    voxelize_time_ms: f64,
+}
+#[derive(Deserialize)]
+struct CanonicalReq { tree: serde_json::Value, #[serde(default)] time: f64, #[serde(default = "d_sha256")] algorithm: String }
+fn d_sha256() -> String { "sha256".into() }
+#[derive(Serialize)]
+struct CanonicalResp { tree: serde_json::Value, canonical: String, hash: String, algorithm: String, version: u32, canonical_time_ms: f64 }
+
+/// Largest upload `/sdf/from-mesh` accepts, and the most grid samples it bakes.
+const MAX_MESH_TRIANGLES: usize = 100_000;
+const MAX_GRID_VALUES: usize = 1 << 20;
+/// Most samples `/sdf/voxelize` evaluates into one volume (256³).
+const MAX_VOXELS: usize = 1 << 24;
+
+#[derive(Serialize)]
+struct Err { error: String, #[serde(skip_serializing_if = "Option::is_none")] details: Option<String>, #[serde(skip_serializing_if = "Option::is_none")] limit: Option<budget::Exceeded> }
 
 #[tokio::main]
 async fn main() {
@@ -48,7 +246,8 @@ async fn main() {
         tracing_subscriber::EnvFilter::try_from_default_env()
             .unwrap_or_else(|_| "sdf_engine=info,tower_http=info".into()),
     ).init();
-    let state = Arc::new(AppState { start_time: Instant::now() });
+    let limits = std::env::var("SDF_BUDGET").map_or(Ok(budget::Limits::default()), |b| budget::Limits::parse(&b, budget::Limits::default())).expect("SDF_BUDGET");
+    let state = Arc::new(AppState { start_time: Instant::now(), budget: limits, jit: jit::Cache::default() });
     let cors = CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any);
     let app = Router::new()
         .route("/health", get(health))
@@ -56,9 +255,25 @@ async fn main() {
         .route("/api/v1/sdf/eval", post(eval))
         .route("/api/v1/sdf/validate", post(validate))
         .route("/api/v1/mesh/generate", post(mesh_generate))
+        .route("/api/v1/mesh/generate/stream", post(mesh_generate_stream))
         .route("/api/v1/shader/transpile", post(shader_transpile))
         .route("/api/v1/primitives", get(list_primitives))
+        .route("/api/v1/sdf/fracture", post(fracture_handler))
+        .route("/api/v1/sdf/cave", post(cave_handler))
+        .route("/api/v1/sdf/optimize", post(optimize_handler))
+        .route("/api/v1/sdf/diff", post(diff_handler))
+        .route("/api/v1/sdf/patch", post(patch_handler))
+        .route("/api/v1/sdf/resolve", post(resolve_handler))
+        .route("/api/v1/sdf/solve", post(solve_handler))
+        .route("/api/v1/sdf/from-mesh", post(from_mesh_handler))
+        .route("/api/v1/sdf/fit", post(fit_handler))
+        .route("/api/v1/sdf/sample", post(sample_handler))
+        .route("/api/v1/sdf/voxelize", post(voxelize_handler))
+        .route("/api/v1/sdf/canonical", post(canonical_handler))
         .route("/api/v1/export", post(export))
+        .route("/ws/sdf/preview", get(preview::handler))
+        // Mesh-backed trees are large; match the gateway's 10 MiB cap.
+        .layer(DefaultBodyLimit::max(10 * 1024 * 1024))
         .layer(cors).layer(TraceLayer::new_for_http()).with_state(state);
     let addr = std::env::var("SDF_ENGINE_ADDR").unwrap_or_else(|_| "0.0.0.0:8081".into());
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
@@ -67,113 +282,693 @@ async fn main() {
 }
 
 async fn health(State(s): State<Arc<AppState>>) -> Json<Health> {
-    Json(Health { status: "ok".into(), version: env!("CARGO_PKG_VERSION").into(), uptime_secs: s.start_time.elapsed().as_secs(), engine: "ALICE-SDF stub".into() })
+    Json(Health { status: "ok".into(), version: env!("CARGO_PKG_VERSION").into(), uptime_secs: s.start_time.elapsed().as_secs(), engine: "sdf-engine compiled evaluator".into() })
 }
 
-fn count_nodes(t: &serde_json::Value) -> usize {
-    let mut c = 1;
-    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { c += count_nodes(ch); } }
-    if let Some(arr) = t.get("children").and_then(|v| v.as_array()) { for ch in arr { c += count_nodes(ch); } }
-    c
+async fn validate(b: budget::Budget, Json(r): Json<ValidateReq>) -> Result<Json<ValidateResp>, (StatusCode, Json<Err>)> {
+    tree_budget(&b, &r.tree)?;
+    let nc = count_nodes(&r.tree); let d = tree_depth(&r.tree); let nt = collect_types(&r.tree);
+    let errs = schema::validate(&r.tree);
+    let lipschitz = if errs.is_empty() { tree::SdfNode::from_json(&r.tree).ok().map(|n| lipschitz::analyze(&n)) } else { None };
+    Ok(Json(ValidateResp { valid: errs.is_empty(), node_count: nc, depth: d, node_types: nt, errors: errs, lipschitz }))
 }
 
-fn tree_depth(t: &serde_json::Value) -> usize {
-    let mut mx = 0;
-    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { mx = mx.max(tree_depth(ch)); } }
-    if let Some(arr) = t.get("children").and_then(|v| v.as_array()) { for ch in arr { mx = mx.max(tree_depth(ch)); } }
-    1 + mx
+async fn compile(b: budget::Budget, Json(r): Json<CompileReq>) -> Result<Json<CompileResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    parse_and_compile(&r.tree)?;
+    Ok(Json(CompileResp { success: true, node_count: count_nodes(&r.tree), depth: tree_depth(&r.tree), compile_time_ms: st.elapsed().as_secs_f64() * 1000.0 }))
 }
 
-fn collect_types(t: &serde_json::Value) -> Vec<String> {
-    let mut v = Vec::new();
-    if let Some(s) = t.get("type").and_then(|x| x.as_str()) { v.push(s.into()); }
-    for k in &["a","b","child"] { if let Some(ch) = t.get(k) { v.extend(collect_types(ch)); } }
-    if let Some(arr) = t.get("children").and_then(|x| x.as_array()) { for ch in arr { v.extend(collect_types(ch)); } }
-    v
+async fn eval(State(s): State<Arc<AppState>>, b: budget::Budget, Json(r): Json<EvalReq>) -> Result<Json<EvalResp>, (StatusCode, Json<Err>)> {
+    blocking(move || eval_blocking(&s, b, r)).await
 }
 
-async fn validate(Json(r): Json<ValidateReq>) -> Json<ValidateResp> {
-    let nc = count_nodes(&r.tree); let d = tree_depth(&r.tree); let nt = collect_types(&r.tree);
-    let mut errs = Vec::new();
-    if r.tree.get("type").is_none() { errs.push("Root missing 'type'".into()); }
-    Json(ValidateResp { valid: errs.is_empty(), node_count: nc, depth: d, node_types: nt, errors: errs })
+fn eval_blocking(s: &AppState, b: budget::Budget, r: EvalReq) -> Result<Json<EvalResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    b.points(r.points.len()).map_err(over_budget)?;
+    check_mode(&r.mode)?;
+    let (node, sdf) = parse_and_compile_at(&r.tree, r.time)?;
+    let (dists, material_ids, materials, fallback) = if r.materials {
+        let (d, ids) = sdf.eval_material_batch(&r.points).into_iter().map(|(d, s)| (d, s.id)).unzip();
+        let fallback = matches!(r.mode.as_str(), "jit" | "bvh").then(|| "materials are evaluated by the interpreter".to_string());
+        (d, Some(ids), Some(sdf.materials.clone()), fallback)
+    } else {
+        let (field, fallback) = sampler(&r.mode, &s.jit, &node, &sdf);
+        (field.get().eval_batch(&r.points), None, None, fallback)
+    };
+    let elapsed = st.elapsed();
+    Ok(Json(EvalResp { point_count: dists.len(), distances: dists, eval_time_ms: elapsed.as_secs_f64()*1000.0, mode: r.mode, fallback, material_ids, materials }))
+}
+
+fn check_mode(mode: &str) -> Result<(), (StatusCode, Json<Err>)> {
+    if matches!(mode, "compiled" | "default" | "jit" | "bvh") { return Ok(()); }
+    Err(bad_request("Unknown mode", format!("'{mode}' is not one of compiled, default, jit, bvh")))
+}
+
+/// What samples a tree: the interpreter, native code or a BVH over its union.
+enum Sampler<'a> { Compiled(&'a compiler::CompiledSdf), Jit(Arc<jit::JitSdf>), Bvh(eval_bvh::BvhSdf) }
+
+impl Sampler<'_> {
+    fn get(&self) -> &dyn Field {
+        match self { Sampler::Compiled(s) => *s, Sampler::Jit(j) => &**j, Sampler::Bvh(b) => b }
+    }
+}
+
+/// Sampler for `mode`, and why the interpreter stands in when it cannot be built.
+fn sampler<'a>(mode: &str, jit: &jit::Cache, node: &tree::SdfNode, sdf: &'a compiler::CompiledSdf) -> (Sampler<'a>, Option<String>) {
+    let built = match mode {
+        "jit" => jit.get(node, sdf).map(Sampler::Jit),
+        "bvh" => eval_bvh::BvhSdf::new(node).map(Sampler::Bvh),
+        _ => Ok(Sampler::Compiled(sdf)),
+    };
+    built.map_or_else(|e| (Sampler::Compiled(sdf), Some(e)), |s| (s, None))
+}
+
+async fn mesh_generate(State(s): State<Arc<AppState>>, b: budget::Budget, Json(r): Json<MeshReq>) -> Result<Json<MeshResp>, (StatusCode, Json<Err>)> {
+    let times = mesh_times(&b, &r)?;
+    blocking(move || build_mesh(r, &times, &b, &s.jit, &mut |_| true).map(Json)).await
+}
+
+/// Server-sent events for a mesh request: `progress` after every slab of cells,
+/// then `done` with the `/mesh/generate` response or `error`. Generation stops
+/// at the next slab once the client disconnects.
+async fn mesh_generate_stream(State(s): State<Arc<AppState>>, b: budget::Budget, Json(r): Json<MeshReq>) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<Err>)> {
+    let times = mesh_times(&b, &r)?;
+    parse_and_compile_at(&r.tree, times[0])?;
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Event>();
+    tokio::task::spawn_blocking(move || {
+        let st = Instant::now();
+        let out = build_mesh(r, &times, &b, &s.jit, &mut |p| {
+            let elapsed = st.elapsed().as_secs_f64() * 1000.0;
+            let eta = elapsed * (p.slabs_total.saturating_sub(p.slabs_done)) as f64 / p.slabs_done.max(1) as f64;
+            let ev = Event::default().event("progress").json_data(serde_json::json!({ "slabs_done": p.slabs_done, "slabs_total": p.slabs_total, "triangles": p.triangles, "elapsed_ms": elapsed, "eta_ms": eta }));
+            tx.send(ev.unwrap_or_default()).is_ok()
+        });
+        let ev = match out { Ok(resp) => Event::default().event("done").json_data(resp), Err((_, Json(e))) => Event::default().event("error").json_data(e) };
+        let _ = tx.send(ev.unwrap_or_default());
+    });
+    let events = futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|e| (Ok(e), rx)) });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Times a mesh request covers: `time`, or the evenly spaced `frames`.
+fn mesh_times(b: &budget::Budget, r: &MeshReq) -> Result<Vec<f64>, (StatusCode, Json<Err>)> {
+    tree_budget(b, &r.tree)?;
+    b.resolution(r.resolution).map_err(over_budget)?;
+    check_mode(&r.mode)?;
+    if !matches!(r.format.as_str(), "obj" | "stl" | "ply" | "glb") {
+        return Err(bad_request("Unknown format", format!("'{}' is not one of obj, stl, ply, glb", r.format)));
+    }
+    let Some(f) = &r.frames else { return Ok(vec![r.time]) };
+    if f.count == 0 || f.count > 240 { return Err(bad_request("Invalid frames", "frames.count must be in 1..=240".into())); }
+    if !(f.start.is_finite() && f.end.is_finite() && f.start <= f.end) { return Err(bad_request("Invalid frames", "frames.start and frames.end must be finite with start <= end".into())); }
+    Ok((0..f.count).map(|i| if f.count == 1 { f.start } else { f.start + (f.end - f.start) * i as f64 / (f.count - 1) as f64 }).collect())
 }
 
-async fn compile(Json(r): Json<CompileReq>) -> Result<Json<CompileResp>, (StatusCode, Json<Err>)> {
+/// Mesh or mesh sequence for `r` at `times`, reporting slabs summed over frames.
+fn build_mesh(r: MeshReq, times: &[f64], b: &budget::Budget, jit: &jit::Cache, progress: &mut dyn FnMut(mesh::Progress) -> bool) -> Result<MeshResp, (StatusCode, Json<Err>)> {
+    use base64::Engine;
     let st = Instant::now();
-    if r.tree.get("type").is_none() {
-        return Err((StatusCode::BAD_REQUEST, Json(Err { error: "Missing type".into(), details: None })));
+    let mut meshes: Vec<(String, mesh::Mesh)> = Vec::with_capacity(times.len());
+    let mut fallback = None;
+    for (i, &t) in times.iter().enumerate() {
+        let before: usize = meshes.iter().map(|(_, m)| m.face_count()).sum();
+        let (name, m, why) = mesh_at(&r.tree, t, r.resolution, &r.mode, b, jit, &mut |p| progress(mesh::Progress {
+            slabs_done: i * p.slabs_total + p.slabs_done, slabs_total: times.len() * p.slabs_total, triangles: before + p.triangles,
+        }))?;
+        fallback = fallback.or(why);
+        meshes.push((name, m));
+    }
+    let ms = || st.elapsed().as_secs_f64() * 1000.0;
+    if r.frames.is_none() {
+        let (name, m) = &meshes[0];
+        let (data_text, data_base64, mtl_text) = encode_mesh(&r.format, name, m);
+        return Ok(MeshResp { vertex_count: m.vertex_count(), face_count: m.face_count(), format: r.format, generation_time_ms: ms(), fallback, data_text, data_base64, mtl_text, frames: None });
+    }
+    let (vertex_count, face_count) = meshes.iter().fold((0, 0), |(v, f), (_, m)| (v + m.vertex_count(), f + m.face_count()));
+    let (frames, data_base64) = if r.format == "glb" {
+        let objects: Vec<(String, &mesh::Mesh)> = meshes.iter().enumerate().map(|(i, (name, m))| (format!("{name}_frame_{i:04}"), m)).collect();
+        let glb = export::to_glb_sequence(&objects, &times.iter().map(|&t| t as f32).collect::<Vec<_>>());
+        let frames = times.iter().zip(&meshes).map(|(&time, (_, m))| MeshFrame { time, vertex_count: m.vertex_count(), face_count: m.face_count(), data_text: None, mtl_text: None }).collect();
+        (frames, Some(base64::engine::general_purpose::STANDARD.encode(glb)))
+    } else {
+        let frames = times.iter().zip(&meshes).map(|(&time, (name, m))| {
+            let (data_text, _, mtl_text) = encode_mesh(&r.format, name, m);
+            MeshFrame { time, vertex_count: m.vertex_count(), face_count: m.face_count(), data_text, mtl_text }
+        }).collect();
+        (frames, None)
+    };
+    Ok(MeshResp { vertex_count, face_count, format: r.format, generation_time_ms: ms(), fallback, data_text: None, data_base64, mtl_text: None, frames: Some(frames) })
+}
+
+/// A frame's name, mesh, and why the interpreter sampled it instead of `mode`.
+type Frame = (String, mesh::Mesh, Option<String>);
+
+/// Painted mesh of `tree` at `time`, named after its root type, sampled as
+/// `mode` asks. Unions of many parts go through a BVH unless the JIT was asked for.
+fn mesh_at(tree: &serde_json::Value, time: f64, resolution: usize, mode: &str, b: &budget::Budget, jit: &jit::Cache, progress: &mut dyn FnMut(mesh::Progress) -> bool) -> Result<Frame, (StatusCode, Json<Err>)> {
+    let (node, sdf) = parse_and_compile_at(tree, time)?;
+    let domain = domain_of(&node)?;
+    let (field, fallback) = match sampler(mode, jit, &node, &sdf) {
+        (Sampler::Compiled(_), None) => (eval_bvh::BvhSdf::for_large(&node).map_or(Sampler::Compiled(&sdf), Sampler::Bvh), None),
+        s => s,
+    };
+    let mut m = match mesh::generate(field.get(), &domain, resolution, &mut |p| !b.expired() && progress(p)) {
+        Some(m) => m,
+        None if b.expired() => return Err(over_budget(b.timed_out())),
+        None => return Err(bad_request("Cancelled", "mesh generation was cancelled".into())),
+    };
+    mesh::paint(&mut m, &sdf);
+    Ok((node.ty, m, fallback))
+}
+
+/// Encoded mesh as (data_text, data_base64, mtl_text).
+fn encode_mesh(format: &str, name: &str, m: &mesh::Mesh) -> (Option<String>, Option<String>, Option<String>) {
+    use base64::Engine;
+    let mtl_text = (format == "obj" && !m.face_materials.is_empty()).then(|| export::to_mtl(m));
+    match format {
+        "obj" => (Some(export::to_obj(name, m)), None, mtl_text),
+        "stl" => (Some(export::to_stl(name, m)), None, None),
+        "ply" => (Some(export::to_ply(m)), None, None),
+        _ => (None, Some(base64::engine::general_purpose::STANDARD.encode(export::to_glb(&[(name.to_string(), m)]))), None),
     }
-    Ok(Json(CompileResp { success: true, node_count: count_nodes(&r.tree), depth: tree_depth(&r.tree), compile_time_ms: st.elapsed().as_secs_f64() * 1000.0 }))
 }
 
-async fn eval(Json(r): Json<EvalReq>) -> Result<Json<EvalResp>, (StatusCode, Json<Err>)> {
+async fn shader_transpile(budget: budget::Budget, Json(r): Json<ShaderReq>) -> Result<Json<ShaderResp>, (StatusCode, Json<Err>)> {
+    blocking(move || shader_transpile_blocking(budget, r)).await
+}
+
+fn shader_transpile_blocking(budget: budget::Budget, r: ShaderReq) -> Result<Json<ShaderResp>, (StatusCode, Json<Err>)> {
     let st = Instant::now();
-    let nt = r.tree.get("type").and_then(|t| t.as_str()).unwrap_or("Sphere");
-    let params = r.tree.get("params").cloned().unwrap_or(serde_json::json!({}));
-    let dists: Vec<f32> = match nt {
-        "Sphere" => { let rad = params.get("radius").and_then(|v| v.as_f64()).unwrap_or(1.0) as f32; r.points.iter().map(|p| (p[0]*p[0]+p[1]*p[1]+p[2]*p[2]).sqrt() - rad).collect() }
-        "Box3d" => { let h = [0.5f32, 0.5, 0.5]; r.points.iter().map(|p| { let q = [p[0].abs()-h[0], p[1].abs()-h[1], p[2].abs()-h[2]]; (q[0].max(0.0).powi(2)+q[1].max(0.0).powi(2)+q[2].max(0.0).powi(2)).sqrt() + q[0].max(q[1]).max(q[2]).min(0.0) }).collect() }
-        "Plane" => r.points.iter().map(|p| p[1]).collect(),
-        _ => r.points.iter().map(|p| (p[0]*p[0]+p[1]*p[1]+p[2]*p[2]).sqrt() - 1.0).collect(),
+    tree_budget(&budget, &r.tree)?;
+    let target = shader::Target::parse(&r.target).ok_or_else(|| bad_request("Unknown target", format!("'{}' is not one of wgsl, glsl, hlsl", r.target)))?;
+    let range = r.time_range.map(|[a, b]| (a, b)).or_else(|| anim::key_range(&r.tree));
+    let Some((a, b)) = range else {
+        let (_, sdf) = parse_and_compile(&r.tree)?;
+        return Ok(Json(ShaderResp { target: r.target, source: shader::transpile(&sdf, target), transpile_time_ms: st.elapsed().as_secs_f64()*1000.0, time_range: None, samples: None }));
     };
-    let elapsed = st.elapsed();
-    Ok(Json(EvalResp { point_count: dists.len(), distances: dists, eval_time_ms: elapsed.as_secs_f64()*1000.0, mode: r.mode }))
+    if !(a.is_finite() && b.is_finite() && a <= b) { return Err(bad_request("Invalid time_range", "time_range must be [start, end] with start <= end".into())); }
+    if !(r.fps > 0.0 && r.fps <= 1000.0) { return Err(bad_request("Invalid fps", "fps must be in (0, 1000]".into())); }
+    // One sample per frame, capped; the shader interpolates linearly in between.
+    let n = (((b - a) * r.fps).ceil() as usize + 1).clamp(2, 1024);
+    let dt = (b - a) / (n - 1) as f64;
+    let samples = (0..n).map(|i| {
+        if budget.expired() { return Err(over_budget(budget.timed_out())); }
+        parse_and_compile_at(&r.tree, a + dt * i as f64).map(|(_, s)| s)
+    }).collect::<Result<Vec<_>, _>>()?;
+    let src = shader::transpile_animated(&samples, a as f32, dt as f32, target).map_err(|e| unprocessable("Not animatable", e))?;
+    Ok(Json(ShaderResp { target: r.target, source: src, transpile_time_ms: st.elapsed().as_secs_f64()*1000.0, time_range: Some([a, b]), samples: Some(n) }))
+}
+
+async fn export(s: State<Arc<AppState>>, b: budget::Budget, Json(r): Json<MeshReq>) -> Result<Json<MeshResp>, (StatusCode, Json<Err>)> {
+    mesh_generate(s, b, Json(r)).await
+}
+
+fn bad_request(error: &str, details: String) -> (StatusCode, Json<Err>) {
+    (StatusCode::BAD_REQUEST, Json(Err { error: error.into(), details: Some(details), limit: None }))
+}
+
+fn unprocessable(error: &str, details: String) -> (StatusCode, Json<Err>) {
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(Err { error: error.into(), details: Some(details), limit: None }))
+}
+
+/// 413 for a request larger than the caller's plan allows, 422 when it ran out of time.
+fn over_budget(e: budget::Exceeded) -> (StatusCode, Json<Err>) {
+    let code = if e.name == "timeout_ms" { StatusCode::UNPROCESSABLE_ENTITY } else { StatusCode::PAYLOAD_TOO_LARGE };
+    (code, Json(Err { error: "Budget exceeded".into(), details: Some(format!("{} exceeds {} ({})", e.requested, e.name, e.max)), limit: Some(e) }))
+}
+
+/// Limits from the gateway's `X-SDF-Budget` header over the engine defaults.
+#[axum::async_trait]
+impl FromRequestParts<Arc<AppState>> for budget::Budget {
+    type Rejection = (StatusCode, Json<Err>);
+    async fn from_request_parts(parts: &mut Parts, s: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let limits = match parts.headers.get(budget::HEADER) {
+            None => s.budget,
+            Some(h) => budget::Limits::parse(h.to_str().unwrap_or("?"), s.budget).map_err(|e| bad_request("Invalid budget", e))?,
+        };
+        Ok(budget::Budget::new(limits))
+    }
+}
+
+/// Run a handler's CPU-bound work on the blocking pool, off the async workers.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, (StatusCode, Json<Err>)> + Send + 'static) -> Result<T, (StatusCode, Json<Err>)> {
+    tokio::task::spawn_blocking(f).await.unwrap_or_else(|e| Err((StatusCode::INTERNAL_SERVER_ERROR, Json(Err { error: "Internal error".into(), details: Some(e.to_string()), limit: None }))))
+}
+
+/// Reject trees with more nodes than the budget allows.
+fn tree_budget(b: &budget::Budget, tree: &serde_json::Value) -> Result<(), (StatusCode, Json<Err>)> { b.nodes(count_nodes(tree)).map_err(over_budget) }
+
+fn parse_and_compile(tree: &serde_json::Value) -> Result<(tree::SdfNode, compiler::CompiledSdf), (StatusCode, Json<Err>)> { parse_and_compile_at(tree, 0.0) }
+
+/// Parse with keyframe curves sampled at `time`, then compile.
+fn parse_and_compile_at(tree: &serde_json::Value, time: f64) -> Result<(tree::SdfNode, compiler::CompiledSdf), (StatusCode, Json<Err>)> {
+    let node = tree::SdfNode::from_json_at(tree, time).map_err(|e| bad_request("Invalid tree", e))?;
+    let sdf = compiler::compile(&node).map_err(|e| bad_request("Compile failed", e))?;
+    Ok((node, sdf))
+}
+
+/// Finite meshing/sampling domain for a tree; unbounded trees fall back to the [-5, 5] modeling range.
+fn domain_of(node: &tree::SdfNode) -> Result<bounds::Aabb, (StatusCode, Json<Err>)> {
+    let b = bounds::bounds(node).clamp_to(5.0);
+    if b.is_empty() { return Err(unprocessable("Empty tree", "tree bounds are empty".into())); }
+    Ok(b)
+}
+
+async fn fracture_handler(b: budget::Budget, Json(r): Json<FractureReq>) -> Result<Json<FractureResp>, (StatusCode, Json<Err>)> {
+    blocking(move || fracture_blocking(b, r)).await
 }
 
-async fn mesh_generate(Json(r): Json<MeshReq>) -> Result<Json<MeshResp>, (StatusCode, Json<Err>)> {
+fn fracture_blocking(b: budget::Budget, r: FractureReq) -> Result<Json<FractureResp>, (StatusCode, Json<Err>)> {
+    use base64::Engine;
     let st = Instant::now();
-    let nt = r.tree.get("type").and_then(|t| t.as_str()).unwrap_or("Sphere");
-    let res = r.resolution.min(64);
-    let mut verts = Vec::new();
-    let mut faces = Vec::new();
-    let rad = r.tree.get("params").and_then(|p| p.get("radius")).and_then(|r| r.as_f64()).unwrap_or(1.0) as f32;
-    for i in 0..=res { for j in 0..=res {
-        let phi = std::f32::consts::PI * i as f32 / res as f32;
-        let theta = 2.0 * std::f32::consts::PI * j as f32 / res as f32;
-        verts.push((rad*phi.sin()*theta.cos(), rad*phi.cos(), rad*phi.sin()*theta.sin()));
-    }}
-    for i in 0..res { for j in 0..res {
-        let a = i*(res+1)+j; let b = a+res+1;
-        faces.push((a,b,a+1)); faces.push((a+1,b,b+1));
-    }}
-    let vc = verts.len(); let fc = faces.len();
-    let obj = if r.format == "obj" {
-        let mut s = format!("# AI Modeler - {nt}\n# V:{vc} F:{fc}\n");
-        for v in &verts { s += &format!("v {:.6} {:.6} {:.6}\n", v.0, v.1, v.2); }
-        for f in &faces { s += &format!("f {} {} {}\n", f.0+1, f.1+1, f.2+1); }
-        Some(s)
-    } else { None };
-    Ok(Json(MeshResp { vertex_count: vc, face_count: fc, format: r.format, generation_time_ms: st.elapsed().as_secs_f64()*1000.0, data_text: obj }))
+    tree_budget(&b, &r.tree)?;
+    if r.mesh { b.resolution(r.resolution).map_err(over_budget)?; }
+    let (node, sdf) = parse_and_compile(&r.tree)?;
+    let domain = domain_of(&node)?;
+    let seeds: Vec<[f32; 3]> = match r.seeds {
+        Some(s) if !s.is_empty() => s,
+        _ => {
+            if r.seed_count == 0 || r.seed_count > 256 { return Err(bad_request("Invalid seed_count", "seed_count must be in 1..=256".into())); }
+            fracture::sample_seeds(&sdf, &domain, r.seed_count, r.seed).map_err(|e| unprocessable("Seed placement failed", e))?
+        }
+    };
+    if seeds.len() > 256 { return Err(bad_request("Too many seeds", format!("{} seeds given, at most 256 allowed", seeds.len()))); }
+    if r.mesh && !matches!(r.format.as_str(), "obj" | "stl" | "ply" | "glb") {
+        return Err(bad_request("Unknown format", format!("'{}' is not one of obj, stl, ply, glb", r.format)));
+    }
+    let noise = r.inner_noise.map(|n| fracture::InnerNoise { amplitude: n.amplitude, frequency: n.frequency, seed: n.seed });
+    let cells = fracture::fracture(&node, &domain, &seeds, noise.as_ref());
+    let meshes: Vec<Option<mesh::Mesh>> = if r.mesh {
+        use rayon::prelude::*;
+        let meshes: Vec<_> = cells.par_iter().map(|c| compiler::compile(&c.tree).ok().and_then(|s| mesh::generate(&s, &bounds::bounds(&c.tree).intersect(&domain), r.resolution, &mut |_| !b.expired()))).collect();
+        if b.expired() { return Err(over_budget(b.timed_out())); }
+        meshes
+    } else { vec![None; cells.len()] };
+    let data_base64 = (r.mesh && r.format == "glb").then(|| {
+        let objs: Vec<(String, &mesh::Mesh)> = meshes.iter().enumerate().filter_map(|(i, m)| m.as_ref().map(|m| (format!("piece_{i}"), m))).collect();
+        base64::engine::general_purpose::STANDARD.encode(export::to_glb(&objs))
+    });
+    let pieces = cells.iter().zip(&meshes).enumerate().map(|(i, (c, m))| FracturePiece {
+        index: i, seed_point: c.seed, tree: c.tree.to_json(), bounds: bounds::bounds(&c.tree).intersect(&domain),
+        mesh: m.as_ref().map(|m| PieceMesh {
+            vertex_count: m.vertex_count(), face_count: m.face_count(), watertight: m.is_watertight(),
+            data_text: match r.format.as_str() {
+                "obj" => Some(export::to_obj(&format!("piece_{i}"), m)),
+                "stl" => Some(export::to_stl(&format!("piece_{i}"), m)),
+                "ply" => Some(export::to_ply(m)),
+                _ => None,
+            },
+        }),
+    }).collect();
+    Ok(Json(FractureResp { cell_count: cells.len(), pieces, format: r.mesh.then_some(r.format), data_base64, fracture_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+}
+
+async fn cave_handler(b: budget::Budget, Json(r): Json<CaveReq>) -> Result<Json<CaveResp>, (StatusCode, Json<Err>)> {
+    blocking(move || cave_blocking(b, r)).await
 }
 
-async fn shader_transpile(Json(r): Json<ShaderReq>) -> Result<Json<ShaderResp>, (StatusCode, Json<Err>)> {
+fn cave_blocking(b: budget::Budget, r: CaveReq) -> Result<Json<CaveResp>, (StatusCode, Json<Err>)> {
     let st = Instant::now();
-    let nt = r.tree.get("type").and_then(|t| t.as_str()).unwrap_or("Sphere");
-    let src = match r.target.as_str() {
-        "wgsl" => format!("// WGSL - {nt}\nfn sdf_scene(p: vec3<f32>) -> f32 {{ return length(p) - 1.0; }}\n@fragment fn fs(@location(0) wp: vec3<f32>) -> @location(0) vec4<f32> {{ let d = sdf_scene(wp); return vec4<f32>(vec3<f32>(0.5-d*0.5), 1.0); }}"),
-        "glsl" => format!("// GLSL - {nt}\n#version 450\nfloat sdf(vec3 p) {{ return length(p)-1.0; }}"),
-        "hlsl" => format!("// HLSL - {nt}\nfloat sdf(float3 p) {{ return length(p)-1.0; }}"),
-        _ => return Err((StatusCode::BAD_REQUEST, Json(Err { error: format!("Unknown target: {}", r.target), details: None }))),
+    if let Some(t) = &r.target { tree_budget(&b, t)?; }
+    if r.tunnel_radius.is_nan() || r.tunnel_radius <= 0.0 { return Err(bad_request("Invalid tunnel_radius", "tunnel_radius must be > 0".into())); }
+    if r.density.is_nan() || r.density <= 0.0 { return Err(bad_request("Invalid density", "density must be > 0".into())); }
+    let host = match &r.target { Some(t) => Some(parse_and_compile(t)?), None => None };
+    let region = match (r.bounds, &host) {
+        (Some(b), _) => b,
+        (None, Some((node, _))) => domain_of(node)?,
+        (None, None) => bounds::Aabb::sym([5.0; 3]),
+    };
+    if region.is_empty() || !region.is_finite() { return Err(bad_request("Invalid bounds", "bounds must be finite with min <= max".into())); }
+    let params = cave::CaveParams {
+        seed: r.seed, density: r.density, tunnel_radius: r.tunnel_radius,
+        chamber_radius: r.chamber_radius.unwrap_or(r.tunnel_radius * 3.0),
+        connectivity: r.connectivity.clamp(0.0, 1.0),
+        roughness: r.roughness.unwrap_or(r.tunnel_radius * 0.3),
     };
-    Ok(Json(ShaderResp { target: r.target, source: src, transpile_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+    let c = cave::generate(&region, &params, host.as_ref().map(|(_, sdf)| sdf));
+    let carved = host.map(|(node, _)| match r.smooth {
+        Some(k) if k > 0.0 => tree::SdfNode::binary("SmoothSubtraction", serde_json::json!({ "k": k }), node, c.tree.clone()),
+        _ => tree::SdfNode::binary("Subtraction", serde_json::json!({}), node, c.tree.clone()),
+    }.to_json());
+    let node_count = count_nodes(&c.tree.to_json());
+    b.nodes(node_count).map_err(over_budget)?;
+    Ok(Json(CaveResp { cave: c.tree.to_json(), carved, chamber_count: c.chambers, tunnel_count: c.tunnels, segment_count: c.segments, node_count, bounds: region, generation_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
 }
 
-async fn export(Json(r): Json<MeshReq>) -> Result<Json<MeshResp>, (StatusCode, Json<Err>)> {
-    mesh_generate(Json(r)).await
+async fn optimize_handler(b: budget::Budget, Json(r): Json<OptimizeReq>) -> Result<Json<OptimizeResp>, (StatusCode, Json<Err>)> {
+    blocking(move || optimize_blocking(b, r)).await
+}
+
+fn optimize_blocking(b: budget::Budget, r: OptimizeReq) -> Result<Json<OptimizeResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    // Rewrites fold params into each other, which would flatten keyframe curves to their t=0 values.
+    if let Some((a, z)) = anim::key_range(&r.tree) {
+        return Err(unprocessable("Animated tree", format!("the tree has keyframe curves over t={a}..{z}; resolve it at a time with /resolve before optimizing")));
+    }
+    // Likewise expressions would be replaced by their values, losing the template /solve works on.
+    if let Some(vars) = r.tree.get("variables").and_then(|v| v.as_object()).filter(|v| !v.is_empty()) {
+        let names: Vec<&str> = vars.keys().map(String::as_str).collect();
+        return Err(unprocessable("Parametric tree", format!("the tree declares variables ({}); resolve it with /resolve before optimizing", names.join(", "))));
+    }
+    let (node, _) = parse_and_compile(&r.tree)?;
+    let before = TreeStats { node_count: count_nodes(&r.tree), depth: tree_depth(&r.tree) };
+    let node = if node.has_materials() { node.materials_to_leaves(None) } else { node };
+    let o = optimize::optimize(&node);
+    let out = o.tree.to_json();
+    let after = TreeStats { node_count: count_nodes(&out), depth: tree_depth(&out) };
+    Ok(Json(OptimizeResp { tree: out, changes: o.changes, empty: o.empty, before, after, optimize_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+}
+
+async fn diff_handler(b: budget::Budget, Json(r): Json<DiffReq>) -> Result<Json<DiffResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.old)?;
+    tree_budget(&b, &r.new)?;
+    let patch = diff::diff(&r.old, &r.new);
+    Ok(Json(DiffResp { identical: patch.ops.is_empty(), op_count: patch.ops.len(), patch, diff_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+}
+
+/// Strict mode (default) applies nothing when any op conflicts and answers 409;
+/// otherwise conflicting ops are skipped and the rest are applied.
+async fn patch_handler(b: budget::Budget, Json(r): Json<PatchReq>) -> Result<(StatusCode, Json<PatchResp>), (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    // Each op walks the tree, so a patch may carry at most max_nodes of them.
+    b.nodes(r.patch.ops.len()).map_err(over_budget)?;
+    let (patched, conflicts) = diff::apply(&r.tree, &r.patch);
+    tree_budget(&b, &patched)?;
+    let rejected = r.strict && !conflicts.is_empty();
+    let out = if rejected { r.tree } else { patched };
+    let errors: Vec<String> = match tree::SdfNode::from_json(&out).and_then(|n| compiler::compile(&n)) { Ok(_) => vec![], Err(e) => vec![e] };
+    let applied = if rejected { 0 } else { r.patch.ops.len() - conflicts.len() };
+    let code = if rejected { StatusCode::CONFLICT } else { StatusCode::OK };
+    Ok((code, Json(PatchResp { tree: out, applied, conflicts, valid: errors.is_empty(), errors, patch_time_ms: st.elapsed().as_secs_f64()*1000.0 })))
+}
+
+/// Re-resolve a parametric tree with variable overrides. `tree` is the literal
+/// result, `template` the input with the overrides written into its `variables`.
+async fn resolve_handler(b: budget::Budget, Json(r): Json<ResolveReq>) -> Result<Json<ResolveResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    let vars = expr::Vars::declare(r.tree.get("variables"), &r.overrides, r.time).map_err(|e| bad_request("Invalid variables", e))?;
+    let node = tree::SdfNode::from_json_with(&r.tree, &vars).map_err(|e| bad_request("Invalid tree", e))?;
+    let errs = schema::check(&node);
+    if !errs.is_empty() { return Err(unprocessable("Invalid parameters", errs.join("; "))); }
+    compiler::compile(&node).map_err(|e| unprocessable("Invalid parameters", e))?;
+    let mut template = r.tree;
+    if let Some(block) = template.get_mut("variables").and_then(|v| v.as_object_mut()) { block.extend(r.overrides); }
+    Ok(Json(ResolveResp { tree: node.to_json(), template, variables: vars.values, resolve_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+}
+
+async fn solve_handler(b: budget::Budget, Json(r): Json<SolveReq>) -> Result<Json<SolveResp>, (StatusCode, Json<Err>)> {
+    blocking(move || solve_blocking(b, r)).await
+}
+
+fn solve_blocking(b: budget::Budget, r: SolveReq) -> Result<Json<SolveResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    if r.max_iterations == 0 || r.max_iterations > 1000 { return Err(bad_request("Invalid max_iterations", "max_iterations must be in 1..=1000".into())); }
+    if r.tolerance <= 0.0 { return Err(bad_request("Invalid tolerance", "tolerance must be > 0".into())); }
+    parse_and_compile(&r.tree)?;
+    let problem = solve::Problem::new(&r.tree, r.free, r.constraints).map_err(|e| bad_request("Invalid problem", e))?;
+    let s = problem.solve(r.max_iterations, r.tolerance, &|| b.expired()).map_err(|e| unprocessable("Solve failed", e))?;
+    if b.expired() { return Err(over_budget(b.timed_out())); }
+    Ok(Json(SolveResp { tree: s.tree, converged: s.converged, iterations: s.iterations, parameters: s.parameters, constraints: s.constraints, solve_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
+}
+
+async fn from_mesh_handler(b: budget::Budget, Json(r): Json<FromMeshReq>) -> Result<Json<FromMeshResp>, (StatusCode, Json<Err>)> {
+    blocking(move || from_mesh_blocking(b, r)).await
+}
+
+fn from_mesh_blocking(b: budget::Budget, r: FromMeshReq) -> Result<Json<FromMeshResp>, (StatusCode, Json<Err>)> {
+    use base64::Engine;
+    let st = Instant::now();
+    let bytes = match (r.data, r.data_base64) {
+        (Some(t), None) => t.into_bytes(),
+        (None, Some(d)) => base64::engine::general_purpose::STANDARD.decode(d.trim()).map_err(|e| bad_request("Invalid data_base64", e.to_string()))?,
+        _ => return Err(bad_request("Missing data", "give exactly one of data, data_base64".into())),
+    };
+    let soup = match r.format.as_str() {
+        "obj" => meshsdf::parse_obj(std::str::from_utf8(&bytes).map_err(|_| bad_request("Invalid mesh", "OBJ must be UTF-8 text".into()))?),
+        "stl" => meshsdf::parse_stl(&bytes),
+        f => return Err(bad_request("Unknown format", format!("'{f}' is not one of obj, stl"))),
+    }.map_err(|e| bad_request("Invalid mesh", e))?;
+    if soup.indices.is_empty() { return Err(unprocessable("Empty mesh", "the mesh has no faces".into())); }
+    if soup.indices.len() > MAX_MESH_TRIANGLES { return Err(bad_request("Mesh too large", format!("{} triangles, at most {MAX_MESH_TRIANGLES} allowed", soup.indices.len()))); }
+    let bounds = soup.vertices.iter().fold(bounds::Aabb::EMPTY, |a, v| a.union(&bounds::Aabb { min: *v, max: *v }));
+    let (node, grid_dims, stored_bricks) = match r.mode.as_str() {
+        "exact" => (meshsdf::exact_node(&soup), None, None),
+        "grid" => {
+            b.resolution(r.resolution).map_err(over_budget)?;
+            if r.resolution < 4 { return Err(bad_request("Invalid resolution", "resolution must be at least 4".into())); }
+            if !(1.0..=16.0).contains(&r.band) { return Err(bad_request("Invalid band", "band must be in 1..=16 cells".into())); }
+            let mesh = meshsdf::TriMesh::new(soup.triangles());
+            let grid = meshsdf::Grid::bake(&mesh, r.resolution, r.band, &|| b.expired()).ok_or_else(|| over_budget(b.timed_out()))?;
+            grid.check().map_err(|e| unprocessable("Grid too large", e))?;
+            if grid.values.len() > MAX_GRID_VALUES {
+                return Err(unprocessable("Grid too large", format!("{} samples in the band, at most {MAX_GRID_VALUES}; lower resolution or band", grid.values.len())));
+            }
+            (grid.node(), Some(grid.dims), Some(grid.values.len() / meshsdf::BRICK.pow(3)))
+        }
+        m => return Err(bad_request("Unknown mode", format!("'{m}' is not one of exact, grid"))),
+    };
+    Ok(Json(FromMeshResp {
+        node, mode: r.mode, vertex_count: soup.vertices.len(), triangle_count: soup.indices.len(), watertight: soup.watertight(), bounds,
+        grid_dims, stored_bricks, import_time_ms: st.elapsed().as_secs_f64()*1000.0,
+    }))
+}
+
+async fn fit_handler(b: budget::Budget, Json(r): Json<FitReq>) -> Result<Json<FitResp>, (StatusCode, Json<Err>)> {
+    blocking(move || fit_blocking(b, r)).await
+}
+
+fn fit_blocking(b: budget::Budget, r: FitReq) -> Result<Json<FitResp>, (StatusCode, Json<Err>)> {
+    use base64::Engine;
+    let st = Instant::now();
+    // The declared vertex count is budgeted before any rows are read.
+    let ply = |d: &[u8]| {
+        b.points(fit::ply_vertex_count(d).map_err(|e| bad_request("Invalid PLY", e))?).map_err(over_budget)?;
+        fit::parse_ply(d).map_err(|e| bad_request("Invalid PLY", e))
+    };
+    let points = match (r.points, r.ply, r.ply_base64) {
+        (Some(p), None, None) => p,
+        (None, Some(t), None) => ply(t.as_bytes())?,
+        (None, None, Some(d)) => ply(&base64::engine::general_purpose::STANDARD.decode(d.trim()).map_err(|e| bad_request("Invalid ply_base64", e.to_string()))?)?,
+        _ => return Err(bad_request("Missing points", "give exactly one of points, ply, ply_base64".into())),
+    };
+    b.points(points.len()).map_err(over_budget)?;
+    if points.len() < 8 { return Err(bad_request("Too few points", format!("{} points given, at least 8 needed", points.len()))); }
+    if points.iter().flatten().any(|x| !x.is_finite()) { return Err(bad_request("Invalid points", "coordinates must be finite".into())); }
+    if r.max_iterations == 0 || r.max_iterations > 500 { return Err(bad_request("Invalid max_iterations", "max_iterations must be in 1..=500".into())); }
+    let fitted = match r.mode.as_str() {
+        "primitive" => {
+            let ty = r.primitive.ok_or_else(|| bad_request("Missing primitive", "mode 'primitive' needs a primitive type".into()))?;
+            fit::fit_primitive(&ty, &points, r.max_iterations, &|| b.expired())
+        }
+        "csg" => {
+            if r.max_primitives == 0 || r.max_primitives > 8 { return Err(bad_request("Invalid max_primitives", "max_primitives must be in 1..=8".into())); }
+            let types = r.primitives.unwrap_or_else(|| ["Sphere", "Box3d", "Cylinder", "Capsule"].map(String::from).to_vec());
+            if types.is_empty() { return Err(bad_request("Invalid primitives", "primitives must not be empty".into())); }
+            fit::fit_csg(&types, &points, r.max_primitives, r.max_iterations, &|| b.expired())
+        }
+        m => return Err(bad_request("Unknown mode", format!("'{m}' is not one of primitive, csg"))),
+    }.map_err(|e| bad_request("Fit failed", e))?;
+    if b.expired() { return Err(over_budget(b.timed_out())); }
+    let (_, sdf) = parse_and_compile(&fitted.tree)?;
+    let errors = sdf.eval_batch(&points);
+    let n = errors.len() as f32;
+    Ok(Json(FitResp {
+        rms_error: (errors.iter().map(|e| e * e).sum::<f32>() / n).sqrt(), mean_error: errors.iter().map(|e| e.abs()).sum::<f32>() / n,
+        max_error: errors.iter().fold(0.0, |m, e| m.max(e.abs())), point_count: errors.len(), errors,
+        tree: fitted.tree, mode: r.mode, primitive_count: fitted.primitives, iterations: fitted.iterations, converged: fitted.converged,
+        fit_time_ms: st.elapsed().as_secs_f64()*1000.0,
+    }))
+}
+
+/// The default domain pads the tree bounds by 5% of their largest side, so
+/// uniform samples also cover the space just outside the surface.
+async fn sample_handler(b: budget::Budget, Json(r): Json<SampleReq>) -> Result<Json<SampleResp>, (StatusCode, Json<Err>)> {
+    blocking(move || sample_blocking(b, r)).await
+}
+
+fn sample_blocking(b: budget::Budget, r: SampleReq) -> Result<Json<SampleResp>, (StatusCode, Json<Err>)> {
+    use base64::Engine;
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    let total = r.uniform.saturating_add(r.near_surface).saturating_add(r.surface);
+    b.points(total).map_err(over_budget)?;
+    if total == 0 { return Err(bad_request("Nothing to sample", "set at least one of uniform, near_surface, surface".into())); }
+    if !matches!(r.format.as_str(), "json" | "binary" | "ply") { return Err(bad_request("Unknown format", format!("'{}' is not one of json, binary, ply", r.format))); }
+    if r.near_surface + r.surface > 0 {
+        b.resolution(r.resolution).map_err(over_budget)?;
+        if r.resolution < 8 { return Err(bad_request("Invalid resolution", "resolution must be at least 8".into())); }
+    }
+    let (node, sdf) = parse_and_compile_at(&r.tree, r.time)?;
+    let domain = match r.bounds {
+        Some(d) if d.is_empty() || !d.is_finite() => return Err(bad_request("Invalid bounds", "bounds must be finite with min <= max".into())),
+        Some(d) => d,
+        None => { let d = domain_of(&node)?; d.expand(math::max_c(d.size()) * 0.05) }
+    };
+    let sigma = r.sigma.unwrap_or(math::max_c(domain.size()) * 0.01);
+    if !(sigma.is_finite() && sigma > 0.0) { return Err(bad_request("Invalid sigma", "sigma must be > 0".into())); }
+    let plan = sample::Plan { uniform: r.uniform, near_surface: r.near_surface, sigma, surface: r.surface, resolution: r.resolution, seed: r.seed };
+    let s = match sample::sample(&sdf, &domain, &plan, &|| b.expired()) {
+        Some(s) => s.map_err(|e| unprocessable("Sampling failed", e))?,
+        None => return Err(over_budget(b.timed_out())),
+    };
+    let (data_text, data_base64) = match r.format.as_str() {
+        "ply" => (Some(s.to_ply(&plan)), None),
+        "binary" => (None, Some(base64::engine::general_purpose::STANDARD.encode(s.to_binary()))),
+        _ => (None, None),
+    };
+    let sample_count = s.points.len();
+    let (points, distances, normals) = if r.format == "json" { (Some(s.points), Some(s.distances), Some(s.normals)) } else { (None, None, None) };
+    Ok(Json(SampleResp {
+        format: r.format, seed: r.seed, bounds: domain, sigma, uniform_count: r.uniform, near_surface_count: r.near_surface, surface_count: r.surface, sample_count,
+        points, distances, normals, data_text, data_base64, sample_time_ms: st.elapsed().as_secs_f64()*1000.0,
+    }))
+}
+
+/// Without `bounds` the volume covers the tree bounds padded by 5% of their
+/// largest side, or by the band plus one voxel when that is wider.
+async fn voxelize_handler(b: budget::Budget, Json(r): Json<VoxelizeReq>) -> Result<Json<VoxelizeResp>, (StatusCode, Json<Err>)> {
+    blocking(move || voxelize_blocking(b, r)).await
+}
+
+fn voxelize_blocking(b: budget::Budget, r: VoxelizeReq) -> Result<Json<VoxelizeResp>, (StatusCode, Json<Err>)> {
+    use base64::Engine;
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    b.resolution(r.resolution).map_err(over_budget)?;
+    if r.resolution < 2 { return Err(bad_request("Invalid resolution", "resolution must be at least 2".into())); }
+    let precision = voxel::Precision::parse(&r.precision).ok_or_else(|| bad_request("Unknown precision", format!("'{}' is not one of f32, f16", r.precision)))?;
+    let sparse = match r.layout.as_str() { "dense" => false, "sparse" => true, l => return Err(bad_request("Unknown layout", format!("'{l}' is not one of dense, sparse"))) };
+    match r.format.as_str() {
+        "raw" | "texture" => {}
+        "nrrd" if sparse => return Err(bad_request("Unsupported format", "NRRD holds dense grids; use raw or texture for the sparse layout".into())),
+        "nrrd" if precision == voxel::Precision::F16 => return Err(bad_request("Unsupported format", "NRRD has no half-float type; use precision f32".into())),
+        "nrrd" => {}
+        f => return Err(bad_request("Unknown format", format!("'{f}' is not one of raw, nrrd, texture"))),
+    }
+    let band = match r.band {
+        Some(w) if !(1.0..=64.0).contains(&w) => return Err(bad_request("Invalid band", "band must be in 1..=64 voxels".into())),
+        Some(w) => Some(w),
+        None => sparse.then_some(4.0),
+    };
+    let (node, sdf) = parse_and_compile_at(&r.tree, r.time)?;
+    let domain = match r.bounds {
+        Some(d) if d.is_empty() || !d.is_finite() => return Err(bad_request("Invalid bounds", "bounds must be finite with min <= max".into())),
+        Some(d) => d,
+        None => {
+            let d = domain_of(&node)?;
+            let side = math::max_c(d.size());
+            d.expand((side * 0.05).max((band.unwrap_or(0.0) + 1.0) * side / r.resolution as f32))
+        }
+    };
+    let mut lattice = voxel::Lattice::over(&domain, r.resolution);
+    if sparse { lattice = lattice.bricked(); }
+    if lattice.count() > MAX_VOXELS { return Err(bad_request("Volume too large", format!("{} samples, at most {MAX_VOXELS}", lattice.count()))); }
+    let band = band.map(|w| w * lattice.voxel);
+    let b64 = |d: &[u8]| base64::engine::general_purpose::STANDARD.encode(d);
+    let mut resp = VoxelizeResp {
+        layout: r.layout, format: r.format, precision: r.precision, dims: lattice.dims, origin: lattice.origin, voxel_size: lattice.voxel, bounds: lattice.bounds(), band,
+        brick_size: None, brick_dims: None, stored_bricks: None, texture_format: None, texture_dims: None, row_pitch: None, index_row_pitch: None,
+        byte_length: 0, data_base64: String::new(), index_base64: None, voxelize_time_ms: 0.0,
+    };
+    let texture = resp.format == "texture";
+    if texture { resp.texture_format = Some(precision.texture_format()); }
+    let data = if sparse {
+        let band = band.unwrap_or_default();
+        let s = voxel::sparse(&sdf, &lattice, band, &|| b.expired()).ok_or_else(|| over_budget(b.timed_out()))?;
+        let nb = lattice.brick_dims();
+        (resp.brick_size, resp.brick_dims, resp.stored_bricks) = (Some(voxel::BRICK), Some(nb), Some(s.stored));
+        if texture {
+            let dims = voxel::atlas_bricks(s.stored).map(|n| n * voxel::BRICK);
+            let (atlas, index) = voxel::atlas(&s, nb, band, precision);
+            (resp.texture_dims, resp.row_pitch, resp.index_row_pitch) = (Some(dims), Some(voxel::row_pitch(dims[0], precision.bytes())), Some(voxel::row_pitch(nb[0], 4)));
+            resp.index_base64 = Some(b64(&index));
+            atlas
+        } else {
+            resp.index_base64 = Some(b64(&s.index.iter().flat_map(|i| i.to_le_bytes()).collect::<Vec<u8>>()));
+            voxel::raw(&s.values, precision)
+        }
+    } else {
+        let values = voxel::dense(&sdf, &lattice, band, &|| b.expired()).ok_or_else(|| over_budget(b.timed_out()))?;
+        match resp.format.as_str() {
+            "nrrd" => voxel::nrrd(&values, &lattice),
+            "texture" => {
+                (resp.texture_dims, resp.row_pitch) = (Some(lattice.dims), Some(voxel::row_pitch(lattice.dims[0], precision.bytes())));
+                voxel::texture(&values, lattice.dims, precision)
+            }
+            _ => voxel::raw(&values, precision),
+        }
+    };
+    resp.byte_length = data.len();
+    resp.data_base64 = b64(&data);
+    resp.voxelize_time_ms = st.elapsed().as_secs_f64()*1000.0;
+    Ok(Json(resp))
+}
+
+async fn canonical_handler(b: budget::Budget, Json(r): Json<CanonicalReq>) -> Result<Json<CanonicalResp>, (StatusCode, Json<Err>)> {
+    let st = Instant::now();
+    tree_budget(&b, &r.tree)?;
+    let node = tree::SdfNode::from_json_at(&r.tree, r.time).map_err(|e| bad_request("Invalid tree", e))?;
+    let errs = schema::check(&node);
+    if !errs.is_empty() { return Err(unprocessable("Invalid parameters", errs.join("; "))); }
+    compiler::compile(&node).map_err(|e| unprocessable("Invalid parameters", e))?;
+    let tree = canon::canonical(&node);
+    let text = canon::text(&tree);
+    let hash = canon::hash(&text, &r.algorithm).ok_or_else(|| bad_request("Unknown algorithm", format!("'{}' is not one of sha256, blake3", r.algorithm)))?;
+    Ok(Json(CanonicalResp { tree, canonical: text, hash, algorithm: r.algorithm, version: canon::VERSION, canonical_time_ms: st.elapsed().as_secs_f64()*1000.0 }))
 }
 
 #[derive(Serialize)]
-struct PrimInfo { name: String, category: String }
-#[derive(Serialize)]
-struct PrimsResp { total: usize, primitives: Vec<PrimInfo>, operations: Vec<PrimInfo>, transforms: Vec<PrimInfo>, modifiers: Vec<PrimInfo> }
+struct PrimsResp { total: usize, primitives: Vec<&'static schema::NodeSchema>, operations: Vec<&'static schema::NodeSchema>, transforms: Vec<&'static schema::NodeSchema>, modifiers: Vec<&'static schema::NodeSchema> }
 
 async fn list_primitives() -> Json<PrimsResp> {
-    let p = |n: &str, c: &str| PrimInfo { name: n.into(), category: c.into() };
-    let prims = vec![p("Sphere","basic"),p("Box3d","basic"),p("Cylinder","basic"),p("Torus","basic"),p("Plane","basic"),p("Capsule","basic"),p("Cone","basic"),p("RoundedBox","extended"),p("Ellipsoid","extended"),p("Pyramid","extended"),p("Octahedron","platonic"),p("Tetrahedron","platonic"),p("Gyroid","tpms"),p("SchwarzP","tpms"),p("Diamond","tpms")];
-    let ops = vec![p("Union","standard"),p("Intersection","standard"),p("Subtraction","standard"),p("SmoothUnion","smooth"),p("SmoothIntersection","smooth"),p("SmoothSubtraction","smooth"),p("ChamferUnion","chamfer"),p("Xor","special"),p("Morph","special")];
-    let trans = vec![p("Translate","spatial"),p("RotateEuler","spatial"),p("Scale","spatial"),p("ScaleNonUniform","spatial")];
-    let mods = vec![p("Twist","deform"),p("Bend","deform"),p("Repeat","pattern"),p("RepeatFinite","pattern"),p("Mirror","pattern"),p("PolarRepeat","pattern"),p("Noise","surface"),p("Shell","surface"),p("Onion","surface")];
-    let total = prims.len()+ops.len()+trans.len()+mods.len();
-    Json(PrimsResp { total, primitives: prims, operations: ops, transforms: trans, modifiers: mods })
+    use schema::Group;
+    let of = |g: Group| schema::NODES.iter().filter(|n| n.group == g).collect::<Vec<_>>();
+    Json(PrimsResp { total: schema::NODES.len(), primitives: of(Group::Primitive), operations: of(Group::Operation), transforms: of(Group::Transform), modifiers: of(Group::Modifier) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn over_budget_maps_sizes_to_413_and_time_to_422() {
+        let (code, Json(e)) = over_budget(budget::Exceeded { name: "max_nodes", max: 50, requested: 51 });
+        assert_eq!(code, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!((e.error.as_str(), e.details.as_deref()), ("Budget exceeded", Some("51 exceeds max_nodes (50)")));
+        assert_eq!(e.limit.map(|l| l.name), Some("max_nodes"));
+        for name in ["max_resolution", "max_points"] {
+            assert_eq!(over_budget(budget::Exceeded { name, max: 1, requested: 2 }).0, StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let (code, Json(e)) = over_budget(budget::Budget::new(budget::Limits { timeout_ms: 0, ..Default::default() }).timed_out());
+        assert_eq!(code, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(e.limit.map(|l| l.name), Some("timeout_ms"));
+    }
+
+    #[test]
+    fn optimize_keeps_templates_out() {
+        let opt = |tree: serde_json::Value| optimize_blocking(budget::Budget::new(budget::Limits::default()), serde_json::from_value(serde_json::json!({ "tree": tree })).unwrap());
+        let Err((code, Json(e))) = opt(serde_json::json!({"variables": {"r": 1}, "type": "Sphere", "params": {"radius": "r * 2"}})) else { panic!("variables were flattened") };
+        assert_eq!((code, e.error.as_str(), e.details.as_deref()), (StatusCode::UNPROCESSABLE_ENTITY, "Parametric tree", Some("the tree declares variables (r); resolve it with /resolve before optimizing")));
+        let Err((_, Json(e))) = opt(serde_json::json!({"type": "Sphere", "params": {"radius": {"keys": [{"t": 0, "value": 1}, {"t": 1, "value": 2}]}}})) else { panic!("curves were flattened") };
+        assert_eq!(e.error, "Animated tree");
+        let Ok(Json(o)) = opt(serde_json::json!({"type": "Translate", "params": {"offset": [0, 0, 0]}, "child": {"type": "Sphere", "params": {}}})) else { panic!("plain trees optimize") };
+        assert_eq!(o.tree["type"], "Sphere");
+    }
+
+    #[test]
+    fn tree_budget_counts_nodes() {
+        let b = budget::Budget::new(budget::Limits { max_nodes: 2, ..Default::default() });
+        let two = serde_json::json!({"type": "Translate", "params": {}, "child": {"type": "Sphere", "params": {}}});
+        assert!(tree_budget(&b, &two).is_ok());
+        let three = serde_json::json!({"type": "Union", "children": [two["child"], {"type": "Box3d", "params": {}}]});
+        assert_eq!(tree_budget(&b, &three).unwrap_err().0, StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
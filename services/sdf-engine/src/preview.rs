@@ -0,0 +1,352 @@
+//! `/ws/sdf/preview`: a live editing session over one WebSocket.
+//!
+//! The client sends a tree, then edits to it. The server keeps the tree and its
+//! last good compilation, and once edits pause for `debounce_ms` (or have kept
+//! coming for four times that, so a dragged slider still previews) pushes one
+//! `update`: validation errors, bounds, the shader and, when asked for, a
+//! low-resolution mesh. Shaders are parametric: while edits leave the source
+//! unchanged, updates carry only the `sdf_params` values that moved. `eval`
+//! messages are answered at once from the last good compilation. Limits come
+//! from the upgrade request's budget header; the timeout applies per update.
+
+use crate::{bad_request, blocking, budget, build_mesh, AppState, over_budget, parse_and_compile_at, tree_budget, unprocessable, Err, MeshReq, MeshResp};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::{extract::State, http::StatusCode, response::Json, response::Response};
+use sdf_core::{bounds, compiler::CompiledSdf, diff, lipschitz, schema, shader, tree};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const MAX_MESH_RESOLUTION: usize = 64;
+const MAX_DEBOUNCE_MS: u64 = 2000;
+
+#[derive(Deserialize)]
+struct Incoming { #[serde(default)] seq: Option<u64>, #[serde(flatten)] msg: ClientMsg }
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMsg {
+    /// Replace the whole tree.
+    Tree { tree: Value },
+    /// A `/sdf/patch` patch; on any conflict the tree stays as it was.
+    Patch { patch: diff::TreePatch },
+    /// Set params of the node at `path`; `null` deletes one.
+    Params { #[serde(default = "d_root")] path: String, params: Map<String, Value> },
+    Options { target: Option<String>, time: Option<f64>, mesh_resolution: Option<usize>, mesh_format: Option<String>, debounce_ms: Option<u64> },
+    /// Distances from the last good compilation.
+    Eval { points: Vec<[f32; 3]> },
+}
+fn d_root() -> String { "root".into() }
+
+#[derive(Clone)]
+struct Options { target: String, time: f64, mesh_resolution: usize, mesh_format: String, debounce_ms: u64 }
+
+impl Default for Options {
+    fn default() -> Self { Options { target: "wgsl".into(), time: 0.0, mesh_resolution: 0, mesh_format: "glb".into(), debounce_ms: 50 } }
+}
+
+#[derive(Clone, Serialize)]
+struct Shader { target: String, source: String, params: Vec<f32> }
+
+#[derive(Serialize)]
+struct Update {
+    #[serde(rename = "type")] kind: &'static str, seq: Option<u64>, valid: bool, errors: Vec<String>, node_count: usize, depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")] bounds: Option<bounds::Aabb>,
+    /// Nodes whose fields overstate or understate distances; the shader already scales its steps.
+    #[serde(skip_serializing_if = "Vec::is_empty")] warnings: Vec<lipschitz::Warning>,
+    #[serde(skip_serializing_if = "Option::is_none")] shader: Option<Shader>,
+    /// `[index, value]` for each `sdf_params` entry that changed since the last shader.
+    #[serde(skip_serializing_if = "Option::is_none")] uniforms: Option<Vec<(usize, f32)>>,
+    #[serde(skip_serializing_if = "Option::is_none")] mesh: Option<MeshResp>,
+    update_time_ms: f64,
+}
+
+/// A valid tree's compilation and the shader sent for it.
+type Compiled = (Arc<CompiledSdf>, Shader);
+
+struct Session {
+    state: Arc<AppState>, limits: budget::Limits, opts: Options, tree: Option<Value>,
+    /// `seq` of the latest edit, echoed by the next update.
+    seq: Option<u64>,
+    sdf: Option<Arc<CompiledSdf>>,
+    /// Last shader sent, which uniform deltas are relative to.
+    shader: Option<Shader>,
+}
+
+pub async fn handler(State(state): State<Arc<AppState>>, b: budget::Budget, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| session(socket, state, b.limits))
+}
+
+async fn session(mut socket: WebSocket, state: Arc<AppState>, limits: budget::Limits) {
+    let mut s = Session { state, limits, opts: Options::default(), tree: None, seq: None, sdf: None, shader: None };
+    // Pending update: when it is due, and the latest it may be put off to.
+    let mut due: Option<(Instant, Instant)> = None;
+    loop {
+        let wait = async { match due { Some((at, _)) => tokio::time::sleep_until(at).await, None => std::future::pending().await } };
+        let reply = tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Text(text))) => match s.receive(&text).await {
+                    Ok(None) if s.tree.is_some() => { due = Some(reschedule(due, Instant::now(), Duration::from_millis(s.opts.debounce_ms))); None }
+                    Ok(reply) => reply,
+                    Err(e) => Some(e),
+                },
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            _ = wait => { due = None; Some(s.flush().await) }
+        };
+        if let Some(v) = reply {
+            if socket.send(Message::Text(v.to_string())).await.is_err() { break; }
+        }
+    }
+}
+
+/// Pending update after an edit at `now`: `debounce` later, but no later than
+/// four debounces after the first edit since the last update.
+fn reschedule(due: Option<(Instant, Instant)>, now: Instant, debounce: Duration) -> (Instant, Instant) {
+    let latest = due.map_or(now + 4 * debounce, |(_, l)| l);
+    ((now + debounce).min(latest), latest)
+}
+
+/// `error` message with the HTTP endpoints' error body.
+fn error(seq: Option<u64>, (_, Json(e)): (StatusCode, Json<Err>)) -> Value {
+    let mut v = serde_json::to_value(e).unwrap_or_default();
+    v["type"] = json!("error");
+    v["seq"] = json!(seq);
+    v
+}
+
+impl Session {
+    /// An immediate reply, or `None` when the message changed what the next update shows.
+    async fn receive(&mut self, text: &str) -> Result<Option<Value>, Value> {
+        let m: Incoming = serde_json::from_str(text).map_err(|e| error(None, bad_request("Invalid message", e.to_string())))?;
+        let seq = m.seq;
+        self.apply(seq, m.msg).await.map_err(|e| error(seq, e))
+    }
+
+    async fn apply(&mut self, seq: Option<u64>, msg: ClientMsg) -> Result<Option<Value>, (StatusCode, Json<Err>)> {
+        match msg {
+            ClientMsg::Tree { tree } => self.tree = Some(tree),
+            ClientMsg::Patch { patch } => self.patch(&patch)?,
+            ClientMsg::Params { path, params } => {
+                let ops = params.into_iter().map(|(key, value)| diff::PatchOp::SetParam { path: path.clone(), key, value, old: None }).collect();
+                self.patch(&diff::TreePatch { ops })?
+            }
+            ClientMsg::Options { target, time, mesh_resolution, mesh_format, debounce_ms } => {
+                let mut o = self.opts.clone();
+                if let Some(t) = target {
+                    shader::Target::parse(&t).ok_or_else(|| bad_request("Unknown target", format!("'{t}' is not one of wgsl, glsl, hlsl")))?;
+                    o.target = t;
+                }
+                if let Some(t) = time {
+                    if !t.is_finite() { return Err(bad_request("Invalid time", "time must be finite".into())); }
+                    o.time = t;
+                }
+                if let Some(r) = mesh_resolution {
+                    if r > MAX_MESH_RESOLUTION { return Err(bad_request("Invalid mesh_resolution", format!("mesh_resolution must be in 0..={MAX_MESH_RESOLUTION}"))); }
+                    budget::Budget::new(self.limits).resolution(r).map_err(over_budget)?;
+                    o.mesh_resolution = r;
+                }
+                if let Some(f) = mesh_format {
+                    if !matches!(f.as_str(), "obj" | "stl" | "ply" | "glb") { return Err(bad_request("Unknown format", format!("'{f}' is not one of obj, stl, ply, glb"))); }
+                    o.mesh_format = f;
+                }
+                if let Some(d) = debounce_ms {
+                    if d > MAX_DEBOUNCE_MS { return Err(bad_request("Invalid debounce_ms", format!("debounce_ms must be in 0..={MAX_DEBOUNCE_MS}"))); }
+                    o.debounce_ms = d;
+                }
+                if o.target != self.opts.target { self.shader = None; }
+                self.opts = o;
+            }
+            ClientMsg::Eval { points } => {
+                let sdf = self.sdf.clone().ok_or_else(|| unprocessable("No tree", "eval needs a valid tree first".into()))?;
+                budget::Budget::new(self.limits).points(points.len()).map_err(over_budget)?;
+                let distances = blocking(move || Ok(sdf.eval_batch(&points))).await?;
+                return Ok(Some(json!({ "type": "eval", "seq": seq, "distances": distances })));
+            }
+        }
+        self.seq = seq.or(self.seq);
+        Ok(None)
+    }
+
+    fn patch(&mut self, p: &diff::TreePatch) -> Result<(), (StatusCode, Json<Err>)> {
+        let tree = self.tree.as_ref().ok_or_else(|| unprocessable("No tree", "send a tree before editing it".into()))?;
+        budget::Budget::new(self.limits).nodes(p.ops.len()).map_err(over_budget)?;
+        let (patched, conflicts) = diff::apply(tree, p);
+        if !conflicts.is_empty() {
+            let list: Vec<String> = conflicts.iter().map(|c| format!("op {} at {}: {}", c.op_index, c.path, c.reason)).collect();
+            return Err(unprocessable("Patch conflict", list.join("; ")));
+        }
+        self.tree = Some(patched);
+        Ok(())
+    }
+
+    /// Build the pending update off the async runtime and keep what it compiled.
+    async fn flush(&mut self) -> Value {
+        let Some(tree) = self.tree.clone() else { return Value::Null };
+        let (seq, opts, prev, limits, state) = (self.seq, self.opts.clone(), self.shader.clone(), self.limits, self.state.clone());
+        match tokio::task::spawn_blocking(move || update(&tree, seq, &opts, prev.as_ref(), limits, &state)).await {
+            Ok(Ok((u, next))) => {
+                if let Some((sdf, shader)) = next { self.sdf = Some(sdf); self.shader = Some(shader); }
+                serde_json::to_value(u).unwrap_or_default()
+            }
+            Ok(Err(e)) => error(seq, e),
+            Err(e) => error(seq, unprocessable("Update failed", e.to_string())),
+        }
+    }
+}
+
+/// Update for `tree`, plus its compilation and shader when it is valid.
+fn update(tree: &Value, seq: Option<u64>, o: &Options, prev: Option<&Shader>, limits: budget::Limits, state: &AppState) -> Result<(Update, Option<Compiled>), (StatusCode, Json<Err>)> {
+    let st = std::time::Instant::now();
+    let b = budget::Budget::new(limits);
+    tree_budget(&b, tree)?;
+    let mut u = Update {
+        kind: "update", seq, valid: false, errors: schema::validate(tree), node_count: tree::count_nodes(tree), depth: tree::tree_depth(tree),
+        bounds: None, warnings: Vec::new(), shader: None, uniforms: None, mesh: None, update_time_ms: 0.0,
+    };
+    let compiled = if u.errors.is_empty() { parse_and_compile_at(tree, o.time).map_err(|(_, Json(e))| u.errors.push(e.details.unwrap_or(e.error))).ok() } else { None };
+    let Some((node, sdf)) = compiled else {
+        u.update_time_ms = st.elapsed().as_secs_f64() * 1000.0;
+        return Ok((u, None));
+    };
+    u.valid = true;
+    let bb = bounds::bounds(&node).clamp_to(5.0);
+    u.bounds = (!bb.is_empty()).then_some(bb);
+    u.warnings = lipschitz::analyze(&node).warnings;
+    let (source, params) = shader::transpile_parametric(&sdf, shader::Target::parse(&o.target).unwrap_or(shader::Target::Wgsl));
+    let next = Shader { target: o.target.clone(), source, params };
+    match prev.filter(|s| s.target == next.target && s.source == next.source) {
+        Some(s) => u.uniforms = Some(s.params.iter().zip(&next.params).enumerate().filter(|(_, (a, b))| a.to_bits() != b.to_bits()).map(|(i, (_, &v))| (i, v)).collect()),
+        None => u.shader = Some(next.clone()),
+    }
+    if o.mesh_resolution > 0 {
+        let r = MeshReq { tree: tree.clone(), resolution: o.mesh_resolution, format: o.mesh_format.clone(), time: o.time, frames: None, mode: "compiled".into() };
+        match build_mesh(r, &[o.time], &b, &state.jit, &mut |_| true) {
+            Ok(m) => u.mesh = Some(m),
+            // Over time is an error; a tree with nothing to mesh just has no mesh.
+            Err(e) if e.1.limit.is_some() => return Err(e),
+            Err(_) => {}
+        }
+    }
+    u.update_time_ms = st.elapsed().as_secs_f64() * 1000.0;
+    Ok((u, Some((Arc::new(sdf), next))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(limits: budget::Limits) -> Session {
+        let state = Arc::new(AppState { start_time: std::time::Instant::now(), budget: limits, jit: sdf_core::jit::Cache::default() });
+        Session { state, limits, opts: Options::default(), tree: None, seq: None, sdf: None, shader: None }
+    }
+
+    async fn send(s: &mut Session, msg: Value) -> Result<Option<Value>, Value> { s.receive(&msg.to_string()).await }
+
+    #[test]
+    fn edits_debounce_up_to_four_delays() {
+        let (t0, d) = (Instant::now(), Duration::from_millis(50));
+        let ms = |n| t0 + Duration::from_millis(n);
+        let due = reschedule(None, t0, d);
+        assert_eq!(due, (ms(50), ms(200)));
+        // Each edit pushes the update back, until the first edit's deadline.
+        let due = reschedule(Some(due), ms(30), d);
+        assert_eq!(due, (ms(80), ms(200)));
+        let due = reschedule(Some(due), ms(120), d);
+        assert_eq!(due, (ms(170), ms(200)));
+        assert_eq!(reschedule(Some(due), ms(180), d), (ms(200), ms(200)));
+        // After an update the next edit starts a new window.
+        assert_eq!(reschedule(None, ms(210), d), (ms(260), ms(410)));
+        assert_eq!(reschedule(None, t0, Duration::ZERO), (t0, t0));
+    }
+
+    #[tokio::test]
+    async fn unchanged_shaders_send_only_the_uniforms_that_moved() {
+        let mut s = session(budget::Limits::default());
+        let sphere = json!({"type": "Translate", "params": {"offset": [0.5, 0, 0]}, "child": {"type": "Sphere", "params": {"radius": 1}}});
+        assert_eq!(send(&mut s, json!({"type": "tree", "seq": 1, "tree": sphere})).await, Ok(None));
+        let first = s.flush().await;
+        assert_eq!((first["type"].as_str(), first["seq"].as_u64(), first["valid"].as_bool()), (Some("update"), Some(1), Some(true)));
+        let params: Vec<f64> = serde_json::from_value(first["shader"]["params"].clone()).unwrap();
+        assert!(first.get("uniforms").is_none());
+
+        assert_eq!(send(&mut s, json!({"type": "params", "seq": 2, "path": "root.child", "params": {"radius": 2}})).await, Ok(None));
+        let moved = s.flush().await;
+        assert!(moved.get("shader").is_none());
+        let i = params.iter().position(|&p| p == 1.0).unwrap();
+        assert_eq!((moved["seq"].as_u64(), &moved["uniforms"]), (Some(2), &json!([[i, 2.0]])));
+        assert_eq!(s.flush().await["uniforms"], json!([]));
+
+        // A new target, or a tree that compiles to different source, resends the shader.
+        assert_eq!(send(&mut s, json!({"type": "options", "target": "glsl"})).await, Ok(None));
+        let glsl = s.flush().await;
+        assert_eq!((glsl["shader"]["target"].as_str(), glsl.get("uniforms")), (Some("glsl"), None));
+        send(&mut s, json!({"type": "tree", "tree": {"type": "Box3d", "params": {}}})).await.unwrap();
+        let boxed = s.flush().await;
+        assert!(boxed["shader"]["source"].is_string() && boxed.get("uniforms").is_none());
+        assert_ne!(boxed["shader"]["source"], glsl["shader"]["source"]);
+    }
+
+    #[tokio::test]
+    async fn conflicting_patches_leave_the_tree_alone() {
+        let mut s = session(budget::Limits::default());
+        let err = send(&mut s, json!({"type": "params", "seq": 1, "params": {"radius": 2}})).await.unwrap_err();
+        assert_eq!((err["type"].as_str(), err["seq"].as_u64(), err["error"].as_str()), (Some("error"), Some(1), Some("No tree")));
+
+        let tree = json!({"type": "Sphere", "params": {"radius": 1}});
+        send(&mut s, json!({"type": "tree", "tree": tree})).await.unwrap();
+        let patch = json!({"ops": [
+            {"op": "set_param", "path": "root", "key": "radius", "value": 3},
+            {"op": "set_param", "path": "root", "key": "radius", "value": 4, "old": 5},
+        ]});
+        let err = send(&mut s, json!({"type": "patch", "seq": 7, "patch": patch})).await.unwrap_err();
+        assert_eq!((err["seq"].as_u64(), err["error"].as_str()), (Some(7), Some("Patch conflict")));
+        assert!(err["details"].as_str().unwrap().starts_with("op 1 at root: "), "{err}");
+        let err = send(&mut s, json!({"type": "patch", "patch": {"ops": [{"op": "remove", "path": "root.child"}]}})).await.unwrap_err();
+        assert_eq!(err["error"].as_str(), Some("Patch conflict"));
+        assert_eq!(s.tree.as_ref(), Some(&tree));
+
+        let ok = json!({"ops": [{"op": "set_param", "path": "root", "key": "radius", "value": 3, "old": 1}]});
+        assert_eq!(send(&mut s, json!({"type": "patch", "seq": 8, "patch": ok})).await, Ok(None));
+        assert_eq!((s.tree.as_ref().unwrap()["params"]["radius"].as_i64(), s.seq), (Some(3), Some(8)));
+    }
+
+    #[tokio::test]
+    async fn eval_uses_the_last_good_compilation() {
+        let mut s = session(budget::Limits { max_points: 2, ..Default::default() });
+        let eval = json!({"type": "eval", "seq": 3, "points": [[2, 0, 0], [0, 0, 0]]});
+        assert_eq!(send(&mut s, eval.clone()).await.unwrap_err()["error"].as_str(), Some("No tree"));
+        send(&mut s, json!({"type": "tree", "tree": {"type": "Sphere", "params": {"radius": 1}}})).await.unwrap();
+        s.flush().await;
+        // An invalid edit keeps answering from the sphere.
+        send(&mut s, json!({"type": "tree", "tree": {"type": "Blob"}})).await.unwrap();
+        assert_eq!(s.flush().await["valid"].as_bool(), Some(false));
+        let reply = send(&mut s, eval).await.unwrap().unwrap();
+        assert_eq!((reply["type"].as_str(), reply["seq"].as_u64(), &reply["distances"]), (Some("eval"), Some(3), &json!([1.0, -1.0])));
+        let err = send(&mut s, json!({"type": "eval", "points": [[0, 0, 0], [0, 0, 0], [0, 0, 0]]})).await.unwrap_err();
+        assert_eq!((err["error"].as_str(), err["limit"]["name"].as_str()), (Some("Budget exceeded"), Some("max_points")));
+    }
+
+    #[tokio::test]
+    async fn options_are_checked_before_they_apply() {
+        let mut s = session(budget::Limits { max_resolution: 32, ..Default::default() });
+        for (msg, title) in [
+            (json!({"type": "options", "target": "msl"}), "Unknown target"),
+            (json!({"type": "options", "mesh_format": "fbx"}), "Unknown format"),
+            (json!({"type": "options", "debounce_ms": 5000}), "Invalid debounce_ms"),
+            (json!({"type": "options", "mesh_resolution": 65}), "Invalid mesh_resolution"),
+            (json!({"type": "options", "mesh_resolution": 48}), "Budget exceeded"),
+            (json!({"type": "zoom"}), "Invalid message"),
+        ] {
+            assert_eq!(send(&mut s, msg.clone()).await.unwrap_err()["error"].as_str(), Some(title), "{msg}");
+        }
+        // A rejected message changes nothing, even the fields it had right.
+        assert!(send(&mut s, json!({"type": "options", "target": "hlsl", "debounce_ms": 5000})).await.is_err());
+        assert_eq!((s.opts.target.as_str(), s.opts.debounce_ms), ("wgsl", 50));
+        assert_eq!(send(&mut s, json!({"type": "options", "target": "hlsl", "mesh_resolution": 16, "debounce_ms": 0})).await, Ok(None));
+        assert_eq!((s.opts.target.as_str(), s.opts.mesh_resolution, s.opts.debounce_ms), ("hlsl", 16, 0));
+    }
+}